@@ -2,10 +2,10 @@
 extern crate test;
 use test::Bencher;
 
-use ndarray_einsum_beta::*;
 use ndarray::prelude::*;
+use ndarray_einsum_beta::*;
+use ndarray_rand::rand_distr::Uniform;
 use ndarray_rand::RandomExt;
-use rand::distributions::Uniform;
 
 fn rand_array<Sh, D: Dimension>(shape: Sh) -> ArrayBase<ndarray::OwnedRepr<f64>, D>
 where
@@ -157,3 +157,35 @@ fn bench_stacked_double_outer_product_large(b: &mut Bencher) {
 
     b.iter(|| einsum("ijk,ilm->ijklm", &[&m1, &m2]));
 }
+
+#[bench]
+fn bench_diagonal_sum_large(b: &mut Bencher) {
+    let m1 = rand_array((300, 300, 40));
+
+    b.iter(|| einsum("iij->j", &[&m1]));
+}
+
+#[bench]
+fn bench_diagonal_sum_sliced_large(b: &mut Bencher) {
+    let m1 = rand_array((600, 300, 40));
+    let sliced = m1.slice(s![..;2, .., ..]);
+
+    b.iter(|| einsum("iij->j", &[&sliced]));
+}
+
+#[bench]
+fn bench_stacked_multiply_medium(b: &mut Bencher) {
+    let m1 = rand_array((20, 30, 40));
+    let m2 = rand_array((20, 40, 50));
+
+    b.iter(|| einsum("bij,bjk->bik", &[&m1, &m2]));
+}
+
+#[bench]
+fn bench_stacked_multiply_sliced_medium(b: &mut Bencher) {
+    let m1 = rand_array((40, 30, 80));
+    let m2 = rand_array((20, 40, 50));
+    let sliced = m1.slice(s![..;-2, .., ..;2]);
+
+    b.iter(|| einsum("bij,bjk->bik", &[&sliced, &m2]));
+}
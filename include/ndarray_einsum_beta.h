@@ -0,0 +1,69 @@
+/*
+ * Copyright 2019 Jared Samet
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C interface of ndarray_einsum_beta, enabled by its `capi` feature. Build the library with
+ *
+ *     cargo rustc --release --features capi --crate-type cdylib
+ *
+ * (or `--crate-type staticlib`). See src/capi.rs for the documentation of each function.
+ */
+#ifndef NDARRAY_EINSUM_BETA_H
+#define NDARRAY_EINSUM_BETA_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EINSUM_OK 0
+#define EINSUM_ERROR_INVALID_ARGUMENT 1
+#define EINSUM_ERROR_CONTRACTION 2
+#define EINSUM_ERROR_PANIC 3
+
+typedef struct EinsumPlan EinsumPlan;
+
+/* The message describing the last error returned on the calling thread. */
+const char *einsum_last_error(void);
+
+/* Plans `subscripts` for operands where operand i has shape shapes[i][0..ndims[i]]. */
+int einsum_plan_create(const char *subscripts, size_t num_operands, const size_t *ndims,
+                       const size_t *const *shapes, EinsumPlan **plan);
+
+void einsum_plan_destroy(EinsumPlan *plan);
+
+size_t einsum_plan_output_ndim(const EinsumPlan *plan);
+
+void einsum_plan_output_shape(const EinsumPlan *plan, size_t *shape);
+
+/*
+ * Strides are counted in elements and can be negative. `strides`, any `strides[i]`, and
+ * `out_strides` can be NULL for arrays that are contiguous in row-major order.
+ */
+int einsum_plan_execute_f64(const EinsumPlan *plan, const double *const *operands,
+                            const ptrdiff_t *const *strides, double *out,
+                            const ptrdiff_t *out_strides);
+
+int einsum_plan_execute_f32(const EinsumPlan *plan, const float *const *operands,
+                            const ptrdiff_t *const *strides, float *out,
+                            const ptrdiff_t *out_strides);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NDARRAY_EINSUM_BETA_H */
@@ -0,0 +1,358 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Procedural macros for [ndarray_einsum_beta](https://docs.rs/ndarray_einsum_beta/).
+//!
+//! Use them through the `macros` feature of `ndarray_einsum_beta`, which re-exports them;
+//! the expansions refer to items in `ndarray_einsum_beta`.
+//!
+//! A proc-macro crate can't depend on the crate whose code it generates, so the `einsum`-formatted
+//! string is checked here by a copy of the rules in `ndarray_einsum_beta`'s `validation` module.
+//! The two have to be changed together.
+use proc_macro::TokenStream;
+use quote::quote;
+use std::collections::HashMap;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Expr, LitStr, Token};
+
+/// The prefix that marks an `einsum`-formatted string whose labels are words separated by
+/// whitespace instead of single letters.
+const LABELS_PREFIX: &str = "labels:";
+
+/// The literal used in `einsum`-formatted strings to stand in for any number of
+/// broadcast axes, e.g. `...ij,...jk->...ik`.
+const ELLIPSIS: &str = "...";
+
+/// The arguments to `einsum!`: a string literal followed by the operands.
+struct EinsumInput {
+    input_string: LitStr,
+    operands: Vec<Expr>,
+}
+
+impl Parse for EinsumInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let input_string: LitStr = input.parse()?;
+        let mut operands = Vec::new();
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            operands = Punctuated::<Expr, Token![,]>::parse_terminated(input)?
+                .into_iter()
+                .collect();
+        }
+        Ok(EinsumInput {
+            input_string,
+            operands,
+        })
+    }
+}
+
+/// Checks that `indices` consists of letters (`char::is_alphabetic`) and at most one ellipsis.
+/// `position` is the byte offset of `indices` in the full string, used for error reporting.
+fn check_indices(indices: &str, position: usize) -> Result<(), String> {
+    let parse_error = |offset: usize, message: &str| {
+        Err(format!(
+            "invalid einsum string at byte {}: {}",
+            position + offset,
+            message
+        ))
+    };
+    let mut found_ellipsis = false;
+    let mut offset = 0;
+    while offset < indices.len() {
+        let rest = &indices[offset..];
+        if rest.starts_with(ELLIPSIS) {
+            if found_ellipsis {
+                return parse_error(offset, "only one ellipsis is allowed per operand");
+            }
+            found_ellipsis = true;
+            offset += ELLIPSIS.len();
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+        if !c.is_alphabetic() {
+            return parse_error(
+                offset,
+                if c == '.' {
+                    "incomplete ellipsis"
+                } else if c.is_whitespace() {
+                    "whitespace in indices (multi-character labels need the prefix \"labels:\")"
+                } else {
+                    "invalid character in indices"
+                },
+            );
+        }
+        offset += c.len_utf8();
+    }
+
+    Ok(())
+}
+
+/// Checks that the whitespace-separated words of `subscripts` (which starts at byte `position`
+/// of the full string) are labels or an ellipsis, with at most one ellipsis, and returns them.
+fn check_labels(subscripts: &str, position: usize) -> Result<Vec<(usize, &str)>, String> {
+    let parse_error = |position: usize, message: &str| {
+        Err(format!(
+            "invalid einsum string at byte {}: {}",
+            position, message
+        ))
+    };
+    let mut words = Vec::new();
+    let mut start = None;
+    for (offset, c) in subscripts
+        .char_indices()
+        .chain(Some((subscripts.len(), ' ')))
+    {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push((position + word_start, &subscripts[word_start..offset]));
+                start = None;
+            }
+            (false, None) => start = Some(offset),
+            _ => {}
+        }
+    }
+    let mut found_ellipsis = false;
+    for &(word_position, word) in words.iter() {
+        if word == ELLIPSIS {
+            if found_ellipsis {
+                return parse_error(word_position, "only one ellipsis is allowed per operand");
+            }
+            found_ellipsis = true;
+            continue;
+        }
+        for (offset, c) in word.char_indices() {
+            if !(c == '_' || c.is_alphabetic() || (offset > 0 && c.is_ascii_digit())) {
+                return parse_error(word_position + offset, "invalid character in label");
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// The same checks as `check_input_string`, for a string whose labels are the words separated
+/// by whitespace, which starts at byte `position` of the full string (after `LABELS_PREFIX`).
+fn check_labelled_string(
+    input_string: &str,
+    position: usize,
+    num_operands: usize,
+) -> Result<Option<usize>, String> {
+    let (operands_string, output_string) = match input_string.find("->") {
+        Some(pos) => (
+            &input_string[..pos],
+            Some((position + pos + 2, &input_string[(pos + 2)..])),
+        ),
+        None => (input_string, None),
+    };
+
+    let mut input_counts: HashMap<&str, usize> = HashMap::new();
+    let mut num_inputs = 0;
+    let mut position = position;
+    for operand_string in operands_string.split(',') {
+        for (_, label) in check_labels(operand_string, position)? {
+            *input_counts.entry(label).or_insert(0) += 1;
+        }
+        position += operand_string.len() + 1;
+        num_inputs += 1;
+    }
+
+    let output_rank = if let Some((position, output_string)) = output_string {
+        let output_labels = check_labels(output_string, position)?;
+        for &(_, label) in output_labels.iter() {
+            if !input_counts.contains_key(label) {
+                return Err(format!(
+                    "requested output contains label '{}' not found in inputs",
+                    label
+                ));
+            }
+        }
+
+        if output_labels.iter().any(|&(_, label)| label == ELLIPSIS) {
+            None
+        } else {
+            Some(output_labels.len())
+        }
+    } else if input_counts.contains_key(ELLIPSIS) {
+        None
+    } else {
+        // Implicit output: the labels that appear exactly once
+        Some(input_counts.values().filter(|&&n| n == 1).count())
+    };
+
+    if num_inputs != num_operands {
+        return Err(format!(
+            "contraction has {} operands but {} were supplied",
+            num_inputs, num_operands
+        ));
+    }
+
+    Ok(output_rank)
+}
+
+/// Performs the checks that don't depend on the shapes of the operands: the string has to
+/// parse, the requested output can't use an index that isn't in the inputs, and there has
+/// to be one operand for each set of input indices.
+///
+/// As in `ndarray_einsum_beta`, a string that starts with `labels:` has labels that are the
+/// words that whitespace separates instead of single letters.
+///
+/// Returns the number of dimensions of the output, unless it depends on the number of axes
+/// covered by an ellipsis.
+fn check_input_string(input_string: &str, num_operands: usize) -> Result<Option<usize>, String> {
+    if let Some(labelled_string) = input_string.strip_prefix(LABELS_PREFIX) {
+        return check_labelled_string(labelled_string, LABELS_PREFIX.len(), num_operands);
+    }
+    let (operands_string, output_string) = match input_string.find("->") {
+        Some(pos) => (
+            &input_string[..pos],
+            Some((pos + 2, &input_string[(pos + 2)..])),
+        ),
+        None => (input_string, None),
+    };
+
+    let mut num_inputs = 0;
+    let mut position = 0;
+    for operand_string in operands_string.split(',') {
+        check_indices(operand_string, position)?;
+        position += operand_string.len() + 1;
+        num_inputs += 1;
+    }
+
+    let output_rank = if let Some((position, output_string)) = output_string {
+        check_indices(output_string, position)?;
+
+        for c in output_string.chars().filter(|&c| c != '.') {
+            if !operands_string.contains(c) {
+                return Err(format!(
+                    "requested output contains index '{}' not found in inputs",
+                    c
+                ));
+            }
+        }
+
+        if output_string.contains(ELLIPSIS) {
+            None
+        } else {
+            Some(output_string.chars().count())
+        }
+    } else if operands_string.contains(ELLIPSIS) {
+        None
+    } else {
+        // Implicit output: the indices that appear exactly once
+        let mut input_counts = HashMap::new();
+        for c in operands_string.chars().filter(|&c| c != ',') {
+            *input_counts.entry(c).or_insert(0) += 1;
+        }
+        Some(input_counts.values().filter(|&&n| n == 1).count())
+    };
+
+    if num_inputs != num_operands {
+        return Err(format!(
+            "contraction has {} operands but {} were supplied",
+            num_inputs, num_operands
+        ));
+    }
+
+    Ok(output_rank)
+}
+
+/// Performs an `einsum` contraction whose string is checked at compile time.
+///
+/// `einsum!("ij,jk->ik", a, b)` expands to
+/// `ndarray_einsum_beta::einsum_dim::<Ix2, _>("ij,jk->ik", &[&a, &b])`, where the number of
+/// dimensions of the output is deduced from the string, so it returns a
+/// `Result<Array2<A>, EinsumError>` without any conversion at run time. When the number of
+/// dimensions isn't fixed by the string (because the output includes an ellipsis) or is more
+/// than 6, the result is an `ArrayD<A>` instead. The plan cache is used if it's enabled.
+///
+/// The operands can be any expressions whose values implement `ArrayLike<A>`; they're borrowed,
+/// not moved.
+///
+/// A string that doesn't parse, an output index that isn't in the inputs, or a number of
+/// operands that doesn't match the string is a compile error, so the only errors left at run
+/// time are mismatched shapes.
+///
+/// ```
+/// use ndarray::prelude::*;
+/// use ndarray_einsum_beta_macros::einsum;
+///
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape_with_order((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape_with_order((3, 4)).unwrap();
+/// let c: Array2<f64> = einsum!("ij,jk->ik", a, b).unwrap();
+/// assert_eq!(c, a.dot(&b));
+/// let total: Array0<f64> = einsum!("ij->", a.view()).unwrap();
+/// assert_eq!(total.into_scalar(), 15.);
+/// let column_sums: Array1<f64> = einsum!("ij", a).map(|t| t.sum_axis(Axis(0))).unwrap();
+/// assert_eq!(column_sums, a.sum_axis(Axis(0)));
+/// let broadcast: ArrayD<f64> = einsum!("...ij->...ji", a).unwrap();
+/// assert_eq!(broadcast, a.t().into_dyn());
+/// let product: Array2<f64> = einsum!("μν,νρ->μρ", a, b).unwrap();
+/// assert_eq!(product, a.dot(&b));
+/// let product: Array2<f64> = einsum!("labels: row inner, inner col -> row col", a, b).unwrap();
+/// assert_eq!(product, a.dot(&b));
+/// ```
+///
+/// ```compile_fail
+/// # use ndarray::prelude::*;
+/// # use ndarray_einsum_beta_macros::einsum;
+/// let a: Array2<f64> = Array::zeros((2, 3));
+/// let b: Array2<f64> = Array::zeros((3, 4));
+/// // error: invalid einsum string at byte 4: invalid character in indices
+/// einsum!("ij,j1->ik", a, b);
+/// ```
+///
+/// ```compile_fail
+/// # use ndarray::prelude::*;
+/// # use ndarray_einsum_beta_macros::einsum;
+/// let a: Array2<f64> = Array::zeros((2, 3));
+/// // error: contraction has 2 operands but 1 were supplied
+/// einsum!("ij,jk->ik", a);
+/// ```
+///
+/// ```compile_fail
+/// # use ndarray::prelude::*;
+/// # use ndarray_einsum_beta_macros::einsum;
+/// let a: Array2<f64> = Array::zeros((2, 3));
+/// let b: Array2<f64> = Array::zeros((3, 4));
+/// // error: requested output contains index 'l' not found in inputs
+/// einsum!("ij,jk->il", a, b);
+/// ```
+#[proc_macro]
+pub fn einsum(input: TokenStream) -> TokenStream {
+    let EinsumInput {
+        input_string,
+        operands,
+    } = parse_macro_input!(input as EinsumInput);
+
+    let output_rank = match check_input_string(&input_string.value(), operands.len()) {
+        Ok(output_rank) => output_rank,
+        Err(message) => {
+            return syn::Error::new(input_string.span(), message)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    // ndarray only implements `Dimension` for fixed-size arrays of up to 6 axes
+    let dimension = match output_rank {
+        Some(ndim) if ndim <= 6 => quote!(::ndarray_einsum_beta::__private::Dim<[usize; #ndim]>),
+        _ => quote!(::ndarray_einsum_beta::__private::IxDyn),
+    };
+
+    quote!(
+        ::ndarray_einsum_beta::einsum_dim::<#dimension, _>(#input_string, &[#(&#operands),*])
+    )
+    .into()
+}
@@ -0,0 +1,216 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains the `Backend` trait, through which the contractors perform their matrix
+//! multiplications, matrix-vector and dot products, permutations and reductions.
+//!
+//! The path executor and the contractors only decide how to reshape the operands of each step;
+//! the arithmetic on the reshaped tensors goes through the methods of `Backend`. Every method
+//! has a default implementation on the CPU (with `ndarray`'s `general_mat_mul`, which uses
+//! `matrixmultiply`, or BLAS with the `blas` feature), so an alternative implementation only
+//! needs to override the primitives it does differently. `CpuBackend` overrides nothing
+//! and is used outside of [`with_backend`](fn.with_backend.html).
+//!
+//! For example, on machines with many cores, faer's multithreaded GEMM is often much faster
+//! than `matrixmultiply`. With the `faer` feature, `FaerBackend` uses it by overriding only
+//! `mat_mul`; permutations and reductions are left to the default implementations.
+use crate::standard_layout_output;
+use crate::summation::{
+    dot, mat_mul, mat_vec_mul, sum_trailing_axes, summation_mode, SummationMode,
+};
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use core::any::Any;
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// The primitives used by the contractors for tensors of `A`s. The default implementations
+/// run on the CPU and respect the current [`SummationMode`](enum.SummationMode.html).
+pub trait Backend<A: LinalgScalar>: Send + Sync {
+    /// Computes `out = alpha * lhs.dot(rhs) + beta * out`. As with `general_mat_mul`, the
+    /// existing contents of `out` are ignored when `beta` is zero.
+    fn mat_mul(
+        &self,
+        alpha: A,
+        lhs: &ArrayView2<A>,
+        rhs: &ArrayView2<A>,
+        beta: A,
+        out: &mut ArrayViewMut2<A>,
+    ) {
+        mat_mul(summation_mode(), alpha, lhs, rhs, beta, out);
+    }
+
+    /// Computes `out = alpha * lhs.dot(rhs) + beta * out` for a matrix `lhs` and a vector
+    /// `rhs`. As with `general_mat_vec_mul`, the existing contents of `out` are ignored when
+    /// `beta` is zero.
+    fn mat_vec_mul(
+        &self,
+        alpha: A,
+        lhs: &ArrayView2<A>,
+        rhs: &ArrayView1<A>,
+        beta: A,
+        out: &mut ArrayViewMut1<A>,
+    ) {
+        mat_vec_mul(summation_mode(), alpha, lhs, rhs, beta, out);
+    }
+
+    /// Returns the dot product of the vectors `lhs` and `rhs`.
+    fn dot(&self, lhs: &ArrayView1<A>, rhs: &ArrayView1<A>) -> A {
+        dot(summation_mode(), lhs, rhs)
+    }
+
+    /// Returns a copy of `tensor` with its axes permuted by `permutation`, as by
+    /// `permuted_axes`. The copy is in standard layout inside
+    /// [`with_standard_layout_output`](fn.with_standard_layout_output.html), and otherwise
+    /// keeps the order of the elements in memory if it can.
+    fn permute(&self, tensor: &ArrayViewD<A>, permutation: &[usize]) -> ArrayD<A> {
+        let permuted = tensor.view().permuted_axes(IxDyn(permutation));
+        if standard_layout_output() {
+            permuted.as_standard_layout().into_owned()
+        } else {
+            permuted.to_owned()
+        }
+    }
+
+    /// Returns the sum of `tensor` over its last `num_axes` axes.
+    fn sum_trailing_axes(&self, tensor: &ArrayViewD<A>, num_axes: usize) -> ArrayD<A> {
+        let mode = summation_mode();
+        if mode != SummationMode::Standard {
+            return sum_trailing_axes(mode, tensor, num_axes);
+        }
+
+        let axis = Axis(tensor.ndim() - num_axes);
+        let mut result = tensor.sum_axis(axis);
+        for _ in 1..num_axes {
+            result = result.sum_axis(axis);
+        }
+        result
+    }
+}
+
+/// The default backend, which uses the default implementation of each primitive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuBackend;
+
+impl<A: LinalgScalar> Backend<A> for CpuBackend {}
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// An `Arc<dyn Backend<A>>` for the element type `A` the backend was set for
+    static BACKEND: RefCell<Option<Arc<dyn Any + Send + Sync>>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous backend when dropped, so that it is restored even if `op` panics.
+#[cfg(feature = "std")]
+struct BackendGuard(Option<Arc<dyn Any + Send + Sync>>);
+
+#[cfg(feature = "std")]
+impl Drop for BackendGuard {
+    fn drop(&mut self) {
+        BACKEND.with(|backend| *backend.borrow_mut() = self.0.take());
+    }
+}
+
+/// Runs `op` with the contractions of `A`s that it performs on the current thread (including
+/// the work they hand to rayon) using `backend` for their primitives, and returns its result.
+/// Contractions of other element types use `CpuBackend`. Calls can be nested.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// /// Counts the matrix multiplications, and otherwise does what `CpuBackend` does
+/// #[derive(Default)]
+/// struct CountingBackend(AtomicUsize);
+///
+/// impl Backend<f64> for CountingBackend {
+///     fn mat_mul(
+///         &self,
+///         alpha: f64,
+///         lhs: &ArrayView2<f64>,
+///         rhs: &ArrayView2<f64>,
+///         beta: f64,
+///         out: &mut ArrayViewMut2<f64>,
+///     ) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///         ndarray::linalg::general_mat_mul(alpha, lhs, rhs, beta, out);
+///     }
+/// }
+///
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let backend = std::sync::Arc::new(CountingBackend::default());
+/// let product = with_backend(backend.clone(), || einsum("ij,jk->ik", &[&a, &b])).unwrap();
+/// assert_eq!(product, a.dot(&b).into_dyn());
+/// assert_eq!(backend.0.load(Ordering::Relaxed), 1);
+/// ```
+#[cfg(feature = "std")]
+pub fn with_backend<A, R, F>(backend: Arc<dyn Backend<A>>, op: F) -> R
+where
+    A: LinalgScalar,
+    F: FnOnce() -> R,
+{
+    let backend: Arc<dyn Any + Send + Sync> = Arc::new(backend);
+    let _guard = BackendGuard(BACKEND.with(|current| current.borrow_mut().replace(backend)));
+    op()
+}
+
+/// Returns the backend set for `A`s by the innermost enclosing
+/// [`with_backend`](fn.with_backend.html) on this thread, or `None` if there isn't one.
+#[cfg(feature = "std")]
+fn current_backend<A: LinalgScalar>() -> Option<Arc<dyn Backend<A>>> {
+    BACKEND.with(|backend| {
+        backend
+            .borrow()
+            .as_ref()
+            .and_then(|backend| backend.downcast_ref::<Arc<dyn Backend<A>>>())
+            .cloned()
+    })
+}
+
+/// Calls `op` with the current backend for `A`s. The backend is cloned out of the thread-local
+/// first, so `op` can itself call `with_backend`.
+pub(crate) fn with_current_backend<A, R>(op: impl FnOnce(&dyn Backend<A>) -> R) -> R
+where
+    A: LinalgScalar,
+{
+    #[cfg(feature = "std")]
+    if let Some(backend) = current_backend::<A>() {
+        return op(&*backend);
+    }
+    op(&CpuBackend)
+}
+
+/// The backend of the current thread, type-erased so that parallel work can carry it over to
+/// the threads that do it.
+#[cfg(feature = "rayon")]
+#[derive(Clone)]
+pub(crate) struct BackendSetting(Option<Arc<dyn Any + Send + Sync>>);
+
+#[cfg(feature = "rayon")]
+impl BackendSetting {
+    pub(crate) fn current() -> Self {
+        BackendSetting(BACKEND.with(|backend| backend.borrow().clone()))
+    }
+
+    /// Runs `op` with this backend.
+    pub(crate) fn apply<R>(&self, op: impl FnOnce() -> R) -> R {
+        let _guard = BackendGuard(BACKEND.with(|current| current.replace(self.0.clone())));
+        op()
+    }
+}
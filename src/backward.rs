@@ -0,0 +1,173 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_backward`, which computes the gradients of a contraction with respect to
+//! its operands for reverse-mode automatic differentiation.
+//!
+//! A contraction is linear in each of its operands, so the gradient with respect to one of them
+//! is another contraction: the upstream gradient of the output and all the other operands are
+//! contracted into the indices of that operand. For `ij,jk->ik`, the gradients are
+//! `ik,jk->ij` and `ij,ik->jk`. Three cases need more than a change of subscripts:
+//!
+//! * An index that only appears in the operand isn't in the other tensors, so the gradient is
+//!   computed without it and then broadcast along it (e.g. for `ij->i`, the gradient is the
+//!   upstream gradient repeated along `j`).
+//! * An index repeated within the operand gives a gradient that is zero off the diagonal (e.g.
+//!   for `ii->`, the identity matrix), which is what an `einsum` output that repeats an index
+//!   produces.
+//! * An axis of length 1 that was broadcast against a longer axis gets the sum of the gradient
+//!   along that axis.
+use crate::{
+    generate_optimized_order, ArrayLike, Contraction, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Returns the gradient of the contraction `sc` with respect to operand `operand_num`.
+fn operand_gradient<A: LinalgScalar>(
+    sc: &SizedContraction,
+    operands: &[&dyn ArrayLike<A>],
+    grad_output: &ArrayViewD<A>,
+    operand_num: usize,
+) -> ArrayD<A> {
+    let operand_indices = &sc.contraction.operand_indices[operand_num];
+    let mut distinct_indices: Vec<char> = Vec::new();
+    for &c in operand_indices.iter() {
+        if !distinct_indices.contains(&c) {
+            distinct_indices.push(c);
+        }
+    }
+
+    // The upstream gradient and the other operands
+    let mut gradient_indices = vec![sc.contraction.output_indices.clone()];
+    let mut gradient_operands: Vec<&dyn ArrayLike<A>> = vec![grad_output];
+    for (n, operand) in operands.iter().enumerate() {
+        if n != operand_num {
+            gradient_indices.push(sc.contraction.operand_indices[n].clone());
+            gradient_operands.push(*operand);
+        }
+    }
+    let is_present = |c: &char| gradient_indices.iter().any(|indices| indices.contains(c));
+    let present_indices: Vec<char> = distinct_indices
+        .iter()
+        .cloned()
+        .filter(is_present)
+        .collect();
+    let mut summation_indices: Vec<char> = Vec::new();
+    for &c in gradient_indices.iter().flatten() {
+        if !present_indices.contains(&c) && !summation_indices.contains(&c) {
+            summation_indices.push(c);
+        }
+    }
+
+    let gradient_sc = SizedContraction {
+        contraction: Contraction {
+            operand_indices: gradient_indices,
+            output_indices: present_indices.clone(),
+            summation_indices,
+        },
+        output_size: sc.output_size.clone(),
+    };
+    let contraction_order = generate_optimized_order(&gradient_sc, OptimizationMethod::Greedy);
+    let mut gradient =
+        EinsumPath::from_path(&contraction_order).contract_operands(&gradient_operands);
+
+    // Broadcast along the indices that only this operand has, and sum along the axes of
+    // length 1 that were broadcast in the forward contraction.
+    let operand_shape = operands[operand_num].into_dyn_view().shape().to_vec();
+    let operand_length =
+        |c: &char| operand_shape[operand_indices.iter().position(|x| x == c).unwrap()];
+    for (axis, c) in present_indices.iter().enumerate() {
+        if operand_length(c) != gradient.shape()[axis] {
+            gradient = gradient.sum_axis(Axis(axis)).insert_axis(Axis(axis));
+        }
+    }
+    for (axis, c) in distinct_indices.iter().enumerate() {
+        if !present_indices.contains(c) {
+            gradient = gradient.insert_axis(Axis(axis));
+        }
+    }
+    let distinct_shape: Vec<usize> = distinct_indices.iter().map(operand_length).collect();
+    let gradient = gradient.broadcast(distinct_shape).unwrap().to_owned();
+
+    if distinct_indices.len() == operand_indices.len() {
+        return gradient;
+    }
+    // Embed the gradient along the diagonals of the repeated indices
+    let mut output_size = sc.output_size.clone();
+    for c in distinct_indices.iter() {
+        output_size.insert(*c, operand_length(c));
+    }
+    let embedding_sc = SizedContraction {
+        contraction: Contraction {
+            operand_indices: vec![distinct_indices],
+            output_indices: operand_indices.clone(),
+            summation_indices: Vec::new(),
+        },
+        output_size,
+    };
+    EinsumPath::new(&embedding_sc).contract_operands(&[&gradient])
+}
+
+/// Given the gradient `grad_output` of some quantity with respect to the result of the
+/// contraction specified by `input_string` and `operands`, returns its gradient with respect
+/// to each of the operands, in order. Each gradient has the shape of its operand.
+///
+/// The gradients are those of a real-valued quantity: for complex elements, they aren't
+/// conjugated.
+///
+/// Returns the same errors as `einsum` for the contraction itself, and
+/// `EinsumError::OutputShapeMismatch` if `grad_output` doesn't have the shape of its result.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let grad_c: Array2<f64> = Array::ones((2, 4));
+/// let grads = einsum_backward("ij,jk->ik", &[&a, &b], &grad_c).unwrap();
+/// assert_eq!(grads[0], grad_c.dot(&b.t()).into_dyn());
+/// assert_eq!(grads[1], a.t().dot(&grad_c).into_dyn());
+///
+/// // The gradient of the trace is the identity matrix
+/// let m: Array2<f64> = Array::zeros((3, 3));
+/// let grads = einsum_backward("ii->", &[&m], &arr0(1.)).unwrap();
+/// assert_eq!(grads[0], Array2::<f64>::eye(3).into_dyn());
+/// ```
+pub fn einsum_backward<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    grad_output: &dyn ArrayLike<A>,
+) -> Result<Vec<ArrayD<A>>, EinsumError> {
+    let sc = SizedContraction::new(input_string, operands)?;
+    let grad_output = grad_output.into_dyn_view();
+    let output_shape: Vec<usize> = sc
+        .contraction
+        .output_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect();
+    if grad_output.shape() != output_shape.as_slice() {
+        return Err(EinsumError::OutputShapeMismatch {
+            expected: output_shape,
+            found: grad_output.shape().to_vec(),
+        });
+    }
+
+    Ok((0..operands.len())
+        .map(|operand_num| operand_gradient(&sc, operands, &grad_output, operand_num))
+        .collect())
+}
@@ -0,0 +1,433 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `BlockSparseTensor`, a tensor whose axes are split into blocks and of which only
+//! the nonzero blocks are stored, and `einsum_block_sparse`, which contracts such tensors
+//! without ever forming them densely.
+//!
+//! Tensors that are invariant under a symmetry (e.g. particle number or spin in quantum
+//! chemistry) only have nonzero elements in the blocks whose symmetry sectors are compatible,
+//! so most of the blocks of a tensor are absent. The contraction order is chosen for the
+//! full shapes, as for dense operands. Then each step loops over the combinations of stored
+//! blocks, one from each input, that agree on the block of every index they share. Each
+//! combination is contracted by the same `SingletonContraction` or `PairContraction` that the
+//! dense executor would use for tensors of the blocks' shapes, and the result is added to the
+//! output block picked out by the blocks of the output indices. Blocks of the output that no
+//! combination contributes to are left out of the result.
+use crate::collections::{hash_map, HashMap};
+use crate::contractors::{
+    PairContraction, PairContractor, SingletonContraction, SingletonContractor,
+};
+use crate::optimizers::OperandNumber;
+use crate::{
+    generate_optimized_order, ContractionOrder, EinsumError, OptimizationMethod, SizedContraction,
+};
+use alloc::boxed::Box;
+use alloc::collections::{btree_map, BTreeMap};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{Data, LinalgScalar, Slice};
+
+/// A tensor whose axes are each split into consecutive blocks, and of which only some of the
+/// blocks are stored. A block is identified by its position along each axis, e.g. `[1, 0]` is
+/// the block of a matrix in the second block of rows and the first block of columns. Blocks
+/// that aren't stored are zero.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let mut tensor = BlockSparseTensor::new(vec![vec![1, 2], vec![2, 1]]);
+/// tensor.insert_block(vec![1, 0], array![[1., 2.], [3., 4.]].into_dyn());
+/// assert_eq!(tensor.shape(), vec![3, 3]);
+/// assert_eq!(
+///     tensor.to_dense(),
+///     array![[0., 0., 0.], [1., 2., 0.], [3., 4., 0.]].into_dyn()
+/// );
+/// let from_dense = BlockSparseTensor::from_dense(&tensor.to_dense(), vec![vec![1, 2], vec![2, 1]]);
+/// assert_eq!(from_dense, tensor);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSparseTensor<A> {
+    block_sizes: Vec<Vec<usize>>,
+    blocks: BTreeMap<Vec<usize>, ArrayD<A>>,
+}
+
+impl<A> BlockSparseTensor<A> {
+    /// Creates a tensor with no stored blocks, whose axis `n` is split into blocks of lengths
+    /// `block_sizes[n]`.
+    pub fn new(block_sizes: Vec<Vec<usize>>) -> Self {
+        BlockSparseTensor {
+            block_sizes,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Stores `block` as the block at position `coords`, returning the block that was stored
+    /// there before, if any.
+    ///
+    /// Panics if `coords` doesn't have one entry per axis, if it is out of bounds, or if `block`
+    /// doesn't have the shape of the block at `coords`.
+    pub fn insert_block(&mut self, coords: Vec<usize>, block: ArrayD<A>) -> Option<ArrayD<A>> {
+        let block_shape = self.block_shape(&coords);
+        assert_eq!(
+            block.shape(),
+            &block_shape[..],
+            "block {:?} should have shape {:?}",
+            coords,
+            block_shape
+        );
+        self.blocks.insert(coords, block)
+    }
+
+    /// Returns the block at position `coords`, or `None` if it isn't stored.
+    pub fn block(&self, coords: &[usize]) -> Option<&ArrayD<A>> {
+        self.blocks.get(coords)
+    }
+
+    /// Returns the stored blocks and their positions, in lexicographic order of the positions.
+    pub fn blocks(&self) -> impl Iterator<Item = (&[usize], &ArrayD<A>)> {
+        self.blocks
+            .iter()
+            .map(|(coords, block)| (&coords[..], block))
+    }
+
+    /// Returns the number of stored blocks.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the lengths of the blocks along each axis.
+    pub fn block_sizes(&self) -> &[Vec<usize>] {
+        &self.block_sizes
+    }
+
+    /// Returns the shape of the block at position `coords`.
+    ///
+    /// Panics if `coords` doesn't have one entry per axis or is out of bounds.
+    pub fn block_shape(&self, coords: &[usize]) -> Vec<usize> {
+        assert!(
+            coords.len() == self.block_sizes.len()
+                && coords
+                    .iter()
+                    .zip(&self.block_sizes)
+                    .all(|(&i, sizes)| i < sizes.len()),
+            "block {:?} is out of bounds for block sizes {:?}",
+            coords,
+            self.block_sizes
+        );
+        coords
+            .iter()
+            .zip(&self.block_sizes)
+            .map(|(&i, sizes)| sizes[i])
+            .collect()
+    }
+
+    /// Returns the shape of the full tensor.
+    pub fn shape(&self) -> Vec<usize> {
+        self.block_sizes
+            .iter()
+            .map(|sizes| sizes.iter().sum())
+            .collect()
+    }
+
+    /// Returns the ranges of the full tensor covered by the block at position `coords`.
+    fn block_slices(&self, coords: &[usize]) -> Vec<Slice> {
+        coords
+            .iter()
+            .zip(&self.block_sizes)
+            .map(|(&i, sizes)| {
+                let start: usize = sizes[..i].iter().sum();
+                Slice::from(start..(start + sizes[i]))
+            })
+            .collect()
+    }
+}
+
+impl<A: LinalgScalar> BlockSparseTensor<A> {
+    /// Splits `array` into blocks of lengths `block_sizes[n]` along axis `n`, and stores the
+    /// blocks that have a nonzero element.
+    ///
+    /// Panics if `block_sizes` doesn't have one entry per axis of `array`, or if the block
+    /// lengths along some axis don't add up to its length.
+    pub fn from_dense<S, D>(array: &ArrayBase<S, D>, block_sizes: Vec<Vec<usize>>) -> Self
+    where
+        S: Data<Elem = A>,
+        D: Dimension,
+    {
+        let mut tensor = BlockSparseTensor::new(block_sizes);
+        assert_eq!(
+            array.shape(),
+            &tensor.shape()[..],
+            "the block sizes don't add up to the shape of the array"
+        );
+
+        let num_blocks: Vec<usize> = tensor.block_sizes.iter().map(|x| x.len()).collect();
+        for coords in ndarray::indices(IxDyn(&num_blocks)) {
+            let coords = coords.slice().to_vec();
+            let slices = tensor.block_slices(&coords);
+            let block = array.slice_each_axis(|axis| slices[axis.axis.index()]);
+            if block.iter().any(|x| !x.is_zero()) {
+                tensor.blocks.insert(coords, block.to_owned().into_dyn());
+            }
+        }
+        tensor
+    }
+
+    /// Returns the full tensor as a dense array, with zeros in place of the blocks that aren't
+    /// stored.
+    pub fn to_dense(&self) -> ArrayD<A> {
+        let mut dense = ArrayD::zeros(self.shape());
+        for (coords, block) in self.blocks.iter() {
+            let slices = self.block_slices(coords);
+            dense
+                .slice_each_axis_mut(|axis| slices[axis.axis.index()])
+                .assign(block);
+        }
+        dense
+    }
+}
+
+/// The compiled contraction of one combination of blocks.
+enum BlockContraction<A> {
+    Singleton(SingletonContraction<A>),
+    Pair(Box<PairContraction<A>>),
+}
+
+/// Stored blocks, one from each input of a step, that agree on the blocks of their shared
+/// indices, along with the block that each index takes.
+struct BlockCombination<'a, A> {
+    assignment: HashMap<char, usize>,
+    blocks: Vec<&'a ArrayD<A>>,
+}
+
+/// Records in `assignment` that each index in `indices` takes the block in the corresponding
+/// entry of `coords`. Returns false, leaving `assignment` partly updated, if some index was
+/// already assigned a different block.
+fn assign_blocks(
+    indices: &[char],
+    coords: &[usize],
+    assignment: &mut HashMap<char, usize>,
+) -> bool {
+    for (&c, &block) in indices.iter().zip(coords) {
+        if *assignment.entry(c).or_insert(block) != block {
+            return false;
+        }
+    }
+    true
+}
+
+/// Performs one step of the contraction, on one or two block-sparse tensors.
+fn contract_step<A: LinalgScalar>(
+    sc: &SizedContraction,
+    operands: &[&BlockSparseTensor<A>],
+) -> BlockSparseTensor<A> {
+    let operand_indices = &sc.contraction.operand_indices;
+    let mut index_blocks: HashMap<char, &Vec<usize>> = HashMap::new();
+    for (indices, operand) in operand_indices.iter().zip(operands) {
+        for (&c, sizes) in indices.iter().zip(&operand.block_sizes) {
+            index_blocks.insert(c, sizes);
+        }
+    }
+    let mut step_indices: Vec<char> = index_blocks.keys().cloned().collect();
+    step_indices.sort_unstable();
+
+    let mut combinations: Vec<BlockCombination<A>> = Vec::new();
+    if operands.len() == 1 {
+        for (coords, block) in operands[0].blocks.iter() {
+            let mut assignment = HashMap::new();
+            if assign_blocks(&operand_indices[0], coords, &mut assignment) {
+                combinations.push(BlockCombination {
+                    assignment,
+                    blocks: vec![block],
+                });
+            }
+        }
+    } else {
+        let shared_indices: Vec<char> = operand_indices[0]
+            .iter()
+            .filter(|c| operand_indices[1].contains(c))
+            .cloned()
+            .collect();
+        let mut rhs_by_shared_blocks: HashMap<Vec<usize>, Vec<_>> = HashMap::new();
+        for (coords, block) in operands[1].blocks.iter() {
+            let mut assignment = HashMap::new();
+            if assign_blocks(&operand_indices[1], coords, &mut assignment) {
+                let shared_blocks = shared_indices.iter().map(|c| assignment[c]).collect();
+                rhs_by_shared_blocks
+                    .entry(shared_blocks)
+                    .or_default()
+                    .push((assignment, block));
+            }
+        }
+        for (coords, lhs_block) in operands[0].blocks.iter() {
+            let mut lhs_assignment = HashMap::new();
+            if !assign_blocks(&operand_indices[0], coords, &mut lhs_assignment) {
+                continue;
+            }
+            let shared_blocks: Vec<usize> =
+                shared_indices.iter().map(|c| lhs_assignment[c]).collect();
+            for (rhs_assignment, rhs_block) in rhs_by_shared_blocks
+                .get(&shared_blocks)
+                .into_iter()
+                .flatten()
+            {
+                let mut assignment = lhs_assignment.clone();
+                assignment.extend(rhs_assignment);
+                combinations.push(BlockCombination {
+                    assignment,
+                    blocks: vec![lhs_block, *rhs_block],
+                });
+            }
+        }
+    }
+
+    let output_indices = &sc.contraction.output_indices;
+    let mut result = BlockSparseTensor::new(
+        output_indices
+            .iter()
+            .map(|c| index_blocks[c].clone())
+            .collect(),
+    );
+    // Combinations of blocks with the same shapes share a compiled contraction
+    let mut contractions: HashMap<Vec<usize>, BlockContraction<A>> = HashMap::new();
+    for BlockCombination { assignment, blocks } in combinations {
+        let block_lengths: Vec<usize> = step_indices
+            .iter()
+            .map(|c| index_blocks[c][assignment[c]])
+            .collect();
+        let contraction = contractions
+            .entry(block_lengths)
+            .or_insert_with_key(|block_lengths| {
+                let block_sc = SizedContraction {
+                    contraction: sc.contraction.clone(),
+                    output_size: step_indices
+                        .iter()
+                        .cloned()
+                        .zip(block_lengths.iter().cloned())
+                        .collect(),
+                };
+                if operands.len() == 1 {
+                    BlockContraction::Singleton(SingletonContraction::new(&block_sc))
+                } else {
+                    BlockContraction::Pair(Box::new(PairContraction::new(&block_sc)))
+                }
+            });
+        let block_result = match contraction {
+            BlockContraction::Singleton(op) => op.contract_singleton(&blocks[0].view()),
+            BlockContraction::Pair(op) => op.contract_pair(&blocks[0].view(), &blocks[1].view()),
+        };
+
+        let output_coords = output_indices.iter().map(|c| assignment[c]).collect();
+        match result.blocks.entry(output_coords) {
+            btree_map::Entry::Occupied(mut entry) => {
+                entry
+                    .get_mut()
+                    .zip_mut_with(&block_result, |sum, &term| *sum = *sum + term);
+            }
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(block_result);
+            }
+        }
+    }
+    result
+}
+
+/// Performs the contraction specified by `input_string` on block-sparse operands and returns
+/// a block-sparse result, whose axes are split into blocks as the corresponding axes of the
+/// operands are.
+///
+/// Every axis with a given index has to be split into the same blocks, so an operand can't
+/// be broadcast; otherwise this returns `EinsumError::BlockStructureMismatch`. Other errors
+/// are the same as those of `einsum` for operands with the full shapes.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // Two block-diagonal matrices, made of a 1x1 block and a 2x2 block
+/// let mut a = BlockSparseTensor::new(vec![vec![1, 2], vec![1, 2]]);
+/// a.insert_block(vec![0, 0], array![[2.]].into_dyn());
+/// a.insert_block(vec![1, 1], array![[1., 2.], [3., 4.]].into_dyn());
+/// let mut b = BlockSparseTensor::new(vec![vec![1, 2], vec![1, 2]]);
+/// b.insert_block(vec![0, 0], array![[5.]].into_dyn());
+/// b.insert_block(vec![1, 1], array![[0., 1.], [1., 0.]].into_dyn());
+///
+/// let c = einsum_block_sparse("ij,jk->ik", &[&a, &b]).unwrap();
+/// // The product is block-diagonal too
+/// assert_eq!(c.num_blocks(), 2);
+/// assert_eq!(c.block(&[1, 1]), Some(&array![[2., 1.], [4., 3.]].into_dyn()));
+/// assert_eq!(
+///     c.to_dense(),
+///     einsum("ij,jk->ik", &[&a.to_dense(), &b.to_dense()]).unwrap()
+/// );
+/// ```
+pub fn einsum_block_sparse<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&BlockSparseTensor<A>],
+) -> Result<BlockSparseTensor<A>, EinsumError> {
+    let operand_shapes: Vec<Vec<usize>> = operands.iter().map(|x| x.shape()).collect();
+    let sc = SizedContraction::from_string_and_shapes(input_string, &operand_shapes)?;
+
+    let mut index_blocks: HashMap<char, &Vec<usize>> = HashMap::new();
+    for (operand_num, (operand, indices)) in operands
+        .iter()
+        .zip(&sc.contraction.operand_indices)
+        .enumerate()
+    {
+        for (axis, (&c, sizes)) in indices.iter().zip(&operand.block_sizes).enumerate() {
+            match index_blocks.entry(c) {
+                hash_map::Entry::Occupied(entry) => {
+                    if *entry.get() != sizes {
+                        return Err(EinsumError::BlockStructureMismatch {
+                            operand: operand_num,
+                            axis,
+                        });
+                    }
+                }
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(sizes);
+                }
+            }
+        }
+    }
+
+    let result = match generate_optimized_order(&sc, OptimizationMethod::Greedy) {
+        ContractionOrder::Singleton(sc) => contract_step(&sc, operands),
+        ContractionOrder::Pairs(order_steps) => {
+            let mut intermediate_results: Vec<Option<BlockSparseTensor<A>>> = Vec::new();
+            for order_step in order_steps.iter() {
+                let operand_nums = [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs];
+                let step_operands: Vec<&BlockSparseTensor<A>> = operand_nums
+                    .iter()
+                    .map(|operand_number| match **operand_number {
+                        OperandNumber::Input(pos) => operands[pos],
+                        OperandNumber::IntermediateResult(pos) => {
+                            intermediate_results[pos].as_ref().unwrap()
+                        }
+                    })
+                    .collect();
+                let result = contract_step(&order_step.sized_contraction, &step_operands);
+
+                // Intermediate results are only used once, so they can be dropped.
+                for operand_number in operand_nums {
+                    if let OperandNumber::IntermediateResult(pos) = *operand_number {
+                        intermediate_results[pos] = None;
+                    }
+                }
+                intermediate_results.push(Some(result));
+            }
+            intermediate_results.pop().unwrap().unwrap()
+        }
+    };
+    Ok(result)
+}
@@ -0,0 +1,185 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `with_max_intermediate_bytes`, which limits the memory taken up by the
+//! intermediate results of a contraction.
+//!
+//! Normally every step of a path computes its whole result, so a path whose intermediate results
+//! don't fit in memory makes the allocation (and the process) fail. Inside
+//! [`with_max_intermediate_bytes`](fn.with_max_intermediate_bytes.html), every way of
+//! performing an `EinsumPath` (`contract_operands`, `contract_operands_into`,
+//! `contract_operands_acc`, `contract_operands_with_workspace` and `par_contract_operands`, and
+//! so `einsum` and `par_einsum`) first checks the largest intermediate result of the path
+//! against the budget. If it's too large, it picks an index of the output and performs the
+//! whole path for one chunk of that index at a time, writing each chunk into its slice of the
+//! result. Every intermediate result that has the index shrinks in proportion to the chunk, so
+//! the index and chunk length are chosen to fit the budget in as few chunks as possible. The
+//! final result itself isn't counted, since it has to be allocated anyway. The budget is
+//! carried over to the rayon threads that parallel contractions run on. The exception is
+//! `execute_with`, which performs each step whole so that it can be inspected.
+use crate::out_of_core::{result_indices, with_index_len};
+use crate::ContractionOrder;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+
+#[cfg(feature = "std")]
+thread_local! {
+    static MAX_INTERMEDIATE_BYTES: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Restores the previous budget when dropped, so that it is restored even if `op` panics.
+#[cfg(feature = "std")]
+struct BudgetGuard(Option<usize>);
+
+#[cfg(feature = "std")]
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        MAX_INTERMEDIATE_BYTES.with(|budget| budget.set(self.0));
+    }
+}
+
+/// Runs `op` with the intermediate results of each contraction it performs on the current
+/// thread limited to `max_bytes` bytes each (see the module documentation), and returns its
+/// result. Calls can be nested.
+///
+/// The budget is met whenever splitting a single output index can meet it. Otherwise (for
+/// example, if the output is a scalar, or the large intermediate result doesn't have any of the
+/// output indices), the contraction is split as finely as that helps, or performed as usual.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array1<f64> = Array::range(0., 100., 1.);
+/// let b: Array1<f64> = Array::range(0., 200., 1.);
+/// let c: Array2<f64> = Array::ones((100, 200));
+/// // The outer product of a and b takes up 160 kB, so it is computed 10 rows at a time
+/// let path = einsum_path("i,j,ij->i", &[&a, &b, &c], OptimizationMethod::Naive).unwrap();
+/// let rows = with_max_intermediate_bytes(16_000, || path.contract_operands(&[&a, &b, &c]));
+/// assert_eq!(rows, (a * b.sum()).into_dyn());
+/// ```
+#[cfg(feature = "std")]
+pub fn with_max_intermediate_bytes<R, F>(max_bytes: usize, op: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    with_budget(Some(max_bytes), op)
+}
+
+/// Runs `op` with the budget set to `max_bytes`, or without a budget if it's `None`.
+#[cfg(feature = "std")]
+pub(crate) fn with_budget<R>(max_bytes: Option<usize>, op: impl FnOnce() -> R) -> R {
+    let _guard = BudgetGuard(MAX_INTERMEDIATE_BYTES.with(|budget| budget.replace(max_bytes)));
+    op()
+}
+
+/// Runs `op`, since a budget can only be set with `std`.
+#[cfg(not(feature = "std"))]
+pub(crate) fn with_budget<R>(_max_bytes: Option<usize>, op: impl FnOnce() -> R) -> R {
+    op()
+}
+
+/// Returns the budget set by the innermost enclosing
+/// [`with_max_intermediate_bytes`](fn.with_max_intermediate_bytes.html) on this thread, or
+/// `None` outside of one.
+#[cfg(feature = "std")]
+pub fn max_intermediate_bytes() -> Option<usize> {
+    MAX_INTERMEDIATE_BYTES.with(|budget| budget.get())
+}
+
+/// Returns `None`, since a budget can only be set with `std`.
+#[cfg(not(feature = "std"))]
+pub fn max_intermediate_bytes() -> Option<usize> {
+    None
+}
+
+/// The number of bytes taken up by the largest intermediate result of `contraction_order`, not
+/// counting the final result.
+fn largest_intermediate_bytes<A>(contraction_order: &ContractionOrder) -> u128 {
+    match contraction_order {
+        ContractionOrder::Singleton(_) => 0,
+        ContractionOrder::Pairs(order_steps) => order_steps[..order_steps.len() - 1]
+            .iter()
+            .map(|step| {
+                let sc = &step.sized_contraction;
+                sc.contraction
+                    .output_indices
+                    .iter()
+                    .map(|c| sc.output_size[c] as u128)
+                    .fold(1, u128::saturating_mul)
+            })
+            .max()
+            .unwrap_or(0)
+            .saturating_mul(core::mem::size_of::<A>() as u128),
+    }
+}
+
+/// Returns the output index to split `contraction_order` along and the length of the chunks,
+/// or `None` if it doesn't need to be (or can't usefully be) split to meet `max_bytes`.
+fn choose_chunking<A>(
+    contraction_order: &ContractionOrder,
+    max_bytes: usize,
+) -> Option<(char, usize)> {
+    let max_bytes = max_bytes as u128;
+    let unsplit_bytes = largest_intermediate_bytes::<A>(contraction_order);
+    if unsplit_bytes <= max_bytes {
+        return None;
+    }
+    let output_indices = result_indices(contraction_order);
+    let output_size = match contraction_order {
+        ContractionOrder::Singleton(_) => return None,
+        ContractionOrder::Pairs(order_steps) => {
+            &order_steps.last().unwrap().sized_contraction.output_size
+        }
+    };
+    let bytes_with_chunks = |index: char, chunk_len: usize| {
+        largest_intermediate_bytes::<A>(&with_index_len(contraction_order, index, chunk_len))
+    };
+
+    // The chunking with the fewest chunks that fits, or else the one with the smallest
+    // intermediate results
+    let mut best_fit: Option<(char, usize)> = None;
+    let mut best_miss: Option<(char, u128)> = None;
+    for &index in output_indices.iter() {
+        let index_len = output_size[&index];
+        let smallest_bytes = bytes_with_chunks(index, 1);
+        if smallest_bytes > max_bytes {
+            if smallest_bytes < best_miss.map_or(unsplit_bytes, |(_, bytes)| bytes) {
+                best_miss = Some((index, smallest_bytes));
+            }
+            continue;
+        }
+        // The largest chunk length that fits, by bisection
+        let (mut fits, mut too_long) = (1, index_len);
+        while too_long - fits > 1 {
+            let chunk_len = (fits + too_long) / 2;
+            if bytes_with_chunks(index, chunk_len) <= max_bytes {
+                fits = chunk_len;
+            } else {
+                too_long = chunk_len;
+            }
+        }
+        let num_chunks =
+            |(index, chunk_len): (char, usize)| output_size[&index].div_ceil(chunk_len);
+        if best_fit.is_none_or(|best| num_chunks((index, fits)) < num_chunks(best)) {
+            best_fit = Some((index, fits));
+        }
+    }
+    best_fit.or_else(|| best_miss.map(|(index, _)| (index, 1)))
+}
+
+/// Returns the output index to split `contraction_order` along and the length of the chunks,
+/// or `None` if the current budget doesn't require it to be split.
+pub(crate) fn budget_chunking<A>(contraction_order: &ContractionOrder) -> Option<(char, usize)> {
+    choose_chunking::<A>(contraction_order, max_intermediate_bytes()?)
+}
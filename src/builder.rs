@@ -0,0 +1,174 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `Einsum`, a builder that puts a contraction together one operand at a time, each
+//! with its own subscripts, instead of as a single `einsum`-formatted string.
+//!
+//! Each operand is validated as soon as it is added, against its subscripts and the operands
+//! before it, and the first error is kept and returned by `eval`. Putting the operands together
+//! with their subscripts one at a time is less error-prone than keeping a string and a list of
+//! operands in step when the contraction is built up by code.
+use crate::validation::check_subscripts;
+use crate::{einsum, ArrayLike, EinsumError, SizedContraction, LABELS_PREFIX};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// A contraction built up one operand at a time.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let ab = Einsum::new().operand(&a, "ij").operand(&b, "jk").output("ik").eval().unwrap();
+/// assert_eq!(ab, a.dot(&b).into_dyn());
+///
+/// // Without an output, the output is implicit, as in `einsum("ij,jk", ...)`
+/// let ab = Einsum::new().operand(&a, "ij").operand(&b, "jk").eval().unwrap();
+/// assert_eq!(ab, a.dot(&b).into_dyn());
+///
+/// // Operand 1 doesn't fit the operands before it
+/// let builder = Einsum::new().operand(&a, "ij").operand(&a, "jk");
+/// assert_eq!(
+///     builder.error(),
+///     Some(&EinsumError::IndexLengthMismatch {
+///         index: "j".to_string(),
+///         first_operand: 0,
+///         first_axis: 1,
+///         expected: 3,
+///         operand: 1,
+///         axis: 0,
+///         found: 2,
+///     })
+/// );
+/// ```
+pub struct Einsum<'a, A> {
+    operands: Vec<&'a dyn ArrayLike<A>>,
+    operand_indices: Vec<String>,
+    output_indices: Option<String>,
+    /// Whether the subscripts are whitespace-separated labels rather than single letters
+    labelled: bool,
+    error: Option<EinsumError>,
+}
+
+impl<'a, A> Default for Einsum<'a, A> {
+    fn default() -> Self {
+        Einsum::new()
+    }
+}
+
+impl<'a, A> Einsum<'a, A> {
+    /// Starts a contraction without any operands.
+    pub fn new() -> Self {
+        Einsum {
+            operands: Vec::new(),
+            operand_indices: Vec::new(),
+            output_indices: None,
+            labelled: false,
+            error: None,
+        }
+    }
+
+    /// Starts a contraction without any operands, whose subscripts are labels separated by
+    /// whitespace (e.g. `"batch chan"`) instead of single letters, as in an `einsum`-formatted
+    /// string that starts with [`LABELS_PREFIX`](constant.LABELS_PREFIX.html).
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let builder = Einsum::labelled()
+    ///     .operand(&a, "row inner")
+    ///     .operand(&b, "inner col")
+    ///     .output("row col");
+    /// assert_eq!(builder.input_string(), "labels:row inner,inner col->row col");
+    /// assert_eq!(builder.eval().unwrap(), a.dot(&b).into_dyn());
+    /// ```
+    pub fn labelled() -> Self {
+        Einsum {
+            labelled: true,
+            ..Einsum::new()
+        }
+    }
+
+    /// The subscripts of the operands added so far, joined into the operands of an
+    /// `einsum`-formatted string.
+    fn operands_string(&self) -> String {
+        let operands_string = self.operand_indices.join(",");
+        if self.labelled {
+            [LABELS_PREFIX, &operands_string].concat()
+        } else {
+            operands_string
+        }
+    }
+
+    /// Adds `operand`, whose axes have the subscripts `indices` (e.g. `"ij"` or `"...j"`, or
+    /// `"batch chan"` if the builder was started with [`labelled`](#method.labelled)).
+    ///
+    /// If the subscripts aren't valid, or don't fit the shape of `operand` and of the operands
+    /// added before it, the error is kept and returned by [`eval`](#method.eval). Parse errors
+    /// give the byte offset in `indices`, and the other errors number the operands in the order
+    /// they were added.
+    pub fn operand(mut self, operand: &'a dyn ArrayLike<A>, indices: &str) -> Self {
+        self.operands.push(operand);
+        self.operand_indices.push(indices.to_string());
+        if self.error.is_none() {
+            self.error = check_subscripts(indices, self.labelled)
+                .and_then(|_| SizedContraction::new(&self.operands_string(), &self.operands))
+                .err();
+        }
+        self
+    }
+
+    /// Sets the subscripts of the output (e.g. `"ik"`), which otherwise are the implicit ones.
+    /// Since operands can still be added, whether they all appear in the operands is only
+    /// checked by `eval`.
+    pub fn output(mut self, indices: &str) -> Self {
+        if self.error.is_none() {
+            self.error = check_subscripts(indices, self.labelled).err();
+        }
+        self.output_indices = Some(indices.to_string());
+        self
+    }
+
+    /// The first error found in the operands or output added so far, if there is one.
+    pub fn error(&self) -> Option<&EinsumError> {
+        self.error.as_ref()
+    }
+
+    /// The `einsum`-formatted string for the contraction built so far, e.g. `"ij,jk->ik"`.
+    pub fn input_string(&self) -> String {
+        let mut input_string = self.operands_string();
+        if let Some(output_indices) = &self.output_indices {
+            input_string.push_str("->");
+            input_string.push_str(output_indices);
+        }
+        input_string
+    }
+
+    /// Performs the contraction as [`einsum`](fn.einsum.html) does, or returns the first error
+    /// found while it was being built.
+    pub fn eval(&self) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: LinalgScalar,
+    {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        einsum(&self.input_string(), &self.operands)
+    }
+}
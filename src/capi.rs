@@ -0,0 +1,373 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `capi` feature, a C interface to the contraction engine, declared in
+//! `include/ndarray_einsum_beta.h`, for calling it from C, C++, Julia and the like. Build the
+//! library to link against with
+//! `cargo rustc --release --features capi --crate-type cdylib` (or `staticlib`).
+//!
+//! A plan is created once for a contraction and the shapes of its operands with
+//! [`einsum_plan_create`](fn.einsum_plan_create.html), executed any number of times on `double`
+//! or `float` operands with [`einsum_plan_execute_f64`](fn.einsum_plan_execute_f64.html) or
+//! [`einsum_plan_execute_f32`](fn.einsum_plan_execute_f32.html), and freed with
+//! [`einsum_plan_destroy`](fn.einsum_plan_destroy.html):
+//!
+//! ```c
+//! const size_t a_shape[] = {2, 3}, b_shape[] = {3, 4};
+//! const size_t ndims[] = {2, 2};
+//! const size_t *shapes[] = {a_shape, b_shape};
+//! EinsumPlan *plan;
+//! if (einsum_plan_create("ij,jk->ik", 2, ndims, shapes, &plan) != EINSUM_OK) {
+//!     fprintf(stderr, "%s\n", einsum_last_error());
+//! }
+//! const double *operands[] = {a, b};
+//! double out[2 * 4];
+//! einsum_plan_execute_f64(plan, operands, NULL, out, NULL);
+//! einsum_plan_destroy(plan);
+//! ```
+//!
+//! Operands and outputs are given as a pointer to their first element (the element at index
+//! 0 along every axis) and their strides, counted in elements (not bytes) and possibly negative;
+//! a null pointer instead of the strides means that the array is contiguous in row-major (C)
+//! order. Every function returns `EINSUM_OK` on success and an error code otherwise, in which
+//! case [`einsum_last_error`](fn.einsum_last_error.html) describes the error.
+use crate::{
+    generate_optimized_order, ArrayLike, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// The call succeeded.
+pub const EINSUM_OK: c_int = 0;
+
+/// A pointer that can't be null was null, or the subscripts aren't valid UTF-8.
+pub const EINSUM_ERROR_INVALID_ARGUMENT: c_int = 1;
+
+/// The contraction is invalid, e.g. the subscripts can't be parsed or an index has different
+/// lengths in different operands.
+pub const EINSUM_ERROR_CONTRACTION: c_int = 2;
+
+/// The contraction engine panicked; this is a bug.
+pub const EINSUM_ERROR_PANIC: c_int = 3;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Records `message` as the last error on this thread and returns `code`.
+fn fail(code: c_int, message: &str) -> c_int {
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+    code
+}
+
+fn contraction_error(e: EinsumError) -> c_int {
+    fail(EINSUM_ERROR_CONTRACTION, &e.to_string())
+}
+
+/// Runs `op`, turning a panic into `EINSUM_ERROR_PANIC`, since a panic can't unwind into C.
+fn guard(op: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(op)).unwrap_or_else(|_| {
+        fail(
+            EINSUM_ERROR_PANIC,
+            "the contraction engine panicked while performing the call",
+        )
+    })
+}
+
+/// A contraction planned for operands of fixed shapes, compiled for both element types.
+pub struct EinsumPlan {
+    shapes: Vec<Vec<usize>>,
+    path_f64: EinsumPath<f64>,
+    path_f32: EinsumPath<f32>,
+}
+
+/// Returns the message describing the last error returned on the calling thread, as a
+/// NUL-terminated string that stays valid until the next call on the thread that fails.
+/// The message is empty if no call has failed.
+#[no_mangle]
+pub extern "C" fn einsum_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+}
+
+/// Plans the contraction described by `subscripts` for `num_operands` operands, where operand
+/// `i` has `ndims[i]` dimensions of lengths `shapes[i][0..ndims[i]]`, and stores the plan in
+/// `*plan`. The order of the pairwise contractions is chosen as by `OptimizationMethod::Greedy`.
+///
+/// # Safety
+///
+/// `subscripts` must be a NUL-terminated string, `ndims` and `shapes` must point to
+/// `num_operands` elements, each `shapes[i]` must point to `ndims[i]` elements, and `plan` must
+/// be valid for writes. The plan has to be freed with `einsum_plan_destroy`.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_create(
+    subscripts: *const c_char,
+    num_operands: usize,
+    ndims: *const usize,
+    shapes: *const *const usize,
+    plan: *mut *mut EinsumPlan,
+) -> c_int {
+    guard(|| {
+        if subscripts.is_null() || plan.is_null() {
+            return fail(
+                EINSUM_ERROR_INVALID_ARGUMENT,
+                "subscripts and plan can't be null",
+            );
+        }
+        if num_operands > 0 && (ndims.is_null() || shapes.is_null()) {
+            return fail(
+                EINSUM_ERROR_INVALID_ARGUMENT,
+                "ndims and shapes can't be null",
+            );
+        }
+        let subscripts = match CStr::from_ptr(subscripts).to_str() {
+            Ok(subscripts) => subscripts,
+            Err(_) => {
+                return fail(
+                    EINSUM_ERROR_INVALID_ARGUMENT,
+                    "subscripts aren't valid UTF-8",
+                )
+            }
+        };
+        let mut operand_shapes = Vec::with_capacity(num_operands);
+        for operand in 0..num_operands {
+            let ndim = *ndims.add(operand);
+            let shape = *shapes.add(operand);
+            if ndim == 0 {
+                operand_shapes.push(Vec::new());
+            } else if shape.is_null() {
+                return fail(EINSUM_ERROR_INVALID_ARGUMENT, "a shape can't be null");
+            } else {
+                operand_shapes.push(std::slice::from_raw_parts(shape, ndim).to_vec());
+            }
+        }
+
+        let sc = match SizedContraction::from_string_and_shapes(subscripts, &operand_shapes) {
+            Ok(sc) => sc,
+            Err(e) => return contraction_error(e),
+        };
+        let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+        *plan = Box::into_raw(Box::new(EinsumPlan {
+            shapes: operand_shapes,
+            path_f64: EinsumPath::from_path(&contraction_order),
+            path_f32: EinsumPath::from_path(&contraction_order),
+        }));
+        EINSUM_OK
+    })
+}
+
+/// Frees a plan created by `einsum_plan_create`. Does nothing if `plan` is null.
+///
+/// # Safety
+///
+/// `plan` must be null or a plan created by `einsum_plan_create` that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_destroy(plan: *mut EinsumPlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
+
+/// Returns the number of dimensions of the result of `plan`.
+///
+/// # Safety
+///
+/// `plan` must be a valid plan created by `einsum_plan_create`.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_output_ndim(plan: *const EinsumPlan) -> usize {
+    (*plan).path_f64.output_shape().len()
+}
+
+/// Writes the shape of the result of `plan` to `shape`, which must have room for
+/// `einsum_plan_output_ndim(plan)` elements.
+///
+/// # Safety
+///
+/// `plan` must be a valid plan created by `einsum_plan_create` and `shape` must be valid for
+/// writes of `einsum_plan_output_ndim(plan)` elements.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_output_shape(plan: *const EinsumPlan, shape: *mut usize) {
+    let output_shape = (*plan).path_f64.output_shape();
+    ptr::copy_nonoverlapping(output_shape.as_ptr(), shape, output_shape.len());
+}
+
+/// The strides of an array of shape `shape` given by `strides`, or of a row-major array of that
+/// shape if `strides` is null.
+unsafe fn strides_or_row_major(shape: &[usize], strides: *const isize) -> Vec<isize> {
+    if strides.is_null() || shape.is_empty() {
+        let mut row_major = vec![0; shape.len()];
+        let mut stride = 1;
+        for (axis, &len) in shape.iter().enumerate().rev() {
+            row_major[axis] = stride;
+            stride *= len.max(1) as isize;
+        }
+        row_major
+    } else {
+        std::slice::from_raw_parts(strides, shape.len()).to_vec()
+    }
+}
+
+/// Moves `ptr` to the lowest address of the array with `shape` and `strides` and negates its
+/// negative strides, returning the axes whose strides were negated, since `from_shape_ptr`
+/// only accepts nonnegative strides.
+unsafe fn normalize_strides<A>(
+    ptr: *const A,
+    shape: &[usize],
+    strides: &mut [isize],
+) -> (*const A, Vec<usize>) {
+    let mut ptr = ptr;
+    let mut reversed_axes = Vec::new();
+    for (axis, (&len, stride)) in shape.iter().zip(strides.iter_mut()).enumerate() {
+        if *stride < 0 && len > 0 {
+            ptr = ptr.offset((len - 1) as isize * *stride);
+            *stride = -*stride;
+            reversed_axes.push(axis);
+        }
+    }
+    (ptr, reversed_axes)
+}
+
+unsafe fn raw_view<'a, A>(
+    ptr: *const A,
+    shape: &[usize],
+    strides: *const isize,
+) -> ArrayViewD<'a, A> {
+    let mut strides = strides_or_row_major(shape, strides);
+    let (ptr, reversed_axes) = normalize_strides(ptr, shape, &mut strides);
+    let strides: Vec<usize> = strides.iter().map(|&stride| stride as usize).collect();
+    let mut view = ArrayView::from_shape_ptr(IxDyn(shape).strides(IxDyn(&strides)), ptr);
+    for axis in reversed_axes {
+        view.invert_axis(Axis(axis));
+    }
+    view
+}
+
+unsafe fn raw_view_mut<'a, A>(
+    ptr: *mut A,
+    shape: &[usize],
+    strides: *const isize,
+) -> ArrayViewMutD<'a, A> {
+    let mut strides = strides_or_row_major(shape, strides);
+    let (ptr, reversed_axes) = normalize_strides(ptr, shape, &mut strides);
+    let strides: Vec<usize> = strides.iter().map(|&stride| stride as usize).collect();
+    let mut view =
+        ArrayViewMut::from_shape_ptr(IxDyn(shape).strides(IxDyn(&strides)), ptr as *mut A);
+    for axis in reversed_axes {
+        view.invert_axis(Axis(axis));
+    }
+    view
+}
+
+unsafe fn execute<A: LinalgScalar>(
+    path: &EinsumPath<A>,
+    shapes: &[Vec<usize>],
+    operands: *const *const A,
+    strides: *const *const isize,
+    out: *mut A,
+    out_strides: *const isize,
+) -> c_int {
+    guard(|| {
+        if out.is_null() || (!shapes.is_empty() && operands.is_null()) {
+            return fail(
+                EINSUM_ERROR_INVALID_ARGUMENT,
+                "operands and out can't be null",
+            );
+        }
+        let mut views = Vec::with_capacity(shapes.len());
+        for (operand, shape) in shapes.iter().enumerate() {
+            let data = *operands.add(operand);
+            if data.is_null() {
+                return fail(EINSUM_ERROR_INVALID_ARGUMENT, "an operand can't be null");
+            }
+            let operand_strides = if strides.is_null() {
+                ptr::null()
+            } else {
+                *strides.add(operand)
+            };
+            views.push(raw_view(data, shape, operand_strides));
+        }
+        let operands: Vec<&dyn ArrayLike<A>> =
+            views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+        let mut out = raw_view_mut(out, &path.output_shape(), out_strides);
+        match path.contract_operands_into(&operands, &mut out) {
+            Ok(()) => EINSUM_OK,
+            Err(e) => contraction_error(e),
+        }
+    })
+}
+
+/// Performs the contraction planned by `plan` on `double` operands and writes the result to
+/// `out`. Operand `i` starts at `operands[i]` and has the shape it was planned for and strides
+/// `strides[i]`; `strides` (or any `strides[i]`) can be null for row-major operands, and
+/// `out_strides` can be null for a row-major output.
+///
+/// # Safety
+///
+/// `plan` must be a valid plan; `operands` (and `strides`, unless it is null) must point to one
+/// element per operand; every element of each operand, and of the output, must be reachable from
+/// its first element by moving along its axes with its strides; and the elements of the
+/// output must not overlap each other or those of an operand.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_execute_f64(
+    plan: *const EinsumPlan,
+    operands: *const *const f64,
+    strides: *const *const isize,
+    out: *mut f64,
+    out_strides: *const isize,
+) -> c_int {
+    if plan.is_null() {
+        return fail(EINSUM_ERROR_INVALID_ARGUMENT, "plan can't be null");
+    }
+    let plan = &*plan;
+    execute(
+        &plan.path_f64,
+        &plan.shapes,
+        operands,
+        strides,
+        out,
+        out_strides,
+    )
+}
+
+/// Same as `einsum_plan_execute_f64`, for `float` operands.
+///
+/// # Safety
+///
+/// The same as for `einsum_plan_execute_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn einsum_plan_execute_f32(
+    plan: *const EinsumPlan,
+    operands: *const *const f32,
+    strides: *const *const isize,
+    out: *mut f32,
+    out_strides: *const isize,
+) -> c_int {
+    if plan.is_null() {
+        return fail(EINSUM_ERROR_INVALID_ARGUMENT, "plan can't be null");
+    }
+    let plan = &*plan;
+    execute(
+        &plan.path_f32,
+        &plan.shapes,
+        operands,
+        strides,
+        out,
+        out_strides,
+    )
+}
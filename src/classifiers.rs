@@ -0,0 +1,189 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `classify_contraction`, which tells callers which kind of contractor a singleton
+//! or pair contraction would be performed with, without compiling a path for it.
+//!
+//! The choice is the one made by `SingletonContraction::new` and `PairContraction::new`: the
+//! indices of the contraction are summarized (how many are summed, taken along a diagonal,
+//! reordered, stacked, and so on) and the summary picks a `SingletonMethod` or `PairMethod`.
+//! For a pair, each operand may first be simplified by a singleton contraction that takes its
+//! diagonals and sums the indices that appear nowhere else.
+//!
+//! `EinsumPath::explain` (and `SizedContraction::explain`, for the path `einsum` would use)
+//! also says why each contractor was chosen, in terms of those summaries.
+use crate::contractors::{PairContraction, PairMethod, SingletonContraction, SingletonMethod};
+use crate::{EinsumError, EinsumPath, EinsumPathSteps, SizedContraction};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The kind of contractor that performs a contraction of one or two operands.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ContractionClass {
+    /// A contraction of a single operand.
+    Singleton {
+        /// The contractor that computes the distinct output indices
+        method: SingletonMethod,
+        /// Whether the result is then written along a diagonal of the output, because the
+        /// output repeats an index (as in `ij->iij`)
+        diagonal_embedding: bool,
+    },
+
+    /// A contraction of two operands.
+    Pair {
+        /// The simplification of the left operand, if it has indices to take a diagonal of or
+        /// sum over before the two are contracted
+        lhs_simplification: Option<SingletonMethod>,
+        /// The simplification of the right operand, as for the left one
+        rhs_simplification: Option<SingletonMethod>,
+        /// The contractor that contracts the simplified operands into the distinct output
+        /// indices
+        method: PairMethod,
+        /// Whether the result is then written along a diagonal of the output
+        diagonal_embedding: bool,
+    },
+}
+
+/// Returns the kind of contractor that `sc` would be performed with: the one chosen for the
+/// (only) step of an `EinsumPath` of a contraction of one or two operands.
+///
+/// Returns `EinsumError::Unsupported` if `sc` has more than two operands, since those are
+/// performed as a sequence of pair contractions that depends on the contraction order; each
+/// step of a [`ContractionOrder`](enum.ContractionOrder.html) can be classified instead.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// let sc = SizedContraction::from_string_and_shapes("bij,bjk->bik", &[vec![2, 3, 4], vec![2, 4, 5]])
+///     .unwrap();
+/// assert_eq!(
+///     classify_contraction(&sc).unwrap(),
+///     ContractionClass::Pair {
+///         lhs_simplification: None,
+///         rhs_simplification: None,
+///         method: PairMethod::BatchedMatMul,
+///         diagonal_embedding: false,
+///     }
+/// );
+///
+/// let sc = SizedContraction::from_string_and_shapes("ii->i", &[vec![3, 3]]).unwrap();
+/// assert_eq!(
+///     classify_contraction(&sc).unwrap(),
+///     ContractionClass::Singleton {
+///         method: SingletonMethod::Diagonalization,
+///         diagonal_embedding: false,
+///     }
+/// );
+/// ```
+pub fn classify_contraction(sc: &SizedContraction) -> Result<ContractionClass, EinsumError> {
+    // The element type doesn't affect the choice of contractor
+    match sc.contraction.operand_indices.len() {
+        1 => Ok(SingletonContraction::<f64>::new(sc).classification()),
+        2 => Ok(PairContraction::<f64>::new(sc).classification()),
+        _ => Err(EinsumError::Unsupported(
+            "only contractions of one or two operands can be classified; classify the steps of a contraction order instead",
+        )),
+    }
+}
+
+/// The contractor chosen for a step of a path and why, returned by
+/// [`EinsumPath::explain`](struct.EinsumPath.html#method.explain).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepExplanation {
+    /// The contraction performed by the step, e.g. `ij,jk->ik`
+    pub einsum_string: String,
+
+    /// The kind of contractor chosen
+    pub class: ContractionClass,
+
+    /// Why the contractor (and any simplification of the operands) was chosen, in terms of the
+    /// indices of the step, e.g. "TensordotGeneral, because no index appears in both operands
+    /// and the output, so it is a single matrix multiplication (1 contracted, 0 stacked, 1 + 1
+    /// outer)"
+    pub reason: String,
+}
+
+/// Shows the contraction and the reason, e.g. `ij,jk->ik: TensordotGeneral, because ...`.
+impl core::fmt::Display for StepExplanation {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}: {}", self.einsum_string, self.reason)
+    }
+}
+
+impl<A> EinsumPath<A> {
+    /// Returns the contractor chosen for each step of the path and why, e.g. to find out why
+    /// two contractions that look equivalent are performed at different speeds.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let a: Array3<f64> = Array::zeros((3, 3, 4));
+    /// let b: Array2<f64> = Array::zeros((4, 5));
+    /// let path = einsum_path("iij,jk->ik", &[&a, &b], OptimizationMethod::Naive).unwrap();
+    /// let explanation = path.explain();
+    /// assert_eq!(explanation.len(), 1);
+    /// assert_eq!(explanation[0].einsum_string, "iij,jk->ik");
+    /// assert_eq!(
+    ///     explanation[0].reason,
+    ///     "lhs iij->ij: Diagonalization, because a diagonal is taken and nothing is summed \
+    ///      (0 summed, 1 diagonalized, 1 reordered); \
+    ///      ij,jk->ik: TensordotGeneral, because no index appears in both operands and the \
+    ///      output, so it is a single matrix multiplication (1 contracted, 0 stacked, 1 + 1 outer)"
+    /// );
+    /// ```
+    pub fn explain(&self) -> Vec<StepExplanation> {
+        let step_contractions = self.contraction_order.step_contractions();
+        match &self.steps {
+            EinsumPathSteps::SingletonContraction(c) => vec![StepExplanation {
+                einsum_string: step_contractions[0].as_einsum_string(),
+                class: c.classification(),
+                reason: c.reason(),
+            }],
+            EinsumPathSteps::PairContractions(steps) => steps
+                .iter()
+                .zip(step_contractions.iter())
+                .map(|(step, sc)| StepExplanation {
+                    einsum_string: sc.as_einsum_string(),
+                    class: step.classification(),
+                    reason: step.reason(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl SizedContraction {
+    /// Returns the contractor chosen for each step of the path that `einsum` performs this
+    /// contraction with, and why; see
+    /// [`EinsumPath::explain`](struct.EinsumPath.html#method.explain).
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes("bij,bjk->bik", &[vec![2, 3, 4], vec![2, 4, 5]])
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     sc.explain()[0].to_string(),
+    ///     "bij,bjk->bik: BatchedMatMul, because besides the indices in both operands and the \
+    ///      output, each operand has one contracted index and one outer index, so it is a batch \
+    ///      of matrix multiplications performed in place (1 contracted, 1 stacked, 1 + 1 outer)"
+    /// );
+    /// ```
+    pub fn explain(&self) -> Vec<StepExplanation> {
+        // The element type doesn't affect the choice of contractors
+        EinsumPath::<f64>::new(self).explain()
+    }
+}
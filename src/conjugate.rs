@@ -0,0 +1,229 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_conj`, which contracts complex operands some of which are conjugated,
+//! e.g. `⟨ψ|O|ψ⟩ = einsum_conj("i,ij,j->", &[&psi, &o, &psi], &[true, false, false])`.
+//!
+//! Conjugation commutes with multiplication and summation, so it doesn't have to be applied
+//! to the operands up front. Instead each tensor carries a flag saying whether it stands for
+//! its conjugate: a step whose two inputs are both flagged (or both unflagged) is performed as
+//! usual and its result is flagged (or not). Only a step that mixes the two has to conjugate
+//! something, and it picks the cheaper of conjugating the flagged input or conjugating the
+//! unflagged input and flagging the result. In `⟨ψ|O|ψ⟩` only the vector `ψ` is ever
+//! conjugated, never `O`. Intermediate results are conjugated in place.
+use crate::contractors::{broadcast_input, EinsumPathSteps, PairContractor, SingletonContractor};
+use crate::optimizers::OperandNumber;
+use crate::{cached_order, ArrayLike, ContractionOrder, EinsumError, EinsumPath};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use num_complex::Complex;
+
+/// Element types with a complex conjugate. For real numbers the conjugate is the number itself.
+pub trait Conjugate: Copy {
+    fn conj(&self) -> Self;
+}
+
+macro_rules! impl_real_conjugate {
+    ($($t:ty),*) => {
+        $(
+            impl Conjugate for $t {
+                fn conj(&self) -> Self {
+                    *self
+                }
+            }
+        )*
+    };
+}
+impl_real_conjugate!(f32, f64);
+
+impl<T: Clone + num_traits::Num + core::ops::Neg<Output = T> + Copy> Conjugate for Complex<T> {
+    fn conj(&self) -> Self {
+        Complex::conj(self)
+    }
+}
+
+/// A tensor that may stand for its own conjugate.
+struct MaybeConjugated<A> {
+    tensor: ArrayD<A>,
+    conjugated: bool,
+}
+
+impl<A> EinsumPath<A> {
+    /// Same as `contract_operands`, except that operand `i` is replaced by its complex conjugate
+    /// if `conj[i]` is `true`. See the [`einsum_conj`](fn.einsum_conj.html) function.
+    ///
+    /// Returns an error if `conj` doesn't have one flag per operand.
+    pub fn contract_operands_conj(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        conj: &[bool],
+    ) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: LinalgScalar + Conjugate,
+    {
+        if conj.len() != operands.len() {
+            return Err(EinsumError::ConjugationFlagCountMismatch {
+                expected: operands.len(),
+                found: conj.len(),
+            });
+        }
+
+        let mut result = match (&self.steps, &self.contraction_order) {
+            (EinsumPathSteps::SingletonContraction(c), ContractionOrder::Singleton(_sc)) => {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!(
+                    "contract_singleton",
+                    subscripts = %_sc.as_einsum_string(),
+                    shape = ?operands[0].into_dyn_view().shape(),
+                    kernel = ?c.method
+                )
+                .entered();
+
+                MaybeConjugated {
+                    tensor: c.contract_singleton(&operands[0].into_dyn_view()),
+                    conjugated: conj[0],
+                }
+            }
+            (EinsumPathSteps::PairContractions(steps), ContractionOrder::Pairs(order_steps)) => {
+                let input_views: Vec<ArrayViewD<A>> =
+                    operands.iter().map(|x| x.into_dyn_view()).collect();
+                let mut intermediate_results: Vec<Option<MaybeConjugated<A>>> = Vec::new();
+                for (step, order_step) in steps.iter().zip(order_steps.iter()) {
+                    let sc = &order_step.sized_contraction;
+                    // Intermediate results are only used once, so they can be taken (and
+                    // conjugated in place if necessary).
+                    let mut take_operand =
+                        |operand_number: &OperandNumber, operand_num| match *operand_number {
+                            OperandNumber::Input(pos) => (
+                                Operand::Input(broadcast_input(&input_views[pos], sc, operand_num)),
+                                conj[pos],
+                            ),
+                            OperandNumber::IntermediateResult(pos) => {
+                                let intermediate = intermediate_results[pos].take().unwrap();
+                                (Operand::Owned(intermediate.tensor), intermediate.conjugated)
+                            }
+                        };
+                    let (mut lhs, lhs_conjugated) = take_operand(&order_step.operand_nums.lhs, 0);
+                    let (mut rhs, rhs_conjugated) = take_operand(&order_step.operand_nums.rhs, 1);
+
+                    // If exactly one side is flagged, either conjugate it, or conjugate the
+                    // other side and the result; conj(x) * y = conj(x * conj(y)).
+                    let mut conjugated = lhs_conjugated && rhs_conjugated;
+                    if lhs_conjugated != rhs_conjugated {
+                        let output_len: usize = sc
+                            .contraction
+                            .output_indices
+                            .iter()
+                            .map(|c| sc.output_size[c])
+                            .product();
+                        let (flagged, unflagged) = if lhs_conjugated {
+                            (&mut lhs, &mut rhs)
+                        } else {
+                            (&mut rhs, &mut lhs)
+                        };
+                        if flagged.len() <= unflagged.len() + output_len {
+                            flagged.conjugate();
+                        } else {
+                            unflagged.conjugate();
+                            conjugated = true;
+                        }
+                    }
+
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::debug_span!(
+                        "contract_pair",
+                        step = intermediate_results.len(),
+                        subscripts = %sc.as_einsum_string(),
+                        lhs_shape = ?lhs.view().shape(),
+                        rhs_shape = ?rhs.view().shape(),
+                        kernel = ?step.method
+                    )
+                    .entered();
+
+                    let tensor = step.contract_pair(&lhs.view(), &rhs.view());
+                    intermediate_results.push(Some(MaybeConjugated { tensor, conjugated }));
+                }
+                intermediate_results.pop().unwrap().unwrap()
+            }
+            _ => panic!(), // steps and contraction_order don't match
+        };
+
+        if result.conjugated {
+            result.tensor.mapv_inplace(|x| x.conj());
+        }
+        Ok(result.tensor)
+    }
+}
+
+/// An operand of a pair step: a view of an input, or an intermediate result that is owned by
+/// the step. Conjugating an input makes a copy; an intermediate result is conjugated in place.
+enum Operand<'a, A> {
+    Input(ArrayViewD<'a, A>),
+    Owned(ArrayD<A>),
+}
+
+impl<A: Conjugate> Operand<'_, A> {
+    fn len(&self) -> usize {
+        match self {
+            Operand::Input(view) => view.len(),
+            Operand::Owned(owned) => owned.len(),
+        }
+    }
+
+    fn conjugate(&mut self) {
+        match self {
+            Operand::Input(view) => *self = Operand::Owned(view.mapv(|x| x.conj())),
+            Operand::Owned(owned) => owned.mapv_inplace(|x| x.conj()),
+        }
+    }
+
+    fn view(&self) -> ArrayViewD<'_, A> {
+        match self {
+            Operand::Input(view) => view.view(),
+            Operand::Owned(owned) => owned.view(),
+        }
+    }
+}
+
+/// Performs the contraction described by `input_string`, replacing operand `i` with its complex
+/// conjugate if `conj[i]` is `true`. The conjugation is deferred through the contraction so that
+/// it is applied to as few elements as possible; in particular, the conjugated operands aren't
+/// copied before the contraction starts.
+///
+/// Returns an error if `conj` doesn't have one flag per operand, or for the same reasons as
+/// `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use num_complex::Complex64;
+/// let psi = array![Complex64::new(1., 1.), Complex64::new(0., 2.)];
+/// let pauli_y = array![
+///     [Complex64::new(0., 0.), Complex64::new(0., -1.)],
+///     [Complex64::new(0., 1.), Complex64::new(0., 0.)]
+/// ];
+/// // ⟨ψ|σy|ψ⟩ is real because σy is Hermitian
+/// let expectation =
+///     einsum_conj("i,ij,j->", &[&psi, &pauli_y, &psi], &[true, false, false]).unwrap();
+/// assert_eq!(expectation, arr0(Complex64::new(4., 0.)).into_dyn());
+/// ```
+pub fn einsum_conj<A: LinalgScalar + Conjugate>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    conj: &[bool],
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    EinsumPath::from_path(&contraction_order).contract_operands_conj(operands, conj)
+}
@@ -36,34 +36,44 @@
 //! can be thought of as an AST that is ready to compute a contraction when supplied with an
 //! actual set of operands to contract.
 
+use crate::budget::{budget_chunking, with_budget};
+use crate::collections::HashSet;
+use crate::layout::into_requested_layout;
 use crate::optimizers::{
-    generate_optimized_order, ContractionOrder, OperandNumber, OptimizationMethod,
+    generate_optimized_order, get_flop_count, ContractionOrder, OperandNumber, OptimizationMethod,
 };
-use crate::{ArrayLike, SizedContraction};
+use crate::out_of_core::contract_in_chunks;
+#[cfg(feature = "rayon")]
+use crate::parallel::{contract_branches, has_independent_branches};
+use crate::{ArrayLike, ContractionClass, EinsumError, SizedContraction};
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Debug;
 use ndarray::prelude::*;
-use ndarray::LinalgScalar;
-use std::collections::HashSet;
-use std::fmt::Debug;
+use ndarray::{CowArray, LinalgScalar, Zip};
 
 mod singleton_contractors;
 use singleton_contractors::{
-    Diagonalization, DiagonalizationAndSummation, Identity, Permutation, PermutationAndSummation,
-    Summation,
+    DiagonalEmbedding, Identity, Permutation, PermutationAndSummation, Summation,
 };
+pub(crate) use singleton_contractors::{Diagonalization, DiagonalizationAndSummation};
 
 mod pair_contractors;
 pub use pair_contractors::TensordotGeneral;
+pub(crate) use pair_contractors::{BatchedMatMul, HadamardProduct};
 use pair_contractors::{
-    BroadcastProductGeneral, HadamardProduct, HadamardProductGeneral, MatrixScalarProduct,
-    MatrixScalarProductGeneral, ScalarMatrixProduct, ScalarMatrixProductGeneral,
-    StackedTensordotGeneral, TensordotFixedPosition,
+    BroadcastProductGeneral, DotProduct, HadamardProductGeneral, MatrixScalarProduct,
+    MatrixScalarProductGeneral, MatrixVectorProduct, ScalarMatrixProduct,
+    ScalarMatrixProductGeneral, StackedTensordotGeneral, TensordotFixedPosition,
 };
 
 mod strategies;
-use strategies::{PairMethod, PairSummary, SingletonMethod, SingletonSummary};
+pub use strategies::{PairMethod, SingletonMethod};
+use strategies::{PairSummary, SingletonSummary};
 
 #[cfg(feature = "serde")]
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// `let new_view = obj.view_singleton(tensor_view);`
 ///
@@ -72,9 +82,10 @@ use serde::Serialize;
 /// any summation over indices and hence return only a subset of the elements of the original tensor:
 /// `Identity`, `Permutation`, and `Diagonalization`. Note that whether `Diagonalization`
 /// can actually return a view is dependent on the memory layout of the input tensor; if the input
-/// tensor is not contiguous, `diag.view_singleton()` will `panic`.
+/// tensor is not contiguous, `diag.view_singleton()` will `panic`. (Its strides can be negative.) The view borrows the data of
+/// the input tensor rather than the input view itself, so it can outlive `tensor_view`.
 pub trait SingletonViewer<A>: Debug {
-    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'b, A>
+    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'a, A>
     where
         'a: 'b,
         A: Clone + LinalgScalar;
@@ -93,8 +104,14 @@ pub trait SingletonContractor<A>: Debug {
 /// `let new_array = obj.contract_pair(lhs_view, rhs_view);`
 ///
 /// All pair contractions should implement this trait. It returns a new owned `ArrayD`. The trait
-/// also has a method with a default implementation, `obj.contract_and_assign_pair(lhs_view: &ArrayViewD,
-/// rhs_view: &ArrayViewD, out: &mut ArrayViewD) -> ()`.
+/// also has methods with default implementations, `obj.contract_and_assign_pair(lhs_view: &ArrayViewD,
+/// rhs_view: &ArrayViewD, out: &mut ArrayViewD) -> ()` and
+/// `obj.contract_and_accumulate_pair(lhs_view, rhs_view, alpha, beta, out) -> ()`, which computes
+/// `out = alpha * result + beta * out` like BLAS GEMM does (without reading `out` if `beta` is zero).
+///
+/// With the `rayon` feature enabled, there is also `obj.par_contract_pair(lhs_view, rhs_view)`,
+/// which by default just calls `contract_pair` and is overridden by the contractors that know
+/// how to split their work across threads.
 pub trait PairContractor<A>: Debug {
     fn contract_pair<'a, 'b, 'c, 'd>(
         &self,
@@ -120,6 +137,37 @@ pub trait PairContractor<A>: Debug {
         let result = self.contract_pair(lhs, rhs);
         out.assign(&result);
     }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        let result = self.contract_pair(lhs, rhs);
+        accumulate(alpha, &result.view(), beta, out);
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        self.contract_pair(lhs, rhs)
+    }
 }
 
 /// Holds a `Box`ed `SingletonContractor` trait object.
@@ -130,21 +178,31 @@ pub trait PairContractor<A>: Debug {
 ///
 /// For example, the contraction `iij->i` will be performed by assigning a `Box`ed
 /// `DiagonalizationAndSummation` to `op`. The contraction `ijk->kij` will be performed
-/// by assigning a `Box`ed `Permutation` to `op`.
+/// by assigning a `Box`ed `Permutation` to `op`. If the output repeats an index, as in
+/// `ij->iij`, `op` computes the distinct output indices (`ij->ij`) and `output_embedding`
+/// writes the result along the diagonal of the requested output.
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct SingletonContraction<A> {
-    method: SingletonMethod,
+    pub(crate) method: SingletonMethod,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    op: Box<dyn SingletonContractor<A> + Send + Sync>,
+    output_embedding: Option<DiagonalEmbedding>,
+    /// What `method` was chosen from, or `None` for a custom contractor
     #[cfg_attr(feature = "serde", serde(skip))]
-    op: Box<dyn SingletonContractor<A>>,
+    summary: Option<SingletonSummary>,
 }
 
 impl<A> SingletonContraction<A> {
     pub fn new(sc: &SizedContraction) -> Self {
-        let singleton_summary = SingletonSummary::new(&sc);
+        let (sc, output_embedding) = split_output_embedding(sc);
+        let sc = &sc;
+        let singleton_summary = SingletonSummary::new(sc);
         let method = singleton_summary.get_strategy();
 
         SingletonContraction {
+            output_embedding,
             method,
+            summary: Some(singleton_summary),
             op: match method {
                 SingletonMethod::Identity => Box::new(Identity::new(sc)),
                 SingletonMethod::Permutation => Box::new(Permutation::new(sc)),
@@ -156,9 +214,20 @@ impl<A> SingletonContraction<A> {
                 SingletonMethod::DiagonalizationAndSummation => {
                     Box::new(DiagonalizationAndSummation::new(sc))
                 }
+                SingletonMethod::Custom => unreachable!(),
             },
         }
     }
+
+    /// A singleton contraction performed entirely by `op`.
+    pub(crate) fn custom(op: Box<dyn SingletonContractor<A> + Send + Sync>) -> Self {
+        SingletonContraction {
+            method: SingletonMethod::Custom,
+            op,
+            output_embedding: None,
+            summary: None,
+        }
+    }
 }
 
 impl<A> SingletonContractor<A> for SingletonContraction<A> {
@@ -167,28 +236,75 @@ impl<A> SingletonContractor<A> for SingletonContraction<A> {
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        self.op.contract_singleton(tensor)
+        let result = self.op.contract_singleton(tensor);
+        match &self.output_embedding {
+            Some(output_embedding) => output_embedding.contract_singleton(&result.view()),
+            None => result,
+        }
     }
 }
 
 impl<A> Debug for SingletonContraction<A> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
-            "SingletonContraction {{ method: {:?}, op: {:?} }}",
-            self.method, self.op
+            "SingletonContraction {{ method: {:?}, op: {:?}, output_embedding: {:?} }}",
+            self.method, self.op, self.output_embedding
         )
     }
 }
 
-/// Holds an `Box<dyn SingletonContractor<A>>` and the resulting simplified indices.
+/// If the output of `sc` repeats an index, returns the contraction with only the distinct output
+/// indices (in order of their first appearance) and the `DiagonalEmbedding` that writes its
+/// result into the requested output. Otherwise returns `sc` unchanged and no embedding.
+fn split_output_embedding(sc: &SizedContraction) -> (SizedContraction, Option<DiagonalEmbedding>) {
+    let output_indices = &sc.contraction.output_indices;
+    let mut distinct_output_indices = Vec::new();
+    for &c in output_indices.iter() {
+        if !distinct_output_indices.contains(&c) {
+            distinct_output_indices.push(c);
+        }
+    }
+    if distinct_output_indices.len() == output_indices.len() {
+        return (sc.clone(), None);
+    }
+
+    let distinct_sc = sc
+        .subset(&sc.contraction.operand_indices, &distinct_output_indices)
+        .unwrap();
+    (distinct_sc, Some(DiagonalEmbedding::new(sc)))
+}
+
+/// Returns the `SingletonViewer` that performs the singleton contraction `sc` as a view of its
+/// input, or `None` if `sc` sums over an index or repeats an output index and so can't be.
+pub(crate) fn singleton_viewer<A>(
+    sc: &SizedContraction,
+) -> Option<Box<dyn SingletonViewer<A> + Send + Sync>> {
+    let (_, output_embedding) = split_output_embedding(sc);
+    if output_embedding.is_some() {
+        return None;
+    }
+    match SingletonSummary::new(sc).get_strategy() {
+        SingletonMethod::Identity => Some(Box::new(Identity::new(sc))),
+        SingletonMethod::Permutation => Some(Box::new(Permutation::new(sc))),
+        SingletonMethod::Diagonalization => Some(Box::new(Diagonalization::new(sc))),
+        _ => None,
+    }
+}
+
+/// Holds an `Box<dyn SingletonContractor<A>>` and the resulting simplified indices, and the
+/// `SingletonViewer` that performs the simplification as a view if it only takes a diagonal.
 #[cfg_attr(feature = "serde", derive(Serialize))]
 struct SimplificationMethodAndOutput<A> {
     method: SingletonMethod,
     #[cfg_attr(feature = "serde", serde(skip))]
-    op: Box<dyn SingletonContractor<A>>,
+    op: Box<dyn SingletonContractor<A> + Send + Sync>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    viewer: Option<Box<dyn SingletonViewer<A> + Send + Sync>>,
     new_indices: Vec<char>,
     einsum_string: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    summary: SingletonSummary,
 }
 
 impl<A> SimplificationMethodAndOutput<A> {
@@ -213,28 +329,57 @@ impl<A> SimplificationMethodAndOutput<A> {
             .intersection(&other_and_output)
             .cloned()
             .collect();
-        let new_indices: Vec<char> = desired_uniques.iter().cloned().collect();
+        // In order of first appearance, so that the compiled path is the same on every run
+        let mut new_indices: Vec<char> = Vec::new();
+        for &c in this_input_indices.iter() {
+            if desired_uniques.contains(&c) && !new_indices.contains(&c) {
+                new_indices.push(c);
+            }
+        }
 
         let simplification_sc = orig_contraction
             .subset(&[this_input_indices.to_vec()], &new_indices)
             .unwrap();
 
-        let SingletonContraction { method, op } = SingletonContraction::new(&simplification_sc);
+        let SingletonContraction {
+            method,
+            op,
+            summary,
+            ..
+        } = SingletonContraction::new(&simplification_sc);
 
         match method {
             SingletonMethod::Identity | SingletonMethod::Permutation => None,
             _ => Some(SimplificationMethodAndOutput {
                 method,
                 op,
+                viewer: singleton_viewer(&simplification_sc),
                 new_indices,
                 einsum_string: simplification_sc.as_einsum_string(),
+                summary: summary.unwrap(),
             }),
         }
     }
 }
 
+impl<A> SimplificationMethodAndOutput<A> {
+    /// Simplifies `tensor`, returning a view of it instead of a copy if the simplification only
+    /// takes a diagonal and `tensor` is contiguous.
+    fn simplify<'a>(&self, tensor: &ArrayViewD<'a, A>) -> CowArray<'a, A, IxDyn>
+    where
+        A: Clone + LinalgScalar,
+    {
+        match &self.viewer {
+            Some(viewer) if tensor.is_empty() || tensor.as_slice_memory_order().is_some() => {
+                CowArray::from(viewer.view_singleton(tensor))
+            }
+            _ => CowArray::from(self.op.contract_singleton(tensor)),
+        }
+    }
+}
+
 impl<A> Debug for SimplificationMethodAndOutput<A> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
             "SingletonContraction {{ method: {:?}, op: {:?}, new_indices: {:?}, einsum_string: {:?} }}",
@@ -261,33 +406,52 @@ impl<A> Debug for SimplificationMethodAndOutput<A> {
 /// be either, it is generally not possible to know at compile time which specific PairContractor
 /// will be used to perform a given contraction, or even which contractions will be performed;
 /// the optimizer could choose a different order.
+///
+/// As with `SingletonContraction`, an output that repeats an index is produced by contracting
+/// into the distinct output indices and then applying `output_embedding`.
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct PairContraction<A> {
     lhs_simplification: Option<SimplificationMethodAndOutput<A>>,
     rhs_simplification: Option<SimplificationMethodAndOutput<A>>,
-    method: PairMethod,
+    pub(crate) method: PairMethod,
     #[cfg_attr(feature = "serde", serde(skip))]
-    op: Box<dyn PairContractor<A>>,
+    op: Box<dyn PairContractor<A> + Send + Sync>,
     simplified_einsum_string: String,
+    output_embedding: Option<DiagonalEmbedding>,
+    /// What `method` was chosen from, or `None` for a custom contractor
+    #[cfg_attr(feature = "serde", serde(skip))]
+    summary: Option<PairSummary>,
 }
 
 impl<A> PairContraction<A> {
     pub fn new(sc: &SizedContraction) -> Self {
+        PairContraction::with_method(sc, None).unwrap()
+    }
+
+    /// Same as `new`, but if `forced_method` is given, the simplified operands are contracted
+    /// with it instead of the method chosen from their indices. Returns `None` if it can't
+    /// perform the contraction.
+    pub(crate) fn with_method(
+        sc: &SizedContraction,
+        forced_method: Option<PairMethod>,
+    ) -> Option<Self> {
         assert_eq!(sc.contraction.operand_indices.len(), 2);
+        let (sc, output_embedding) = split_output_embedding(sc);
+        let sc = &sc;
         let lhs_indices = &sc.contraction.operand_indices[0];
         let rhs_indices = &sc.contraction.operand_indices[1];
         let output_indices = &sc.contraction.output_indices;
 
         let lhs_simplification = SimplificationMethodAndOutput::from_indices_and_sizes(
-            &lhs_indices,
-            &rhs_indices,
-            &output_indices,
+            lhs_indices,
+            rhs_indices,
+            output_indices,
             sc,
         );
         let rhs_simplification = SimplificationMethodAndOutput::from_indices_and_sizes(
-            &rhs_indices,
-            &lhs_indices,
-            &output_indices,
+            rhs_indices,
+            lhs_indices,
+            output_indices,
             sc,
         );
         let new_lhs_indices = match &lhs_simplification {
@@ -300,13 +464,17 @@ impl<A> PairContraction<A> {
         };
 
         let reduced_sc = sc
-            .subset(&[new_lhs_indices, new_rhs_indices], &output_indices)
+            .subset(&[new_lhs_indices, new_rhs_indices], output_indices)
             .unwrap();
 
         let pair_summary = PairSummary::new(&reduced_sc);
-        let method = pair_summary.get_strategy();
+        let method = match forced_method {
+            Some(method) if pair_summary.supports(method, &reduced_sc) => method,
+            Some(_) => return None,
+            None => pair_summary.get_strategy(),
+        };
 
-        let op: Box<dyn PairContractor<A>> = match method {
+        let op: Box<dyn PairContractor<A> + Send + Sync> = match method {
             PairMethod::HadamardProduct => {
                 // Never gets returned in current implementation
                 Box::new(HadamardProduct::new(&reduced_sc))
@@ -336,17 +504,65 @@ impl<A> PairContraction<A> {
             PairMethod::StackedTensordotGeneral => {
                 Box::new(StackedTensordotGeneral::new(&reduced_sc))
             }
+            PairMethod::BatchedMatMul => Box::new(BatchedMatMul::new(&reduced_sc)),
+            PairMethod::MatrixVectorProduct => Box::new(MatrixVectorProduct::new(&reduced_sc)),
+            PairMethod::DotProduct => Box::new(DotProduct::new(&reduced_sc)),
             PairMethod::BroadcastProductGeneral => {
                 // Never gets returned in current implementation
                 Box::new(BroadcastProductGeneral::new(&reduced_sc))
             }
+            PairMethod::Custom => unreachable!(),
         };
-        PairContraction {
+        Some(PairContraction {
             lhs_simplification,
             rhs_simplification,
             method,
             op,
             simplified_einsum_string: reduced_sc.as_einsum_string(),
+            output_embedding,
+            summary: Some(pair_summary),
+        })
+    }
+
+    /// A pair contraction of `sc` performed entirely by `op`, without simplifying either operand.
+    pub(crate) fn custom(
+        sc: &SizedContraction,
+        op: Box<dyn PairContractor<A> + Send + Sync>,
+    ) -> Self {
+        PairContraction {
+            lhs_simplification: None,
+            rhs_simplification: None,
+            method: PairMethod::Custom,
+            op,
+            simplified_einsum_string: sc.as_einsum_string(),
+            output_embedding: None,
+            summary: None,
+        }
+    }
+
+    /// Simplifies the two tensors and contracts them, producing the distinct output indices.
+    fn contract_simplified_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        match (&self.lhs_simplification, &self.rhs_simplification) {
+            (None, None) => self.op.contract_pair(lhs, rhs),
+            (Some(lhs_contraction), None) => self
+                .op
+                .contract_pair(&lhs_contraction.simplify(lhs).view(), rhs),
+            (None, Some(rhs_contraction)) => self
+                .op
+                .contract_pair(lhs, &rhs_contraction.simplify(rhs).view()),
+            (Some(lhs_contraction), Some(rhs_contraction)) => self.op.contract_pair(
+                &lhs_contraction.simplify(lhs).view(),
+                &rhs_contraction.simplify(rhs).view(),
+            ),
         }
     }
 }
@@ -362,24 +578,126 @@ impl<A> PairContractor<A> for PairContraction<A> {
         'c: 'd,
         A: Clone + LinalgScalar,
     {
+        let result = self.contract_simplified_pair(lhs, rhs);
+        match &self.output_embedding {
+            Some(output_embedding) => output_embedding.contract_singleton(&result.view()),
+            None => result,
+        }
+    }
+
+    fn contract_and_assign_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        if let Some(output_embedding) = &self.output_embedding {
+            let result = self.contract_simplified_pair(lhs, rhs);
+            output_embedding.embed_into(&result.view(), out);
+            return;
+        }
         match (&self.lhs_simplification, &self.rhs_simplification) {
-            (None, None) => self.op.contract_pair(lhs, rhs),
+            (None, None) => self.op.contract_and_assign_pair(lhs, rhs, out),
+            (Some(lhs_contraction), None) => {
+                self.op
+                    .contract_and_assign_pair(&lhs_contraction.simplify(lhs).view(), rhs, out)
+            }
+            (None, Some(rhs_contraction)) => {
+                self.op
+                    .contract_and_assign_pair(lhs, &rhs_contraction.simplify(rhs).view(), out)
+            }
+            (Some(lhs_contraction), Some(rhs_contraction)) => self.op.contract_and_assign_pair(
+                &lhs_contraction.simplify(lhs).view(),
+                &rhs_contraction.simplify(rhs).view(),
+                out,
+            ),
+        }
+    }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        if self.output_embedding.is_some() {
+            let result = self.contract_pair(lhs, rhs);
+            accumulate(alpha, &result.view(), beta, out);
+            return;
+        }
+        match (&self.lhs_simplification, &self.rhs_simplification) {
+            (None, None) => self
+                .op
+                .contract_and_accumulate_pair(lhs, rhs, alpha, beta, out),
+            (Some(lhs_contraction), None) => self.op.contract_and_accumulate_pair(
+                &lhs_contraction.simplify(lhs).view(),
+                rhs,
+                alpha,
+                beta,
+                out,
+            ),
+            (None, Some(rhs_contraction)) => self.op.contract_and_accumulate_pair(
+                lhs,
+                &rhs_contraction.simplify(rhs).view(),
+                alpha,
+                beta,
+                out,
+            ),
+            (Some(lhs_contraction), Some(rhs_contraction)) => self.op.contract_and_accumulate_pair(
+                &lhs_contraction.simplify(lhs).view(),
+                &rhs_contraction.simplify(rhs).view(),
+                alpha,
+                beta,
+                out,
+            ),
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let result = match (&self.lhs_simplification, &self.rhs_simplification) {
+            (None, None) => self.op.par_contract_pair(lhs, rhs),
             (Some(lhs_contraction), None) => self
                 .op
-                .contract_pair(&lhs_contraction.op.contract_singleton(lhs).view(), rhs),
+                .par_contract_pair(&lhs_contraction.simplify(lhs).view(), rhs),
             (None, Some(rhs_contraction)) => self
                 .op
-                .contract_pair(lhs, &rhs_contraction.op.contract_singleton(rhs).view()),
-            (Some(lhs_contraction), Some(rhs_contraction)) => self.op.contract_pair(
-                &lhs_contraction.op.contract_singleton(lhs).view(),
-                &rhs_contraction.op.contract_singleton(rhs).view(),
+                .par_contract_pair(lhs, &rhs_contraction.simplify(rhs).view()),
+            (Some(lhs_contraction), Some(rhs_contraction)) => self.op.par_contract_pair(
+                &lhs_contraction.simplify(lhs).view(),
+                &rhs_contraction.simplify(rhs).view(),
             ),
+        };
+        match &self.output_embedding {
+            Some(output_embedding) => output_embedding.contract_singleton(&result.view()),
+            None => result,
         }
     }
 }
 
 impl<A> Debug for PairContraction<A> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
             "PairContraction {{ \
@@ -387,12 +705,14 @@ impl<A> Debug for PairContraction<A> {
              rhs_simplification: {:?}, \
              method: {:?}, \
              op: {:?}, \
-             simplified_einsum_string: {:?}",
+             simplified_einsum_string: {:?}, \
+             output_embedding: {:?}",
             self.lhs_simplification,
             self.rhs_simplification,
             self.method,
             self.op,
-            self.simplified_einsum_string
+            self.simplified_einsum_string,
+            self.output_embedding
         )
     }
 }
@@ -418,6 +738,30 @@ pub enum EinsumPathSteps<A> {
 /// and for each step in the path, how to perform the pairwise contraction. For example, two tensors might be contracted
 /// with one another by computing the Hadamard (element-wise) product of the tensors, while a different pair might be contracted
 /// by performing a matrix multiplication. The contractions that will be performed are fully specified within the `EinsumPath`.
+///
+/// An `EinsumPath` doesn't borrow anything and its contractors are `Send` and `Sync`, so it can be
+/// stored in a struct and compiled once for use by several threads:
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let path = std::sync::Arc::new(
+///     einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap(),
+/// );
+/// let handles: Vec<_> = (0..2)
+///     .map(|scale| {
+///         let path = path.clone();
+///         let m1 = &m1 * scale as f64;
+///         let m2 = m2.clone();
+///         std::thread::spawn(move || path.contract_operands(&[&m1, &m2]))
+///     })
+///     .collect();
+/// for (scale, handle) in handles.into_iter().enumerate() {
+///     assert_eq!(handle.join().unwrap(), (&m1 * scale as f64).dot(&m2).into_dyn());
+/// }
+/// ```
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct EinsumPath<A> {
     /// The order in which tensors should be paired off and contracted with one another
@@ -429,12 +773,22 @@ pub struct EinsumPath<A> {
 
 impl<A> EinsumPath<A> {
     pub fn new(sc: &SizedContraction) -> Self {
-        let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+        let contraction_order = generate_optimized_order(sc, OptimizationMethod::Naive);
 
         EinsumPath::from_path(&contraction_order)
     }
 
     pub fn from_path(contraction_order: &ContractionOrder) -> Self {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "compile_path",
+            num_steps = match contraction_order {
+                ContractionOrder::Singleton(_) => 1,
+                ContractionOrder::Pairs(steps) => steps.len(),
+            }
+        )
+        .entered();
+
         match contraction_order {
             ContractionOrder::Singleton(sized_contraction) => EinsumPath {
                 contraction_order: contraction_order.clone(),
@@ -459,40 +813,937 @@ impl<A> EinsumPath<A> {
 }
 
 impl<A> EinsumPath<A> {
+    /// Performs the contraction on `operands`.
+    ///
+    /// Panics if `operands` don't have the number and shapes of the operands the path was
+    /// planned for; use `try_contract_operands` to get an error instead.
     pub fn contract_operands(&self, operands: &[&dyn ArrayLike<A>]) -> ArrayD<A>
+    where
+        A: Clone + LinalgScalar,
+    {
+        self.contract_operands_with(
+            operands,
+            &|step, lhs, rhs| step.contract_pair(lhs, rhs),
+            FinalOutput::New,
+            &mut |_, _| {},
+        )
+        .unwrap()
+    }
+
+    /// Same as `contract_operands`, but returns an error instead of panicking if `operands`
+    /// can't be contracted with this path: if there are the wrong number of them, or if an
+    /// operand doesn't have the shape the path was planned for. Where the path broadcasts an
+    /// input operand, an axis of length 1 is also accepted.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// assert_eq!(path.try_contract_operands(&[&m1, &m2]).unwrap(), m1.dot(&m2).into_dyn());
+    ///
+    /// let m3: Array2<f64> = Array::zeros((4, 4));
+    /// assert_eq!(
+    ///     path.try_contract_operands(&[&m1, &m3]).unwrap_err(),
+    ///     EinsumError::ShapeMismatch { operand: 1, axis: 0, expected: 3, found: 4 }
+    /// );
+    /// ```
+    pub fn try_contract_operands(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+    ) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: Clone + LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        Ok(self.contract_operands(operands))
+    }
+
+    /// Returns an error unless `operands` have the number and shapes of the input operands of
+    /// the contraction order, allowing an axis of length 1 wherever a pairwise step broadcasts it.
+    pub(crate) fn check_operands(&self, operands: &[&dyn ArrayLike<A>]) -> Result<(), EinsumError> {
+        // Nothing is allocated, so that paths performed with a workspace can check their operands
+        let num_inputs = match &self.contraction_order {
+            ContractionOrder::Singleton(_) => 1,
+            ContractionOrder::Pairs(order_steps) => order_steps.len() + 1,
+        };
+        if operands.len() != num_inputs {
+            return Err(EinsumError::OperandCountMismatch {
+                expected: num_inputs,
+                found: operands.len(),
+            });
+        }
+
+        for (operand, array) in operands.iter().enumerate() {
+            // The step that uses the operand (every input is used by exactly one), its indices
+            // there, and whether it is broadcast
+            let (sc, indices, broadcast) = match &self.contraction_order {
+                ContractionOrder::Singleton(sc) => (sc, &sc.contraction.operand_indices[0], false),
+                ContractionOrder::Pairs(order_steps) => order_steps
+                    .iter()
+                    .find_map(|order_step| {
+                        let sc = &order_step.sized_contraction;
+                        let operand_nums =
+                            [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs];
+                        operand_nums
+                            .iter()
+                            .position(|n| matches!(n, OperandNumber::Input(pos) if *pos == operand))
+                            .map(|operand_num| {
+                                (sc, &sc.contraction.operand_indices[operand_num], true)
+                            })
+                    })
+                    .unwrap(),
+            };
+            let view = array.into_dyn_view();
+            let shape = view.shape();
+            if shape.len() != indices.len() {
+                return Err(EinsumError::RankMismatch {
+                    operand,
+                    expected: indices.len(),
+                    found: shape.len(),
+                });
+            }
+            for (axis, (&found, c)) in shape.iter().zip(indices).enumerate() {
+                let expected = sc.output_size[c];
+                if found != expected && !(broadcast && found == 1) {
+                    return Err(EinsumError::ShapeMismatch {
+                        operand,
+                        axis,
+                        expected,
+                        found,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `contract_operands`, except that `inspect` is called with the index of each step and
+    /// its result as soon as the step has been performed, e.g. to look for the step at which NaNs
+    /// or infinities appear, or to save the intermediate results. The steps are numbered as in
+    /// `contraction_order`; for a singleton contraction there is a single step 0 whose result is
+    /// the final result. If an index has length 0, no steps are performed and `inspect` is only
+    /// called for the last step, with a result of zeros.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let m3: Array1<f64> = Array::ones(4);
+    /// let path = einsum_path("ij,jk,k->i", &[&m1, &m2, &m3], OptimizationMethod::Greedy).unwrap();
+    /// let mut shapes = Vec::new();
+    /// let result = path.execute_with(&[&m1, &m2, &m3], |step_index, intermediate| {
+    ///     shapes.push((step_index, intermediate.shape().to_vec()));
+    /// });
+    /// // jk,k->j and then ij,j->i
+    /// assert_eq!(shapes, vec![(0, vec![3]), (1, vec![2])]);
+    /// assert_eq!(result, m1.dot(&m2).dot(&m3).into_dyn());
+    /// ```
+    pub fn execute_with<F>(&self, operands: &[&dyn ArrayLike<A>], mut inspect: F) -> ArrayD<A>
+    where
+        A: Clone + LinalgScalar,
+        F: FnMut(usize, &ArrayD<A>),
+    {
+        // Each step is performed whole, so that `inspect` sees its whole result
+        with_budget(None, || {
+            self.contract_operands_with(
+                operands,
+                &|step, lhs, rhs| step.contract_pair(lhs, rhs),
+                FinalOutput::New,
+                &mut inspect,
+            )
+        })
+        .unwrap()
+    }
+
+    /// Same as `contract_operands`, except that the result is written into `out` instead of a
+    /// newly allocated array. Where possible the final step writes directly into `out`;
+    /// for example, the final matrix multiplication of a `TensordotGeneral` step is performed
+    /// in place if `out` is in standard layout. `out` can have any memory layout.
+    ///
+    /// Returns an error if `out` doesn't have the shape of the result, or if `operands` can't be
+    /// contracted with this path, as for `try_contract_operands`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// let mut out: Array2<f64> = Array::zeros((2, 4));
+    /// path.contract_operands_into(&[&m1, &m2], &mut out.view_mut().into_dyn()).unwrap();
+    /// assert_eq!(out, m1.dot(&m2));
+    ///
+    /// let mut wrong_shape: Array2<f64> = Array::zeros((4, 2));
+    /// assert_eq!(
+    ///     path.contract_operands_into(&[&m1, &m2], &mut wrong_shape.view_mut().into_dyn()),
+    ///     Err(EinsumError::OutputShapeMismatch { expected: vec![2, 4], found: vec![4, 2] })
+    /// );
+    /// ```
+    pub fn contract_operands_into(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError>
+    where
+        A: Clone + LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        self.check_output_shape(out)?;
+        self.contract_operands_with(
+            operands,
+            &|step, lhs, rhs| step.contract_pair(lhs, rhs),
+            FinalOutput::Assign(out),
+            &mut |_, _| {},
+        );
+        Ok(())
+    }
+
+    /// Computes `out = alpha * result + beta * out`, where `result` is what `contract_operands`
+    /// would return. As in BLAS, `out` isn't read if `beta` is zero. When the final step is a
+    /// matrix multiplication and `out` is in standard layout, the scaling and accumulation are
+    /// done by the matrix multiplication itself instead of with a temporary.
+    ///
+    /// Returns an error if `out` doesn't have the shape of the result, or if `operands` can't be
+    /// contracted with this path, as for `try_contract_operands`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// let mut out: Array2<f64> = Array::ones((2, 4));
+    /// path.contract_operands_acc(&[&m1, &m2], 2., 3., &mut out.view_mut().into_dyn()).unwrap();
+    /// assert_eq!(out, 2. * m1.dot(&m2) + 3.);
+    /// ```
+    pub fn contract_operands_acc(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        alpha: A,
+        beta: A,
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError>
+    where
+        A: Clone + LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        self.check_output_shape(out)?;
+        self.contract_operands_with(
+            operands,
+            &|step, lhs, rhs| step.contract_pair(lhs, rhs),
+            FinalOutput::Accumulate { alpha, beta, out },
+            &mut |_, _| {},
+        );
+        Ok(())
+    }
+
+    /// The shape of the result of the contraction.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array3<f64> = Array::zeros((2, 3, 4));
+    /// let m2: Array2<f64> = Array::zeros((4, 5));
+    /// let path = einsum_path("ijk,kl->lji", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// assert_eq!(path.output_shape(), vec![5, 3, 2]);
+    /// ```
+    pub fn output_shape(&self) -> Vec<usize> {
+        let last_contraction = match &self.contraction_order {
+            ContractionOrder::Singleton(sc) => sc,
+            ContractionOrder::Pairs(order_steps) => &order_steps.last().unwrap().sized_contraction,
+        };
+        last_contraction
+            .contraction
+            .output_indices
+            .iter()
+            .map(|c| last_contraction.output_size[c])
+            .collect()
+    }
+
+    /// Whether some index of the contraction has length 0, in which case the result is all
+    /// zeros (or empty) and no steps have to be performed.
+    pub(crate) fn has_zero_length_index(&self) -> bool {
+        self.contraction_order
+            .step_contractions()
+            .iter()
+            .any(|sc| sc.output_size.values().any(|&len| len == 0))
+    }
+
+    /// Returns an error if `out` doesn't have the shape of the result of the contraction.
+    pub(crate) fn check_output_shape(&self, out: &ArrayViewMutD<A>) -> Result<(), EinsumError> {
+        let expected = self.output_shape();
+        if out.shape() != expected.as_slice() {
+            return Err(EinsumError::OutputShapeMismatch {
+                expected,
+                found: out.shape().to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Same as `contract_operands`, except that each pairwise contraction is split across the
+    /// current rayon thread pool wherever the contractor supports it, and independent branches
+    /// of the contraction order are performed concurrently. Use
+    /// [`with_num_threads`](fn.with_num_threads.html) to limit the number of threads used.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+    /// let m2: Array3<f64> = Array::range(0., 40., 1.).into_shape((2, 4, 5)).unwrap();
+    /// let path = einsum_path("bij,bjk->bik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// assert_eq!(
+    ///     path.par_contract_operands(&[&m1, &m2]),
+    ///     path.contract_operands(&[&m1, &m2])
+    /// );
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn par_contract_operands(&self, operands: &[&dyn ArrayLike<A>]) -> ArrayD<A>
+    where
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        if let (EinsumPathSteps::PairContractions(steps), ContractionOrder::Pairs(order_steps)) =
+            (&self.steps, &self.contraction_order)
+        {
+            if has_independent_branches(order_steps)
+                && !self.has_zero_length_index()
+                && budget_chunking::<A>(&self.contraction_order).is_none()
+            {
+                return into_requested_layout(contract_branches(order_steps, steps, operands));
+            }
+        }
+        self.contract_operands_with(
+            operands,
+            &|step, lhs, rhs| step.par_contract_pair(lhs, rhs),
+            FinalOutput::New,
+            &mut |_, _| {},
+        )
+        .unwrap()
+    }
+
+    /// Walks the contraction order, using `contract_pair` to perform each pairwise step.
+    /// Returns the result if `final_output` is `FinalOutput::New` and `None` otherwise.
+    /// `after_step` is called with the number and result of each step whose result is
+    /// computed as a new array, which is every step if `final_output` is `FinalOutput::New`
+    /// and every step except the last otherwise.
+    ///
+    /// If the budget set by `with_max_intermediate_bytes` requires it, the path is performed in
+    /// chunks instead, each with `contract_pair`, and `after_step` is only called with the
+    /// final result.
+    fn contract_operands_with(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        contract_pair: &PairContractFn<A>,
+        final_output: FinalOutput<A>,
+        after_step: &mut dyn FnMut(usize, &ArrayD<A>),
+    ) -> Option<ArrayD<A>>
     where
         A: Clone + LinalgScalar,
     {
         // Uncomment for help debugging
         // println!("{:?}", self);
+        if let Some((index, chunk_len)) = budget_chunking::<A>(&self.contraction_order) {
+            return self.contract_in_budget_chunks(
+                operands,
+                (index, chunk_len),
+                contract_pair,
+                final_output,
+                after_step,
+            );
+        }
+        if self.has_zero_length_index() {
+            // Every element of the result is an empty sum (or there are no elements at all)
+            let result = ArrayD::zeros(self.output_shape());
+            return match final_output {
+                FinalOutput::New => {
+                    after_step(
+                        self.contraction_order.step_contractions().len() - 1,
+                        &result,
+                    );
+                    Some(result)
+                }
+                FinalOutput::Assign(out) => {
+                    out.fill(A::zero());
+                    None
+                }
+                FinalOutput::Accumulate { alpha, beta, out } => {
+                    accumulate(alpha, &result.view(), beta, out);
+                    None
+                }
+            };
+        }
         match (&self.steps, &self.contraction_order) {
-            (EinsumPathSteps::SingletonContraction(c), ContractionOrder::Singleton(_)) => {
-                c.contract_singleton(&operands[0].into_dyn_view())
+            (EinsumPathSteps::SingletonContraction(c), ContractionOrder::Singleton(_sc)) => {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!(
+                    "contract_singleton",
+                    subscripts = %_sc.as_einsum_string(),
+                    shape = ?operands[0].into_dyn_view().shape(),
+                    kernel = ?c.method
+                )
+                .entered();
+
+                let result = c.contract_singleton(&operands[0].into_dyn_view());
+                match final_output {
+                    FinalOutput::New => {
+                        let result = into_requested_layout(result);
+                        after_step(0, &result);
+                        Some(result)
+                    }
+                    FinalOutput::Assign(out) => {
+                        out.assign(&result);
+                        None
+                    }
+                    FinalOutput::Accumulate { alpha, beta, out } => {
+                        accumulate(alpha, &result.view(), beta, out);
+                        None
+                    }
+                }
             }
             (EinsumPathSteps::PairContractions(steps), ContractionOrder::Pairs(order_steps)) => {
-                let mut intermediate_results: Vec<ArrayD<A>> = Vec::new();
-                for (step, order_step) in steps.iter().zip(order_steps.iter()) {
+                let input_views: Vec<ArrayViewD<A>> =
+                    operands.iter().map(|x| x.into_dyn_view()).collect();
+                // Each intermediate result is an operand of exactly one later step, after which
+                // it is freed
+                let mut intermediate_results: Vec<Option<ArrayD<A>>> = Vec::new();
+                let num_steps = steps.len();
+                let mut final_output = final_output;
+                for (step_num, (step, order_step)) in
+                    steps.iter().zip(order_steps.iter()).enumerate()
+                {
+                    let sc = &order_step.sized_contraction;
                     let lhs = match order_step.operand_nums.lhs {
-                        OperandNumber::Input(pos) => operands[pos].into_dyn_view(),
-                        OperandNumber::IntermediateResult(pos) => intermediate_results[pos].view(),
+                        OperandNumber::Input(pos) => broadcast_input(&input_views[pos], sc, 0),
+                        OperandNumber::IntermediateResult(pos) => {
+                            intermediate_results[pos].as_ref().unwrap().view()
+                        }
                     };
                     let rhs = match order_step.operand_nums.rhs {
-                        OperandNumber::Input(pos) => operands[pos].into_dyn_view(),
-                        OperandNumber::IntermediateResult(pos) => intermediate_results[pos].view(),
+                        OperandNumber::Input(pos) => broadcast_input(&input_views[pos], sc, 1),
+                        OperandNumber::IntermediateResult(pos) => {
+                            intermediate_results[pos].as_ref().unwrap().view()
+                        }
                     };
-                    let intermediate_result = step.contract_pair(&lhs, &rhs);
-                    // let lhs = match order_step.
-                    intermediate_results.push(intermediate_result);
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::debug_span!(
+                        "contract_pair",
+                        step = step_num,
+                        subscripts = %sc.as_einsum_string(),
+                        lhs_shape = ?lhs.shape(),
+                        rhs_shape = ?rhs.shape(),
+                        kernel = ?step.method
+                    )
+                    .entered();
+
+                    if step_num == num_steps - 1 {
+                        match &mut final_output {
+                            FinalOutput::New => {}
+                            FinalOutput::Assign(out) => {
+                                step.contract_and_assign_pair(&lhs, &rhs, out);
+                                return None;
+                            }
+                            FinalOutput::Accumulate { alpha, beta, out } => {
+                                step.contract_and_accumulate_pair(&lhs, &rhs, *alpha, *beta, out);
+                                return None;
+                            }
+                        }
+                    }
+                    let mut intermediate_result = contract_pair(step, &lhs, &rhs);
+                    if step_num == num_steps - 1 {
+                        intermediate_result = into_requested_layout(intermediate_result);
+                    }
+                    for operand_num in [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs]
+                    {
+                        if let OperandNumber::IntermediateResult(pos) = *operand_num {
+                            intermediate_results[pos] = None;
+                        }
+                    }
+                    after_step(step_num, &intermediate_result);
+                    intermediate_results.push(Some(intermediate_result));
                 }
                 intermediate_results.pop().unwrap()
             }
             _ => panic!(), // steps and contraction_order don't match
         }
     }
+
+    /// Performs the path `chunk_len` positions of the output index `index` at a time, as
+    /// `contract_operands_with` does when the budget requires it. The budget is lifted while
+    /// the chunks are contracted, since they already fit it as well as they can.
+    fn contract_in_budget_chunks(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        (index, chunk_len): (char, usize),
+        contract_pair: &PairContractFn<A>,
+        final_output: FinalOutput<A>,
+        after_step: &mut dyn FnMut(usize, &ArrayD<A>),
+    ) -> Option<ArrayD<A>>
+    where
+        A: Clone + LinalgScalar,
+    {
+        let input_views: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+        let contract_chunk = |path: &EinsumPath<A>,
+                              chunk_operands: &[&dyn ArrayLike<A>],
+                              out: &mut ArrayViewMutD<A>| {
+            path.contract_operands_with(
+                chunk_operands,
+                contract_pair,
+                FinalOutput::Assign(out),
+                &mut |_, _| {},
+            );
+            Ok(())
+        };
+        let contract_into = |out: &mut ArrayViewMutD<A>| {
+            with_budget(None, || {
+                contract_in_chunks(
+                    &self.contraction_order,
+                    &input_views,
+                    index,
+                    chunk_len,
+                    out,
+                    contract_chunk,
+                )
+            })
+            .unwrap()
+        };
+        match final_output {
+            FinalOutput::New => {
+                let mut result = ArrayD::zeros(self.output_shape());
+                contract_into(&mut result.view_mut());
+                let result = into_requested_layout(result);
+                after_step(
+                    self.contraction_order.step_contractions().len() - 1,
+                    &result,
+                );
+                Some(result)
+            }
+            FinalOutput::Assign(out) => {
+                contract_into(out);
+                None
+            }
+            FinalOutput::Accumulate { alpha, beta, out } => {
+                let mut result = ArrayD::zeros(self.output_shape());
+                contract_into(&mut result.view_mut());
+                accumulate(alpha, &result.view(), beta, out);
+                None
+            }
+        }
+    }
+}
+
+/// Performs a pairwise step of a path on its two operands
+type PairContractFn<A> = dyn Fn(&PairContraction<A>, &ArrayViewD<A>, &ArrayViewD<A>) -> ArrayD<A>;
+
+/// What `EinsumPath::contract_operands_with` should do with the result of the final step
+enum FinalOutput<'a, 'b, A> {
+    /// Return it as a new array
+    New,
+
+    /// Assign it to `out`
+    Assign(&'a mut ArrayViewMutD<'b, A>),
+
+    /// Set `out = alpha * result + beta * out`
+    Accumulate {
+        alpha: A,
+        beta: A,
+        out: &'a mut ArrayViewMutD<'b, A>,
+    },
+}
+
+/// Sets `out = alpha * result + beta * out`. As in BLAS, `out` isn't read if `beta` is zero, so
+/// it can contain anything (even NaNs) beforehand.
+fn accumulate<A>(alpha: A, result: &ArrayViewD<A>, beta: A, out: &mut ArrayViewMutD<A>)
+where
+    A: LinalgScalar,
+{
+    if beta.is_zero() {
+        Zip::from(out)
+            .and(result)
+            .for_each(|out_elem, &result_elem| *out_elem = alpha * result_elem);
+    } else {
+        Zip::from(out)
+            .and(result)
+            .for_each(|out_elem, &result_elem| *out_elem = alpha * result_elem + beta * *out_elem);
+    }
+}
+
+/// Returns a view of an input operand broadcast to the axis lengths the `SizedContraction`
+/// expects for it. This only differs from a plain view when the operand has axes of length 1
+/// that are broadcast against longer axes with the same index in another operand.
+pub(crate) fn broadcast_input<'a, 'b, A>(
+    operand: &'b ArrayViewD<'a, A>,
+    sc: &SizedContraction,
+    operand_num: usize,
+) -> ArrayViewD<'b, A> {
+    let expected_shape: Vec<usize> = sc.contraction.operand_indices[operand_num]
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect();
+    if operand.shape() == expected_shape.as_slice() {
+        operand.view()
+    } else {
+        operand.broadcast(IxDyn(&expected_shape)).unwrap()
+    }
+}
+
+impl<A> EinsumPath<A> {
+    /// Describes the contraction tree in the DOT language of [Graphviz](https://graphviz.org/),
+    /// so that the planner's decisions can be checked visually, e.g. with `dot -Tsvg`.
+    ///
+    /// Each input operand is a box labeled with its indices and shape. Each step is an ellipse
+    /// labeled with its contraction, the contractor that performs it (along with any
+    /// simplifications of its inputs), the shape of its result, and its FLOP count as counted by
+    /// [`ContractionOrder::flop_count`](enum.ContractionOrder.html#method.flop_count). The final step has a double border.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::zeros((2, 3));
+    /// let m2: Array2<f64> = Array::zeros((3, 4));
+    /// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// assert_eq!(
+    ///     path.to_dot(),
+    ///     r#"digraph einsum {
+    ///     input0 [shape=box, label="input 0\nij\n[2, 3]"];
+    ///     input0 -> step0 [label="lhs"];
+    ///     input1 [shape=box, label="input 1\njk\n[3, 4]"];
+    ///     input1 -> step0 [label="rhs"];
+    ///     step0 [peripheries=2, label="step 0\nij,jk->ik\nTensordotGeneral\n[2, 4]\n48 flops"];
+    /// }"#
+    /// );
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let shape_of = |indices: &[char], sc: &SizedContraction| -> Vec<usize> {
+            indices.iter().map(|c| sc.output_size[c]).collect()
+        };
+        let input_node = |num: usize, indices: &[char], sc: &SizedContraction| {
+            format!(
+                "    input{} [shape=box, label=\"input {}\\n{}\\n{:?}\"];",
+                num,
+                num,
+                indices.iter().collect::<String>(),
+                shape_of(indices, sc)
+            )
+        };
+        let step_node = |num: usize, is_last: bool, sc: &SizedContraction, methods: &[String]| {
+            let num_terms = sc.contraction.operand_indices.len();
+            format!(
+                "    step{} [{}label=\"step {}\\n{}\\n{}\\n{:?}\\n{} flops\"];",
+                num,
+                if is_last { "peripheries=2, " } else { "" },
+                num,
+                sc.as_einsum_string(),
+                methods.join("\\n"),
+                shape_of(&sc.contraction.output_indices, sc),
+                get_flop_count(sc, num_terms)
+            )
+        };
+
+        let mut lines = vec!["digraph einsum {".to_string()];
+        match (&self.contraction_order, &self.steps) {
+            (
+                ContractionOrder::Singleton(sc),
+                EinsumPathSteps::SingletonContraction(singleton_contraction),
+            ) => {
+                lines.push(input_node(0, &sc.contraction.operand_indices[0], sc));
+                lines.push("    input0 -> step0;".to_string());
+                let methods = [format!("{:?}", singleton_contraction.method)];
+                lines.push(step_node(0, true, sc, &methods));
+            }
+            (ContractionOrder::Pairs(order_steps), EinsumPathSteps::PairContractions(steps)) => {
+                for (step_num, (order_step, pair_contraction)) in
+                    order_steps.iter().zip(steps.iter()).enumerate()
+                {
+                    let sc = &order_step.sized_contraction;
+                    let operands = [
+                        (
+                            "lhs",
+                            &order_step.operand_nums.lhs,
+                            &pair_contraction.lhs_simplification,
+                        ),
+                        (
+                            "rhs",
+                            &order_step.operand_nums.rhs,
+                            &pair_contraction.rhs_simplification,
+                        ),
+                    ];
+                    let mut methods = vec![format!("{:?}", pair_contraction.method)];
+                    for (side, (name, operand_num, simplification)) in operands.iter().enumerate() {
+                        let node = match operand_num {
+                            OperandNumber::Input(num) => {
+                                lines.push(input_node(
+                                    *num,
+                                    &sc.contraction.operand_indices[side],
+                                    sc,
+                                ));
+                                format!("input{}", num)
+                            }
+                            OperandNumber::IntermediateResult(num) => format!("step{}", num),
+                        };
+                        lines.push(format!(
+                            "    {} -> step{} [label=\"{}\"];",
+                            node, step_num, name
+                        ));
+                        if let Some(simplification) = simplification {
+                            methods.push(format!(
+                                "{}: {:?} ({})",
+                                name, simplification.method, simplification.einsum_string
+                            ));
+                        }
+                    }
+                    lines.push(step_node(
+                        step_num,
+                        step_num + 1 == steps.len(),
+                        sc,
+                        &methods,
+                    ));
+                }
+            }
+            _ => unreachable!(),
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+/// The reason given for a step performed by a contractor from a `ContractorRegistry`
+const CUSTOM_REASON: &str = "Custom, because a ContractorRegistry supplied it";
+
+/// Appended to the reason given for a step whose output repeats an index
+const EMBEDDING_REASON: &str =
+    "; the output repeats an index, so the result is written along its diagonal (DiagonalEmbedding)";
+
+impl<A> SingletonContraction<A> {
+    /// The contractor used, e.g. `Permutation`, followed by `+ DiagonalEmbedding` if the result
+    /// is then written along a diagonal of the output.
+    fn description(&self) -> String {
+        let mut description = format!("{:?}", self.method);
+        if self.output_embedding.is_some() {
+            description.push_str(" + DiagonalEmbedding");
+        }
+        description
+    }
+
+    /// The contractor used and why it was chosen; see `SingletonSummary::reason`.
+    pub(crate) fn reason(&self) -> String {
+        let mut reason = match &self.summary {
+            Some(summary) => summary.reason(),
+            None => CUSTOM_REASON.to_string(),
+        };
+        if self.output_embedding.is_some() {
+            reason.push_str(EMBEDDING_REASON);
+        }
+        reason
+    }
+
+    pub(crate) fn classification(&self) -> ContractionClass {
+        ContractionClass::Singleton {
+            method: self.method,
+            diagonal_embedding: self.output_embedding.is_some(),
+        }
+    }
+}
+
+impl<A> PairContraction<A> {
+    /// The contractor used, e.g. `TensordotGeneral`, followed by the simplifications applied to
+    /// the operands beforehand and `+ DiagonalEmbedding` if the result is then written along
+    /// a diagonal of the output.
+    fn description(&self) -> String {
+        let mut description = format!("{:?}", self.method);
+        if self.output_embedding.is_some() {
+            description.push_str(" + DiagonalEmbedding");
+        }
+        let simplifications = [
+            ("lhs", &self.lhs_simplification),
+            ("rhs", &self.rhs_simplification),
+        ];
+        for (name, simplification) in simplifications.iter() {
+            if let Some(simplification) = simplification {
+                description.push_str(&format!(
+                    ", {}: {:?} {}",
+                    name, simplification.method, simplification.einsum_string
+                ));
+            }
+        }
+        description
+    }
+
+    /// The contractor used and why it was chosen, preceded by the simplifications of the operands
+    /// and why they were chosen; see `PairSummary::reason`.
+    pub(crate) fn reason(&self) -> String {
+        let mut reasons = Vec::new();
+        let simplifications = [
+            ("lhs", &self.lhs_simplification),
+            ("rhs", &self.rhs_simplification),
+        ];
+        for (name, simplification) in simplifications.iter() {
+            if let Some(simplification) = simplification {
+                reasons.push(format!(
+                    "{} {}: {}",
+                    name,
+                    simplification.einsum_string,
+                    simplification.summary.reason()
+                ));
+            }
+        }
+        let reason = match &self.summary {
+            Some(summary) if summary.get_strategy() != self.method => format!(
+                "{:?}, because PathOptions forced it (instead of {})",
+                self.method,
+                summary.reason()
+            ),
+            Some(summary) => summary.reason(),
+            None => CUSTOM_REASON.to_string(),
+        };
+        if reasons.is_empty() {
+            reasons.push(reason);
+        } else {
+            reasons.push(format!("{}: {}", self.simplified_einsum_string, reason));
+        }
+        let mut reason = reasons.join("; ");
+        if self.output_embedding.is_some() {
+            reason.push_str(EMBEDDING_REASON);
+        }
+        reason
+    }
+
+    pub(crate) fn classification(&self) -> ContractionClass {
+        ContractionClass::Pair {
+            lhs_simplification: self.lhs_simplification.as_ref().map(|s| s.method),
+            rhs_simplification: self.rhs_simplification.as_ref().map(|s| s.method),
+            method: self.method,
+            diagonal_embedding: self.output_embedding.is_some(),
+        }
+    }
+}
+
+/// Lists the contractor chosen for each step, one per line.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let m1: Array3<f64> = Array::zeros((3, 3, 4));
+/// let m2: Array2<f64> = Array::zeros((4, 5));
+/// let path = einsum_path("iij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+/// assert_eq!(
+///     path.steps.to_string(),
+///     "step 0: TensordotGeneral, lhs: Diagonalization iij->ij"
+/// );
+/// ```
+impl<A> core::fmt::Display for EinsumPathSteps<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            EinsumPathSteps::SingletonContraction(step) => {
+                write!(f, "step 0: {}", step.description())
+            }
+            EinsumPathSteps::PairContractions(steps) => {
+                for (step_num, step) in steps.iter().enumerate() {
+                    if step_num > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "step {}: {}", step_num, step.description())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prints a table with a row for each step: the tensors it contracts (input operands or the
+/// results of earlier steps), its subscripts, the shape of its result, and the contractor
+/// chosen for it, along with the simplifications applied to its operands first. This shows
+/// what is actually performed, e.g. when reporting a performance issue.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let m1: Array2<f64> = Array::zeros((2, 3));
+/// let m2: Array2<f64> = Array::zeros((3, 4));
+/// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+/// assert_eq!(
+///     path.to_string(),
+///     "\
+/// step  operands                       subscripts           shape  contractor
+///    0  input 0, input 1                ij,jk->ik          [2, 4]  TensordotGeneral"
+/// );
+/// ```
+impl<A> core::fmt::Display for EinsumPath<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let shape_of = |sc: &SizedContraction| -> Vec<usize> {
+            sc.contraction
+                .output_indices
+                .iter()
+                .map(|c| sc.output_size[c])
+                .collect()
+        };
+        let operand_name = |operand_num: &OperandNumber| match operand_num {
+            OperandNumber::Input(num) => format!("input {}", num),
+            OperandNumber::IntermediateResult(num) => format!("step {}", num),
+        };
+        let rows: Vec<(String, &SizedContraction, String)> =
+            match (&self.contraction_order, &self.steps) {
+                (ContractionOrder::Singleton(sc), EinsumPathSteps::SingletonContraction(step)) => {
+                    vec![("input 0".to_string(), sc, step.description())]
+                }
+                (
+                    ContractionOrder::Pairs(order_steps),
+                    EinsumPathSteps::PairContractions(steps),
+                ) => order_steps
+                    .iter()
+                    .zip(steps.iter())
+                    .map(|(order_step, step)| {
+                        let operands = format!(
+                            "{}, {}",
+                            operand_name(&order_step.operand_nums.lhs),
+                            operand_name(&order_step.operand_nums.rhs)
+                        );
+                        (operands, &order_step.sized_contraction, step.description())
+                    })
+                    .collect(),
+                _ => unreachable!(),
+            };
+
+        write!(
+            f,
+            "{:>4}  {:<24} {:>16} {:>15}  contractor",
+            "step", "operands", "subscripts", "shape"
+        )?;
+        for (step_num, (operands, sc, description)) in rows.iter().enumerate() {
+            write!(
+                f,
+                "\n{:>4}  {:<24} {:>16} {:>15}  {}",
+                step_num,
+                operands,
+                sc.as_einsum_string(),
+                format!("{:?}", shape_of(sc)),
+                description
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Only the `contraction_order` is read back; the steps are compiled from it again with
+/// [`EinsumPath::from_path`](struct.EinsumPath.html#method.from_path), so a path
+/// serialized by one process (or for one element type) can be executed by another.
+#[cfg(feature = "serde")]
+impl<'de, A> Deserialize<'de> for EinsumPath<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct SerializedPath {
+            contraction_order: ContractionOrder,
+        }
+
+        let SerializedPath { contraction_order } = SerializedPath::deserialize(deserializer)?;
+        Ok(EinsumPath::from_path(&contraction_order))
+    }
 }
 
 impl<A> Debug for EinsumPath<A> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match &self.steps {
             EinsumPathSteps::SingletonContraction(step) => write!(f, "only_step: {:?}", step),
             EinsumPathSteps::PairContractions(steps) => write!(f, "steps: {:?}", steps),
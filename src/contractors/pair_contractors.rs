@@ -37,11 +37,22 @@
 //! one way to express the same contraction; some preliminary benchmarking has been
 //! done to identify the faster choice.
 
+use crate::collections::HashSet;
+use alloc::vec::Vec;
 use ndarray::prelude::*;
-use ndarray::LinalgScalar;
-use std::collections::HashSet;
-
-use super::{PairContractor, Permutation, SingletonContractor, SingletonViewer};
+use ndarray::{Data, LinalgScalar};
+
+#[cfg(feature = "rayon")]
+use ndarray::parallel::prelude::*;
+#[cfg(feature = "rayon")]
+use ndarray::Zip;
+
+use super::{accumulate, PairContractor, Permutation, SingletonContractor, SingletonViewer};
+use crate::backend::with_current_backend;
+#[cfg(feature = "rayon")]
+use crate::deterministic_reductions;
+#[cfg(feature = "rayon")]
+use crate::parallel::{ThreadSettings, DETERMINISTIC_BLOCK_LEN};
 use crate::SizedContraction;
 
 #[cfg(feature = "serde")]
@@ -60,10 +71,10 @@ fn maybe_find_outputs_in_inputs_unique(
             let input_pos = input_indices
                 .iter()
                 .position(|&input_char| input_char == output_char);
-            if input_pos.is_some() {
+            if let Some(pos) = input_pos {
                 assert!(input_indices
                     .iter()
-                    .skip(input_pos.unwrap() + 1)
+                    .skip(pos + 1)
                     .position(|&input_char| input_char == output_char)
                     .is_none());
             }
@@ -91,6 +102,18 @@ fn find_outputs_in_inputs_unique(output_indices: &[char], input_indices: &[char]
 /// [len_uncontracted_lhs, len_contracted_axes], reshaping the RHS into shape
 /// [len_contracted_axes, len_contracted_rhs], matrix-multiplying the two reshaped tensor,
 /// and then reshaping the result into [...self.output_shape].
+///
+/// The matrix multiplication is done by `ndarray`'s `dot`, so with the `blas` feature enabled,
+/// `f32` and `f64` contractions are dispatched to BLAS GEMM. A tensor is only copied if it can't
+/// be viewed as a matrix with arbitrary row and column strides, e.g. a transposed matrix is
+/// multiplied as it is. (ndarray itself falls back from BLAS to `matrixmultiply` for matrices
+/// with neither unit row nor unit column strides.)
+///
+/// `contract_and_assign_pair` and `contract_and_accumulate_pair` multiply directly into the
+/// output tensor if it can be viewed as a matrix, e.g. if it is in standard layout or in
+/// column-major order, without allocating a temporary. `par_contract_pair` splits the output matrix into one
+/// block of rows (or of columns, if the output is wider than it is tall) per thread and multiplies
+/// each block separately.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
 pub struct TensordotFixedPosition {
@@ -178,6 +201,97 @@ impl TensordotFixedPosition {
             output_shape,
         }
     }
+
+    /// Reshapes the LHS and RHS into the two matrices to be multiplied, copying a tensor
+    /// only if it can't be viewed as a (possibly strided) matrix.
+    fn as_matrices<'a, 'b, 'c, 'd, A>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> (CowArray<'b, A, Ix2>, CowArray<'d, A, Ix2>)
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone,
+    {
+        (
+            as_matrix(lhs, self.len_uncontracted_lhs, self.len_contracted_axes),
+            as_matrix(rhs, self.len_contracted_axes, self.len_uncontracted_rhs),
+        )
+    }
+}
+
+/// Reshapes `tensor` into a `[rows, columns]` matrix whose rows are indexed by its first axes
+/// and columns by the rest, copying it only if `matrix_view` can't view it as one.
+fn as_matrix<'a, 'b, A>(
+    tensor: &'b ArrayViewD<'a, A>,
+    rows: usize,
+    columns: usize,
+) -> CowArray<'b, A, Ix2>
+where
+    'a: 'b,
+    A: Clone,
+{
+    if tensor.is_standard_layout() {
+        CowArray::from(
+            tensor
+                .view()
+                .into_shape_with_order((rows, columns))
+                .unwrap(),
+        )
+    } else if let Some(matrix) = matrix_view(tensor.view(), rows, columns) {
+        CowArray::from(matrix)
+    } else {
+        CowArray::from(
+            Array::from_shape_vec([rows, columns], tensor.iter().cloned().collect()).unwrap(),
+        )
+    }
+}
+
+/// Views `tensor` as a `[rows, columns]` matrix without copying it, if the axes that make up
+/// the rows can be merged into one and so can the axes that make up the columns, i.e. if each
+/// group is laid out as in a standard-layout array, whatever the strides between the groups (as
+/// for a permuted view). The matrix multiplication handles arbitrary row and column strides.
+fn matrix_view<S: Data>(
+    tensor: ArrayBase<S, IxDyn>,
+    rows: usize,
+    columns: usize,
+) -> Option<ArrayBase<S, Ix2>> {
+    if rows == 0 || columns == 0 {
+        return None;
+    }
+    let ndim = tensor.ndim();
+    // Axes of length 1 can go in either group, so any split with the right lengths will do
+    let num_row_axes =
+        (0..=ndim).find(|&axis| tensor.shape()[..axis].iter().product::<usize>() == rows)?;
+
+    let mut matrix = tensor;
+    for (start, end) in [(0, num_row_axes), (num_row_axes, ndim)] {
+        for axis in start..end.saturating_sub(1) {
+            if !matrix.merge_axes(Axis(axis), Axis(axis + 1)) {
+                return None;
+            }
+        }
+    }
+    // Only the last axis of each group is left with a length other than 1
+    let last_row_axis = num_row_axes.checked_sub(1);
+    let last_column_axis = if num_row_axes < ndim {
+        Some(ndim - 1)
+    } else {
+        None
+    };
+    for axis in (0..ndim).rev() {
+        if Some(axis) != last_row_axis && Some(axis) != last_column_axis {
+            matrix.index_axis_inplace(Axis(axis), 0);
+        }
+    }
+    if last_row_axis.is_none() {
+        matrix.insert_axis_inplace(Axis(0));
+    }
+    if last_column_axis.is_none() {
+        matrix.insert_axis_inplace(Axis(1));
+    }
+    matrix.into_dimensionality().ok()
 }
 
 impl<A> PairContractor<A> for TensordotFixedPosition {
@@ -191,36 +305,173 @@ impl<A> PairContractor<A> for TensordotFixedPosition {
         'c: 'd,
         A: Clone + LinalgScalar,
     {
-        let lhs_array;
-        let lhs_view = if lhs.is_standard_layout() {
-            lhs.view()
-                .into_shape_with_order((self.len_uncontracted_lhs, self.len_contracted_axes))
-                .unwrap()
-        } else {
-            lhs_array = Array::from_shape_vec(
-                [self.len_uncontracted_lhs, self.len_contracted_axes],
-                lhs.iter().cloned().collect(),
+        let (lhs_matrix, rhs_matrix) = self.as_matrices(lhs, rhs);
+
+        let mut output = Array::zeros((self.len_uncontracted_lhs, self.len_uncontracted_rhs));
+        with_current_backend(|backend| {
+            backend.mat_mul(
+                A::one(),
+                &lhs_matrix.view(),
+                &rhs_matrix.view(),
+                A::zero(),
+                &mut output.view_mut(),
             )
-            .unwrap();
-            lhs_array.view()
-        };
+        });
+        output
+            .into_shape_with_order(IxDyn(&self.output_shape))
+            .unwrap()
+    }
 
-        let rhs_array;
-        let rhs_view = if rhs.is_standard_layout() {
-            rhs.view()
-                .into_shape_with_order((self.len_contracted_axes, self.len_uncontracted_rhs))
-                .unwrap()
-        } else {
-            rhs_array = Array::from_shape_vec(
-                [self.len_contracted_axes, self.len_uncontracted_rhs],
-                rhs.iter().cloned().collect(),
+    fn contract_and_assign_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        self.contract_and_accumulate_pair(lhs, rhs, A::one(), A::zero(), out);
+    }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        let (rows, columns) = (self.len_uncontracted_lhs, self.len_uncontracted_rhs);
+        let mat_mul =
+            |lhs: &ArrayViewD<A>, rhs: &ArrayViewD<A>, out_matrix: &mut ArrayViewMut2<A>| {
+                let (lhs_matrix, rhs_matrix) = self.as_matrices(lhs, rhs);
+                with_current_backend(|backend| {
+                    backend.mat_mul(
+                        alpha,
+                        &lhs_matrix.view(),
+                        &rhs_matrix.view(),
+                        beta,
+                        out_matrix,
+                    )
+                });
+            };
+        if let Some(mut out_matrix) = matrix_view(out.view_mut(), rows, columns) {
+            mat_mul(lhs, rhs, &mut out_matrix);
+            return;
+        }
+
+        // An output in column-major order can't be viewed as a matrix, but it can once the
+        // order of its row axes and of its column axes are both reversed. The rows of the LHS
+        // and the columns of the RHS are reversed to match.
+        let num_contracted_axes = (lhs.ndim() + rhs.ndim() - out.ndim()) / 2;
+        let num_row_axes = lhs.ndim() - num_contracted_axes;
+        let reversed_rows: Vec<usize> = (0..num_row_axes).rev().collect();
+        let reversed_columns: Vec<usize> = (num_row_axes..out.ndim()).rev().collect();
+        let lhs_order: Vec<usize> = reversed_rows
+            .iter()
+            .cloned()
+            .chain(num_row_axes..lhs.ndim())
+            .collect();
+        let rhs_order: Vec<usize> = (0..num_contracted_axes)
+            .chain(
+                reversed_columns
+                    .iter()
+                    .map(|&axis| axis - num_row_axes + num_contracted_axes),
             )
-            .unwrap();
-            rhs_array.view()
+            .collect();
+        let out_order: Vec<usize> = reversed_rows.into_iter().chain(reversed_columns).collect();
+        let reversed_out = out.view_mut().permuted_axes(IxDyn(&out_order));
+        if let Some(mut out_matrix) = matrix_view(reversed_out, rows, columns) {
+            let reversed_lhs = lhs.view().permuted_axes(IxDyn(&lhs_order));
+            let reversed_rhs = rhs.view().permuted_axes(IxDyn(&rhs_order));
+            mat_mul(&reversed_lhs, &reversed_rhs, &mut out_matrix);
+        } else {
+            let result = PairContractor::<A>::contract_pair(self, lhs, rhs);
+            accumulate(alpha, &result.view(), beta, out);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let (lhs_matrix, rhs_matrix) = self.as_matrices(lhs, rhs);
+        let num_threads = rayon::current_num_threads();
+        let split_rows = self.len_uncontracted_lhs >= self.len_uncontracted_rhs;
+        let len_split = if split_rows {
+            self.len_uncontracted_lhs
+        } else {
+            self.len_uncontracted_rhs
         };
+        let deterministic = deterministic_reductions();
+        if !deterministic && (num_threads < 2 || len_split < 2) {
+            return PairContractor::<A>::contract_pair(self, lhs, rhs);
+        }
+        // Deterministic blocks don't depend on the number of threads
+        let block_size = if deterministic {
+            DETERMINISTIC_BLOCK_LEN
+        } else {
+            len_split.div_ceil(num_threads)
+        };
+        // The settings are thread-local, so they have to be passed to the worker threads
+        let settings = ThreadSettings::current();
+
+        let mut output: Array2<A> =
+            Array::zeros((self.len_uncontracted_lhs, self.len_uncontracted_rhs));
+        if split_rows {
+            output
+                .axis_chunks_iter_mut(Axis(0), block_size)
+                .into_par_iter()
+                .zip(lhs_matrix.axis_chunks_iter(Axis(0), block_size))
+                .for_each(|(mut output_block, lhs_block)| {
+                    settings.apply(|| {
+                        with_current_backend(|backend| {
+                            backend.mat_mul(
+                                A::one(),
+                                &lhs_block,
+                                &rhs_matrix.view(),
+                                A::zero(),
+                                &mut output_block,
+                            )
+                        })
+                    })
+                });
+        } else {
+            output
+                .axis_chunks_iter_mut(Axis(1), block_size)
+                .into_par_iter()
+                .zip(rhs_matrix.axis_chunks_iter(Axis(1), block_size))
+                .for_each(|(mut output_block, rhs_block)| {
+                    settings.apply(|| {
+                        with_current_backend(|backend| {
+                            backend.mat_mul(
+                                A::one(),
+                                &lhs_matrix.view(),
+                                &rhs_block,
+                                A::zero(),
+                                &mut output_block,
+                            )
+                        })
+                    })
+                });
+        }
 
-        lhs_view
-            .dot(&rhs_view)
+        output
             .into_shape_with_order(IxDyn(&self.output_shape))
             .unwrap()
     }
@@ -258,9 +509,9 @@ impl TensordotGeneral {
             &lhs_shape,
             &rhs_shape,
             lhs_indices,
-            &rhs_indices,
-            &contracted_indices,
-            &output_indices,
+            rhs_indices,
+            contracted_indices,
+            output_indices,
         )
     }
 
@@ -272,8 +523,8 @@ impl TensordotGeneral {
         contracted_indices: &[char],
         output_indices: &[char],
     ) -> Self {
-        let lhs_contracted_axes = find_outputs_in_inputs_unique(&contracted_indices, &lhs_indices);
-        let rhs_contracted_axes = find_outputs_in_inputs_unique(&contracted_indices, &rhs_indices);
+        let lhs_contracted_axes = find_outputs_in_inputs_unique(contracted_indices, lhs_indices);
+        let rhs_contracted_axes = find_outputs_in_inputs_unique(contracted_indices, rhs_indices);
         let mut uncontracted_chars: Vec<char> = lhs_indices
             .iter()
             .filter(|&&input_char| {
@@ -295,11 +546,11 @@ impl TensordotGeneral {
             .cloned()
             .collect();
         uncontracted_chars.append(&mut rhs_uncontracted_chars);
-        let output_order = find_outputs_in_inputs_unique(&output_indices, &uncontracted_chars);
+        let output_order = find_outputs_in_inputs_unique(output_indices, &uncontracted_chars);
 
         TensordotGeneral::from_shapes_and_axis_numbers(
-            &lhs_shape,
-            &rhs_shape,
+            lhs_shape,
+            rhs_shape,
             &lhs_contracted_axes,
             &rhs_contracted_axes,
             &output_order,
@@ -364,7 +615,7 @@ impl TensordotGeneral {
                 num_contracted_axes,
             );
 
-        let output_permutation = Permutation::from_indices(&output_order);
+        let output_permutation = Permutation::from_indices(output_order);
 
         TensordotGeneral {
             lhs_permutation,
@@ -394,6 +645,74 @@ impl<A> PairContractor<A> for TensordotGeneral {
         self.output_permutation
             .contract_singleton(&tensordotted.view())
     }
+
+    fn contract_and_assign_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        // Writing the tensor dot product into a view of the output with the output permutation
+        // undone is the same as writing it and then permuting it.
+        let permuted_lhs = self.lhs_permutation.view_singleton(lhs);
+        let permuted_rhs = self.rhs_permutation.view_singleton(rhs);
+        let mut unpermuted_out = self.output_permutation.inverse().view_singleton_mut(out);
+        self.tensordot_fixed_position.contract_and_assign_pair(
+            &permuted_lhs,
+            &permuted_rhs,
+            &mut unpermuted_out,
+        );
+    }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        let permuted_lhs = self.lhs_permutation.view_singleton(lhs);
+        let permuted_rhs = self.rhs_permutation.view_singleton(rhs);
+        let mut unpermuted_out = self.output_permutation.inverse().view_singleton_mut(out);
+        self.tensordot_fixed_position.contract_and_accumulate_pair(
+            &permuted_lhs,
+            &permuted_rhs,
+            alpha,
+            beta,
+            &mut unpermuted_out,
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let permuted_lhs = self.lhs_permutation.view_singleton(lhs);
+        let permuted_rhs = self.rhs_permutation.view_singleton(rhs);
+        let tensordotted = self
+            .tensordot_fixed_position
+            .par_contract_pair(&permuted_lhs, &permuted_rhs);
+        self.output_permutation
+            .contract_singleton(&tensordotted.view())
+    }
 }
 
 /// Computes the Hadamard (element-wise) product of two tensors.
@@ -418,7 +737,7 @@ impl HadamardProduct {
         HadamardProduct {}
     }
 
-    fn from_nothing() -> Self {
+    pub fn from_nothing() -> Self {
         HadamardProduct {}
     }
 }
@@ -436,6 +755,22 @@ impl<A> PairContractor<A> for HadamardProduct {
     {
         lhs * rhs
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        Zip::from(lhs)
+            .and(rhs)
+            .par_map_collect(|&lhs_elem, &rhs_elem| lhs_elem * rhs_elem)
+    }
 }
 
 /// Permutes the axes of the LHS and RHS tensors to the order in which those axes appear in the
@@ -492,6 +827,23 @@ impl<A> PairContractor<A> for HadamardProductGeneral {
             &self.rhs_permutation.view_singleton(rhs),
         )
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        self.hadamard_product.par_contract_pair(
+            &self.lhs_permutation.view_singleton(lhs),
+            &self.rhs_permutation.view_singleton(rhs),
+        )
+    }
 }
 
 /// Multiplies every element of the RHS tensor by the single scalar in the 0-d LHS tensor.
@@ -532,9 +884,24 @@ impl<A> PairContractor<A> for ScalarMatrixProduct {
         'c: 'd,
         A: Clone + LinalgScalar,
     {
-        let lhs_0d: A = lhs.first().unwrap().clone();
+        let lhs_0d: A = *lhs.first().unwrap();
         rhs.mapv(|x| x * lhs_0d)
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let lhs_0d: A = *lhs.first().unwrap();
+        Zip::from(rhs).par_map_collect(|&x| x * lhs_0d)
+    }
 }
 
 /// Permutes the axes of the RHS tensor to the output order and multiply all elements by the single
@@ -591,6 +958,21 @@ impl<A> PairContractor<A> for ScalarMatrixProductGeneral {
         self.scalar_matrix_product
             .contract_pair(lhs, &self.rhs_permutation.view_singleton(rhs))
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        self.scalar_matrix_product
+            .par_contract_pair(lhs, &self.rhs_permutation.view_singleton(rhs))
+    }
 }
 
 /// Multiplies every element of the LHS tensor by the single scalar in the 0-d RHS tensor.
@@ -631,9 +1013,24 @@ impl<A> PairContractor<A> for MatrixScalarProduct {
         'c: 'd,
         A: Clone + LinalgScalar,
     {
-        let rhs_0d: A = rhs.first().unwrap().clone();
+        let rhs_0d: A = *rhs.first().unwrap();
         lhs.mapv(|x| x * rhs_0d)
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let rhs_0d: A = *rhs.first().unwrap();
+        Zip::from(lhs).par_map_collect(|&x| x * rhs_0d)
+    }
 }
 
 /// Permutes the axes of the LHS tensor to the output order and multiply all elements by the single
@@ -690,6 +1087,21 @@ impl<A> PairContractor<A> for MatrixScalarProductGeneral {
         self.matrix_scalar_product
             .contract_pair(&self.lhs_permutation.view_singleton(lhs), rhs)
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        self.matrix_scalar_product
+            .par_contract_pair(&self.lhs_permutation.view_singleton(lhs), rhs)
+    }
 }
 
 /// Permutes the axes of the LHS and RHS tensor, broadcasts into the output shape,
@@ -720,18 +1132,10 @@ impl BroadcastProductGeneral {
         let rhs_indices = &sc.contraction.operand_indices[1];
         let output_indices = &sc.contraction.output_indices;
 
-        let maybe_lhs_indices = maybe_find_outputs_in_inputs_unique(&output_indices, &lhs_indices);
-        let maybe_rhs_indices = maybe_find_outputs_in_inputs_unique(&output_indices, &rhs_indices);
-        let lhs_indices: Vec<usize> = maybe_lhs_indices
-            .iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .collect();
-        let rhs_indices: Vec<usize> = maybe_rhs_indices
-            .iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .collect();
+        let maybe_lhs_indices = maybe_find_outputs_in_inputs_unique(output_indices, lhs_indices);
+        let maybe_rhs_indices = maybe_find_outputs_in_inputs_unique(output_indices, rhs_indices);
+        let lhs_indices: Vec<usize> = maybe_lhs_indices.iter().flatten().cloned().collect();
+        let rhs_indices: Vec<usize> = maybe_rhs_indices.iter().flatten().cloned().collect();
         let lhs_insertions: Vec<usize> = maybe_lhs_indices
             .iter()
             .enumerate()
@@ -758,33 +1162,68 @@ impl BroadcastProductGeneral {
             hadamard_product,
         }
     }
-}
 
-impl<A> PairContractor<A> for BroadcastProductGeneral {
-    fn contract_pair<'a, 'b, 'c, 'd>(
+    /// Permutes both tensors and inserts the missing axes so that both views can be
+    /// broadcast to the output shape.
+    fn adjust_operands<'a, 'b, 'c, 'd, A>(
         &self,
         lhs: &'b ArrayViewD<'a, A>,
         rhs: &'d ArrayViewD<'c, A>,
-    ) -> ArrayD<A>
+    ) -> (ArrayViewD<'b, A>, ArrayViewD<'d, A>)
     where
         'a: 'b,
         'c: 'd,
         A: Clone + LinalgScalar,
     {
-        let mut adjusted_lhs = self.lhs_permutation.view_singleton(lhs);
-        let mut adjusted_rhs = self.rhs_permutation.view_singleton(rhs);
+        let mut adjusted_lhs = self.lhs_permutation.view_singleton(lhs).reborrow();
+        let mut adjusted_rhs = self.rhs_permutation.view_singleton(rhs).reborrow();
         for &i in self.lhs_insertions.iter() {
             adjusted_lhs = adjusted_lhs.insert_axis(Axis(i));
         }
         for &i in self.rhs_insertions.iter() {
             adjusted_rhs = adjusted_rhs.insert_axis(Axis(i));
         }
+        (adjusted_lhs, adjusted_rhs)
+    }
+}
+
+impl<A> PairContractor<A> for BroadcastProductGeneral {
+    fn contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        let (adjusted_lhs, adjusted_rhs) = self.adjust_operands(lhs, rhs);
         let output_shape = IxDyn(&self.output_sizes);
         let broadcast_lhs = adjusted_lhs.broadcast(output_shape.clone()).unwrap();
         let broadcast_rhs = adjusted_rhs.broadcast(output_shape).unwrap();
         self.hadamard_product
             .contract_pair(&broadcast_lhs, &broadcast_rhs)
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let (adjusted_lhs, adjusted_rhs) = self.adjust_operands(lhs, rhs);
+        let output_shape = IxDyn(&self.output_sizes);
+        let broadcast_lhs = adjusted_lhs.broadcast(output_shape.clone()).unwrap();
+        let broadcast_rhs = adjusted_rhs.broadcast(output_shape).unwrap();
+        self.hadamard_product
+            .par_contract_pair(&broadcast_lhs, &broadcast_rhs)
+    }
 }
 
 // TODO: Micro-optimization: Have a version without the output permutation,
@@ -806,8 +1245,8 @@ impl<A> PairContractor<A> for BroadcastProductGeneral {
 ///
 /// This is the most general contraction and in theory could handle all pairwise contractions,
 /// but is less performant than special-casing when there are no "stack" indices. It is also
-/// currently the only case that requires `.outer_iter_mut()` (which might make parallelizing
-/// operations more difficult).
+/// currently the only case that requires `.outer_iter_mut()`. `par_contract_pair` hands the
+/// subviews along the (flattened) stack axis out to the rayon thread pool.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
 pub struct StackedTensordotGeneral {
@@ -834,8 +1273,8 @@ impl StackedTensordotGeneral {
         let rhs_indices = &sc.contraction.operand_indices[1];
         let output_indices = &sc.contraction.output_indices;
 
-        let maybe_lhs_axes = maybe_find_outputs_in_inputs_unique(&output_indices, &lhs_indices);
-        let maybe_rhs_axes = maybe_find_outputs_in_inputs_unique(&output_indices, &rhs_indices);
+        let maybe_lhs_axes = maybe_find_outputs_in_inputs_unique(output_indices, lhs_indices);
+        let maybe_rhs_axes = maybe_find_outputs_in_inputs_unique(output_indices, rhs_indices);
         let mut lhs_stack_axes = Vec::new();
         let mut rhs_stack_axes = Vec::new();
         let mut stack_indices = Vec::new();
@@ -883,9 +1322,10 @@ impl StackedTensordotGeneral {
         }
 
         for (lhs_pos, &lhs_char) in lhs_indices.iter().enumerate() {
-            if let None = output_indices
+            if output_indices
                 .iter()
                 .position(|&output_char| output_char == lhs_char)
+                .is_none()
             {
                 // Contracted index
                 lhs_contracted_axes.push(lhs_pos);
@@ -931,7 +1371,7 @@ impl StackedTensordotGeneral {
             intermediate_shape.push(sc.output_size[rhs_char]);
         }
 
-        let output_order = find_outputs_in_inputs_unique(&output_indices, &intermediate_indices);
+        let output_order = find_outputs_in_inputs_unique(output_indices, &intermediate_indices);
         let output_shape = intermediate_indices
             .iter()
             .map(|c| sc.output_size[c])
@@ -957,6 +1397,80 @@ impl StackedTensordotGeneral {
             output_permutation,
         }
     }
+
+    /// Permutes the LHS and RHS so that the stack axes come first and reshapes them into
+    /// tensors whose first axis is the product of all the stack axes, copying a tensor only if
+    /// its stack axes can't be merged into one.
+    fn reshape_operands<'a, 'b, 'c, 'd, A>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> (CowArray<'a, A, IxDyn>, CowArray<'c, A, IxDyn>)
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        (
+            reshape_stack_axes(
+                self.lhs_permutation.view_singleton(lhs),
+                &self.lhs_output_shape,
+            ),
+            reshape_stack_axes(
+                self.rhs_permutation.view_singleton(rhs),
+                &self.rhs_output_shape,
+            ),
+        )
+    }
+
+    /// Unflattens the stack axes of the intermediate result and permutes it into the output order.
+    fn permute_output<A>(&self, intermediate_result: ArrayD<A>) -> ArrayD<A>
+    where
+        A: Clone + LinalgScalar,
+    {
+        let intermediate_reshaped = intermediate_result
+            .into_shape_with_order(IxDyn(&self.output_shape))
+            .unwrap();
+        self.output_permutation
+            .contract_singleton(&intermediate_reshaped.view())
+    }
+}
+
+/// Reshapes `permuted` into `shape` as a view if its strides allow it, and otherwise copies it
+/// into a standard-layout array of that shape.
+fn reshape_stack_axes<'a, A: Clone>(
+    permuted: ArrayViewD<'a, A>,
+    shape: &[usize],
+) -> CowArray<'a, A, IxDyn> {
+    if let Some(reshaped) = stack_view(&permuted, shape) {
+        return CowArray::from(reshaped);
+    }
+    CowArray::from(Array::from_shape_vec(IxDyn(shape), permuted.iter().cloned().collect()).unwrap())
+}
+
+/// Views `permuted` with its leading axes merged into the first axis of `shape` (the rest of
+/// which are the remaining axes of `permuted`), if they can be merged without copying.
+fn stack_view<'a, A>(permuted: &ArrayViewD<'a, A>, shape: &[usize]) -> Option<ArrayViewD<'a, A>> {
+    // An empty stack axis can't be indexed below; the copy handles it
+    if permuted.is_empty() {
+        return None;
+    }
+    let num_stack_axes = permuted.ndim() + 1 - shape.len();
+    let mut merged = permuted.clone();
+    if num_stack_axes == 0 {
+        merged.insert_axis_inplace(Axis(0));
+        return Some(merged);
+    }
+    for axis in 0..num_stack_axes - 1 {
+        if !merged.merge_axes(Axis(axis), Axis(axis + 1)) {
+            return None;
+        }
+    }
+    // Only the last of the merged axes is left with a length other than 1
+    for _ in 0..num_stack_axes - 1 {
+        merged.index_axis_inplace(Axis(0), 0);
+    }
+    Some(merged)
 }
 
 impl<A> PairContractor<A> for StackedTensordotGeneral {
@@ -970,18 +1484,7 @@ impl<A> PairContractor<A> for StackedTensordotGeneral {
         'c: 'd,
         A: Clone + LinalgScalar,
     {
-        let lhs_permuted = self.lhs_permutation.view_singleton(lhs);
-        let lhs_reshaped = Array::from_shape_vec(
-            IxDyn(&self.lhs_output_shape),
-            lhs_permuted.iter().cloned().collect(),
-        )
-        .unwrap();
-        let rhs_permuted = self.rhs_permutation.view_singleton(rhs);
-        let rhs_reshaped = Array::from_shape_vec(
-            IxDyn(&self.rhs_output_shape),
-            rhs_permuted.iter().cloned().collect(),
-        )
-        .unwrap();
+        let (lhs_reshaped, rhs_reshaped) = self.reshape_operands(lhs, rhs);
         let mut intermediate_result: ArrayD<A> = Array::zeros(IxDyn(&self.intermediate_shape));
         let mut lhs_iter = lhs_reshaped.outer_iter();
         let mut rhs_iter = rhs_reshaped.outer_iter();
@@ -994,10 +1497,442 @@ impl<A> PairContractor<A> for StackedTensordotGeneral {
                 &mut output_subview,
             );
         }
-        let intermediate_reshaped = intermediate_result
-            .into_shape_with_order(IxDyn(&self.output_shape))
-            .unwrap();
-        self.output_permutation
-            .contract_singleton(&intermediate_reshaped.view())
+        self.permute_output(intermediate_result)
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let (lhs_reshaped, rhs_reshaped) = self.reshape_operands(lhs, rhs);
+        let mut intermediate_result: ArrayD<A> = Array::zeros(IxDyn(&self.intermediate_shape));
+        let settings = ThreadSettings::current();
+        intermediate_result
+            .outer_iter_mut()
+            .into_par_iter()
+            .zip(lhs_reshaped.outer_iter())
+            .zip(rhs_reshaped.outer_iter())
+            .for_each(|((mut output_subview, lhs_subview), rhs_subview)| {
+                settings.apply(|| {
+                    PairContractor::<A>::contract_and_assign_pair(
+                        &self.tensordot_fixed_position,
+                        &lhs_subview,
+                        &rhs_subview,
+                        &mut output_subview,
+                    )
+                })
+            });
+        self.permute_output(intermediate_result)
+    }
+}
+
+/// Performs a batch of matrix multiplications in place, for a contraction of the form
+/// `...ik,...kj->...ij`: each tensor has one index that is contracted and one that appears in
+/// only it and the output, and all of its other indices (the "batch" indices) appear in both
+/// tensors and the output, in any order.
+///
+/// `StackedTensordotGeneral` can perform the same contractions, but it permutes the tensors so
+/// that the stack axes come first and merges them (copying a tensor whose stack axes can't be
+/// merged, such as a broadcast one), views each subview as a matrix, and permutes the result
+/// into the output order. Here the tensors and a standard-layout output are only viewed with
+/// their axes permuted into the order `(batch, row, column)`, and each matrix multiplication
+/// reads its operands and writes its result directly through those views, whatever their
+/// strides.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct BatchedMatMul {
+    /// The order of the axes of the LHS in the view that has its batch axes first, then its
+    /// row axis and then its contracted axis
+    lhs_order: Vec<usize>,
+
+    /// The order of the axes of the RHS in the view that has its batch axes first, then its
+    /// contracted axis and then its column axis
+    rhs_order: Vec<usize>,
+
+    /// The order of the axes of the output in the view that has its batch axes first, then
+    /// its row axis and then its column axis
+    output_order: Vec<usize>,
+
+    /// The shape of the output
+    output_shape: Vec<usize>,
+}
+
+impl BatchedMatMul {
+    pub fn new(sc: &SizedContraction) -> Self {
+        assert_eq!(sc.contraction.operand_indices.len(), 2);
+        let lhs_indices = &sc.contraction.operand_indices[0];
+        let rhs_indices = &sc.contraction.operand_indices[1];
+        let output_indices = &sc.contraction.output_indices;
+
+        let batch_indices: Vec<char> = output_indices
+            .iter()
+            .cloned()
+            .filter(|c| lhs_indices.contains(c) && rhs_indices.contains(c))
+            .collect();
+        let find_one = |indices: &[char], filter: &dyn Fn(&char) -> bool| {
+            let mut found = indices.iter().cloned().filter(|c| filter(c));
+            let index = found.next().unwrap();
+            assert!(found.next().is_none());
+            index
+        };
+        let row_index = find_one(lhs_indices, &|c| {
+            output_indices.contains(c) && !rhs_indices.contains(c)
+        });
+        let column_index = find_one(rhs_indices, &|c| {
+            output_indices.contains(c) && !lhs_indices.contains(c)
+        });
+        let contracted_index = find_one(lhs_indices, &|c| !output_indices.contains(c));
+        assert_eq!(batch_indices.len() + 2, lhs_indices.len());
+        assert_eq!(batch_indices.len() + 2, rhs_indices.len());
+
+        let order = |indices: &[char], last_two: [char; 2]| -> Vec<usize> {
+            find_outputs_in_inputs_unique(&[&batch_indices[..], &last_two].concat(), indices)
+        };
+        BatchedMatMul {
+            lhs_order: order(lhs_indices, [row_index, contracted_index]),
+            rhs_order: order(rhs_indices, [contracted_index, column_index]),
+            output_order: order(output_indices, [row_index, column_index]),
+            output_shape: output_indices.iter().map(|c| sc.output_size[c]).collect(),
+        }
+    }
+}
+
+/// Computes `out = alpha * lhs.dot(rhs) + beta * out` for each matrix of the batch, where the
+/// last two axes of each tensor are the rows and columns of its matrices and the others are the
+/// batch axes, which have the same lengths in all three.
+fn mat_mul_batches<A: LinalgScalar>(
+    lhs: &ArrayViewD<A>,
+    rhs: &ArrayViewD<A>,
+    alpha: A,
+    beta: A,
+    out: &mut ArrayViewMutD<A>,
+) {
+    let batch_shape = IxDyn(&out.shape()[..out.ndim() - 2]);
+    with_current_backend(|backend| {
+        for batch_index in ndarray::indices(batch_shape) {
+            let batch_index = batch_index.slice();
+            backend.mat_mul(
+                alpha,
+                &batch_matrix(lhs.view(), batch_index),
+                &batch_matrix(rhs.view(), batch_index),
+                beta,
+                &mut batch_matrix(out.view_mut(), batch_index),
+            );
+        }
+    });
+}
+
+/// Returns the matrix made of the last two axes of `tensor` at `batch_index` along the others.
+fn batch_matrix<S: Data>(
+    mut tensor: ArrayBase<S, IxDyn>,
+    batch_index: &[usize],
+) -> ArrayBase<S, Ix2> {
+    for &index in batch_index.iter() {
+        tensor.index_axis_inplace(Axis(0), index);
+    }
+    tensor.into_dimensionality().unwrap()
+}
+
+impl<A> PairContractor<A> for BatchedMatMul {
+    fn contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        let mut output = Array::zeros(IxDyn(&self.output_shape));
+        self.contract_and_assign_pair(lhs, rhs, &mut output.view_mut());
+        output
+    }
+
+    fn contract_and_assign_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        self.contract_and_accumulate_pair(lhs, rhs, A::one(), A::zero(), out);
+    }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        mat_mul_batches(
+            &lhs.view().permuted_axes(IxDyn(&self.lhs_order)),
+            &rhs.view().permuted_axes(IxDyn(&self.rhs_order)),
+            alpha,
+            beta,
+            &mut out.view_mut().permuted_axes(IxDyn(&self.output_order)),
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let mut output = Array::zeros(IxDyn(&self.output_shape));
+        if self.output_order.len() == 2 {
+            self.contract_and_assign_pair(lhs, rhs, &mut output.view_mut());
+            return output;
+        }
+        // The matrices along the first batch axis are multiplied concurrently
+        let lhs = lhs.view().permuted_axes(IxDyn(&self.lhs_order));
+        let rhs = rhs.view().permuted_axes(IxDyn(&self.rhs_order));
+        let settings = ThreadSettings::current();
+        output
+            .view_mut()
+            .permuted_axes(IxDyn(&self.output_order))
+            .outer_iter_mut()
+            .into_par_iter()
+            .zip(lhs.outer_iter())
+            .zip(rhs.outer_iter())
+            .for_each(|((mut output_subview, lhs_subview), rhs_subview)| {
+                settings.apply(|| {
+                    mat_mul_batches(
+                        &lhs_subview,
+                        &rhs_subview,
+                        A::one(),
+                        A::zero(),
+                        &mut output_subview,
+                    )
+                })
+            });
+        output
+    }
+}
+
+/// Multiplies a matrix by a vector, for a contraction in which one tensor has two indices and
+/// the other has one, which is contracted with either index of the first, e.g. `ij,j->i`,
+/// `ij,i->j` or `j,ij->i`.
+///
+/// `TensordotGeneral` can perform the same contractions, but it reshapes the vector into a
+/// one-column matrix and multiplies the two as matrices, which for small and medium sizes costs
+/// more than the product itself. Here the matrix is only viewed (transposed, if its first index
+/// is the contracted one) and multiplied by the vector with `Backend::mat_vec_mul`, which is
+/// BLAS GEMV with the `blas` feature.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct MatrixVectorProduct {
+    /// Whether the matrix is the LHS (and the vector the RHS)
+    matrix_is_lhs: bool,
+
+    /// Whether the first index of the matrix is the contracted one
+    transpose: bool,
+
+    /// The length of the output
+    output_len: usize,
+}
+
+impl MatrixVectorProduct {
+    pub fn new(sc: &SizedContraction) -> Self {
+        assert_eq!(sc.contraction.operand_indices.len(), 2);
+        let lhs_indices = &sc.contraction.operand_indices[0];
+        let rhs_indices = &sc.contraction.operand_indices[1];
+        let output_indices = &sc.contraction.output_indices;
+        assert_eq!(output_indices.len(), 1);
+
+        let matrix_is_lhs = lhs_indices.len() == 2;
+        let (matrix_indices, vector_indices) = if matrix_is_lhs {
+            (lhs_indices, rhs_indices)
+        } else {
+            (rhs_indices, lhs_indices)
+        };
+        assert_eq!(matrix_indices.len(), 2);
+        assert_eq!(vector_indices.len(), 1);
+        let transpose = matrix_indices[0] == vector_indices[0];
+        assert_eq!(matrix_indices[transpose as usize], output_indices[0]);
+
+        MatrixVectorProduct {
+            matrix_is_lhs,
+            transpose,
+            output_len: sc.output_size[&output_indices[0]],
+        }
+    }
+
+    /// Returns the LHS and RHS as the matrix, with the output index first, and the vector.
+    fn as_matrix_and_vector<'a, A>(
+        &self,
+        lhs: &ArrayViewD<'a, A>,
+        rhs: &ArrayViewD<'a, A>,
+    ) -> (ArrayView2<'a, A>, ArrayView1<'a, A>) {
+        let (matrix, vector) = if self.matrix_is_lhs {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        let matrix: ArrayView2<A> = matrix.clone().into_dimensionality().unwrap();
+        let matrix = if self.transpose {
+            matrix.reversed_axes()
+        } else {
+            matrix
+        };
+        (matrix, vector.clone().into_dimensionality().unwrap())
+    }
+}
+
+impl<A> PairContractor<A> for MatrixVectorProduct {
+    fn contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        let mut output = Array::zeros(IxDyn(&[self.output_len]));
+        self.contract_and_assign_pair(lhs, rhs, &mut output.view_mut());
+        output
+    }
+
+    fn contract_and_assign_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        self.contract_and_accumulate_pair(lhs, rhs, A::one(), A::zero(), out);
+    }
+
+    fn contract_and_accumulate_pair<'a, 'b, 'c, 'd, 'e, 'f>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+        alpha: A,
+        beta: A,
+        out: &'f mut ArrayViewMutD<'e, A>,
+    ) where
+        'a: 'b,
+        'c: 'd,
+        'e: 'f,
+        A: Clone + LinalgScalar,
+    {
+        let (matrix, vector) = self.as_matrix_and_vector(&lhs.view(), &rhs.view());
+        let mut out: ArrayViewMut1<A> = out.view_mut().into_dimensionality().unwrap();
+        with_current_backend(|backend| {
+            backend.mat_vec_mul(alpha, &matrix, &vector, beta, &mut out)
+        });
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar + Send + Sync,
+    {
+        let num_threads = rayon::current_num_threads();
+        let deterministic = deterministic_reductions();
+        if !deterministic && (num_threads < 2 || self.output_len < 2) {
+            return PairContractor::<A>::contract_pair(self, lhs, rhs);
+        }
+        // Deterministic blocks don't depend on the number of threads
+        let block_size = if deterministic {
+            DETERMINISTIC_BLOCK_LEN
+        } else {
+            self.output_len.div_ceil(num_threads)
+        };
+        let settings = ThreadSettings::current();
+
+        let (matrix, vector) = self.as_matrix_and_vector(&lhs.view(), &rhs.view());
+        let mut output: Array1<A> = Array::zeros(self.output_len);
+        output
+            .axis_chunks_iter_mut(Axis(0), block_size)
+            .into_par_iter()
+            .zip(matrix.axis_chunks_iter(Axis(0), block_size))
+            .for_each(|(mut output_block, matrix_block)| {
+                settings.apply(|| {
+                    with_current_backend(|backend| {
+                        backend.mat_vec_mul(
+                            A::one(),
+                            &matrix_block,
+                            &vector,
+                            A::zero(),
+                            &mut output_block,
+                        )
+                    })
+                })
+            });
+        output.into_dyn()
+    }
+}
+
+/// Computes the dot product of two vectors with the same index, e.g. `i,i->`, with
+/// `Backend::dot` instead of reshaping them into a row and a column for `TensordotGeneral` to
+/// multiply as matrices.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct DotProduct {}
+
+impl DotProduct {
+    pub fn new(sc: &SizedContraction) -> Self {
+        assert_eq!(sc.contraction.operand_indices.len(), 2);
+        let lhs_indices = &sc.contraction.operand_indices[0];
+        let rhs_indices = &sc.contraction.operand_indices[1];
+        assert_eq!(lhs_indices.len(), 1);
+        assert_eq!(lhs_indices, rhs_indices);
+        assert!(sc.contraction.output_indices.is_empty());
+
+        DotProduct {}
+    }
+}
+
+impl<A> PairContractor<A> for DotProduct {
+    fn contract_pair<'a, 'b, 'c, 'd>(
+        &self,
+        lhs: &'b ArrayViewD<'a, A>,
+        rhs: &'d ArrayViewD<'c, A>,
+    ) -> ArrayD<A>
+    where
+        'a: 'b,
+        'c: 'd,
+        A: Clone + LinalgScalar,
+    {
+        let lhs: ArrayView1<A> = lhs.view().into_dimensionality().unwrap();
+        let rhs: ArrayView1<A> = rhs.view().into_dimensionality().unwrap();
+        arr0(with_current_backend(|backend| backend.dot(&lhs, &rhs))).into_dyn()
     }
 }
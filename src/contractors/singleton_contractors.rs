@@ -18,12 +18,15 @@
 //! All the structs here perform perform some combination of
 //! permutation of the input axes (e.g. `ijk->jki`), diagonalization across repeated but
 //! un-summed axes (e.g. `ii->i`),
-//! and summation across axes not present in the output index list (e.g. `ijk->j`).
+//! and summation across axes not present in the output index list (e.g. `ijk->j`),
+//! or else embed a tensor along the diagonal of an output with repeated indices (e.g. `i->ii`).
 
+use alloc::vec::Vec;
 use ndarray::prelude::*;
 use ndarray::LinalgScalar;
 
 use super::{SingletonContractor, SingletonViewer};
+use crate::backend::with_current_backend;
 use crate::{Contraction, SizedContraction};
 
 #[cfg(feature = "serde")]
@@ -43,12 +46,12 @@ impl Identity {
 }
 
 impl<A> SingletonViewer<A> for Identity {
-    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'b, A>
+    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'a, A>
     where
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        tensor.view()
+        tensor.clone()
     }
 }
 
@@ -99,15 +102,37 @@ impl Permutation {
             permutation: permutation.to_vec(),
         }
     }
+
+    /// Returns the permutation that undoes this one.
+    pub fn inverse(&self) -> Self {
+        let mut inverse = vec![0; self.permutation.len()];
+        for (i, &axis) in self.permutation.iter().enumerate() {
+            inverse[axis] = i;
+        }
+        Permutation {
+            permutation: inverse,
+        }
+    }
+
+    /// Same as `view_singleton`, but for a mutable view.
+    pub fn view_singleton_mut<'a, 'b, A>(
+        &self,
+        tensor: &'b mut ArrayViewMutD<'a, A>,
+    ) -> ArrayViewMutD<'b, A>
+    where
+        'a: 'b,
+    {
+        tensor.view_mut().permuted_axes(IxDyn(&self.permutation))
+    }
 }
 
 impl<A> SingletonViewer<A> for Permutation {
-    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'b, A>
+    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'a, A>
     where
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        tensor.view().permuted_axes(IxDyn(&self.permutation))
+        tensor.clone().permuted_axes(IxDyn(&self.permutation))
     }
 }
 
@@ -117,10 +142,7 @@ impl<A> SingletonContractor<A> for Permutation {
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        tensor
-            .view()
-            .permuted_axes(IxDyn(&self.permutation))
-            .to_owned()
+        with_current_backend(|backend| backend.permute(tensor, &self.permutation))
     }
 }
 
@@ -130,7 +152,6 @@ impl<A> SingletonContractor<A> for Permutation {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
 pub struct Summation {
-    orig_axis_list: Vec<usize>,
     adjusted_axis_list: Vec<usize>,
 }
 
@@ -147,13 +168,9 @@ impl Summation {
 
     fn from_sizes(start_index: usize, num_summed_axes: usize) -> Self {
         assert!(num_summed_axes >= 1);
-        let orig_axis_list = (start_index..(start_index + num_summed_axes)).collect();
         let adjusted_axis_list = (0..num_summed_axes).map(|_| start_index).collect();
 
-        Summation {
-            orig_axis_list,
-            adjusted_axis_list,
-        }
+        Summation { adjusted_axis_list }
     }
 }
 
@@ -163,11 +180,9 @@ impl<A> SingletonContractor<A> for Summation {
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        let mut result = tensor.sum_axis(Axis(self.adjusted_axis_list[0]));
-        for &axis in self.adjusted_axis_list[1..].iter() {
-            result = result.sum_axis(Axis(axis));
-        }
-        result
+        with_current_backend(|backend| {
+            backend.sum_trailing_axes(tensor, self.adjusted_axis_list.len())
+        })
     }
 }
 
@@ -225,25 +240,43 @@ impl Diagonalization {
 }
 
 impl<A> SingletonViewer<A> for Diagonalization {
-    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'b, A>
+    fn view_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayViewD<'a, A>
     where
         'a: 'b,
         A: Clone + LinalgScalar,
     {
+        if tensor.is_empty() {
+            // An empty tensor's strides can be anything, but so can those of its diagonal
+            return ArrayView::from_shape(IxDyn(&self.output_shape), &[]).unwrap();
+        }
         // Construct the stride array on the fly by enumerating (idx, stride) from strides() and
-        // adding stride to self.which_index_is_this
-        let mut strides = vec![0; self.output_shape.len()];
+        // adding stride to self.which_index_is_this. The strides can have either sign.
+        let mut strides: Vec<isize> = vec![0; self.output_shape.len()];
         for (idx, &stride) in tensor.strides().iter().enumerate() {
-            assert!(stride > 0);
-            strides[self.input_to_output_mapping[idx]] += stride as usize;
+            strides[self.input_to_output_mapping[idx]] += stride;
         }
 
-        // Output shape we want is already stored in self.output_shape
-        // let t = ArrayView::from_shape(IxDyn(&[3]).strides(IxDyn(&[4])), &sl).unwrap();
-        let data_slice = tensor.as_slice_memory_order().unwrap();
+        // The slice starts at the lowest address of the tensor, and the view has to start at the
+        // lowest address of the diagonal, which is never below it. The first element of both
+        // is at an offset of (length - 1) * |stride| past it for each negative stride.
+        let offset_of_first = |shape: &[usize], strides: &[isize]| -> usize {
+            shape
+                .iter()
+                .zip(strides)
+                .filter(|(_, &stride)| stride < 0)
+                .map(|(&len, &stride)| (len - 1) * stride.unsigned_abs())
+                .sum()
+        };
+        let start = offset_of_first(tensor.shape(), tensor.strides())
+            - offset_of_first(&self.output_shape, &strides);
+
+        // Output shape we want is already stored in self.output_shape. ndarray takes negative
+        // strides as their two's complement.
+        let data_slice = tensor.to_slice_memory_order().unwrap();
+        let strides: Vec<usize> = strides.iter().map(|&stride| stride as usize).collect();
         ArrayView::from_shape(
             IxDyn(&self.output_shape).strides(IxDyn(&strides)),
-            &data_slice,
+            &data_slice[start..],
         )
         .unwrap()
     }
@@ -255,11 +288,25 @@ impl<A> SingletonContractor<A> for Diagonalization {
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        // We're only using this method if the tensor is not contiguous
-        // Clones twice as a result
-        let cloned_tensor: ArrayD<A> =
-            Array::from_shape_vec(tensor.raw_dim(), tensor.iter().cloned().collect()).unwrap();
-        self.view_singleton(&cloned_tensor.view()).into_owned()
+        if tensor.as_slice_memory_order().is_some() {
+            self.view_singleton(tensor).to_owned()
+        } else {
+            self.gather(tensor)
+        }
+    }
+}
+
+impl Diagonalization {
+    /// Copies the elements of the diagonal out of `tensor` one at a time, for a tensor that
+    /// isn't contiguous and so can't be viewed by `view_singleton`. Only the diagonal is copied.
+    fn gather<A: Clone>(&self, tensor: &ArrayViewD<A>) -> ArrayD<A> {
+        let mut input_index = IxDyn::zeros(tensor.ndim());
+        Array::from_shape_fn(IxDyn(&self.output_shape), |output_index| {
+            for (axis, &output_axis) in self.input_to_output_mapping.iter().enumerate() {
+                input_index[axis] = output_index[output_axis];
+            }
+            tensor[&input_index].clone()
+        })
     }
 }
 
@@ -285,11 +332,12 @@ impl PermutationAndSummation {
             output_order.push(input_pos);
         }
         for (i, &input_char) in sc.contraction.operand_indices[0].iter().enumerate() {
-            if let None = sc
+            if sc
                 .contraction
                 .output_indices
                 .iter()
                 .position(|&output_char| output_char == input_char)
+                .is_none()
             {
                 output_order.push(i);
             }
@@ -351,23 +399,100 @@ impl<A> SingletonContractor<A> for DiagonalizationAndSummation {
         'a: 'b,
         A: Clone + LinalgScalar,
     {
-        // We can only do Diagonalization directly as a view if all the strides are
-        // positive and if the tensor is contiguous. We can't know this just from
-        // looking at the SizedContraction; we need the actual tensor that will
-        // be operated on. So this needs to get checked at "runtime".
+        // We can only do Diagonalization directly as a view if the tensor is contiguous
+        // (with strides of either sign). We can't know this just from looking at the
+        // SizedContraction; we need the actual tensor that will be operated on. So this
+        // needs to get checked at "runtime".
         //
-        // If either condition fails, we use the contract_singleton version to
-        // create a new tensor and view() that intermediate result.
-        let contracted_singleton;
-        let viewed_singleton = if tensor.as_slice_memory_order().is_some()
-            && tensor.strides().iter().all(|&stride| stride > 0)
-        {
-            self.diagonalization.view_singleton(tensor)
+        // If it isn't, we copy out just the diagonal and sum that.
+        let gathered;
+        let viewed_singleton = if tensor.as_slice_memory_order().is_some() {
+            self.diagonalization.view_singleton(tensor).reborrow()
         } else {
-            contracted_singleton = self.diagonalization.contract_singleton(tensor);
-            contracted_singleton.view()
+            gathered = self.diagonalization.gather(tensor);
+            gathered.view()
         };
 
         self.summation.contract_singleton(&viewed_singleton)
     }
 }
+
+/// Writes the elements of the input tensor along the generalized diagonal of an output tensor in
+/// which some indices are repeated, and zero everywhere else. This is the inverse of
+/// `Diagonalization`: the input has the distinct output indices, in order of their first
+/// appearance.
+///
+/// Examples:
+///
+/// 1. `i->ii`
+/// 2. `ij->iji`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct DiagonalEmbedding {
+    output_to_input_mapping: Vec<usize>,
+    output_shape: Vec<usize>,
+}
+
+impl DiagonalEmbedding {
+    pub fn new(sc: &SizedContraction) -> Self {
+        let SizedContraction {
+            contraction: Contraction {
+                ref output_indices, ..
+            },
+            ref output_size,
+        } = sc;
+
+        let mut distinct_output_indices = Vec::new();
+        let mut output_to_input_mapping = Vec::new();
+        for &c in output_indices.iter() {
+            match distinct_output_indices.iter().position(|&x| x == c) {
+                Some(pos) => output_to_input_mapping.push(pos),
+                None => {
+                    output_to_input_mapping.push(distinct_output_indices.len());
+                    distinct_output_indices.push(c);
+                }
+            }
+        }
+        let output_shape = output_indices.iter().map(|c| output_size[c]).collect();
+
+        DiagonalEmbedding {
+            output_to_input_mapping,
+            output_shape,
+        }
+    }
+
+    /// Zeroes `out` and writes `tensor` along its diagonal. `out` must have the output shape.
+    pub fn embed_into<A>(&self, tensor: &ArrayViewD<A>, out: &mut ArrayViewMutD<A>)
+    where
+        A: Clone + LinalgScalar,
+    {
+        if !out.is_standard_layout() {
+            let embedded = self.contract_singleton(tensor);
+            out.assign(&embedded);
+            return;
+        }
+
+        // Summing the strides of the copies of each index gives a view of the diagonal
+        let mut strides = vec![0; tensor.ndim()];
+        for (idx, &stride) in out.strides().iter().enumerate() {
+            strides[self.output_to_input_mapping[idx]] += stride as usize;
+        }
+        out.fill(A::zero());
+        let data_slice = out.as_slice_mut().unwrap();
+        ArrayViewMut::from_shape(IxDyn(tensor.shape()).strides(IxDyn(&strides)), data_slice)
+            .unwrap()
+            .assign(tensor);
+    }
+}
+
+impl<A> SingletonContractor<A> for DiagonalEmbedding {
+    fn contract_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, A>) -> ArrayD<A>
+    where
+        'a: 'b,
+        A: Clone + LinalgScalar,
+    {
+        let mut embedded = Array::zeros(IxDyn(&self.output_shape));
+        self.embed_into(tensor, &mut embedded.view_mut());
+        embedded
+    }
+}
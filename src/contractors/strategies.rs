@@ -21,21 +21,39 @@
 //!
 //! The code here has some duplication and is probably not the most idiomatic way to accomplish this.
 
+use crate::collections::{HashMap, HashSet};
 use crate::SizedContraction;
-use std::collections::{HashMap, HashSet};
+use alloc::string::String;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+/// The kind of contractor chosen for a contraction of a single tensor, based on whether it
+/// takes a diagonal (repeats an input index), sums over an index, and reorders the axes.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum SingletonMethod {
+    /// Returns the tensor as it is, e.g. `ij->ij`
     Identity,
+
+    /// Permutes the axes, e.g. `ij->ji`
     Permutation,
+
+    /// Sums over some axes without reordering the others, e.g. `ijk->ij`
     Summation,
+
+    /// Takes a diagonal and possibly reorders the axes, e.g. `iij->ji`
     Diagonalization,
+
+    /// Sums over some axes and reorders the others, e.g. `ijk->kj`
     PermutationAndSummation,
+
+    /// Takes a diagonal and sums over some axes, e.g. `iij->j`
     DiagonalizationAndSummation,
+
+    /// A contractor supplied through a [`ContractorRegistry`](struct.ContractorRegistry.html)
+    /// (never chosen by `get_strategy`)
+    Custom,
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -52,7 +70,7 @@ impl SingletonSummary {
         let output_indices = &sc.contraction.output_indices;
         let input_indices = &sc.contraction.operand_indices[0];
 
-        SingletonSummary::from_indices(&input_indices, &output_indices)
+        SingletonSummary::from_indices(input_indices, output_indices)
     }
 
     fn from_indices(input_indices: &[char], output_indices: &[char]) -> Self {
@@ -89,26 +107,93 @@ impl SingletonSummary {
             (_, _, _) => SingletonMethod::DiagonalizationAndSummation,
         }
     }
+
+    /// The contractor chosen by `get_strategy` and why, e.g. "DiagonalizationAndSummation,
+    /// because a diagonal is taken and axes are summed (1 summed, 1 diagonalized, 1 reordered)".
+    pub fn reason(&self) -> String {
+        let method = self.get_strategy();
+        let why = match method {
+            SingletonMethod::Identity => "nothing is summed, taken along a diagonal or reordered",
+            SingletonMethod::Permutation => "the axes are only reordered",
+            SingletonMethod::Summation => "axes are summed and the others stay in order",
+            SingletonMethod::Diagonalization => "a diagonal is taken and nothing is summed",
+            SingletonMethod::PermutationAndSummation => "axes are summed and the others reordered",
+            SingletonMethod::DiagonalizationAndSummation => {
+                "a diagonal is taken and axes are summed"
+            }
+            SingletonMethod::Custom => unreachable!(),
+        };
+        format!(
+            "{:?}, because {} ({} summed, {} diagonalized, {} reordered)",
+            method, why, self.num_summed_axes, self.num_diagonalized_axes, self.num_reordered_axes
+        )
+    }
 }
 
+/// The kind of contractor chosen for a contraction of two tensors (after any simplification of
+/// each one), based on how many of their indices are summed over (contracted), appear in only
+/// one of them (outer), or appear in both and the output (stacked). Some of the kinds are only
+/// special cases of others and aren't currently chosen.
 #[allow(dead_code)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum PairMethod {
+    /// The element-wise product of two tensors with the same indices in the same order, e.g.
+    /// `ij,ij->ij` (not currently chosen, as `HadamardProductGeneral` covers it)
     HadamardProduct,
+
+    /// The element-wise product of two tensors with the same indices, e.g. `ij,ji->ij`
     HadamardProductGeneral,
+
+    /// A matrix multiplication contracting the last axes of the left tensor with the first
+    /// axes of the right one, e.g. `ij,jk->ik` (not currently chosen, as `TensordotGeneral`
+    /// covers it)
     TensordotFixedPosition,
+
+    /// A matrix multiplication of the permuted tensors, e.g. `ji,kj->ki`
     TensordotGeneral,
+
+    /// The right tensor times the 0-d left tensor (not currently chosen, as
+    /// `ScalarMatrixProductGeneral` covers it)
     ScalarMatrixProduct,
+
+    /// The permuted right tensor times the 0-d left tensor, which arises when every index of
+    /// the left tensor is summed over first, e.g. `i,jk->kj`
     ScalarMatrixProductGeneral,
+
+    /// The left tensor times the 0-d right tensor (not currently chosen, as
+    /// `MatrixScalarProductGeneral` covers it)
     MatrixScalarProduct,
+
+    /// The permuted left tensor times the 0-d right tensor, which arises when every index of
+    /// the right tensor is summed over first, e.g. `ij,k->ji`
     MatrixScalarProductGeneral,
+
+    /// A broadcast element-wise product, e.g. `ij,jk->ijk` (not currently chosen, as
+    /// `StackedTensordotGeneral` is faster)
     BroadcastProductGeneral,
+
+    /// A batch of matrix multiplications over the stacked indices, e.g. `bijk,bjkl->bil`
     StackedTensordotGeneral,
+
+    /// A batch of matrix multiplications performed in place, when each tensor has one
+    /// contracted index, one outer index and stacked indices, e.g. `bij,bjk->bik`
+    BatchedMatMul,
+
+    /// A matrix times a vector that is contracted with either of its indices, e.g. `ij,j->i`
+    /// or `ij,i->j`
+    MatrixVectorProduct,
+
+    /// The dot product of two vectors, e.g. `i,i->`
+    DotProduct,
+
+    /// A contractor supplied through a [`ContractorRegistry`](struct.ContractorRegistry.html)
+    /// (never chosen by `get_strategy`)
+    Custom,
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct PairSummary {
     num_stacked_axes: usize,
     num_lhs_outer_axes: usize,
@@ -123,7 +208,7 @@ impl PairSummary {
         let lhs_indices = &sc.contraction.operand_indices[0];
         let rhs_indices = &sc.contraction.operand_indices[1];
 
-        PairSummary::from_indices(&lhs_indices, &rhs_indices, &output_indices)
+        PairSummary::from_indices(lhs_indices, rhs_indices, output_indices)
     }
 
     fn from_indices(lhs_indices: &[char], rhs_indices: &[char], output_indices: &[char]) -> Self {
@@ -163,8 +248,106 @@ impl PairSummary {
             // This contractor works, but appears to be slower
             // than StackedTensordotGeneral
             // (0, _, _, _) => PairMethod::BroadcastProductGeneral,
+            (1, 0, 0, 0) => PairMethod::DotProduct,
+            (1, 1, 0, 0) | (1, 0, 1, 0) => PairMethod::MatrixVectorProduct,
             (_, _, _, 0) => PairMethod::TensordotGeneral,
+            (1, 1, 1, _) => PairMethod::BatchedMatMul,
             (_, _, _, _) => PairMethod::StackedTensordotGeneral,
         }
     }
+
+    /// The contractor chosen by `get_strategy` and why, e.g. "TensordotGeneral, because no
+    /// index appears in both operands and the output, so it is a single matrix multiplication
+    /// (1 contracted, 0 stacked, 1 + 1 outer)".
+    pub fn reason(&self) -> String {
+        let method = self.get_strategy();
+        let why = match method {
+            PairMethod::HadamardProductGeneral => {
+                "no index is contracted or appears in only one operand, so it is an element-wise product"
+            }
+            PairMethod::ScalarMatrixProductGeneral => {
+                "the left operand has no indices, so it is a scalar times the right operand"
+            }
+            PairMethod::MatrixScalarProductGeneral => {
+                "the right operand has no indices, so it is the left operand times a scalar"
+            }
+            PairMethod::TensordotGeneral => {
+                "no index appears in both operands and the output, so it is a single matrix multiplication"
+            }
+            PairMethod::StackedTensordotGeneral => {
+                "some indices appear in both operands and the output, so it is a batch of matrix multiplications over them"
+            }
+            PairMethod::MatrixVectorProduct => {
+                "one operand has a contracted index and an outer index and the other only the contracted index, so it is a matrix-vector product"
+            }
+            PairMethod::DotProduct => {
+                "both operands only have the contracted index, so it is a dot product"
+            }
+            PairMethod::BatchedMatMul => {
+                "besides the indices in both operands and the output, each operand has one contracted index and one outer index, so it is a batch of matrix multiplications performed in place"
+            }
+            _ => unreachable!(),
+        };
+        format!(
+            "{:?}, because {} ({} contracted, {} stacked, {} + {} outer)",
+            method,
+            why,
+            self.num_contracted_axes,
+            self.num_stacked_axes,
+            self.num_lhs_outer_axes,
+            self.num_rhs_outer_axes
+        )
+    }
+
+    /// Whether `method` can perform the pair contraction `sc` (whose operands have distinct
+    /// indices), which this summarizes. The methods chosen by `get_strategy` only depend on the
+    /// numbers of each kind of index; the others also need the indices to be in a particular
+    /// order.
+    pub fn supports(&self, method: PairMethod, sc: &SizedContraction) -> bool {
+        let lhs_indices = &sc.contraction.operand_indices[0];
+        let rhs_indices = &sc.contraction.operand_indices[1];
+        let output_indices = &sc.contraction.output_indices;
+        let no_outer_axes = self.num_lhs_outer_axes == 0 && self.num_rhs_outer_axes == 0;
+        match method {
+            PairMethod::HadamardProduct => {
+                lhs_indices == rhs_indices && lhs_indices == output_indices
+            }
+            PairMethod::HadamardProductGeneral => self.num_contracted_axes == 0 && no_outer_axes,
+            PairMethod::TensordotFixedPosition => {
+                let num_contracted = self.num_contracted_axes;
+                let num_lhs_outer = lhs_indices.len() - num_contracted;
+                self.num_stacked_axes == 0
+                    && lhs_indices[num_lhs_outer..] == rhs_indices[..num_contracted]
+                    && lhs_indices[..num_lhs_outer]
+                        .iter()
+                        .chain(rhs_indices[num_contracted..].iter())
+                        .eq(output_indices.iter())
+            }
+            PairMethod::TensordotGeneral => self.num_stacked_axes == 0,
+            PairMethod::ScalarMatrixProduct => {
+                lhs_indices.is_empty() && rhs_indices == output_indices
+            }
+            PairMethod::ScalarMatrixProductGeneral => lhs_indices.is_empty(),
+            PairMethod::MatrixScalarProduct => {
+                rhs_indices.is_empty() && lhs_indices == output_indices
+            }
+            PairMethod::MatrixScalarProductGeneral => rhs_indices.is_empty(),
+            PairMethod::BroadcastProductGeneral => self.num_contracted_axes == 0,
+            PairMethod::StackedTensordotGeneral => true,
+            PairMethod::BatchedMatMul => {
+                self.num_contracted_axes == 1
+                    && self.num_lhs_outer_axes == 1
+                    && self.num_rhs_outer_axes == 1
+            }
+            PairMethod::MatrixVectorProduct => {
+                self.num_contracted_axes == 1
+                    && self.num_stacked_axes == 0
+                    && self.num_lhs_outer_axes + self.num_rhs_outer_axes == 1
+            }
+            PairMethod::DotProduct => {
+                self.num_contracted_axes == 1 && self.num_stacked_axes == 0 && no_outer_axes
+            }
+            PairMethod::Custom => false,
+        }
+    }
 }
@@ -0,0 +1,337 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `cuda` feature, contains `CudaExecutor`, which runs a
+//! [`KernelProgram`](struct.KernelProgram.html) on an NVIDIA GPU with
+//! [cudarc](https://crates.io/crates/cudarc).
+//!
+//! `BatchedGemm` (the matrix multiplication of a `TensordotGeneral` step) is a single strided
+//! batched cuBLAS GEMM, with the operands swapped since cuBLAS is column-major. `StridedCopy`
+//! and `SumTrailing` are one-thread-per-output-element kernels compiled with NVRTC when the
+//! executor is created. The operands are copied to the device once per call, each
+//! intermediate buffer is freed once the last kernel that reads it has been queued, and only
+//! the result is copied back.
+//!
+//! The CUDA libraries are loaded when an executor is created rather than linked, so the
+//! feature builds on machines without CUDA.
+//!
+//! The executor is experimental. Its test compares it with `einsum` (including the cuBLAS
+//! GEMMs) but is skipped on machines without a CUDA device, which so far includes every
+//! machine the tests are run on.
+use crate::plan_cache::cached_order;
+use crate::{ArrayLike, BufferId, EinsumError, Kernel, KernelProgram};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use cudarc::cublas::{sys::cublasOperation_t, CudaBlas, Gemm, GemmConfig, StridedBatchedConfig};
+use cudarc::driver::{
+    CudaContext, CudaFunction, CudaModule, CudaSlice, CudaStream, DeviceRepr, LaunchConfig,
+    PushKernelArg, ValidAsZeroBits,
+};
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// The kernels, instantiated for each element type with the name of the type as a suffix.
+const KERNELS: &str = r#"
+#define KERNELS(T)                                                                            \
+extern "C" __global__ void strided_copy_##T(                                                  \
+    const T *input, T *output, const unsigned long long *params) {                            \
+    /* params: the number of elements, the rank, the shape and the strides */                 \
+    unsigned long long len = params[0], rank = params[1];                                     \
+    for (unsigned long long p = blockIdx.x * (unsigned long long)blockDim.x + threadIdx.x;    \
+         p < len; p += (unsigned long long)blockDim.x * gridDim.x) {                          \
+        unsigned long long rest = p, offset = 0;                                              \
+        for (unsigned long long axis = rank; axis > 0; axis--) {                              \
+            unsigned long long axis_len = params[1 + axis];                                   \
+            offset += (rest % axis_len) * params[1 + rank + axis];                            \
+            rest /= axis_len;                                                                 \
+        }                                                                                     \
+        output[p] = input[offset];                                                            \
+    }                                                                                         \
+}                                                                                             \
+extern "C" __global__ void sum_trailing_##T(                                                  \
+    const T *input, T *output, unsigned long long num_kept, unsigned long long num_summed) {  \
+    for (unsigned long long p = blockIdx.x * (unsigned long long)blockDim.x + threadIdx.x;    \
+         p < num_kept; p += (unsigned long long)blockDim.x * gridDim.x) {                     \
+        T total = 0;                                                                          \
+        for (unsigned long long i = 0; i < num_summed; i++) {                                 \
+            total += input[p * num_summed + i];                                               \
+        }                                                                                     \
+        output[p] = total;                                                                    \
+    }                                                                                         \
+}
+
+KERNELS(float)
+KERNELS(double)
+"#;
+
+/// The number of threads of a block.
+const BLOCK_SIZE: u32 = 256;
+
+/// The most blocks a launch has; each thread handles several elements beyond that.
+const MAX_BLOCKS: u32 = 65535;
+
+/// An element type `CudaExecutor` can contract: `f32` or `f64`.
+pub trait CudaElement: LinalgScalar + DeviceRepr + ValidAsZeroBits {
+    /// The name of the type in CUDA C, which suffixes the names of its kernels
+    const CUDA_NAME: &'static str;
+}
+
+impl CudaElement for f32 {
+    const CUDA_NAME: &'static str = "float";
+}
+
+impl CudaElement for f64 {
+    const CUDA_NAME: &'static str = "double";
+}
+
+/// Runs `KernelProgram`s of `f32`s or `f64`s on an NVIDIA GPU, with cuBLAS for the matrix
+/// multiplications.
+///
+/// This is experimental: it hasn't yet been checked against `einsum` on a CUDA device (see the
+/// module documentation).
+///
+/// ```no_run
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape_with_order((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape_with_order((3, 4)).unwrap();
+/// let executor = CudaExecutor::new(0).unwrap();
+/// let product = executor.einsum("ij,jk->ik", &[&a, &b]).unwrap();
+/// assert_eq!(product, a.dot(&b).into_dyn());
+/// ```
+#[derive(Debug)]
+pub struct CudaExecutor {
+    stream: Arc<CudaStream>,
+    blas: CudaBlas,
+    module: Arc<CudaModule>,
+}
+
+impl CudaExecutor {
+    /// Creates an executor on the default stream of the GPU numbered `ordinal`.
+    ///
+    /// Returns `EinsumError::Unsupported` if the CUDA driver, cuBLAS or NVRTC can't be loaded,
+    /// there is no such GPU, or the kernels can't be compiled for it.
+    pub fn new(ordinal: usize) -> Result<Self, EinsumError> {
+        // SAFETY: these only try to load the libraries, which cudarc would otherwise panic
+        // about not finding
+        let loaded = unsafe {
+            cudarc::driver::sys::is_culib_present()
+                && cudarc::cublas::sys::is_culib_present()
+                && cudarc::nvrtc::sys::is_culib_present()
+        };
+        if !loaded {
+            return Err(EinsumError::Unsupported(
+                "the CUDA driver, cuBLAS or NVRTC couldn't be loaded",
+            ));
+        }
+        let context = CudaContext::new(ordinal)
+            .map_err(|_| EinsumError::Unsupported("the CUDA device couldn't be opened"))?;
+        CudaExecutor::from_stream(context.default_stream())
+    }
+
+    /// Creates an executor that queues its work on `stream`.
+    ///
+    /// Returns `EinsumError::Unsupported` if cuBLAS can't be initialized or the kernels can't
+    /// be compiled for the device.
+    pub fn from_stream(stream: Arc<CudaStream>) -> Result<Self, EinsumError> {
+        let unsupported = EinsumError::Unsupported("the CUDA kernels couldn't be compiled");
+        let ptx = cudarc::nvrtc::compile_ptx(KERNELS).map_err(|_| unsupported.clone())?;
+        let module = stream.context().load_module(ptx).map_err(|_| unsupported)?;
+        let blas = CudaBlas::new(stream.clone())
+            .map_err(|_| EinsumError::Unsupported("cuBLAS couldn't be initialized"))?;
+        Ok(CudaExecutor {
+            stream,
+            blas,
+            module,
+        })
+    }
+
+    /// Runs `program` on the GPU and returns the result. The operands are broadcast to the
+    /// shapes of `inputs` first, as by [`KernelProgram::execute`].
+    ///
+    /// Returns `EinsumError::Unsupported` if a CUDA call fails, e.g. because the device runs
+    /// out of memory, or a matrix has more than `i32::MAX` rows or columns. Panics if an
+    /// operand can't be broadcast to the shape of its input.
+    ///
+    /// [`KernelProgram::execute`]: struct.KernelProgram.html#method.execute
+    pub fn execute<A>(
+        &self,
+        program: &KernelProgram,
+        operands: &[&dyn ArrayLike<A>],
+    ) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: CudaElement,
+        CudaBlas: Gemm<A>,
+    {
+        assert_eq!(operands.len(), program.inputs.len());
+        let failed = |_| EinsumError::Unsupported("a CUDA call failed");
+        let mut buffers: Vec<Option<CudaSlice<A>>> = Vec::new();
+        for (operand, shape) in operands.iter().zip(program.inputs.iter()) {
+            let view = operand.into_dyn_view();
+            let elements = view.broadcast(shape.clone()).unwrap();
+            let elements = elements.as_standard_layout();
+            let buffer = if elements.is_empty() {
+                self.stream.alloc_zeros(1)
+            } else {
+                self.stream.clone_htod(elements.as_slice().unwrap())
+            };
+            buffers.push(Some(buffer.map_err(failed)?));
+        }
+
+        // Each buffer is freed once the last kernel that reads it has been queued; the stream
+        // keeps the memory until the kernel has run
+        let last_uses = program.last_uses();
+        for (kernel_num, kernel) in program.kernels.iter().enumerate() {
+            let len = kernel.output_len();
+            let mut output = self.stream.alloc_zeros::<A>(len.max(1)).map_err(failed)?;
+            let buffer = |id: &BufferId| buffers[*id].as_ref().unwrap();
+            match kernel {
+                Kernel::StridedCopy {
+                    input,
+                    shape,
+                    strides,
+                } => {
+                    let mut params = vec![len as u64, shape.len() as u64];
+                    params.extend(shape.iter().chain(strides.iter()).map(|&x| x as u64));
+                    let params = self.stream.clone_htod(&params).map_err(failed)?;
+                    let function = self.function::<A>("strided_copy")?;
+                    let mut launch = self.stream.launch_builder(&function);
+                    launch.arg(buffer(input)).arg(&mut output).arg(&params);
+                    // SAFETY: the kernel reads `params` as its shape and strides, which only
+                    // reach elements of `input`, and writes the first `len` elements of
+                    // `output`
+                    unsafe { launch.launch(launch_config(len)) }.map_err(failed)?;
+                }
+                Kernel::SumTrailing {
+                    input,
+                    shape,
+                    num_axes,
+                } => {
+                    let num_summed = shape[shape.len() - num_axes..].iter().product::<usize>();
+                    let (num_kept, num_summed) = (len as u64, num_summed as u64);
+                    let function = self.function::<A>("sum_trailing")?;
+                    let mut launch = self.stream.launch_builder(&function);
+                    launch
+                        .arg(buffer(input))
+                        .arg(&mut output)
+                        .arg(&num_kept)
+                        .arg(&num_summed);
+                    // SAFETY: `input` has `num_kept * num_summed` elements and `output` has
+                    // `num_kept`
+                    unsafe { launch.launch(launch_config(len)) }.map_err(failed)?;
+                }
+                Kernel::BatchedGemm {
+                    lhs,
+                    rhs,
+                    batch,
+                    m,
+                    n,
+                    k,
+                } => {
+                    // An empty product is already zero
+                    if len > 0 && *k > 0 {
+                        let size = |x: usize| {
+                            i32::try_from(x).map_err(|_| {
+                                EinsumError::Unsupported("a matrix is too large for cuBLAS")
+                            })
+                        };
+                        // The product of row-major matrices is the transpose of the product of
+                        // their transposes, which are the same buffers read as column-major
+                        let config = StridedBatchedConfig {
+                            gemm: GemmConfig {
+                                transa: cublasOperation_t::CUBLAS_OP_N,
+                                transb: cublasOperation_t::CUBLAS_OP_N,
+                                m: size(*n)?,
+                                n: size(*m)?,
+                                k: size(*k)?,
+                                alpha: A::one(),
+                                lda: size(*n)?,
+                                ldb: size(*k)?,
+                                beta: A::zero(),
+                                ldc: size(*n)?,
+                            },
+                            batch_size: size(*batch)?,
+                            stride_a: (k * n) as i64,
+                            stride_b: (m * k) as i64,
+                            stride_c: (m * n) as i64,
+                        };
+                        // SAFETY: `rhs`, `lhs` and `output` hold `batch` matrices of the
+                        // shapes and strides given by the configuration
+                        unsafe {
+                            self.blas.gemm_strided_batched(
+                                config,
+                                buffer(rhs),
+                                buffer(lhs),
+                                &mut output,
+                            )
+                        }
+                        .map_err(|_| EinsumError::Unsupported("a cuBLAS call failed"))?;
+                    }
+                }
+            }
+
+            for input in kernel.inputs() {
+                if last_uses[input] == Some(kernel_num) && input != program.output {
+                    buffers[input] = None;
+                }
+            }
+            buffers.push(Some(output));
+        }
+
+        let output = buffers[program.output].as_ref().unwrap();
+        let mut elements = self.stream.clone_dtoh(output).map_err(failed)?;
+        elements.truncate(program.buffer_len(program.output));
+        Ok(ArrayD::from_shape_vec(program.output_shape.clone(), elements).unwrap())
+    }
+
+    /// Performs the contraction described by `input_string` on the GPU, lowering the order
+    /// `einsum` would use with
+    /// [`ContractionOrder::lower`](enum.ContractionOrder.html#method.lower).
+    ///
+    /// Returns the errors of `einsum`, and `EinsumError::Unsupported` if a step can't be
+    /// lowered or a CUDA call fails.
+    pub fn einsum<A>(
+        &self,
+        input_string: &str,
+        operands: &[&dyn ArrayLike<A>],
+    ) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: CudaElement,
+        CudaBlas: Gemm<A>,
+    {
+        let program = cached_order(input_string, operands)?.lower()?;
+        self.execute(&program, operands)
+    }
+
+    /// Returns the kernel `name` for `A`s.
+    fn function<A: CudaElement>(&self, name: &str) -> Result<CudaFunction, EinsumError> {
+        self.module
+            .load_function(&format!("{}_{}", name, A::CUDA_NAME))
+            .map_err(|_| EinsumError::Unsupported("a CUDA kernel couldn't be loaded"))
+    }
+}
+
+/// Launches enough blocks for one thread per element of a buffer of `len` elements, up to
+/// `MAX_BLOCKS`.
+fn launch_config(len: usize) -> LaunchConfig {
+    let blocks = len
+        .div_ceil(BLOCK_SIZE as usize)
+        .clamp(1, MAX_BLOCKS as usize);
+    LaunchConfig {
+        grid_dim: (blocks as u32, 1, 1),
+        block_dim: (BLOCK_SIZE, 1, 1),
+        shared_mem_bytes: 0,
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `Dual`, a dual number for forward-mode automatic differentiation, and
+//! `einsum_jvp`, which uses it to compute the derivative of a contraction along given
+//! directions.
+//!
+//! The executor only asks of its elements what `ndarray::LinalgScalar` asks: that they be
+//! `Copy + 'static` and have `+`, `-`, `*`, `/`, zero and one. Any type that satisfies that goes
+//! through every contractor unchanged, without BLAS, which is only used for `f32` and `f64` (and
+//! their complex versions). That includes `Dual` and the dual numbers of crates like `num-dual`,
+//! so `einsum` on arrays of dual numbers computes the contraction and its derivative together.
+use crate::{einsum, ArrayLike, EinsumError};
+use alloc::vec::Vec;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, ScalarOperand, Zip};
+use num_traits::{One, Zero};
+
+/// A dual number `value + derivative ε`, where `ε² = 0`. Arithmetic on dual numbers carries
+/// the derivative along by the sum, product and quotient rules, so a function evaluated on
+/// `Dual::variable(x)` returns its value and its derivative at `x`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // d/dt of the trace of (a + t b)^2 at t = 0 is 2 tr(a b)
+/// let a: Array2<f64> = array![[1., 2.], [3., 4.]];
+/// let b: Array2<f64> = array![[0., 1.], [1., 0.]];
+/// let x = ndarray::Zip::from(&a).and(&b).map_collect(|&a, &b| Dual::new(a, b));
+/// let trace = einsum("ij,ji->", &[&x, &x]).unwrap()[[]];
+/// assert_eq!(trace.value, 29.);
+/// assert_eq!(trace.derivative, 2. * einsum("ij,ji->", &[&a, &b]).unwrap()[[]]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dual<T> {
+    pub value: T,
+    pub derivative: T,
+}
+
+impl<T> Dual<T> {
+    pub fn new(value: T, derivative: T) -> Self {
+        Dual { value, derivative }
+    }
+}
+
+impl<T: Zero> Dual<T> {
+    /// A constant, whose derivative is zero.
+    pub fn constant(value: T) -> Self {
+        Dual::new(value, T::zero())
+    }
+}
+
+impl<T: One> Dual<T> {
+    /// The variable with respect to which derivatives are taken, whose derivative is one.
+    pub fn variable(value: T) -> Self {
+        Dual::new(value, T::one())
+    }
+}
+
+impl<T: LinalgScalar> Add for Dual<T> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Dual::new(self.value + rhs.value, self.derivative + rhs.derivative)
+    }
+}
+
+impl<T: LinalgScalar> Sub for Dual<T> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Dual::new(self.value - rhs.value, self.derivative - rhs.derivative)
+    }
+}
+
+impl<T: LinalgScalar> Mul for Dual<T> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Dual::new(
+            self.value * rhs.value,
+            self.derivative * rhs.value + self.value * rhs.derivative,
+        )
+    }
+}
+
+impl<T: LinalgScalar> Div for Dual<T> {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        Dual::new(
+            self.value / rhs.value,
+            (self.derivative * rhs.value - self.value * rhs.derivative) / (rhs.value * rhs.value),
+        )
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Dual<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Dual::new(-self.value, -self.derivative)
+    }
+}
+
+impl<T: LinalgScalar> Zero for Dual<T> {
+    fn zero() -> Self {
+        Dual::new(T::zero(), T::zero())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value.is_zero() && self.derivative.is_zero()
+    }
+}
+
+impl<T: LinalgScalar> One for Dual<T> {
+    fn one() -> Self {
+        Dual::new(T::one(), T::zero())
+    }
+}
+
+impl<T: LinalgScalar> ScalarOperand for Dual<T> {}
+
+/// Returns the result of the contraction specified by `input_string` and `primals`, and its
+/// derivative when each operand `primals[n]` moves in the direction `tangents[n]` (i.e. the
+/// Jacobian-vector product), computed together by contracting arrays of `Dual` numbers.
+///
+/// Returns `EinsumError::TangentShapeMismatch` if a tangent doesn't have the shape of its
+/// operand, `EinsumError::OperandCountMismatch` if there isn't one tangent per operand, and
+/// otherwise the same errors as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = array![[1., 2.], [3., 4.]];
+/// let b: Array2<f64> = array![[5., 6.], [7., 8.]];
+/// // Only b moves, so the derivative of a b along db is a db
+/// let da: Array2<f64> = Array::zeros((2, 2));
+/// let db: Array2<f64> = Array::eye(2);
+/// let (product, derivative) = einsum_jvp("ij,jk->ik", &[&a, &b], &[&da, &db]).unwrap();
+/// assert_eq!(product, a.dot(&b).into_dyn());
+/// assert_eq!(derivative, a.into_dyn());
+/// ```
+pub fn einsum_jvp<A: LinalgScalar>(
+    input_string: &str,
+    primals: &[&dyn ArrayLike<A>],
+    tangents: &[&dyn ArrayLike<A>],
+) -> Result<(ArrayD<A>, ArrayD<A>), EinsumError> {
+    if tangents.len() != primals.len() {
+        return Err(EinsumError::OperandCountMismatch {
+            expected: primals.len(),
+            found: tangents.len(),
+        });
+    }
+
+    let mut duals = Vec::with_capacity(primals.len());
+    for (operand, (primal, tangent)) in primals.iter().zip(tangents).enumerate() {
+        let (primal, tangent) = (primal.into_dyn_view(), tangent.into_dyn_view());
+        if primal.shape() != tangent.shape() {
+            return Err(EinsumError::TangentShapeMismatch {
+                operand,
+                expected: primal.shape().to_vec(),
+                found: tangent.shape().to_vec(),
+            });
+        }
+        duals.push(
+            Zip::from(&primal)
+                .and(&tangent)
+                .map_collect(|&x, &dx| Dual::new(x, dx)),
+        );
+    }
+
+    let dual_operands: Vec<&dyn ArrayLike<Dual<A>>> =
+        duals.iter().map(|x| x as &dyn ArrayLike<Dual<A>>).collect();
+    let result = einsum(input_string, &dual_operands)?;
+    Ok((result.mapv(|x| x.value), result.mapv(|x| x.derivative)))
+}
@@ -0,0 +1,360 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `EinsumError`, the error type returned when a contraction can't be parsed,
+//! validated, or sized.
+use crate::PairMethod;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The ways in which an `einsum`-formatted string, or the operands supplied along with it,
+/// can fail validation.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// assert_eq!(
+///     Contraction::new("ij,j1->ik").unwrap_err(),
+///     EinsumError::Parse { position: 4, message: "invalid character in indices" }
+/// );
+///
+/// let m1: Array2<f64> = Array::zeros((2, 3));
+/// let m2: Array2<f64> = Array::zeros((4, 5));
+/// assert_eq!(
+///     einsum("ij,jk->ik", &[&m1, &m2]).unwrap_err(),
+///     EinsumError::IndexLengthMismatch {
+///         index: "j".to_string(),
+///         first_operand: 0,
+///         first_axis: 1,
+///         expected: 3,
+///         operand: 1,
+///         axis: 0,
+///         found: 4,
+///     }
+/// );
+/// assert_eq!(
+///     einsum("ij,jk->ik", &[&m1, &m2]).unwrap_err().to_string(),
+///     "index 'j' has length 3 on axis 1 of operand 0 but length 4 on axis 0 of operand 1"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EinsumError {
+    /// The string isn't in `einsum` format. `position` is the byte offset in the string
+    /// at which the problem was found.
+    Parse {
+        position: usize,
+        message: &'static str,
+    },
+
+    /// An index in the requested output doesn't appear in any of the operands.
+    OutputIndexNotInInputs { index: char },
+
+    /// An index was used that isn't part of the contraction being subsetted.
+    UnknownIndex { index: char },
+
+    /// The number of operands supplied doesn't match the number in the string.
+    OperandCountMismatch { expected: usize, found: usize },
+
+    /// The number of indices given for an operand doesn't match its number of dimensions.
+    RankMismatch {
+        operand: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    /// Two axes with the index `index` have different lengths, neither of which is 1: axis
+    /// `first_axis` of operand `first_operand`, which set the length of the index to `expected`,
+    /// and axis `axis` of operand `operand`, whose length is `found`. The two axes can belong to
+    /// the same operand. `index` is the index as it was written: a letter, a label of a
+    /// labelled string such as `chan_in`, or an integer label of `einsum_labels`.
+    IndexLengthMismatch {
+        index: String,
+        first_operand: usize,
+        first_axis: usize,
+        expected: usize,
+        operand: usize,
+        axis: usize,
+        found: usize,
+    },
+
+    /// Axis `axis` of operand `operand` has length `found` instead of the length `expected`
+    /// that it was required to have, e.g. by the shapes a `ContractExpression` was planned for.
+    ShapeMismatch {
+        operand: usize,
+        axis: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    /// The array supplied to receive the result doesn't have the shape of the result.
+    OutputShapeMismatch {
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+
+    /// A result with a fixed number of dimensions was requested, but the contraction produces
+    /// a result with `expected` dimensions.
+    OutputRankMismatch { expected: usize, found: usize },
+
+    /// An explicit contraction path has the wrong number of steps: a contraction of `n`
+    /// operands takes `n - 1` pairwise steps.
+    PathLengthMismatch { expected: usize, found: usize },
+
+    /// Step `step` of an explicit contraction path names a position that is out of range
+    /// (`remaining` tensors are left at that step) or contracts a tensor with itself.
+    InvalidPathStep {
+        step: usize,
+        pair: (usize, usize),
+        remaining: usize,
+    },
+
+    /// The number of conjugation flags passed to `einsum_conj` (`found`) isn't the number of
+    /// operands (`expected`).
+    ConjugationFlagCountMismatch { expected: usize, found: usize },
+
+    /// The number of batch flags passed to `einsum_vmap` (`found`) isn't the number of
+    /// operands (`expected`).
+    BatchFlagCountMismatch { expected: usize, found: usize },
+
+    /// A product or sum computed by `einsum_checked` (or by `einsum_integer` with
+    /// `IntegerArithmetic::Checked`) overflowed the element type.
+    IntegerOverflow,
+
+    /// Axis `axis` of operand `operand` of `einsum_block_sparse` is split into blocks
+    /// differently than another axis with the same index.
+    BlockStructureMismatch { operand: usize, axis: usize },
+
+    /// A symmetry passed to `einsum_symmetric` names an operand or axis that doesn't exist,
+    /// the same axis twice, or two axes of different lengths.
+    InvalidSymmetry {
+        operand: usize,
+        axes: (usize, usize),
+    },
+
+    /// A label passed to `ncon` breaks one of its rules: labels are nonzero, a positive label
+    /// appears exactly twice, and a negative label appears exactly once, in the connections
+    /// and in the output order (if one is given).
+    InvalidNconLabel { label: i32, message: &'static str },
+
+    /// The tangent passed to `einsum_jvp` for operand `operand` doesn't have the shape of the
+    /// operand.
+    TangentShapeMismatch {
+        operand: usize,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+
+    /// The index that `einsum_sliced` was asked to slice isn't summed over.
+    InvalidSliceIndex { index: char },
+
+    /// The contractor that `PathOptions` forces for step `step` of a path can't perform that
+    /// step, or the path has no such step.
+    UnsupportedPairMethod { step: usize, method: PairMethod },
+
+    /// The buffer passed to `einsum_from_raw` for operand `operand` has `found` elements, but
+    /// its shape `shape` has a different number of elements.
+    BufferLengthMismatch {
+        operand: usize,
+        shape: Vec<usize>,
+        found: usize,
+    },
+
+    /// A subsystem that `partial_trace` was asked to trace out doesn't exist or is listed
+    /// more than once.
+    InvalidSubsystem {
+        subsystem: usize,
+        message: &'static str,
+    },
+
+    /// A length passed to `rearrange` is for an axis that isn't in its pattern, or the length
+    /// of the axis `axis`, which a group on the left of the pattern splits an axis into, can't
+    /// be determined.
+    InvalidAxisLength { axis: String, message: &'static str },
+
+    /// The string is valid but asks for something that isn't supported.
+    Unsupported(&'static str),
+}
+
+impl EinsumError {
+    /// Replaces the index named by an `IndexLengthMismatch` with the label it was written as,
+    /// if `label` gives one for it.
+    pub(crate) fn with_label(self, label: impl Fn(char) -> Option<String>) -> Self {
+        match self {
+            EinsumError::IndexLengthMismatch {
+                index,
+                first_operand,
+                first_axis,
+                expected,
+                operand,
+                axis,
+                found,
+            } => EinsumError::IndexLengthMismatch {
+                index: index.chars().next().and_then(label).unwrap_or(index),
+                first_operand,
+                first_axis,
+                expected,
+                operand,
+                axis,
+                found,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for EinsumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EinsumError::Parse { position, message } => {
+                write!(f, "invalid einsum string at byte {}: {}", position, message)
+            }
+            EinsumError::OutputIndexNotInInputs { index } => write!(
+                f,
+                "requested output contains index '{}' not found in inputs",
+                index
+            ),
+            EinsumError::UnknownIndex { index } => {
+                write!(f, "index '{}' is not part of the contraction", index)
+            }
+            EinsumError::OperandCountMismatch { expected, found } => write!(
+                f,
+                "contraction has {} operands but {} were supplied",
+                expected, found
+            ),
+            EinsumError::RankMismatch {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "operand {} has {} indices but {} dimensions",
+                operand, expected, found
+            ),
+            EinsumError::IndexLengthMismatch {
+                index,
+                first_operand,
+                first_axis,
+                expected,
+                operand,
+                axis,
+                found,
+            } => write!(
+                f,
+                "index '{}' has length {} on axis {} of operand {} but length {} on axis {} of operand {}",
+                index, expected, first_axis, first_operand, found, axis, operand
+            ),
+            EinsumError::ShapeMismatch {
+                operand,
+                axis,
+                expected,
+                found,
+            } => write!(
+                f,
+                "axis {} of operand {} has length {} instead of {}",
+                axis, operand, found, expected
+            ),
+            EinsumError::OutputShapeMismatch { expected, found } => write!(
+                f,
+                "output array has shape {:?} but the result has shape {:?}",
+                found, expected
+            ),
+            EinsumError::OutputRankMismatch { expected, found } => write!(
+                f,
+                "requested output has {} dimensions but the result has {}",
+                found, expected
+            ),
+            EinsumError::PathLengthMismatch { expected, found } => write!(
+                f,
+                "contraction path should have {} steps but has {}",
+                expected, found
+            ),
+            EinsumError::InvalidPathStep {
+                step,
+                pair,
+                remaining,
+            } => {
+                if pair.0 == pair.1 {
+                    write!(f, "step {} contracts position {} with itself", step, pair.0)
+                } else {
+                    write!(
+                        f,
+                        "step {} contracts positions {:?} but only {} tensors remain",
+                        step, pair, remaining
+                    )
+                }
+            }
+            EinsumError::ConjugationFlagCountMismatch { expected, found } => write!(
+                f,
+                "expected {} conjugation flags (one per operand) but {} were supplied",
+                expected, found
+            ),
+            EinsumError::BatchFlagCountMismatch { expected, found } => write!(
+                f,
+                "expected {} batch flags (one per operand) but {} were supplied",
+                expected, found
+            ),
+            EinsumError::IntegerOverflow => {
+                write!(f, "integer overflow in an intermediate product or sum")
+            }
+            EinsumError::BlockStructureMismatch { operand, axis } => write!(
+                f,
+                "axis {} of operand {} is split into blocks differently than another axis with the same index",
+                axis, operand
+            ),
+            EinsumError::InvalidSymmetry { operand, axes } => write!(
+                f,
+                "operand {} can't be symmetric under swapping axes {} and {}",
+                operand, axes.0, axes.1
+            ),
+            EinsumError::InvalidNconLabel { label, message } => {
+                write!(f, "invalid ncon label {}: {}", label, message)
+            }
+            EinsumError::TangentShapeMismatch {
+                operand,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tangent of operand {} has shape {:?} but the operand has shape {:?}",
+                operand, found, expected
+            ),
+            EinsumError::InvalidSliceIndex { index } => {
+                write!(f, "index '{}' can't be sliced since it isn't summed", index)
+            }
+            EinsumError::UnsupportedPairMethod { step, method } => {
+                write!(f, "step {} of the path can't be performed with {:?}", step, method)
+            }
+            EinsumError::BufferLengthMismatch {
+                operand,
+                shape,
+                found,
+            } => write!(
+                f,
+                "operand {} has shape {:?} but its buffer has {} elements",
+                operand, shape, found
+            ),
+            EinsumError::InvalidSubsystem { subsystem, message } => {
+                write!(f, "can't trace out subsystem {}: {}", subsystem, message)
+            }
+            EinsumError::InvalidAxisLength { axis, message } => {
+                write!(f, "invalid length for axis '{}': {}", axis, message)
+            }
+            EinsumError::Unsupported(message) => write!(f, "unsupported contraction: {}", message),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EinsumError {}
@@ -0,0 +1,183 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `ContractExpression`, a contraction that is planned once from the operand shapes
+//! and then evaluated any number of times.
+//!
+//! `einsum` parses the string, sizes the contraction, chooses an order, and compiles the
+//! `EinsumPath` on every call. When the same contraction is performed repeatedly on operands
+//! of the same shapes, all of that work can be done once up front by
+//! [`contract_expression`](fn.contract_expression.html).
+use crate::{
+    generate_optimized_order, ArrayLike, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// A contraction that has been parsed, validated, sized, and path-optimized for a fixed set of
+/// operand shapes. Created by [`contract_expression`](fn.contract_expression.html) or
+/// [`ContractExpression::new`](struct.ContractExpression.html#method.new).
+#[derive(Debug)]
+pub struct ContractExpression<A> {
+    sized_contraction: SizedContraction,
+    operand_shapes: Vec<Vec<usize>>,
+    path: EinsumPath<A>,
+}
+
+impl<A> ContractExpression<A> {
+    /// Plans the contraction described by `input_string` for operands of shapes `operand_shapes`,
+    /// choosing the contraction order with `optimization_strategy`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let expr: ContractExpression<f64> = ContractExpression::new(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![2, 3], vec![3, 4], vec![4, 5]],
+    ///     OptimizationMethod::Reverse,
+    /// ).unwrap();
+    /// assert_eq!(expr.sized_contraction().as_einsum_string(), "ij,jk,kl->il");
+    /// ```
+    pub fn new(
+        input_string: &str,
+        operand_shapes: &[Vec<usize>],
+        optimization_strategy: OptimizationMethod,
+    ) -> Result<Self, EinsumError> {
+        let sized_contraction =
+            SizedContraction::from_string_and_shapes(input_string, operand_shapes)?;
+        let contraction_order = generate_optimized_order(&sized_contraction, optimization_strategy);
+
+        Ok(ContractExpression {
+            sized_contraction,
+            operand_shapes: operand_shapes.to_vec(),
+            path: EinsumPath::from_path(&contraction_order),
+        })
+    }
+
+    /// The sized contraction the expression was planned for.
+    pub fn sized_contraction(&self) -> &SizedContraction {
+        &self.sized_contraction
+    }
+
+    /// The shapes the operands passed to `eval` must have.
+    pub fn operand_shapes(&self) -> &[Vec<usize>] {
+        &self.operand_shapes
+    }
+
+    /// The compiled execution plan.
+    pub fn path(&self) -> &EinsumPath<A> {
+        &self.path
+    }
+
+    /// Returns an error unless `operands` has the number and shapes of the operands the
+    /// expression was planned for.
+    fn check_operands(&self, operands: &[&dyn ArrayLike<A>]) -> Result<(), EinsumError> {
+        if operands.len() != self.operand_shapes.len() {
+            return Err(EinsumError::OperandCountMismatch {
+                expected: self.operand_shapes.len(),
+                found: operands.len(),
+            });
+        }
+        for (operand, (array, expected_shape)) in
+            operands.iter().zip(&self.operand_shapes).enumerate()
+        {
+            let view = array.into_dyn_view();
+            let shape = view.shape();
+            if shape.len() != expected_shape.len() {
+                return Err(EinsumError::RankMismatch {
+                    operand,
+                    expected: expected_shape.len(),
+                    found: shape.len(),
+                });
+            }
+            for (axis, (&found, &expected)) in shape.iter().zip(expected_shape).enumerate() {
+                if found != expected {
+                    return Err(EinsumError::ShapeMismatch {
+                        operand,
+                        axis,
+                        expected,
+                        found,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Performs the contraction on `operands`, which must have the shapes the expression
+    /// was planned for.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let expr = contract_expression("ij,jk->ik", &[vec![2, 3], vec![3, 4]]).unwrap();
+    /// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// assert_eq!(expr.eval(&[&a, &b]).unwrap(), a.dot(&b).into_dyn());
+    ///
+    /// let c: Array2<f64> = Array::zeros((3, 5));
+    /// assert_eq!(
+    ///     expr.eval(&[&a, &c]).unwrap_err(),
+    ///     EinsumError::ShapeMismatch { operand: 1, axis: 1, expected: 4, found: 5 }
+    /// );
+    /// ```
+    pub fn eval(&self, operands: &[&dyn ArrayLike<A>]) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        Ok(self.path.contract_operands(operands))
+    }
+
+    /// Same as `eval`, but writes the result into `out`. Returns an error if `out` doesn't
+    /// have the shape of the result.
+    pub fn eval_into(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError>
+    where
+        A: LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        self.path.contract_operands_into(operands, out)
+    }
+}
+
+/// Plans the contraction described by `input_string` for operands of shapes `operand_shapes`
+/// and returns a [`ContractExpression`](struct.ContractExpression.html) that can be evaluated
+/// repeatedly without parsing or planning again. The contraction order is chosen with
+/// `OptimizationMethod::Greedy`; use `ContractExpression::new` to choose another method.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let expr = contract_expression("bij,bjk->bik", &[vec![4, 2, 3], vec![4, 3, 5]]).unwrap();
+/// for i in 0..3 {
+///     let a: Array3<f64> = Array::from_elem((4, 2, 3), i as f64);
+///     let b: Array3<f64> = Array::ones((4, 3, 5));
+///     assert_eq!(
+///         expr.eval(&[&a, &b]).unwrap(),
+///         einsum("bij,bjk->bik", &[&a, &b]).unwrap()
+///     );
+/// }
+/// ```
+pub fn contract_expression<A>(
+    input_string: &str,
+    operand_shapes: &[Vec<usize>],
+) -> Result<ContractExpression<A>, EinsumError> {
+    ContractExpression::new(input_string, operand_shapes, OptimizationMethod::Greedy)
+}
@@ -0,0 +1,121 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `faer` feature, contains `FaerBackend`, a [`Backend`](trait.Backend.html) that
+//! performs matrix multiplications with [faer](https://crates.io/crates/faer)'s GEMM.
+//!
+//! The matrices are passed to faer as views built from the pointers, shapes and strides of the
+//! `ArrayView2`s (which are in elements, as faer expects), so nothing is copied. The other
+//! primitives use the default implementations.
+use crate::summation::{mat_mul, summation_mode, SummationMode};
+use crate::Backend;
+use faer::traits::ComplexField;
+use faer::{Accum, MatMut, MatRef, Par};
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// A backend whose matrix multiplications are performed by faer, which on machines with many
+/// cores is often much faster than `matrixmultiply`. Select it for a call with
+/// [`with_backend`](fn.with_backend.html) as an `Arc<dyn Backend<A>>` for the element type `A`
+/// of the contraction; it is implemented for the element types faer supports, such as `f32`,
+/// `f64`, `Complex<f32>` and `Complex<f64>`.
+///
+/// Matrix multiplications outside of [`SummationMode::Standard`](enum.SummationMode.html) use
+/// the default implementation, since faer only sums in the standard way.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use std::sync::Arc;
+///
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape_with_order((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape_with_order((3, 4)).unwrap();
+/// let backend: Arc<dyn Backend<f64>> = Arc::new(FaerBackend::default());
+/// let product = with_backend(backend, || einsum("ij,jk->ik", &[&a, &b])).unwrap();
+/// assert_eq!(product, a.dot(&b).into_dyn());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaerBackend {
+    parallelism: Par,
+}
+
+impl FaerBackend {
+    /// Creates a backend that runs faer's GEMM with `parallelism`, e.g. `Par::Seq` to keep
+    /// each matrix multiplication on the calling thread.
+    pub fn new(parallelism: Par) -> Self {
+        FaerBackend { parallelism }
+    }
+
+    /// Returns the parallelism faer's GEMM is run with.
+    pub fn parallelism(&self) -> Par {
+        self.parallelism
+    }
+}
+
+impl Default for FaerBackend {
+    /// Runs faer's GEMM on every thread of the current rayon thread pool.
+    fn default() -> Self {
+        FaerBackend::new(Par::rayon(0))
+    }
+}
+
+impl<A> Backend<A> for FaerBackend
+where
+    A: LinalgScalar + ComplexField,
+{
+    fn mat_mul(
+        &self,
+        alpha: A,
+        lhs: &ArrayView2<A>,
+        rhs: &ArrayView2<A>,
+        beta: A,
+        out: &mut ArrayViewMut2<A>,
+    ) {
+        let mode = summation_mode();
+        if mode != SummationMode::Standard {
+            mat_mul(mode, alpha, lhs, rhs, beta, out);
+            return;
+        }
+
+        // faer either overwrites the output or adds to it, so any other beta scales it first.
+        // As with `general_mat_mul`, the existing contents are ignored when beta is zero.
+        let accum = if beta.is_zero() {
+            Accum::Replace
+        } else {
+            if !(beta - A::one()).is_zero() {
+                out.map_inplace(|x| *x = beta * *x);
+            }
+            Accum::Add
+        };
+
+        let (m, k) = lhs.dim();
+        let n = rhs.ncols();
+        // SAFETY: each view is built from the pointer, shape and strides of an ndarray view of
+        // the same elements, which it borrows for no longer than that view.
+        let (lhs, rhs, out) = unsafe {
+            (
+                MatRef::from_raw_parts(lhs.as_ptr(), m, k, lhs.strides()[0], lhs.strides()[1]),
+                MatRef::from_raw_parts(rhs.as_ptr(), k, n, rhs.strides()[0], rhs.strides()[1]),
+                MatMut::from_raw_parts_mut(
+                    out.as_mut_ptr(),
+                    m,
+                    n,
+                    out.strides()[0],
+                    out.strides()[1],
+                ),
+            )
+        };
+        faer::linalg::matmul::matmul(out, accum, lhs, rhs, alpha, self.parallelism);
+    }
+}
@@ -0,0 +1,484 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `trace`, `diag`, `outer`, `hadamard`, `batch_matmul` and `bilinear`, which perform
+//! the contractions `ii`, `ii->i`, `i,j->ij`, `ij,ij->ij` (for any number of dimensions),
+//! `...ij,...jk->...ik` and `bi,ijk,bj->bk` without an `einsum`-formatted string,
+//! `partial_trace`, which builds the contraction for a partial trace of a density matrix, and
+//! `kron`, which reshapes the outer product `ij,kl->ikjl` into the Kronecker product.
+//!
+//! Like [`tensordot`](fn.tensordot.html), they build the contractor for their contraction
+//! directly from the shapes of the operands, skipping the parsing, validation and path
+//! optimization performed by `einsum`, so they are cheaper for small operands.
+use crate::collections::HashMap;
+use crate::contractors::{
+    BatchedMatMul, Diagonalization, DiagonalizationAndSummation, HadamardProduct, PairContractor,
+    SingletonContractor,
+};
+use crate::optimizers::generate_path_from_pairs;
+use crate::{
+    einsum_labels, label_index, tensordot, Contraction, EinsumError, EinsumPath, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{Data, LinalgScalar};
+
+/// Returns the `SizedContraction` `ii->output_indices` for a square matrix with `len` rows,
+/// where `output_indices` is either empty or `i`.
+fn square_diagonal(output_indices: &[char], len: usize) -> SizedContraction {
+    let mut output_size = HashMap::new();
+    output_size.insert('i', len);
+    let summation_indices = if output_indices.is_empty() {
+        vec!['i']
+    } else {
+        Vec::new()
+    };
+    SizedContraction {
+        contraction: Contraction {
+            operand_indices: vec![vec!['i', 'i']],
+            output_indices: output_indices.to_vec(),
+            summation_indices,
+        },
+        output_size,
+    }
+}
+
+/// Returns an error unless `m` is square.
+fn check_square<A, S: Data<Elem = A>>(m: &ArrayBase<S, Ix2>) -> Result<(), EinsumError> {
+    let (rows, columns) = m.dim();
+    if rows == columns {
+        Ok(())
+    } else {
+        Err(EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 1,
+            expected: rows,
+            found: columns,
+        })
+    }
+}
+
+/// Returns the sum of the diagonal of the square matrix `m`, the same as `einsum("ii", &[m])`.
+///
+/// Returns `EinsumError::ShapeMismatch` if `m` isn't square.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let m: Array2<f64> = Array::range(0., 9., 1.).into_shape((3, 3)).unwrap();
+/// assert_eq!(trace(&m).unwrap(), 12.);
+/// assert_eq!(trace(&m).unwrap(), einsum_scalar("ii", &[&m]).unwrap());
+/// assert_eq!(
+///     trace(&m.slice(s![.., ..2])).unwrap_err(),
+///     EinsumError::ShapeMismatch { operand: 0, axis: 1, expected: 3, found: 2 }
+/// );
+/// ```
+pub fn trace<A, S>(m: &ArrayBase<S, Ix2>) -> Result<A, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+{
+    check_square(m)?;
+    let contractor = DiagonalizationAndSummation::new(&square_diagonal(&[], m.nrows()));
+    Ok(contractor
+        .contract_singleton(&m.view().into_dyn())
+        .into_dimensionality::<Ix0>()
+        .unwrap()
+        .into_scalar())
+}
+
+/// Returns the diagonal of the square matrix `m`, the same as `einsum("ii->i", &[m])`.
+///
+/// Returns `EinsumError::ShapeMismatch` if `m` isn't square.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let m: Array2<f64> = Array::range(0., 9., 1.).into_shape((3, 3)).unwrap();
+/// assert_eq!(diag(&m).unwrap(), arr1(&[0., 4., 8.]));
+/// assert_eq!(diag(&m.t()).unwrap(), m.diag());
+/// ```
+pub fn diag<A, S>(m: &ArrayBase<S, Ix2>) -> Result<Array1<A>, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+{
+    check_square(m)?;
+    let contractor = Diagonalization::new(&square_diagonal(&['i'], m.nrows()));
+    Ok(contractor
+        .contract_singleton(&m.view().into_dyn())
+        .into_dimensionality::<Ix1>()
+        .unwrap())
+}
+
+/// Returns the outer product of the vectors `u` and `v`, the same as `einsum("i,j->ij", &[u, v])`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let u = arr1(&[1., 2.]);
+/// let v = arr1(&[3., 4., 5.]);
+/// assert_eq!(outer(&u, &v), arr2(&[[3., 4., 5.], [6., 8., 10.]]));
+/// ```
+pub fn outer<A, S, S2>(u: &ArrayBase<S, Ix1>, v: &ArrayBase<S2, Ix1>) -> Array2<A>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    S2: Data<Elem = A>,
+{
+    tensordot(u, v, &[], &[])
+        .into_dimensionality::<Ix2>()
+        .unwrap()
+}
+
+/// Returns the Kronecker product of the `m × n` matrix `a` and the `p × q` matrix `b`, the
+/// `mp × nq` matrix made of the blocks `a[[i, j]] * b`: the result of `einsum("ij,kl->ikjl",
+/// &[a, b])` with the axes `i` and `k` and the axes `j` and `l` merged.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a = arr2(&[[1., 2.], [3., 4.]]);
+/// let b = arr2(&[[0., 1., 2.]]);
+/// assert_eq!(
+///     kron(&a, &b),
+///     arr2(&[[0., 1., 2., 0., 2., 4.], [0., 3., 6., 0., 4., 8.]])
+/// );
+/// ```
+pub fn kron<A, S, S2>(a: &ArrayBase<S, Ix2>, b: &ArrayBase<S2, Ix2>) -> Array2<A>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    S2: Data<Elem = A>,
+{
+    let (m, n) = a.dim();
+    let (p, q) = b.dim();
+    tensordot(a, b, &[], &[])
+        .permuted_axes(IxDyn(&[0, 2, 1, 3]))
+        .into_shape_clone((m * p, n * q))
+        .unwrap()
+}
+
+/// Returns the element-wise product of `a` and `b`, the same as `einsum("ij,ij->ij", &[a, b])`
+/// for matrices (and the corresponding string for any other number of dimensions). As in
+/// `einsum`, an axis of length 1 is broadcast against the same axis of the other operand.
+///
+/// Returns `EinsumError::RankMismatch` if `a` and `b` (of dimension type `IxDyn`) have different
+/// numbers of dimensions, and `EinsumError::ShapeMismatch` if an axis of `b` has a different
+/// length than the same axis of `a` and neither is 1.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a = arr2(&[[1., 2.], [3., 4.]]);
+/// let b = arr2(&[[5., 6.], [7., 8.]]);
+/// assert_eq!(hadamard(&a, &b).unwrap(), arr2(&[[5., 12.], [21., 32.]]));
+/// let column = arr2(&[[1.], [10.]]);
+/// assert_eq!(hadamard(&column, &a).unwrap(), arr2(&[[1., 2.], [30., 40.]]));
+/// ```
+pub fn hadamard<A, S, S2, D>(
+    a: &ArrayBase<S, D>,
+    b: &ArrayBase<S2, D>,
+) -> Result<Array<A, D>, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    S2: Data<Elem = A>,
+    D: Dimension,
+{
+    if a.ndim() != b.ndim() {
+        return Err(EinsumError::RankMismatch {
+            operand: 1,
+            expected: a.ndim(),
+            found: b.ndim(),
+        });
+    }
+    for (axis, (&expected, &found)) in a.shape().iter().zip(b.shape()).enumerate() {
+        if expected != found && expected != 1 && found != 1 {
+            return Err(EinsumError::ShapeMismatch {
+                operand: 1,
+                axis,
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(HadamardProduct::from_nothing()
+        .contract_pair(&a.view().into_dyn(), &b.view().into_dyn())
+        .into_dimensionality::<D>()
+        .unwrap())
+}
+
+/// Multiplies the stacks of matrices `a` and `b`, of shapes `(..., m, k)` and `(..., k, n)`,
+/// into a stack of shape `(..., m, n)`: the same as `einsum("...ij,...jk->...ik", &[a, b])`.
+///
+/// As in numpy's `matmul`, the leading (batch) axes are broadcast against each other: they are
+/// aligned from the right, an operand with fewer of them is treated as having extra axes of
+/// length 1 at the front, and an axis of length 1 is repeated to match the other operand.
+///
+/// Returns `EinsumError::RankMismatch` if an operand has fewer than two dimensions, and
+/// `EinsumError::ShapeMismatch` if the `k` axis of `b`, or one of its batch axes, doesn't match
+/// `a`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array4<f64> = Array::range(0., 48., 1.).into_shape((2, 3, 2, 4)).unwrap();
+/// let b: Array3<f64> = Array::range(0., 60., 1.).into_shape((3, 4, 5)).unwrap();
+/// let product = batch_matmul(&a, &b).unwrap();
+/// assert_eq!(product.shape(), &[2, 3, 2, 5]);
+/// let lhs: ArrayView2<f64> = a.slice(s![1, 2, .., ..]);
+/// let rhs: ArrayView2<f64> = b.slice(s![2, .., ..]);
+/// assert_eq!(product.slice(s![1, 2, .., ..]), lhs.dot(&rhs));
+/// assert_eq!(product, einsum("...ij,...jk->...ik", &[&a, &b]).unwrap());
+/// ```
+pub fn batch_matmul<A, S, S2, D, E>(
+    a: &ArrayBase<S, D>,
+    b: &ArrayBase<S2, E>,
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    S2: Data<Elem = A>,
+    D: Dimension,
+    E: Dimension,
+{
+    for (operand, ndim) in [a.ndim(), b.ndim()].iter().enumerate() {
+        if *ndim < 2 {
+            return Err(EinsumError::RankMismatch {
+                operand,
+                expected: 2,
+                found: *ndim,
+            });
+        }
+    }
+    let (a_batch, a_matrix) = a.shape().split_at(a.ndim() - 2);
+    let (b_batch, b_matrix) = b.shape().split_at(b.ndim() - 2);
+    let (m, k) = (a_matrix[0], a_matrix[1]);
+    let n = b_matrix[1];
+    if b_matrix[0] != k {
+        return Err(EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: b.ndim() - 2,
+            expected: k,
+            found: b_matrix[0],
+        });
+    }
+
+    // The batch axes, aligned from the right
+    let num_batch_axes = a_batch.len().max(b_batch.len());
+    let batch_len = |batch: &[usize], axis: usize| {
+        (axis + batch.len())
+            .checked_sub(num_batch_axes)
+            .map(|axis| batch[axis])
+    };
+    let mut shape = Vec::with_capacity(num_batch_axes + 2);
+    for axis in 0..num_batch_axes {
+        shape.push(match (batch_len(a_batch, axis), batch_len(b_batch, axis)) {
+            (Some(expected), Some(found)) if expected != found && expected != 1 && found != 1 => {
+                return Err(EinsumError::ShapeMismatch {
+                    operand: 1,
+                    axis: axis + b_batch.len() - num_batch_axes,
+                    expected,
+                    found,
+                })
+            }
+            (Some(a_len), Some(b_len)) => a_len.max(b_len),
+            (len, None) | (None, len) => len.unwrap(),
+        });
+    }
+
+    // The batch axes are labeled a, b, c, ..., followed by the matrix axes
+    let labels: Vec<char> = (0..num_batch_axes + 3)
+        .map(|label| label_index(label).unwrap())
+        .collect();
+    let (batch_indices, matrix_indices) = labels.split_at(num_batch_axes);
+    let (i, j, l) = (matrix_indices[0], matrix_indices[1], matrix_indices[2]);
+    let with_batch = |x: char, y: char| [batch_indices, &[x, y]].concat();
+    let mut output_size: HashMap<char, usize> = batch_indices
+        .iter()
+        .cloned()
+        .zip(shape.iter().cloned())
+        .collect();
+    output_size.insert(i, m);
+    output_size.insert(j, k);
+    output_size.insert(l, n);
+    let sc = SizedContraction {
+        contraction: Contraction {
+            operand_indices: vec![with_batch(i, j), with_batch(j, l)],
+            output_indices: with_batch(i, l),
+            summation_indices: vec![j],
+        },
+        output_size,
+    };
+
+    let a_shape = [&shape[..], &[m, k]].concat();
+    let b_shape = [&shape[..], &[k, n]].concat();
+    Ok(BatchedMatMul::new(&sc).contract_pair(
+        &a.broadcast(IxDyn(&a_shape)).unwrap(),
+        &b.broadcast(IxDyn(&b_shape)).unwrap(),
+    ))
+}
+
+/// Applies the bilinear map `a` to each pair of rows of `x` and `y`: `result[[b, k]]` is the
+/// sum over `i` and `j` of `x[[b, i]] * a[[i, j, k]] * y[[b, j]]`, the same as
+/// `einsum("bi,ijk,bj->bk", &[x, a, y])`. This is PyTorch's `torch.nn.functional.bilinear`
+/// without the bias, except that the output axis of `a` is the last one instead of the first.
+///
+/// `a` is contracted with `x` first if that produces the smaller intermediate result (of shape
+/// `(b, j, k)`), and with `y` first otherwise, so each step is a single matrix multiplication
+/// followed by a batched matrix-vector product.
+///
+/// Returns an error if the shapes don't fit together, as `einsum` would.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let x: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let a: Array3<f64> = Array::range(0., 60., 1.).into_shape((3, 4, 5)).unwrap();
+/// let y: Array2<f64> = Array::range(0., 8., 1.).into_shape((2, 4)).unwrap();
+/// let result = bilinear(&x, &a, &y).unwrap();
+/// assert_eq!(result.clone().into_dyn(), einsum("bi,ijk,bj->bk", &[&x, &a, &y]).unwrap());
+/// let first = a.index_axis(Axis(2), 0);
+/// assert_eq!(result[[1, 0]], x.row(1).dot(&first.dot(&y.row(1))));
+/// ```
+pub fn bilinear<A, S, S2, S3>(
+    x: &ArrayBase<S, Ix2>,
+    a: &ArrayBase<S2, Ix3>,
+    y: &ArrayBase<S3, Ix2>,
+) -> Result<Array2<A>, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    S2: Data<Elem = A>,
+    S3: Data<Elem = A>,
+{
+    let contraction = Contraction::from_char_indices(
+        &[vec!['b', 'i'], vec!['i', 'j', 'k'], vec!['b', 'j']],
+        &['b', 'k'],
+    )?;
+    let sc = SizedContraction::from_contraction_and_operands(&contraction, &[x, a, y])?;
+    let path = if sc.output_size[&'j'] <= sc.output_size[&'i'] {
+        [(0, 1), (0, 1)]
+    } else {
+        [(1, 2), (0, 1)]
+    };
+    let contraction_order = generate_path_from_pairs(&sc, &path);
+    Ok(EinsumPath::from_path(&contraction_order)
+        .contract_operands(&[x, a, y])
+        .into_dimensionality::<Ix2>()
+        .unwrap())
+}
+
+/// Traces out the subsystems `traced_subsystems` of the density matrix `rho` of a system made
+/// up of subsystems of dimensions `subsystem_dims`, so that `rho` is a square matrix whose
+/// dimension is the product of `subsystem_dims`, with the first subsystem the most significant
+/// (as in the Kronecker product `A ⊗ B ⊗ ...`). The result is the density matrix of the
+/// remaining subsystems, in the same order.
+///
+/// `rho` is viewed as a tensor with a row axis and a column axis for each subsystem, and the
+/// row and column axes of each traced subsystem are given the same index: for three subsystems
+/// with the second traced out, this is `einsum("abcdbf->acdf", ...)`, reshaped into a matrix.
+///
+/// Returns `EinsumError::ShapeMismatch` if `rho` isn't a square matrix of the right dimension,
+/// and `EinsumError::InvalidSubsystem` if a traced subsystem doesn't exist or is listed twice.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // The product state |0><0| ⊗ rho_b of a qubit and a qutrit
+/// let zero = arr2(&[[1., 0.], [0., 0.]]);
+/// let rho_b = arr2(&[[0.5, 0.1, 0.], [0.1, 0.3, 0.], [0., 0., 0.2]]);
+/// let rho = einsum("ij,kl->ikjl", &[&zero, &rho_b])
+///     .unwrap()
+///     .into_shape_clone((6, 6))
+///     .unwrap();
+/// assert_eq!(partial_trace(&rho, &[2, 3], &[0]).unwrap(), rho_b);
+/// assert_eq!(partial_trace(&rho, &[2, 3], &[1]).unwrap(), zero);
+/// assert_eq!(partial_trace(&rho, &[2, 3], &[0, 1]).unwrap(), arr2(&[[1.]]));
+/// assert_eq!(
+///     partial_trace(&rho, &[2, 3], &[2]).unwrap_err(),
+///     EinsumError::InvalidSubsystem { subsystem: 2, message: "there is no such subsystem" }
+/// );
+/// ```
+pub fn partial_trace<A, S>(
+    rho: &ArrayBase<S, Ix2>,
+    subsystem_dims: &[usize],
+    traced_subsystems: &[usize],
+) -> Result<Array2<A>, EinsumError>
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+{
+    let num_subsystems = subsystem_dims.len();
+    let mut traced = vec![false; num_subsystems];
+    for &subsystem in traced_subsystems.iter() {
+        if subsystem >= num_subsystems {
+            return Err(EinsumError::InvalidSubsystem {
+                subsystem,
+                message: "there is no such subsystem",
+            });
+        }
+        if traced[subsystem] {
+            return Err(EinsumError::InvalidSubsystem {
+                subsystem,
+                message: "it is listed more than once",
+            });
+        }
+        traced[subsystem] = true;
+    }
+    let dim: usize = subsystem_dims.iter().product();
+    for (axis, &found) in rho.shape().iter().enumerate() {
+        if found != dim {
+            return Err(EinsumError::ShapeMismatch {
+                operand: 0,
+                axis,
+                expected: dim,
+                found,
+            });
+        }
+    }
+
+    // Row axis s has the label s and column axis s the label num_subsystems + s, unless
+    // subsystem s is traced out, in which case it has the label s too.
+    let row_labels: Vec<usize> = (0..num_subsystems).collect();
+    let column_labels: Vec<usize> = (0..num_subsystems)
+        .map(|s| if traced[s] { s } else { num_subsystems + s })
+        .collect();
+    let kept = |labels: &[usize]| -> Vec<usize> {
+        labels
+            .iter()
+            .zip(traced.iter())
+            .filter(|(_, &traced)| !traced)
+            .map(|(&label, _)| label)
+            .collect()
+    };
+    let output_labels = [kept(&row_labels), kept(&column_labels)].concat();
+    let kept_dim: usize = subsystem_dims
+        .iter()
+        .zip(traced.iter())
+        .filter(|(_, &traced)| !traced)
+        .map(|(&dim, _)| dim)
+        .product();
+
+    let tensor = rho
+        .to_shape(IxDyn(&[subsystem_dims, subsystem_dims].concat()))
+        .unwrap();
+    let result = einsum_labels(
+        &[&tensor],
+        &[[row_labels, column_labels].concat()],
+        &output_labels,
+    )?;
+    Ok(result.into_shape_clone((kept_dim, kept_dim)).unwrap())
+}
@@ -0,0 +1,203 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_integer` and `einsum_checked`, which contract integer operands with a
+//! chosen behavior on overflow, instead of wrapping around (in release builds) or panicking
+//! (in debug builds) like `einsum`.
+//!
+//! The contraction is performed over the `Wrapping`, `Saturating`, or `Checked` semiring. The
+//! elements of `Checked` are `Option`s: an overflow produces `None`, which absorbs every later
+//! sum or product it's part of.
+use crate::{einsum_semiring, ArrayLike, EinsumError, Semiring};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ndarray::prelude::*;
+use num_traits::{
+    CheckedAdd, CheckedMul, SaturatingAdd, SaturatingMul, WrappingAdd, WrappingMul, Zero,
+};
+
+/// How `einsum_integer` handles a sum or product that overflows the element type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntegerArithmetic {
+    /// Wrap around modulo 2^bits, as release builds do. The result is the exact result
+    /// modulo 2^bits, regardless of the contraction order.
+    Wrapping,
+
+    /// Clamp to the minimum or maximum value of the type. Saturating addition isn't
+    /// associative when the terms have mixed signs, so once some sum has saturated, the
+    /// result can depend on the order in which the terms are added.
+    Saturating,
+
+    /// Return `EinsumError::IntegerOverflow`.
+    Checked,
+}
+
+/// Integer arithmetic that wraps around on overflow.
+#[derive(Debug, Clone, Copy)]
+pub struct Wrapping<T>(PhantomData<T>);
+
+impl<T> Semiring for Wrapping<T>
+where
+    T: WrappingAdd + WrappingMul + Zero + Copy,
+{
+    type Element = T;
+
+    fn zero() -> T {
+        T::zero()
+    }
+
+    fn add(lhs: &T, rhs: &T) -> T {
+        lhs.wrapping_add(rhs)
+    }
+
+    fn mul(lhs: &T, rhs: &T) -> T {
+        lhs.wrapping_mul(rhs)
+    }
+}
+
+/// Integer arithmetic that saturates at the bounds of the type on overflow.
+#[derive(Debug, Clone, Copy)]
+pub struct Saturating<T>(PhantomData<T>);
+
+impl<T> Semiring for Saturating<T>
+where
+    T: SaturatingAdd + SaturatingMul + Zero + Copy,
+{
+    type Element = T;
+
+    fn zero() -> T {
+        T::zero()
+    }
+
+    fn add(lhs: &T, rhs: &T) -> T {
+        lhs.saturating_add(rhs)
+    }
+
+    fn mul(lhs: &T, rhs: &T) -> T {
+        lhs.saturating_mul(rhs)
+    }
+}
+
+/// Integer arithmetic that detects overflow: `Some(x)` is the integer `x` and `None` is the
+/// result of any sum or product that overflowed, or that had an operand that overflowed.
+#[derive(Debug, Clone, Copy)]
+pub struct Checked<T>(PhantomData<T>);
+
+impl<T> Semiring for Checked<T>
+where
+    T: CheckedAdd + CheckedMul + Zero + Copy,
+{
+    type Element = Option<T>;
+
+    fn zero() -> Option<T> {
+        Some(T::zero())
+    }
+
+    fn add(lhs: &Option<T>, rhs: &Option<T>) -> Option<T> {
+        lhs.zip(*rhs).and_then(|(lhs, rhs)| lhs.checked_add(&rhs))
+    }
+
+    fn mul(lhs: &Option<T>, rhs: &Option<T>) -> Option<T> {
+        lhs.zip(*rhs).and_then(|(lhs, rhs)| lhs.checked_mul(&rhs))
+    }
+
+    fn sum(terms: &[Option<T>]) -> Option<T> {
+        terms
+            .iter()
+            .try_fold(T::zero(), |sum, term| sum.checked_add(&(*term)?))
+    }
+}
+
+/// Performs the contraction described by `input_string` on integer operands, returning
+/// `EinsumError::IntegerOverflow` if any product or sum computed along the way overflows the
+/// element type. Because the intermediate results depend on the contraction order, a result
+/// that fits could in principle be reported as an overflow of some intermediate result.
+///
+/// Returns an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array1<i32> = Array::from_elem(3, 30_000);
+/// // 3 * 30_000 * 30_000 doesn't fit in an i32
+/// assert_eq!(
+///     einsum_checked("i,i->", &[&a, &a]).unwrap_err(),
+///     EinsumError::IntegerOverflow
+/// );
+/// let b: Array1<i64> = a.mapv(i64::from);
+/// assert_eq!(
+///     einsum_checked("i,i->", &[&b, &b]).unwrap(),
+///     arr0(2_700_000_000).into_dyn()
+/// );
+/// ```
+pub fn einsum_checked<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: CheckedAdd + CheckedMul + Zero + Copy,
+{
+    let checked_operands: Vec<ArrayD<Option<A>>> = operands
+        .iter()
+        .map(|operand| operand.into_dyn_view().mapv(Some))
+        .collect();
+    let checked_operand_refs: Vec<&dyn ArrayLike<Option<A>>> = checked_operands
+        .iter()
+        .map(|operand| operand as &dyn ArrayLike<Option<A>>)
+        .collect();
+    let result = einsum_semiring::<Checked<A>>(input_string, &checked_operand_refs)?;
+
+    if result.iter().any(Option::is_none) {
+        return Err(EinsumError::IntegerOverflow);
+    }
+    Ok(result.mapv(Option::unwrap))
+}
+
+/// Performs the contraction described by `input_string` on integer operands, with sums and
+/// products that overflow the element type handled as specified by `arithmetic`.
+///
+/// Returns `EinsumError::IntegerOverflow` if `arithmetic` is `IntegerArithmetic::Checked` and
+/// something overflows, or an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let samples: Array1<i16> = array![20_000, 20_000];
+/// let gains: Array1<i16> = array![1, 1];
+/// let mix = |arithmetic| einsum_integer("i,i->", &[&samples, &gains], arithmetic);
+/// assert_eq!(mix(IntegerArithmetic::Saturating).unwrap(), arr0(i16::MAX).into_dyn());
+/// assert_eq!(mix(IntegerArithmetic::Wrapping).unwrap(), arr0(40_000u16 as i16).into_dyn());
+/// assert_eq!(mix(IntegerArithmetic::Checked).unwrap_err(), EinsumError::IntegerOverflow);
+/// ```
+pub fn einsum_integer<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    arithmetic: IntegerArithmetic,
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: CheckedAdd
+        + CheckedMul
+        + SaturatingAdd
+        + SaturatingMul
+        + WrappingAdd
+        + WrappingMul
+        + Zero
+        + Copy,
+{
+    match arithmetic {
+        IntegerArithmetic::Wrapping => einsum_semiring::<Wrapping<A>>(input_string, operands),
+        IntegerArithmetic::Saturating => einsum_semiring::<Saturating<A>>(input_string, operands),
+        IntegerArithmetic::Checked => einsum_checked(input_string, operands),
+    }
+}
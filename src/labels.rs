@@ -0,0 +1,227 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `Contraction::from_labels` and `einsum_labels`, which specify a contraction by
+//! integer labels instead of an `einsum`-formatted string, so that the number of distinct
+//! indices isn't limited by the alphabet, and `Contraction::from_indices`, which takes the
+//! indices of the operands and the output as slices of `char`s or integer labels, so that code
+//! that builds contractions doesn't have to format a string for them to be parsed.
+//!
+//! The rest of the library identifies indices by `char`, so each label is translated into the
+//! `char` given by [`label_index`](fn.label_index.html): the first 26 labels are the letters
+//! `a` to `z`, so that small contractions read the same as their strings, and the others are
+//! characters of the Unicode private use planes, which no string can contain. There are more
+//! than 130,000 of these.
+use crate::passes::contract_simplified;
+use crate::{
+    generate_optimized_order, ArrayLike, Contraction, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// The first character of the Unicode private use planes (15 and 16)
+const FIRST_PRIVATE_USE: u32 = 0xF0000;
+
+/// The last character of the Unicode private use planes
+const LAST_PRIVATE_USE: u32 = 0x10FFFF;
+
+/// The number of distinct integer labels
+const NUM_LABELS: usize = 26 + (LAST_PRIVATE_USE - FIRST_PRIVATE_USE + 1) as usize;
+
+/// Returns the index that the integer label `label` stands for, or `None` if `label` is too
+/// large to have one. Labels 0 to 25 are the letters `a` to `z`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// assert_eq!(label_index(0), Some('a'));
+/// assert_eq!(label_index(25), Some('z'));
+/// assert!(label_index(1000).is_some());
+/// assert_eq!(label_index(1_000_000), None);
+/// ```
+pub fn label_index(label: usize) -> Option<char> {
+    if label < 26 {
+        Some((b'a' + label as u8) as char)
+    } else if label < NUM_LABELS {
+        core::char::from_u32(FIRST_PRIVATE_USE + (label - 26) as u32)
+    } else {
+        None
+    }
+}
+
+/// Returns the integer label that stands for `index`, the inverse of `label_index`.
+fn index_label(index: char) -> Option<usize> {
+    match index {
+        'a'..='z' => Some((index as u8 - b'a') as usize),
+        _ if (index as u32) >= FIRST_PRIVATE_USE => {
+            Some((index as u32 - FIRST_PRIVATE_USE) as usize + 26)
+        }
+        _ => None,
+    }
+}
+
+/// The label of an axis in [`Contraction::from_indices`](struct.Contraction.html#method.from_indices):
+/// either the index itself, as a `char`, or an integer label, which stands for the index given
+/// by [`label_index`](fn.label_index.html).
+pub trait Label: Copy {
+    /// The index that this label stands for.
+    fn index(self) -> Result<char, EinsumError>;
+}
+
+impl Label for char {
+    fn index(self) -> Result<char, EinsumError> {
+        Ok(self)
+    }
+}
+
+impl Label for usize {
+    fn index(self) -> Result<char, EinsumError> {
+        label_index(self).ok_or(EinsumError::Unsupported(
+            "integer labels must be less than 131098",
+        ))
+    }
+}
+
+/// Translates `labels` into indices.
+fn label_indices<L: Label>(labels: &[L]) -> Result<Vec<char>, EinsumError> {
+    labels.iter().map(|label| label.index()).collect()
+}
+
+impl Contraction {
+    /// Creates a `Contraction` in which axis `m` of operand `n` has the index (or integer
+    /// label) `operand_indices[n][m]` and the output has the indices `output_indices`, without
+    /// going through an `einsum`-formatted string: `from_indices(&[&['i', 'j'], &['j', 'k']],
+    /// &['i', 'k'])` is the same as `Contraction::new("ij,jk->ik")`. There is no implicit
+    /// output, and any `char` can be used as an index, not just the letters that a string can
+    /// contain.
+    ///
+    /// Returns `EinsumError::OutputIndexNotInInputs` if an output index isn't the index of any
+    /// axis, and `EinsumError::Unsupported` if an integer label is too large to have an index.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let from_chars = Contraction::from_indices(&[&['i', 'j'], &['j', 'k']], &['i', 'k']).unwrap();
+    /// let from_string = Contraction::new("ij,jk->ik").unwrap();
+    /// assert_eq!(from_chars.operand_indices, from_string.operand_indices);
+    /// assert_eq!(from_chars.output_indices, from_string.output_indices);
+    /// assert_eq!(from_chars.summation_indices, from_string.summation_indices);
+    ///
+    /// let from_labels = Contraction::from_indices(&[&[8, 9], &[9, 10]], &[8, 10]).unwrap();
+    /// assert_eq!(from_labels.summation_indices, vec![label_index(9).unwrap()]);
+    ///
+    /// assert_eq!(
+    ///     Contraction::from_indices(&[&['i', 'j']], &['k']).unwrap_err(),
+    ///     EinsumError::OutputIndexNotInInputs { index: 'k' }
+    /// );
+    /// ```
+    pub fn from_indices<L: Label>(
+        operand_indices: &[&[L]],
+        output_indices: &[L],
+    ) -> Result<Self, EinsumError> {
+        let operand_indices = operand_indices
+            .iter()
+            .map(|indices| label_indices(indices))
+            .collect::<Result<Vec<Vec<char>>, EinsumError>>()?;
+        let output_indices = label_indices(output_indices)?;
+        Contraction::from_char_indices(&operand_indices, &output_indices)
+    }
+
+    /// Creates a `Contraction` in which axis `m` of operand `n` has the integer label
+    /// `operand_labels[n][m]` and the output has the labels `output_labels`, with the same
+    /// meaning as the letters of an `einsum`-formatted string: `operand_labels = [[0, 1], [1,
+    /// 2]]` and `output_labels = [0, 2]` is `ij,jk->ik`. There is no implicit output.
+    ///
+    /// Each label becomes the index given by [`label_index`](fn.label_index.html), which is the
+    /// index named in the errors. Returns `EinsumError::Unsupported` if a label is too large to
+    /// have an index, and `EinsumError::OutputIndexNotInInputs` if an output label isn't the
+    /// label of any axis.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let contraction = Contraction::from_labels(&[vec![0, 1], vec![1, 2]], &[0, 2]).unwrap();
+    /// assert_eq!(contraction.operand_indices, vec![vec!['a', 'b'], vec!['b', 'c']]);
+    /// assert_eq!(contraction.summation_indices, vec!['b']);
+    ///
+    /// // A chain of 100 matrices, which needs 101 distinct indices
+    /// let operand_labels: Vec<Vec<usize>> = (0..100).map(|n| vec![n, n + 1]).collect();
+    /// let contraction = Contraction::from_labels(&operand_labels, &[0, 100]).unwrap();
+    /// assert_eq!(contraction.summation_indices.len(), 99);
+    /// ```
+    pub fn from_labels(
+        operand_labels: &[Vec<usize>],
+        output_labels: &[usize],
+    ) -> Result<Self, EinsumError> {
+        let operand_labels: Vec<&[usize]> = operand_labels.iter().map(|x| &x[..]).collect();
+        Contraction::from_indices(&operand_labels, output_labels)
+    }
+}
+
+/// Performs the contraction in which axis `m` of `operands[n]` has the integer label
+/// `operand_labels[n][m]` and the output has the labels `output_labels`, as `einsum` would
+/// perform the corresponding string. See
+/// [`Contraction::from_labels`](struct.Contraction.html#method.from_labels) for the labels and
+/// the errors; otherwise returns the same errors as `einsum`, except that an
+/// `EinsumError::IndexLengthMismatch` names the integer label rather than its index.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let ab = einsum_labels(&[&a, &b], &[vec![0, 1], vec![1, 2]], &[0, 2]).unwrap();
+/// assert_eq!(ab, a.dot(&b).into_dyn());
+///
+/// // The trace of the product of 60 matrices, with 60 distinct indices
+/// let identity: Array2<f64> = Array::eye(3);
+/// let operands: Vec<&dyn ArrayLike<f64>> = (0..60).map(|_| &identity as &dyn ArrayLike<f64>).collect();
+/// let operand_labels: Vec<Vec<usize>> = (0..60).map(|n| vec![n, (n + 1) % 60]).collect();
+/// let trace = einsum_labels(&operands, &operand_labels, &[]).unwrap();
+/// assert_eq!(trace, arr0(3.).into_dyn());
+///
+/// let c: Array2<f64> = Array::zeros((4, 5));
+/// assert_eq!(
+///     einsum_labels(&[&a, &c], &[vec![0, 40], vec![40, 2]], &[0, 2])
+///         .unwrap_err()
+///         .to_string(),
+///     "index '40' has length 3 on axis 1 of operand 0 but length 4 on axis 0 of operand 1"
+/// );
+/// ```
+pub fn einsum_labels<A: LinalgScalar>(
+    operands: &[&dyn ArrayLike<A>],
+    operand_labels: &[Vec<usize>],
+    output_labels: &[usize],
+) -> Result<ArrayD<A>, EinsumError> {
+    einsum_named_labels(operands, operand_labels, output_labels, |label| {
+        label.to_string()
+    })
+}
+
+/// Same as `einsum_labels`, but errors name the integer label `n` as `name(n)`.
+pub(crate) fn einsum_named_labels<A: LinalgScalar>(
+    operands: &[&dyn ArrayLike<A>],
+    operand_labels: &[Vec<usize>],
+    output_labels: &[usize],
+    name: impl Fn(usize) -> String,
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction = Contraction::from_labels(operand_labels, output_labels)?;
+    let sc = SizedContraction::from_contraction_and_operands(&contraction, operands)
+        .map_err(|e| e.with_label(|c| index_label(c).map(&name)))?;
+    let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    if let Some(result) = contract_simplified(&contraction_order, operands) {
+        return Ok(result);
+    }
+    Ok(EinsumPath::from_path(&contraction_order).contract_operands(operands))
+}
@@ -0,0 +1,159 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_in_order`, which returns the result in a requested memory order, and
+//! `with_standard_layout_output`, which guarantees that results are in standard layout.
+//!
+//! The result of `einsum` is in whatever layout its last step produced, which is usually but
+//! not always row-major. Here the result is allocated in the requested order first and the
+//! contraction is written into it, so that e.g. the final matrix multiplication of a
+//! `TensordotGeneral` step writes a column-major result directly instead of the result being
+//! transposed into a copy afterwards.
+//!
+//! A result that isn't in standard layout is almost always a permuted copy of a standard-layout
+//! array. Inside [`with_standard_layout_output`](fn.with_standard_layout_output.html), the
+//! permutations performed by the default `Backend` copy their elements into standard layout
+//! instead of keeping the order they had in memory, so the final permutation of a step does the
+//! compaction as part of the copy it makes anyway. Any result still not in standard layout at
+//! the end of the last step is copied into standard layout then.
+use crate::plan_cache::cached_order;
+use crate::{ArrayLike, EinsumError, EinsumPath};
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Order};
+
+#[cfg(feature = "std")]
+thread_local! {
+    static STANDARD_LAYOUT_OUTPUT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous setting when dropped, so that it is restored even if `op` panics.
+#[cfg(feature = "std")]
+struct LayoutGuard(bool);
+
+#[cfg(feature = "std")]
+impl Drop for LayoutGuard {
+    fn drop(&mut self) {
+        STANDARD_LAYOUT_OUTPUT.with(|standard| standard.set(self.0));
+    }
+}
+
+/// Runs `op` with every array returned by the contractions it performs on the current thread
+/// (by `einsum`, `EinsumPath::contract_operands` and the like) in standard layout, and returns
+/// its result. Calls can be nested.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+/// let permuted = einsum("ijk->kji", &[&a]).unwrap();
+/// assert!(!permuted.is_standard_layout());
+///
+/// let standard = with_standard_layout_output(|| einsum("ijk->kji", &[&a])).unwrap();
+/// assert!(standard.is_standard_layout());
+/// assert_eq!(standard, permuted);
+/// ```
+#[cfg(feature = "std")]
+pub fn with_standard_layout_output<R, F>(op: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    with_layout_setting(true, op)
+}
+
+/// Runs `op` with the setting of `with_standard_layout_output` set to `standard`.
+#[cfg(feature = "std")]
+pub(crate) fn with_layout_setting<R>(standard: bool, op: impl FnOnce() -> R) -> R {
+    let _guard = LayoutGuard(STANDARD_LAYOUT_OUTPUT.with(|setting| setting.replace(standard)));
+    op()
+}
+
+/// Whether this thread is inside [`with_standard_layout_output`](fn.with_standard_layout_output.html).
+#[cfg(feature = "std")]
+pub fn standard_layout_output() -> bool {
+    STANDARD_LAYOUT_OUTPUT.with(|standard| standard.get())
+}
+
+/// Returns `false`, since `with_standard_layout_output` is only available with `std`.
+#[cfg(not(feature = "std"))]
+pub fn standard_layout_output() -> bool {
+    false
+}
+
+/// Returns `result`, copied into standard layout if it isn't already and
+/// `with_standard_layout_output` requires it.
+pub(crate) fn into_requested_layout<A: Clone>(result: ArrayD<A>) -> ArrayD<A> {
+    if standard_layout_output() && !result.is_standard_layout() {
+        result.as_standard_layout().into_owned()
+    } else {
+        result
+    }
+}
+
+impl<A> EinsumPath<A> {
+    /// Same as `contract_operands`, except that the result is in row-major (C) or column-major
+    /// (Fortran) order, as requested by `order`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// use ndarray::Order;
+    ///
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    /// let result = path.contract_operands_in_order(&[&m1, &m2], Order::ColumnMajor);
+    /// assert_eq!(result, m1.dot(&m2).into_dyn());
+    /// assert_eq!(result.strides(), &[1, 2]);
+    /// ```
+    pub fn contract_operands_in_order(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        order: Order,
+    ) -> ArrayD<A>
+    where
+        A: LinalgScalar,
+    {
+        let shape = IxDyn(&self.output_shape()).set_f(order.is_column_major());
+        let mut out = ArrayD::zeros(shape);
+        self.contract_operands_into(operands, &mut out.view_mut())
+            .unwrap();
+        out
+    }
+}
+
+/// Same as [`einsum`](fn.einsum.html), but returns the result in row-major (C) or column-major
+/// (Fortran) order, as requested by `order`, e.g. to pass it on to LAPACK routines that expect
+/// column-major matrices.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use ndarray::Order;
+///
+/// let a: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 20., 1.).into_shape((4, 5)).unwrap();
+/// let result = einsum_in_order("ijk,kl->ijl", &[&a, &b], Order::ColumnMajor).unwrap();
+/// assert_eq!(result, einsum("ijk,kl->ijl", &[&a, &b]).unwrap());
+/// assert!(result.t().is_standard_layout());
+/// ```
+pub fn einsum_in_order<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    order: Order,
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    Ok(EinsumPath::from_path(&contraction_order).contract_operands_in_order(operands, order))
+}
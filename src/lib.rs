@@ -78,6 +78,17 @@
 //!
 //! ```
 //!
+//! Build a diagonal matrix (unlike numpy, an output index can be repeated)
+//! ```
+//! # use ndarray_einsum_beta::*;
+//! # use ndarray::prelude::*;
+//! # let b: Array1<f64> = Array::range(0., 5., 1.);
+//! assert_eq!(
+//!     einsum("i->ii", &[&b]).unwrap(),
+//!     Array2::from_diag(&b).into_dyn()
+//! );
+//! ```
+//!
 //! Sum over an axis
 //! ```
 //! # use ndarray_einsum_beta::*;
@@ -143,6 +154,34 @@
 //! );
 //! ```
 //!
+//! Batch matrix multiplication, broadcasting over any leading axes
+//! ```
+//! # use ndarray_einsum_beta::*;
+//! # use ndarray::prelude::*;
+//! let stacked: Array3<f64> = Array::range(0., 24., 1.)
+//!     .into_shape((4,2,3,)).unwrap();
+//! let single: Array2<f64> = Array::range(0., 6., 1.)
+//!     .into_shape((3,2,)).unwrap();
+//! let product = einsum("...ij,...jk->...ik", &[&stacked, &single]).unwrap();
+//! assert_eq!(product.shape(), &[4, 2, 2]);
+//! assert_eq!(
+//!     product.index_axis(Axis(0), 1),
+//!     stacked.index_axis(Axis(0), 1).dot(&single).into_dyn()
+//! );
+//! ```
+//!
+//! Scale each row, broadcasting an axis of length 1 as numpy does
+//! ```
+//! # use ndarray_einsum_beta::*;
+//! # use ndarray::prelude::*;
+//! let scales: Array2<f64> = array![[1.], [10.]];
+//! let m: Array2<f64> = array![[1., 2., 3.], [4., 5., 6.]];
+//! assert_eq!(
+//!     einsum("ij,ij->ij", &[&scales, &m]).unwrap(),
+//!     array![[1., 2., 3.], [40., 50., 60.]].into_dyn()
+//! );
+//! ```
+//!
 //! Compute the path separately from the result
 //! ```
 //! # use ndarray_einsum_beta::*;
@@ -164,20 +203,210 @@
 //!     c.dot(&d).into_dyn()
 //! );
 //! ```
+//!
+//! Without its default `std` feature the crate is `no_std` and only needs `alloc`, like
+//! `ndarray` itself; see `Cargo.toml` for the parts that are left out.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
+
+use alloc::vec::Vec;
 use ndarray::prelude::*;
 use ndarray::{Data, IxDyn, LinalgScalar};
 
+/// The maps and sets used throughout the crate: `std`'s hash maps, or B-tree maps without `std`.
+mod collections {
+    #[cfg(not(feature = "std"))]
+    pub(crate) use alloc::collections::BTreeSet as HashSet;
+    #[cfg(not(feature = "std"))]
+    pub(crate) use alloc::collections::{btree_map as hash_map, BTreeMap as HashMap};
+    #[cfg(feature = "std")]
+    pub(crate) use std::collections::{hash_map, HashMap, HashSet};
+}
+
+mod error;
+pub use error::EinsumError;
+
 mod validation;
 pub use validation::{
-    validate, validate_and_optimize_order, validate_and_size, Contraction, SizedContraction,
+    output_shape, validate, validate_and_optimize_order, validate_and_size,
+    validate_and_size_from_shapes, Contraction, SizedContraction, LABELS_PREFIX,
 };
 
 mod optimizers;
 pub use optimizers::{generate_optimized_order, ContractionOrder, OptimizationMethod};
 
 mod contractors;
-pub use contractors::{EinsumPath, EinsumPathSteps};
-use contractors::{PairContractor, TensordotGeneral};
+use contractors::TensordotGeneral;
+pub use contractors::{EinsumPath, EinsumPathSteps, PairMethod, SingletonMethod};
+pub use contractors::{PairContractor, SingletonContractor};
+
+mod plan_cache;
+use plan_cache::cached_order;
+#[cfg(feature = "std")]
+pub use plan_cache::{
+    clear_plan_cache, disable_plan_cache, enable_plan_cache, plan_cache_stats, PlanCacheStats,
+};
+
+mod report;
+pub use report::{einsum_path_report, PathReport, PathReportStep};
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub use report::{ExecutionReport, StepExecutionReport};
+
+mod expression;
+pub use expression::{contract_expression, ContractExpression};
+
+mod conjugate;
+pub use conjugate::{einsum_conj, Conjugate};
+
+mod precision;
+pub use precision::{einsum_widened, Widen};
+
+mod summation;
+#[cfg(feature = "std")]
+pub use summation::with_summation_mode;
+pub use summation::{summation_mode, SummationMode};
+
+mod semiring;
+pub use semiring::{
+    einsum_bool, einsum_logsumexp, einsum_semiring, Boolean, LogSumExp, MaxPlus, MinPlus, Semiring,
+};
+
+mod integer;
+pub use integer::{
+    einsum_checked, einsum_integer, Checked, IntegerArithmetic, Saturating, Wrapping,
+};
+
+mod sparse;
+pub use sparse::{einsum_sparse, EinsumOperand, OperandRef, SparseOperand};
+
+mod block_sparse;
+pub use block_sparse::{einsum_block_sparse, BlockSparseTensor};
+
+mod symmetry;
+pub use symmetry::{einsum_symmetric, IndexSymmetry, SymmetryKind};
+
+mod ncon;
+pub use ncon::ncon;
+
+mod many;
+pub use many::einsum_many;
+
+mod backward;
+pub use backward::einsum_backward;
+
+mod dual;
+pub use dual::{einsum_jvp, Dual};
+
+mod vmap;
+pub use vmap::einsum_vmap;
+
+mod out_of_core;
+pub use out_of_core::einsum_chunked_into;
+
+mod budget;
+pub use budget::max_intermediate_bytes;
+#[cfg(feature = "std")]
+pub use budget::with_max_intermediate_bytes;
+
+mod slicing;
+pub use slicing::einsum_sliced;
+
+mod lowering;
+pub use lowering::{BufferId, Kernel, KernelProgram};
+
+mod backend;
+#[cfg(feature = "std")]
+pub use backend::with_backend;
+pub use backend::{Backend, CpuBackend};
+
+mod passes;
+use passes::contract_simplified;
+
+mod views;
+pub use views::{einsum_shared, einsum_view, einsum_view_singleton};
+
+mod layout;
+#[cfg(feature = "std")]
+pub use layout::with_standard_layout_output;
+pub use layout::{einsum_in_order, standard_layout_output};
+
+mod operands;
+pub use operands::{einsum2, einsum3, einsum_from_raw, einsum_iter};
+
+mod helpers;
+pub use helpers::{batch_matmul, bilinear, diag, hadamard, kron, outer, partial_trace, trace};
+
+mod rearrange;
+pub use rearrange::rearrange;
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra_interop;
+#[cfg(feature = "nalgebra")]
+pub use nalgebra_interop::{einsum_dmatrix, einsum_dvector};
+
+#[cfg(feature = "faer")]
+mod faer_backend;
+#[cfg(feature = "faer")]
+pub use faer_backend::FaerBackend;
+
+#[cfg(feature = "cuda")]
+mod cuda_executor;
+#[cfg(feature = "cuda")]
+pub use cuda_executor::{CudaElement, CudaExecutor};
+
+#[cfg(feature = "wgpu")]
+mod wgpu_executor;
+#[cfg(feature = "wgpu")]
+pub use wgpu_executor::WgpuExecutor;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+mod workspace;
+pub use workspace::EinsumWorkspace;
+
+mod naive;
+pub use naive::einsum_naive;
+
+mod classifiers;
+pub use classifiers::{classify_contraction, ContractionClass, StepExplanation};
+
+mod registry;
+pub use registry::ContractorRegistry;
+
+mod path_options;
+pub use path_options::{einsum_with_options, PathOptions};
+
+mod labels;
+pub use labels::{einsum_labels, label_index, Label};
+
+mod builder;
+pub use builder::Einsum;
+
+#[cfg(feature = "macros")]
+pub use ndarray_einsum_beta_macros::einsum;
+
+/// Items used by the expansion of `einsum!`. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use ndarray::{Dim, IxDyn};
+}
+
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "rayon")]
+pub use parallel::{
+    deterministic_reductions, par_einsum, par_einsum_in, with_deterministic_reductions,
+    with_num_threads, with_thread_pool, ThreadPool, ThreadPoolBuildError,
+};
 
 /// This trait is implemented for all `ArrayBase` variants and is parameterized by the data type.
 ///
@@ -185,7 +414,8 @@ use contractors::{PairContractor, TensordotGeneral};
 /// can take a slice `&[&dyn ArrayLike<A>]` where the elements of the slice can have
 /// different numbers of dimensions and can be a mixture of `Array` and `ArrayView`.
 pub trait ArrayLike<A> {
-    fn into_dyn_view(&self) -> ArrayView<A, IxDyn>;
+    #[allow(clippy::wrong_self_convention)]
+    fn into_dyn_view(&self) -> ArrayView<'_, A, IxDyn>;
 }
 
 impl<A, S, D> ArrayLike<A> for ArrayBase<S, D>
@@ -193,7 +423,7 @@ where
     S: Data<Elem = A>,
     D: Dimension,
 {
-    fn into_dyn_view(&self) -> ArrayView<A, IxDyn> {
+    fn into_dyn_view(&self) -> ArrayView<'_, A, IxDyn> {
         self.view().into_dyn()
     }
 }
@@ -211,19 +441,188 @@ pub fn einsum_path<A>(
     input_string: &str,
     operands: &[&dyn ArrayLike<A>],
     optimization_strategy: OptimizationMethod,
-) -> Result<EinsumPath<A>, &'static str> {
+) -> Result<EinsumPath<A>, EinsumError> {
     let contraction_order =
         validate_and_optimize_order(input_string, operands, optimization_strategy)?;
     Ok(EinsumPath::from_path(&contraction_order))
 }
 
 /// Performs all steps of the process in one function: parse the string, compile the execution plan, and execute the contraction.
+///
+/// As in numpy, the `->` and output indices can be left off (implicit mode), in which case
+/// the output consists of the indices that appear exactly once in the inputs, in alphabetical
+/// order, preceded by any axes covered by an ellipsis.
+///
+/// An index can be any letter: besides `a`-`z`, uppercase letters and the letters of other
+/// alphabets work too, as in `"μν,νρ->μρ"`. In the implicit output they are sorted by code
+/// point, so uppercase letters come before lowercase ones and Greek letters after both.
+///
+/// A 0-d operand has empty indices, as in `"i,->i"` (which scales a vector by a scalar) or
+/// `",->"` (which multiplies two scalars).
+///
+/// Indices can also be words separated by whitespace, if the string starts with `labels:`, as
+/// in `"labels: batch chan_in, chan_in chan_out -> batch chan_out"`; see
+/// [`Contraction::new`](struct.Contraction.html#method.new).
+///
+/// If the plan cache has been enabled with [`enable_plan_cache`](fn.enable_plan_cache.html),
+/// the parsing, validation, and choice of contraction order are skipped when the same string
+/// has already been used with operands of the same shapes.
+///
+/// Before the contraction is performed, indices of length 1 (see
+/// [`SizedContraction::squeezable_indices`]) are left out, and indices that always appear next
+/// to each other in the same order (see [`SizedContraction::adjacent_index_groups`]) are fused
+/// into one, as long as the operands' axes for them can be merged without copying.
+///
+/// [`SizedContraction::squeezable_indices`]: struct.SizedContraction.html#method.squeezable_indices
+/// [`SizedContraction::adjacent_index_groups`]: struct.SizedContraction.html#method.adjacent_index_groups
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// assert_eq!(
+///     einsum("ij,jk", &[&a, &b]).unwrap(),
+///     einsum("ij,jk->ik", &[&a, &b]).unwrap()
+/// );
+/// assert_eq!(
+///     einsum("jk,ij", &[&b, &a]).unwrap(),
+///     einsum("ij,jk->ik", &[&a, &b]).unwrap()
+/// );
+/// let scale = arr0(2.);
+/// assert_eq!(einsum("ij,->ij", &[&a, &scale]).unwrap(), (&a * 2.).into_dyn());
+/// ```
 pub fn einsum<A: LinalgScalar>(
     input_string: &str,
     operands: &[&dyn ArrayLike<A>],
-) -> Result<ArrayD<A>, &'static str> {
-    let sized_contraction = validate_and_size(input_string, operands)?;
-    Ok(einsum_sc(&sized_contraction, operands))
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    if let Some(result) = contract_simplified(&contraction_order, operands) {
+        return Ok(result);
+    }
+    Ok(EinsumPath::from_path(&contraction_order).contract_operands(operands))
+}
+
+/// Same as [`einsum`](fn.einsum.html), but returns an array with a fixed number of dimensions
+/// instead of an `ArrayD`, e.g. an `Array2` for `einsum_dim::<Ix2, _>("ij,jk->ik", ...)`.
+/// `Ix0` can be used for contractions that produce a scalar.
+///
+/// Returns an error if the output in `input_string` doesn't have the number of dimensions of `D`.
+/// Passing `IxDyn` accepts any output, just like `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let c: Array2<f64> = einsum_dim("ij,jk->ik", &[&a, &b]).unwrap();
+/// assert_eq!(c, a.dot(&b));
+/// let total = einsum_dim::<Ix0, _>("ij->", &[&a]).unwrap();
+/// assert_eq!(total.into_scalar(), 15.);
+/// assert_eq!(
+///     einsum_dim::<Ix1, _>("ij,jk->ik", &[&a, &b]).unwrap_err(),
+///     EinsumError::OutputRankMismatch { expected: 2, found: 1 }
+/// );
+/// ```
+pub fn einsum_dim<D, A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<Array<A, D>, EinsumError>
+where
+    D: Dimension,
+    A: LinalgScalar,
+{
+    let contraction_order = cached_order(input_string, operands)?;
+    let path = EinsumPath::from_path(&contraction_order);
+    let output_rank = path.output_shape().len();
+    if let Some(ndim) = D::NDIM {
+        if ndim != output_rank {
+            return Err(EinsumError::OutputRankMismatch {
+                expected: output_rank,
+                found: ndim,
+            });
+        }
+    }
+    Ok(path
+        .contract_operands(operands)
+        .into_dimensionality::<D>()
+        .unwrap())
+}
+
+/// Same as [`einsum`](fn.einsum.html) for a contraction whose output has no indices (e.g.
+/// `"ij,ij->"`), but returns the scalar result itself instead of a 0-d `ArrayD`.
+///
+/// Returns `EinsumError::OutputRankMismatch` if the output in `input_string` has any indices.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// assert_eq!(einsum_scalar("ij,ij->", &[&a, &a]).unwrap(), 55.);
+/// assert_eq!(
+///     einsum_scalar("ij->i", &[&a]).unwrap_err(),
+///     EinsumError::OutputRankMismatch { expected: 1, found: 0 }
+/// );
+/// ```
+pub fn einsum_scalar<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<A, EinsumError> {
+    Ok(einsum_dim::<Ix0, A>(input_string, operands)?.into_scalar())
+}
+
+/// Same as [`einsum`](fn.einsum.html), but writes the result into `out` instead of allocating a
+/// new array, so that a contraction performed repeatedly can reuse the same output array.
+///
+/// Returns an error if `out` doesn't have the shape of the result.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let mut out: Array2<f64> = Array::zeros((2, 4));
+/// for _ in 0..3 {
+///     einsum_into("ij,jk->ik", &[&a, &b], &mut out.view_mut().into_dyn()).unwrap();
+/// }
+/// assert_eq!(out, a.dot(&b));
+/// ```
+pub fn einsum_into<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    out: &mut ArrayViewMutD<A>,
+) -> Result<(), EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    EinsumPath::from_path(&contraction_order).contract_operands_into(operands, out)
+}
+
+/// Computes `out = alpha * einsum(input_string, operands) + beta * out`, in the style of BLAS GEMM.
+///
+/// When the final pairwise contraction is a matrix multiplication and `out` is in standard
+/// layout, `alpha` and `beta` are passed straight to the matrix multiplication, so no temporary
+/// is needed for the result. As in BLAS, `out` isn't read if `beta` is zero.
+///
+/// Returns an error if `out` doesn't have the shape of the result.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let c: Array2<f64> = Array::ones((2, 4));
+/// let mut out = c.clone();
+/// einsum_acc("ij,jk->ik", &[&a, &b], 0.5, 2., &mut out.view_mut().into_dyn()).unwrap();
+/// assert_eq!(out, 0.5 * a.dot(&b) + 2. * &c);
+/// ```
+pub fn einsum_acc<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    alpha: A,
+    beta: A,
+    out: &mut ArrayViewMutD<A>,
+) -> Result<(), EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    EinsumPath::from_path(&contraction_order).contract_operands_acc(operands, alpha, beta, out)
 }
 
 /// Compute tensor dot product between two tensors.
@@ -284,8 +683,8 @@ where
     let rhs_axes_copy: Vec<_> = rhs_axes.iter().map(|x| x.index()).collect();
     let output_order: Vec<usize> = (0..(lhs.ndim() + rhs.ndim() - 2 * (lhs_axes.len()))).collect();
     let tensordotter = TensordotGeneral::from_shapes_and_axis_numbers(
-        &lhs.shape(),
-        &rhs.shape(),
+        lhs.shape(),
+        rhs.shape(),
         &lhs_axes_copy,
         &rhs_axes_copy,
         &output_order,
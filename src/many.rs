@@ -0,0 +1,229 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_many`, which performs several contractions of the same operands and
+//! computes the intermediate results they have in common only once.
+//!
+//! Each contraction is planned as by `einsum`. The tensor produced by a step only depends on
+//! which input operands went into it, on which of their axes share an index, and on which
+//! indices it keeps, so it is identified by those, with the indices renamed in order of
+//! appearance; two steps that are identified the same way, in the same contraction or in
+//! different ones, produce the same tensor up to a permutation of its axes. The first such
+//! step is performed and the others reuse its result.
+use crate::collections::HashMap;
+use crate::contractors::{
+    broadcast_input, PairContraction, PairContractor, SingletonContraction, SingletonContractor,
+};
+use crate::optimizers::OperandNumber;
+use crate::plan_cache::cached_order;
+use crate::{validate_and_size, ArrayLike, ContractionOrder, EinsumError, SizedContraction};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Identifies the tensor produced by a step: the input operands that went into it, then the
+/// renamed indices of each of them, then the kept indices (sorted) and their lengths.
+type StepKey = (Vec<usize>, Vec<Vec<usize>>, Vec<usize>, Vec<usize>);
+
+/// Returns the key of the step that contracts the input operands `involved` into a tensor
+/// with indices `output_indices`, along with the renamed output indices in their actual
+/// order. Returns `None` if an output index is repeated, in which case the result isn't shared.
+fn step_key(
+    sc: &SizedContraction,
+    involved: &[usize],
+    output_indices: &[char],
+) -> Option<(StepKey, Vec<usize>)> {
+    let mut new_names: HashMap<char, usize> = HashMap::new();
+    let renamed_operands: Vec<Vec<usize>> = involved
+        .iter()
+        .map(|&operand| {
+            sc.contraction.operand_indices[operand]
+                .iter()
+                .map(|c| {
+                    let next_name = new_names.len();
+                    *new_names.entry(*c).or_insert(next_name)
+                })
+                .collect()
+        })
+        .collect();
+
+    let renamed_output: Vec<usize> = output_indices.iter().map(|c| new_names[c]).collect();
+    let mut sorted_output = renamed_output.clone();
+    sorted_output.sort_unstable();
+    if sorted_output.windows(2).any(|pair| pair[0] == pair[1]) {
+        return None;
+    }
+    let mut lengths = vec![0; new_names.len()];
+    for (c, &name) in new_names.iter() {
+        lengths[name] = sc.output_size[c];
+    }
+
+    Some((
+        (involved.to_vec(), renamed_operands, sorted_output, lengths),
+        renamed_output,
+    ))
+}
+
+/// A tensor computed by a step, which may be shared between contractions, and the
+/// permutation that puts its axes in the order this contraction expects.
+struct SharedResult<A> {
+    tensor: Rc<ArrayD<A>>,
+    permutation: Vec<usize>,
+}
+
+impl<A> SharedResult<A> {
+    fn view(&self) -> ArrayViewD<'_, A> {
+        self.tensor.view().permuted_axes(self.permutation.clone())
+    }
+}
+
+/// The results of the steps performed so far, shared between all the contractions.
+struct StepResults<A> {
+    results: HashMap<StepKey, (Rc<ArrayD<A>>, Vec<usize>)>,
+}
+
+impl<A> StepResults<A> {
+    /// Returns the result of the step that contracts `involved` into `output_indices`,
+    /// calling `compute` to perform it unless an identical step was performed before.
+    fn get_or_compute<F>(
+        &mut self,
+        sc: &SizedContraction,
+        involved: &[usize],
+        output_indices: &[char],
+        compute: F,
+    ) -> SharedResult<A>
+    where
+        F: FnOnce() -> ArrayD<A>,
+    {
+        let (key, renamed_output) = match step_key(sc, involved, output_indices) {
+            Some(key) => key,
+            None => {
+                let tensor = compute();
+                let permutation = (0..tensor.ndim()).collect();
+                return SharedResult {
+                    tensor: Rc::new(tensor),
+                    permutation,
+                };
+            }
+        };
+        let (tensor, stored_output) = self
+            .results
+            .entry(key)
+            .or_insert_with(|| (Rc::new(compute()), renamed_output.clone()));
+        let permutation = renamed_output
+            .iter()
+            .map(|name| stored_output.iter().position(|x| x == name).unwrap())
+            .collect();
+        SharedResult {
+            tensor: tensor.clone(),
+            permutation,
+        }
+    }
+}
+
+/// Performs each of the contractions in `input_strings` on `operands`, as `einsum` would, and
+/// returns their results in the same order. Intermediate results that two or more of the
+/// contractions have in common (or the whole result, if two of the strings describe the same
+/// contraction) are only computed once, so all of them are kept until every contraction is done.
+///
+/// Returns the first error that any of the strings produces.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let c: Array2<f64> = Array::range(0., 8., 1.).into_shape((4, 2)).unwrap();
+///
+/// // The product of a and b is only computed once
+/// let results = einsum_many(&["ij,jk,kl->il", "ij,jk,kl->l", "ab,bc,cd->"], &[&a, &b, &c]).unwrap();
+/// let abc = a.dot(&b).dot(&c);
+/// assert_eq!(results[0], abc.clone().into_dyn());
+/// assert_eq!(results[1], abc.sum_axis(Axis(0)).into_dyn());
+/// assert_eq!(results[2], arr0(abc.sum()).into_dyn());
+/// ```
+pub fn einsum_many<A: LinalgScalar>(
+    input_strings: &[&str],
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<Vec<ArrayD<A>>, EinsumError> {
+    let input_views: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+    let mut step_results = StepResults {
+        results: HashMap::new(),
+    };
+
+    let mut outputs = Vec::with_capacity(input_strings.len());
+    for input_string in input_strings.iter() {
+        let sc = validate_and_size(input_string, operands)?;
+        let result = match cached_order(input_string, operands)? {
+            ContractionOrder::Singleton(step_sc) => {
+                step_results.get_or_compute(&sc, &[0], &step_sc.contraction.output_indices, || {
+                    SingletonContraction::new(&step_sc).contract_singleton(&broadcast_input(
+                        &input_views[0],
+                        &step_sc,
+                        0,
+                    ))
+                })
+            }
+            ContractionOrder::Pairs(order_steps) => {
+                // The input operands that went into each intermediate result, sorted
+                let mut intermediate_operands: Vec<Vec<usize>> = Vec::new();
+                let mut intermediate_results: Vec<SharedResult<A>> = Vec::new();
+                for order_step in order_steps.iter() {
+                    let step_sc = &order_step.sized_contraction;
+                    let mut involved = Vec::new();
+                    for operand_number in
+                        [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs]
+                    {
+                        match *operand_number {
+                            OperandNumber::Input(pos) => involved.push(pos),
+                            OperandNumber::IntermediateResult(pos) => {
+                                involved.extend(&intermediate_operands[pos])
+                            }
+                        }
+                    }
+                    involved.sort_unstable();
+
+                    let intermediates = &intermediate_results;
+                    let result = step_results.get_or_compute(
+                        &sc,
+                        &involved,
+                        &step_sc.contraction.output_indices,
+                        || {
+                            let view =
+                                |operand_number: &OperandNumber, operand_num| match *operand_number
+                                {
+                                    OperandNumber::Input(pos) => {
+                                        broadcast_input(&input_views[pos], step_sc, operand_num)
+                                    }
+                                    OperandNumber::IntermediateResult(pos) => {
+                                        intermediates[pos].view()
+                                    }
+                                };
+                            PairContraction::new(step_sc).contract_pair(
+                                &view(&order_step.operand_nums.lhs, 0),
+                                &view(&order_step.operand_nums.rhs, 1),
+                            )
+                        },
+                    );
+                    intermediate_operands.push(involved);
+                    intermediate_results.push(result);
+                }
+                intermediate_results.pop().unwrap()
+            }
+        };
+        outputs.push(result.view().to_owned());
+    }
+    Ok(outputs)
+}
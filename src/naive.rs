@@ -0,0 +1,72 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_naive`, a reference implementation of `einsum` for testing the optimized
+//! one against.
+//!
+//! There is no contraction order and there are no contractors: every element of the output is
+//! computed by a single loop over all the values of the summed indices, adding up the products
+//! of the corresponding elements of all the operands, so the result is the definition of the
+//! contraction. This is the loop nest behind `einsum_semiring`, over ordinary arithmetic.
+use crate::semiring::contract_loop_nest;
+use crate::{ArrayLike, EinsumError, Semiring, SizedContraction};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Ordinary addition and multiplication.
+struct Arithmetic<A>(PhantomData<A>);
+
+impl<A: LinalgScalar> Semiring for Arithmetic<A> {
+    type Element = A;
+
+    fn zero() -> A {
+        A::zero()
+    }
+
+    fn add(lhs: &A, rhs: &A) -> A {
+        *lhs + *rhs
+    }
+
+    fn mul(lhs: &A, rhs: &A) -> A {
+        *lhs * *rhs
+    }
+}
+
+/// Performs the same contraction as [`einsum`](fn.einsum.html) with a single loop nest over
+/// all the indices at once, without optimizing the contraction order or using any of the
+/// contractors. It's far slower than `einsum` (its cost is the product of the lengths of all
+/// the indices), but simple enough to be obviously correct, so it can serve as the reference
+/// in differential or property tests of `einsum` with small operands.
+///
+/// Accepts the same strings and operands as `einsum`, including ellipses, broadcasting of axes
+/// of length 1, and repeated output indices, and returns the same errors.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array3<f64> = Array::range(0., 18., 1.).into_shape((3, 3, 2)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 8., 1.).into_shape((2, 4)).unwrap();
+/// let naive = einsum_naive("iij,jk->ki", &[&a, &b]).unwrap();
+/// assert_eq!(naive, einsum("iij,jk->ki", &[&a, &b]).unwrap());
+/// ```
+pub fn einsum_naive<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError> {
+    let sc = SizedContraction::new(input_string, operands)?;
+    let input_views: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+    Ok(contract_loop_nest::<Arithmetic<A>>(&sc, &input_views))
+}
@@ -0,0 +1,146 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `nalgebra` feature, contains the implementations of `ArrayLike` and
+//! `EinsumOperand` for `nalgebra::DMatrix`, `DVector` and `RowDVector`, and `einsum_dmatrix`
+//! and `einsum_dvector`, which return the result as a `DMatrix` or a `DVector`.
+//!
+//! nalgebra stores matrices in column-major order, so a `DMatrix` is viewed as a 2-d array in
+//! Fortran order without being copied, and the vectors are viewed as 1-d arrays. The results
+//! are computed in column-major order (as by `einsum_in_order`) so that their elements can be
+//! moved into the returned matrix or vector without being copied either.
+use crate::plan_cache::cached_order;
+use crate::{ArrayLike, EinsumError, EinsumOperand, EinsumPath, OperandRef};
+use alloc::vec::Vec;
+use nalgebra::{DMatrix, DVector, RowDVector, Scalar};
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Order};
+
+impl<A> ArrayLike<A> for DMatrix<A> {
+    fn into_dyn_view(&self) -> ArrayView<'_, A, IxDyn> {
+        let shape = IxDyn(&[self.nrows(), self.ncols()]).f();
+        ArrayView::from_shape(shape, self.as_slice()).unwrap()
+    }
+}
+
+impl<A> ArrayLike<A> for DVector<A> {
+    fn into_dyn_view(&self) -> ArrayView<'_, A, IxDyn> {
+        ArrayView::from(self.as_slice()).into_dyn()
+    }
+}
+
+impl<A> ArrayLike<A> for RowDVector<A> {
+    fn into_dyn_view(&self) -> ArrayView<'_, A, IxDyn> {
+        ArrayView::from(self.as_slice()).into_dyn()
+    }
+}
+
+impl<A> EinsumOperand<A> for DMatrix<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::Dense(self.into_dyn_view())
+    }
+}
+
+impl<A> EinsumOperand<A> for DVector<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::Dense(self.into_dyn_view())
+    }
+}
+
+impl<A> EinsumOperand<A> for RowDVector<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::Dense(self.into_dyn_view())
+    }
+}
+
+/// Performs the contraction and returns its result in column-major order, or returns
+/// `EinsumError::OutputRankMismatch` if the result doesn't have `ndim` dimensions.
+fn contract_column_major<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    ndim: usize,
+) -> Result<(Vec<usize>, Vec<A>), EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    let path = EinsumPath::from_path(&contraction_order);
+    let shape = path.output_shape();
+    if shape.len() != ndim {
+        return Err(EinsumError::OutputRankMismatch {
+            expected: shape.len(),
+            found: ndim,
+        });
+    }
+    let result = path.contract_operands_in_order(operands, Order::ColumnMajor);
+    let (elements, _) = result.into_raw_vec_and_offset();
+    Ok((shape, elements))
+}
+
+/// Same as [`einsum`](fn.einsum.html) for a contraction whose output has two indices, but
+/// returns the result as a `nalgebra::DMatrix`. Operands can be `ndarray` arrays or nalgebra
+/// matrices and vectors.
+///
+/// Returns `EinsumError::OutputRankMismatch` if the output in `input_string` doesn't have two
+/// indices.
+///
+/// ```
+/// # #[cfg(feature = "nalgebra")] {
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use nalgebra::{DMatrix, DVector};
+///
+/// let a = DMatrix::from_row_slice(2, 3, &[0., 1., 2., 3., 4., 5.]);
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let product = einsum_dmatrix("ij,jk->ik", &[&a, &b]).unwrap();
+/// let b_nalgebra = DMatrix::from_row_slice(3, 4, b.as_slice().unwrap());
+/// assert_eq!(product, &a * &b_nalgebra);
+///
+/// let v = DVector::from_vec(vec![1., 2.]);
+/// assert_eq!(einsum_dmatrix("i,j->ij", &[&v, &v]).unwrap(), &v * v.transpose());
+/// # }
+/// ```
+pub fn einsum_dmatrix<A: LinalgScalar + Scalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<DMatrix<A>, EinsumError> {
+    let (shape, elements) = contract_column_major(input_string, operands, 2)?;
+    Ok(DMatrix::from_vec(shape[0], shape[1], elements))
+}
+
+/// Same as [`einsum`](fn.einsum.html) for a contraction whose output has one index, but
+/// returns the result as a `nalgebra::DVector`. Operands can be `ndarray` arrays or nalgebra
+/// matrices and vectors.
+///
+/// Returns `EinsumError::OutputRankMismatch` if the output in `input_string` doesn't have one
+/// index.
+///
+/// ```
+/// # #[cfg(feature = "nalgebra")] {
+/// # use ndarray_einsum_beta::*;
+/// use nalgebra::{DMatrix, DVector};
+///
+/// let a = DMatrix::from_row_slice(2, 3, &[0., 1., 2., 3., 4., 5.]);
+/// let v = DVector::from_vec(vec![1., 1., 1.]);
+/// assert_eq!(einsum_dvector("ij,j->i", &[&a, &v]).unwrap(), &a * &v);
+/// assert_eq!(
+///     einsum_dvector("ij->ij", &[&a]).unwrap_err(),
+///     EinsumError::OutputRankMismatch { expected: 2, found: 1 }
+/// );
+/// # }
+/// ```
+pub fn einsum_dvector<A: LinalgScalar + Scalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<DVector<A>, EinsumError> {
+    let (_, elements) = contract_column_major(input_string, operands, 1)?;
+    Ok(DVector::from_vec(elements))
+}
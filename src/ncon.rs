@@ -0,0 +1,139 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `ncon`, the interface to tensor-network contractions used by most tensor-network
+//! codes, in which the axes of each tensor are labelled by integers instead of letters.
+//!
+//! A positive label names a bond: it has to appear exactly twice, either on two different
+//! tensors (which are contracted along it) or twice on the same tensor (which is traced over
+//! it). A negative label names an open leg, which has to appear exactly once and is kept in
+//! the result. The labels are numbered in increasing order and passed to `einsum_labels`, so
+//! the contraction is validated, sized, planned and performed exactly as by `einsum`, without
+//! a limit on the number of labels.
+use crate::labels::einsum_named_labels;
+use crate::{ArrayLike, EinsumError};
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// The labels of each operand and of the output for `einsum_labels`, and the `ncon` label that
+/// each of those labels stands for.
+type NumberedLabels = (Vec<Vec<usize>>, Vec<usize>, Vec<i32>);
+
+/// Translates the labels of an `ncon` contraction into the labels of each operand and of the
+/// output for `einsum_labels`.
+fn ncon_labels(
+    connects: &[&[i32]],
+    output_order: Option<&[i32]>,
+) -> Result<NumberedLabels, EinsumError> {
+    let invalid = |label, message| Err(EinsumError::InvalidNconLabel { label, message });
+
+    let mut counts: BTreeMap<i32, usize> = BTreeMap::new();
+    for &label in connects.iter().flat_map(|x| x.iter()) {
+        *counts.entry(label).or_insert(0) += 1;
+    }
+    for (&label, &count) in counts.iter() {
+        if label == 0 {
+            return invalid(label, "labels can't be zero");
+        } else if label > 0 && count != 2 {
+            return invalid(label, "a positive label has to appear exactly twice");
+        } else if label < 0 && count != 1 {
+            return invalid(label, "a negative label has to appear exactly once");
+        }
+    }
+
+    let open_labels: Vec<i32> = match output_order {
+        Some(output_order) => {
+            for &label in output_order {
+                if label >= 0 || !counts.contains_key(&label) {
+                    return invalid(label, "the output order has a label that isn't an open leg");
+                }
+            }
+            let mut distinct = output_order.to_vec();
+            distinct.sort_unstable();
+            distinct.dedup();
+            if distinct.len() != output_order.len()
+                || output_order.len() != counts.keys().filter(|&&x| x < 0).count()
+            {
+                return Err(EinsumError::InvalidNconLabel {
+                    label: *counts
+                        .keys()
+                        .find(|&&x| x < 0 && !output_order.contains(&x))
+                        .unwrap_or(&output_order[0]),
+                    message: "the output order has to list each open leg exactly once",
+                });
+            }
+            output_order.to_vec()
+        }
+        // By default, the open legs are ordered -1, -2, -3, ...
+        None => counts.keys().filter(|&&x| x < 0).rev().cloned().collect(),
+    };
+
+    let numbers: BTreeMap<i32, usize> = counts.keys().cloned().zip(0..).collect();
+    let operand_labels: Vec<Vec<usize>> = connects
+        .iter()
+        .map(|labels| labels.iter().map(|label| numbers[label]).collect())
+        .collect();
+    let output_labels: Vec<usize> = open_labels.iter().map(|label| numbers[label]).collect();
+    Ok((
+        operand_labels,
+        output_labels,
+        counts.keys().cloned().collect(),
+    ))
+}
+
+/// Contracts the tensor network in which axis `m` of `tensors[n]` is labelled `connects[n][m]`.
+/// The axes of the result are the open legs (the negative labels), in the order given by
+/// `output_order` or, if it's `None`, in the order -1, -2, -3, ...
+///
+/// Returns `EinsumError::InvalidNconLabel` if a label is zero, a positive label doesn't appear
+/// exactly twice, a negative label appears more than once, or `output_order` isn't an ordering
+/// of the negative labels. Otherwise, the errors are those of `einsum`, with the operands and
+/// axes numbered as in `tensors` and indices named by their labels in `connects`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let c: Array1<f64> = Array::range(0., 4., 1.);
+///
+/// // A matrix product, and its transpose
+/// let ab = ncon(&[&a, &b], &[&[-1, 1], &[1, -2]], None).unwrap();
+/// assert_eq!(ab, a.dot(&b).into_dyn());
+/// let ab_t = ncon(&[&a, &b], &[&[-1, 1], &[1, -2]], Some(&[-2, -1])).unwrap();
+/// assert_eq!(ab_t, a.dot(&b).t().into_dyn());
+///
+/// // A chain of three tensors, with the last one closing the chain off
+/// let abc = ncon(&[&a, &b, &c], &[&[-1, 1], &[1, 2], &[2]], None).unwrap();
+/// assert_eq!(abc, a.dot(&b).dot(&c).into_dyn());
+/// ```
+pub fn ncon<A: LinalgScalar>(
+    tensors: &[&dyn ArrayLike<A>],
+    connects: &[&[i32]],
+    output_order: Option<&[i32]>,
+) -> Result<ArrayD<A>, EinsumError> {
+    if tensors.len() != connects.len() {
+        return Err(EinsumError::OperandCountMismatch {
+            expected: connects.len(),
+            found: tensors.len(),
+        });
+    }
+    let (operand_labels, output_labels, connect_labels) = ncon_labels(connects, output_order)?;
+    einsum_named_labels(tensors, &operand_labels, &output_labels, |label| {
+        connect_labels[label].to_string()
+    })
+}
@@ -0,0 +1,160 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_iter`, `einsum2`, `einsum3` and `einsum_from_raw`, which take their
+//! operands some other way than as a slice of `&dyn ArrayLike<A>`.
+//!
+//! They all collect the operands into such a slice (of views, which don't copy anything) and
+//! call `einsum`, so they behave exactly like it.
+use crate::{einsum, ArrayLike, EinsumError};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Same as [`einsum`](fn.einsum.html), but takes the operands as anything that can be iterated
+/// over to get views of them, such as a `Vec` of views or an iterator that produces them. The
+/// views all have the same dimension type `D`, which is `IxDyn` for operands of different
+/// numbers of dimensions.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let matrices: Vec<Array2<f64>> = (0..3).map(|i| Array::eye(2) * (i + 1) as f64).collect();
+/// let product = einsum_iter("ij,jk,kl->il", matrices.iter().map(|m| m.view())).unwrap();
+/// assert_eq!(product, (Array::eye(2) * 6.).into_dyn());
+///
+/// let v: Array1<f64> = Array::ones(2);
+/// let views = vec![matrices[0].view().into_dyn(), v.view().into_dyn()];
+/// assert_eq!(einsum_iter("ij,j->i", views).unwrap(), arr1(&[1., 1.]).into_dyn());
+/// ```
+pub fn einsum_iter<'a, A, D, I>(input_string: &str, operands: I) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar + 'a,
+    D: Dimension,
+    I: IntoIterator<Item = ArrayView<'a, A, D>>,
+{
+    let views: Vec<ArrayView<'a, A, D>> = operands.into_iter().collect();
+    let operands: Vec<&dyn ArrayLike<A>> = views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+    einsum(input_string, &operands)
+}
+
+/// Same as [`einsum`](fn.einsum.html) for a contraction of two operands, which can be arrays
+/// or views of any dimension.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// assert_eq!(einsum2("ij,jk->ik", &a, &b).unwrap(), a.dot(&b).into_dyn());
+/// ```
+pub fn einsum2<A, L, R>(input_string: &str, lhs: &L, rhs: &R) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar,
+    L: ArrayLike<A> + ?Sized,
+    R: ArrayLike<A> + ?Sized,
+{
+    einsum(input_string, &[&lhs.into_dyn_view(), &rhs.into_dyn_view()])
+}
+
+/// Same as [`einsum`](fn.einsum.html) for a contraction of three operands, which can be arrays
+/// or views of any dimension.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let v: Array1<f64> = Array::range(0., 4., 1.).into_shape(4).unwrap();
+/// assert_eq!(
+///     einsum3("ij,jk,k->i", &a, &b, &v.view()).unwrap(),
+///     a.dot(&b).dot(&v).into_dyn()
+/// );
+/// ```
+pub fn einsum3<A, T, U, V>(
+    input_string: &str,
+    first: &T,
+    second: &U,
+    third: &V,
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar,
+    T: ArrayLike<A> + ?Sized,
+    U: ArrayLike<A> + ?Sized,
+    V: ArrayLike<A> + ?Sized,
+{
+    einsum(
+        input_string,
+        &[
+            &first.into_dyn_view(),
+            &second.into_dyn_view(),
+            &third.into_dyn_view(),
+        ],
+    )
+}
+
+/// Same as [`einsum`](fn.einsum.html), but takes each operand as a buffer holding its elements
+/// in row-major (C) order and its shape, for data that isn't in an `ndarray` array, e.g. data
+/// received through FFI or read from a file. The buffers aren't copied. Data in column-major
+/// (Fortran) order can be passed with its shape reversed and the indices of the operand in
+/// `input_string` reversed too.
+///
+/// Returns `EinsumError::BufferLengthMismatch` if the length of a buffer isn't the number of
+/// elements of its shape, and otherwise the same errors as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a = [0., 1., 2., 3., 4., 5.];
+/// let b = [1., 1., 1.];
+/// let result = einsum_from_raw("ij,j->i", &[(&a, &[2, 3]), (&b, &[3])]).unwrap();
+/// assert_eq!(result, arr1(&[3., 12.]).into_dyn());
+///
+/// // The same buffer in column-major order
+/// let a_f = [0., 3., 1., 4., 2., 5.];
+/// let result = einsum_from_raw("ji,j->i", &[(&a_f, &[3, 2]), (&b, &[3])]).unwrap();
+/// assert_eq!(result, arr1(&[3., 12.]).into_dyn());
+///
+/// assert_eq!(
+///     einsum_from_raw("ij,j->i", &[(&a, &[2, 2]), (&b, &[3])]).unwrap_err(),
+///     EinsumError::BufferLengthMismatch { operand: 0, shape: vec![2, 2], found: 6 }
+/// );
+/// ```
+pub fn einsum_from_raw<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[(&[A], &[usize])],
+) -> Result<ArrayD<A>, EinsumError> {
+    let mut views: Vec<ArrayViewD<A>> = Vec::with_capacity(operands.len());
+    for (operand, &(buffer, shape)) in operands.iter().enumerate() {
+        let view = ArrayView::from_shape(IxDyn(shape), buffer)
+            .ok()
+            .filter(|_| {
+                shape
+                    .iter()
+                    .try_fold(1usize, |len, &axis_len| len.checked_mul(axis_len))
+                    == Some(buffer.len())
+            });
+        match view {
+            Some(view) => views.push(view),
+            None => {
+                return Err(EinsumError::BufferLengthMismatch {
+                    operand,
+                    shape: shape.to_vec(),
+                    found: buffer.len(),
+                })
+            }
+        }
+    }
+    einsum_iter(input_string, views)
+}
@@ -14,8 +14,9 @@
 
 //! Methods to produce a `ContractionOrder`, specifying what order in which to perform pairwise contractions between tensors
 //! in order to perform the full contraction.
+use crate::collections::HashSet;
 use crate::SizedContraction;
-use std::collections::HashSet;
+use alloc::vec::Vec;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -63,7 +64,151 @@ pub enum ContractionOrder {
     Pairs(Vec<Pair>),
 }
 
-/// Strategy for optimizing the contraction. The only currently supported options are "Naive" and "Reverse".
+impl ContractionOrder {
+    /// Exports the order as a list of pairs of positions in the format used by opt_einsum
+    /// (and accepted by [`SizedContraction::with_explicit_path`](struct.SizedContraction.html#method.with_explicit_path)):
+    /// at each step, the two tensors contracted are identified by their positions in the list
+    /// of remaining tensors, both are removed from the list, and the result is appended to the end.
+    /// A singleton contraction has no pairwise steps, so its path is empty.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl,lm->im",
+    ///     &[vec![2, 3], vec![3, 4], vec![4, 5], vec![5, 6]],
+    /// ).unwrap();
+    /// let naive = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    /// assert_eq!(naive.to_explicit_path(), vec![(0, 1), (2, 0), (1, 0)]);
+    /// let reverse = generate_optimized_order(&sc, OptimizationMethod::Reverse);
+    /// assert_eq!(reverse.to_explicit_path(), vec![(3, 2), (2, 1), (1, 0)]);
+    /// ```
+    pub fn to_explicit_path(&self) -> Vec<(usize, usize)> {
+        let steps = match self {
+            ContractionOrder::Singleton(_) => return Vec::new(),
+            ContractionOrder::Pairs(steps) => steps,
+        };
+        let num_inputs = steps.len() + 1;
+        let mut remaining: Vec<(bool, usize)> = (0..num_inputs).map(|i| (false, i)).collect();
+        let position = |remaining: &[(bool, usize)], operand_num: &OperandNumber| {
+            let key = match *operand_num {
+                OperandNumber::Input(i) => (false, i),
+                OperandNumber::IntermediateResult(i) => (true, i),
+            };
+            remaining.iter().position(|&r| r == key).unwrap()
+        };
+
+        let mut path = Vec::new();
+        for (step_num, step) in steps.iter().enumerate() {
+            let lhs = position(&remaining, &step.operand_nums.lhs);
+            let rhs = position(&remaining, &step.operand_nums.rhs);
+            path.push((lhs, rhs));
+            let (lhs_key, rhs_key) = (remaining[lhs], remaining[rhs]);
+            remaining.retain(|&r| r != lhs_key && r != rhs_key);
+            remaining.push((true, step_num));
+        }
+        path
+    }
+
+    /// The `SizedContraction` performed at each step of the order
+    pub(crate) fn step_contractions(&self) -> Vec<&SizedContraction> {
+        match self {
+            ContractionOrder::Singleton(sized_contraction) => vec![sized_contraction],
+            ContractionOrder::Pairs(steps) => {
+                steps.iter().map(|step| &step.sized_contraction).collect()
+            }
+        }
+    }
+
+    /// An estimate of the number of floating-point operations needed to perform all the
+    /// steps, counted the same way as numpy and opt_einsum do: for each step, the product of the
+    /// lengths of all the indices involved, times 2 if any of them are summed (a multiplication
+    /// and an addition) and 1 otherwise. Very large counts saturate at `u128::MAX`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![10, 20], vec![20, 30], vec![30, 1]],
+    /// ).unwrap();
+    /// let naive = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    /// assert_eq!(naive.flop_count(), 2 * 10 * 20 * 30 + 2 * 10 * 30 * 1);
+    /// let reverse = generate_optimized_order(&sc, OptimizationMethod::Reverse);
+    /// assert_eq!(reverse.flop_count(), 2 * 20 * 30 * 1 + 2 * 10 * 20 * 1);
+    /// ```
+    pub fn flop_count(&self) -> u128 {
+        let num_terms = match self {
+            ContractionOrder::Singleton(_) => 1,
+            ContractionOrder::Pairs(_) => 2,
+        };
+        self.step_contractions()
+            .iter()
+            .map(|sc| get_flop_count(sc, num_terms))
+            .fold(0, u128::saturating_add)
+    }
+
+    /// The number of elements in the largest tensor produced by any of the steps, including
+    /// the final result.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![10, 20], vec![20, 30], vec![30, 1]],
+    /// ).unwrap();
+    /// let naive = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    /// assert_eq!(naive.largest_intermediate(), 10 * 30);
+    /// let reverse = generate_optimized_order(&sc, OptimizationMethod::Reverse);
+    /// assert_eq!(reverse.largest_intermediate(), 20);
+    /// ```
+    pub fn largest_intermediate(&self) -> u128 {
+        self.step_contractions()
+            .iter()
+            .map(|sc| get_saturating_size(&sc.contraction.output_indices, sc))
+            .max()
+            .unwrap()
+    }
+
+    /// The total number of bytes taken up by the tensors produced by all of the steps, including
+    /// the final result, if their elements have type `A`. This is an upper bound on the memory
+    /// allocated for the results of the steps, since an intermediate result is freed once it has
+    /// been used. It doesn't count the temporary copies that some steps make of their inputs.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![10, 20], vec![20, 30], vec![30, 1]],
+    /// ).unwrap();
+    /// let naive = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    /// assert_eq!(naive.total_intermediate_bytes::<f64>(), 8 * (10 * 30 + 10 * 1));
+    /// let reverse = generate_optimized_order(&sc, OptimizationMethod::Reverse);
+    /// assert_eq!(reverse.total_intermediate_bytes::<f32>(), 4 * (20 * 1 + 10 * 1));
+    /// ```
+    pub fn total_intermediate_bytes<A>(&self) -> u128 {
+        self.step_contractions()
+            .iter()
+            .map(|sc| get_saturating_size(&sc.contraction.output_indices, sc))
+            .fold(0, u128::saturating_add)
+            .saturating_mul(core::mem::size_of::<A>() as u128)
+    }
+}
+
+/// The number of floating-point operations needed to perform a contraction with `num_terms`
+/// operands in a single step, as counted by opt_einsum: the size of the full index space,
+/// times the number of multiplications per element (`num_terms - 1`, but at least 1),
+/// plus 1 if there are summed indices.
+pub(crate) fn get_flop_count(sized_contraction: &SizedContraction, num_terms: usize) -> u128 {
+    let all_indices: Vec<char> = sized_contraction.output_size.keys().cloned().collect();
+    let mut op_factor = core::cmp::max(1, num_terms - 1) as u128;
+    if !sized_contraction.contraction.summation_indices.is_empty() {
+        op_factor += 1;
+    }
+    get_saturating_size(&all_indices, sized_contraction).saturating_mul(op_factor)
+}
+
+/// Strategy for optimizing the contraction. The only currently supported options are "Naive", "Reverse",
+/// "Greedy", and "Optimal".
 ///
 /// TODO: Figure out whether this should be done with traits
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -78,11 +223,27 @@ pub enum OptimizationMethod {
     /// that this is actually functioning properly.
     Reverse,
 
-    /// (Not yet supported) Something like [this](https://optimized-einsum.readthedocs.io/en/latest/greedy_path.html)
+    /// At each step, contracts whichever pair of the remaining tensors removes the most elements,
+    /// i.e. minimizes the size of the intermediate result minus the sizes of the two tensors being
+    /// contracted, breaking ties by the number of multiplications required. Pairs sharing an index
+    /// are preferred over outer products. Similar to opt_einsum's
+    /// [greedy path](https://optimized-einsum.readthedocs.io/en/latest/greedy_path.html).
     Greedy,
 
-    /// (Not yet supported) Something like [this](https://optimized-einsum.readthedocs.io/en/latest/optimal_path.html)
-    Optimal,
+    /// Searches every possible contraction order (using dynamic programming over the subsets of
+    /// the operands) for the one requiring the fewest multiplications, similar to opt_einsum's
+    /// [optimal path](https://optimized-einsum.readthedocs.io/en/latest/optimal_path.html).
+    ///
+    /// If `memory_limit` is given, orders in which any intermediate result (not counting the final
+    /// output) has more than `memory_limit` elements are rejected. If every order is rejected, the
+    /// order with the smallest largest intermediate is used instead.
+    ///
+    /// The search takes time exponential in the number of operands, so it is only practical
+    /// for contractions of up to about a dozen tensors; use `Greedy` for larger ones.
+    ///
+    /// Without the (default) `optimal` feature, the search is left out of the crate and the
+    /// `Greedy` order is used instead.
+    Optimal { memory_limit: Option<usize> },
 
     /// (Not yet supported) Something like [this](https://optimized-einsum.readthedocs.io/en/latest/branching_path.html)
     Branch,
@@ -253,7 +414,7 @@ fn generate_path(sized_contraction: &SizedContraction, tensor_order: &[usize]) -
                 // Phew, now make the mini-contraction.
                 let sc = generate_sized_contraction_pair(
                     &lhs_indices,
-                    &rhs_indices,
+                    rhs_indices,
                     &output_indices,
                     &permuted_contraction,
                 );
@@ -280,6 +441,349 @@ fn generate_path(sized_contraction: &SizedContraction, tensor_order: &[usize]) -
     }
 }
 
+/// Returns the indices of the result of contracting `lhs_indices` with `rhs_indices`, given the
+/// indices of the tensors that will still remain afterwards: the indices of either tensor that are
+/// needed by one of the remaining tensors or by the output, in the order in which they first
+/// appear in the LHS and RHS. If nothing remains, this is the final contraction and the result
+/// is just the output.
+fn generate_pair_output_indices(
+    lhs_indices: &[char],
+    rhs_indices: &[char],
+    other_operand_indices: &[Vec<char>],
+    output_indices: &[char],
+) -> Vec<char> {
+    if other_operand_indices.is_empty() {
+        return output_indices.to_vec();
+    }
+    let remaining_indices = get_remaining_indices(other_operand_indices, output_indices);
+    let mut pair_output_indices = Vec::new();
+    for &c in lhs_indices.iter().chain(rhs_indices.iter()) {
+        if remaining_indices.contains(&c) && !pair_output_indices.contains(&c) {
+            pair_output_indices.push(c);
+        }
+    }
+    pair_output_indices
+}
+
+/// Generate the path from a list of pairs of positions, in the format used by opt_einsum:
+/// the tensors to contract at each step are identified by their positions in the list of
+/// remaining tensors, and both are removed from the list, with the result of contracting them
+/// appended to the end. For example, the naive order for four tensors is `[(0, 1), (0, 2), (0, 1)]`.
+pub(crate) fn generate_path_from_pairs(
+    sized_contraction: &SizedContraction,
+    pairs: &[(usize, usize)],
+) -> ContractionOrder {
+    let operand_indices = &sized_contraction.contraction.operand_indices;
+    if operand_indices.len() == 1 {
+        return ContractionOrder::Singleton(sized_contraction.clone());
+    }
+    assert_eq!(pairs.len(), operand_indices.len() - 1);
+
+    let mut remaining: Vec<(OperandNumber, Vec<char>)> = operand_indices
+        .iter()
+        .enumerate()
+        .map(|(i, indices)| (OperandNumber::Input(i), indices.clone()))
+        .collect();
+    let mut steps = Vec::new();
+
+    for (step_num, &(i, j)) in pairs.iter().enumerate() {
+        assert_ne!(i, j);
+        // Remove the later position first so the earlier one stays valid
+        let (first, second) = if i < j { (j, i) } else { (i, j) };
+        let first_removed = remaining.remove(first);
+        let second_removed = remaining.remove(second);
+        let ((lhs_num, lhs_indices), (rhs_num, rhs_indices)) = if i < j {
+            (second_removed, first_removed)
+        } else {
+            (first_removed, second_removed)
+        };
+
+        let other_operand_indices: Vec<Vec<char>> = remaining
+            .iter()
+            .map(|(_, indices)| indices.clone())
+            .collect();
+        let output_indices = generate_pair_output_indices(
+            &lhs_indices,
+            &rhs_indices,
+            &other_operand_indices,
+            &sized_contraction.contraction.output_indices,
+        );
+        let sc = generate_sized_contraction_pair(
+            &lhs_indices,
+            &rhs_indices,
+            &output_indices,
+            sized_contraction,
+        );
+        steps.push(Pair {
+            sized_contraction: sc,
+            operand_nums: OperandNumPair {
+                lhs: lhs_num,
+                rhs: rhs_num,
+            },
+        });
+        remaining.push((OperandNumber::IntermediateResult(step_num), output_indices));
+    }
+
+    ContractionOrder::Pairs(steps)
+}
+
+/// The number of elements in a tensor with the given indices
+fn get_tensor_size(indices: &[char], sized_contraction: &SizedContraction) -> usize {
+    indices
+        .iter()
+        .map(|c| sized_contraction.output_size[c])
+        .product()
+}
+
+/// Chooses the pairs to contract one step at a time; see `OptimizationMethod::Greedy`.
+fn greedy_pairs(sized_contraction: &SizedContraction) -> Vec<(usize, usize)> {
+    let mut remaining = sized_contraction.contraction.operand_indices.clone();
+    let output_indices = &sized_contraction.contraction.output_indices;
+    let mut pairs = Vec::new();
+
+    while remaining.len() > 1 {
+        // (shares an index, size removed, flops, i, j)
+        let mut best: Option<(bool, i128, usize, usize, usize)> = None;
+
+        for i in 0..remaining.len() {
+            for j in (i + 1)..remaining.len() {
+                let lhs_indices = &remaining[i];
+                let rhs_indices = &remaining[j];
+                let shares_index = lhs_indices.iter().any(|c| rhs_indices.contains(c));
+                let other_operand_indices: Vec<Vec<char>> = remaining
+                    .iter()
+                    .enumerate()
+                    .filter(|&(k, _)| k != i && k != j)
+                    .map(|(_, indices)| indices.clone())
+                    .collect();
+                let pair_output_indices = generate_pair_output_indices(
+                    lhs_indices,
+                    rhs_indices,
+                    &other_operand_indices,
+                    output_indices,
+                );
+                let size_removed = get_tensor_size(&pair_output_indices, sized_contraction) as i128
+                    - get_tensor_size(lhs_indices, sized_contraction) as i128
+                    - get_tensor_size(rhs_indices, sized_contraction) as i128;
+                let all_indices: HashSet<char> = get_existing_indices(lhs_indices, rhs_indices);
+                let flops = all_indices
+                    .iter()
+                    .map(|c| sized_contraction.output_size[c])
+                    .product();
+
+                let is_better = match best {
+                    None => true,
+                    Some((best_shares_index, best_size_removed, best_flops, _, _)) => {
+                        (!shares_index, size_removed, flops)
+                            < (!best_shares_index, best_size_removed, best_flops)
+                    }
+                };
+                if is_better {
+                    best = Some((shares_index, size_removed, flops, i, j));
+                }
+            }
+        }
+
+        let (_, _, _, i, j) = best.unwrap();
+        let rhs_indices = remaining.remove(j);
+        let lhs_indices = remaining.remove(i);
+        let new_indices =
+            generate_pair_output_indices(&lhs_indices, &rhs_indices, &remaining, output_indices);
+        remaining.push(new_indices);
+        pairs.push((i, j));
+    }
+
+    pairs
+}
+
+/// The best way found so far to contract one subset of the operands into a single tensor in
+/// `optimal_pairs`.
+#[cfg(feature = "optimal")]
+#[derive(Clone, Copy)]
+struct SubsetContraction {
+    /// The total number of multiplications needed to contract the subset
+    flops: u128,
+
+    /// The two subsets that are contracted to produce this one (or `None` for a single operand)
+    split: Option<(usize, usize)>,
+}
+
+/// Returns the indices of the tensor resulting from contracting each subset of the operands,
+/// where bit `i` of the subset is set if operand `i` is in it. These are the indices of the
+/// operands in the subset that are needed later, either by an operand outside the subset or by
+/// the output, and don't depend on the order in which the subset was contracted.
+#[cfg(feature = "optimal")]
+fn generate_subset_indices(sized_contraction: &SizedContraction) -> Vec<Vec<char>> {
+    let operand_indices = &sized_contraction.contraction.operand_indices;
+    let output_indices = &sized_contraction.contraction.output_indices;
+    let num_operands = operand_indices.len();
+    assert!(num_operands < usize::BITS as usize);
+
+    (0..(1usize << num_operands))
+        .map(|subset| {
+            let outside: Vec<Vec<char>> = (0..num_operands)
+                .filter(|i| subset & (1 << i) == 0)
+                .map(|i| operand_indices[i].clone())
+                .collect();
+            let needed = get_remaining_indices(&outside, output_indices);
+            let mut indices = Vec::new();
+            for i in (0..num_operands).filter(|i| subset & (1 << i) != 0) {
+                for &c in operand_indices[i].iter() {
+                    if needed.contains(&c) && !indices.contains(&c) {
+                        indices.push(c);
+                    }
+                }
+            }
+            indices
+        })
+        .collect()
+}
+
+/// The product of the lengths of the given indices, saturating instead of overflowing
+fn get_saturating_size(indices: &[char], sized_contraction: &SizedContraction) -> u128 {
+    indices
+        .iter()
+        .map(|c| sized_contraction.output_size[c] as u128)
+        .fold(1, |acc, x| acc.saturating_mul(x))
+}
+
+/// Finds the cheapest order by dynamic programming over the subsets of the operands. Since the
+/// result of contracting a subset doesn't depend on how it was contracted, neither does the cost
+/// of contracting it with another subset, so the cheapest way to contract a subset is the cheapest
+/// way to split it into two already-contracted subsets.
+///
+/// Intermediate results with more than `memory_limit` elements are skipped. Returns `None`
+/// if no order satisfies the limit.
+#[cfg(feature = "optimal")]
+fn optimal_subset_contractions(
+    sized_contraction: &SizedContraction,
+    subset_indices: &[Vec<char>],
+    memory_limit: Option<u128>,
+) -> Option<Vec<Option<SubsetContraction>>> {
+    let num_operands = sized_contraction.contraction.operand_indices.len();
+    let full_set = (1 << num_operands) - 1;
+
+    let mut best: Vec<Option<SubsetContraction>> = vec![None; full_set + 1];
+    for i in 0..num_operands {
+        best[1 << i] = Some(SubsetContraction {
+            flops: 0,
+            split: None,
+        });
+    }
+
+    // Every proper subset of a subset is numerically smaller, so this visits the subsets in an
+    // order in which both halves of every split have already been solved.
+    for subset in 1..=full_set {
+        if subset.count_ones() < 2 {
+            continue;
+        }
+        if let Some(limit) = memory_limit {
+            if subset != full_set
+                && get_saturating_size(&subset_indices[subset], sized_contraction) > limit
+            {
+                continue;
+            }
+        }
+
+        // Only consider the splits where the lowest operand is on the left, so that each
+        // split is only considered once.
+        let lowest_bit = subset & subset.wrapping_neg();
+        let mut left = (subset - 1) & subset;
+        while left > 0 {
+            let right = subset ^ left;
+            if left & lowest_bit != 0 {
+                if let (Some(left_best), Some(right_best)) = (best[left], best[right]) {
+                    let mut step_indices = subset_indices[left].clone();
+                    for &c in subset_indices[right].iter() {
+                        if !step_indices.contains(&c) {
+                            step_indices.push(c);
+                        }
+                    }
+                    let flops = left_best
+                        .flops
+                        .saturating_add(right_best.flops)
+                        .saturating_add(get_saturating_size(&step_indices, sized_contraction));
+                    if best[subset].is_none_or(|current| flops < current.flops) {
+                        best[subset] = Some(SubsetContraction {
+                            flops,
+                            split: Some((left, right)),
+                        });
+                    }
+                }
+            }
+            left = (left - 1) & subset;
+        }
+    }
+
+    if best[full_set].is_some() {
+        Some(best)
+    } else {
+        None
+    }
+}
+
+/// Converts the tree of splits found by `optimal_subset_contractions` into opt_einsum-style pairs
+/// of positions by contracting the subsets in post-order.
+#[cfg(feature = "optimal")]
+fn add_subset_pairs(
+    subset: usize,
+    best: &[Option<SubsetContraction>],
+    remaining: &mut Vec<usize>,
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    if let Some((left, right)) = best[subset].unwrap().split {
+        add_subset_pairs(left, best, remaining, pairs);
+        add_subset_pairs(right, best, remaining, pairs);
+        let left_pos = remaining.iter().position(|&x| x == left).unwrap();
+        let right_pos = remaining.iter().position(|&x| x == right).unwrap();
+        pairs.push((left_pos, right_pos));
+        remaining.retain(|&x| x != left && x != right);
+        remaining.push(subset);
+    }
+}
+
+/// Chooses the pairs to contract by exhaustive search; see `OptimizationMethod::Optimal`.
+#[cfg(feature = "optimal")]
+fn optimal_pairs(
+    sized_contraction: &SizedContraction,
+    memory_limit: Option<usize>,
+) -> Vec<(usize, usize)> {
+    let num_operands = sized_contraction.contraction.operand_indices.len();
+    let full_set: usize = (1 << num_operands) - 1;
+    let subset_indices = generate_subset_indices(sized_contraction);
+
+    let best = optimal_subset_contractions(
+        sized_contraction,
+        &subset_indices,
+        memory_limit.map(|limit| limit as u128),
+    )
+    .unwrap_or_else(|| {
+        // Nothing fits, so use the smallest limit that something does fit. Only the sizes of
+        // the possible intermediates need to be tried, and a larger limit never rules out an
+        // order that a smaller one allowed, so the smallest one can be found by bisection.
+        let mut limits: Vec<u128> = (1..full_set)
+            .filter(|subset| subset.count_ones() >= 2)
+            .map(|subset| get_saturating_size(&subset_indices[subset], sized_contraction))
+            .collect();
+        limits.sort_unstable();
+        limits.dedup();
+        let smallest_feasible = limits.partition_point(|&limit| {
+            optimal_subset_contractions(sized_contraction, &subset_indices, Some(limit)).is_none()
+        });
+        optimal_subset_contractions(
+            sized_contraction,
+            &subset_indices,
+            limits.get(smallest_feasible).cloned(),
+        )
+        .unwrap()
+    });
+
+    let mut remaining: Vec<usize> = (0..num_operands).map(|i| 1 << i).collect();
+    let mut pairs = Vec::new();
+    add_subset_pairs(full_set, &best, &mut remaining, &mut pairs);
+    pairs
+}
+
 /// Contracts the first two operands, then contracts the result with the third operand, etc.
 fn naive_order(sized_contraction: &SizedContraction) -> Vec<usize> {
     (0..sized_contraction.contraction.operand_indices.len()).collect()
@@ -295,14 +799,39 @@ fn reverse_order(sized_contraction: &SizedContraction) -> Vec<usize> {
 // TODO: Maybe this should take a function pointer from &SizedContraction -> Vec<usize>?
 /// Given a `SizedContraction` and an optimization strategy, returns an order in which to
 /// perform pairwise contractions in order to produce the final result
+///
+/// With the `tracing` feature enabled, this runs inside a `generate_optimized_order` span.
 pub fn generate_optimized_order(
     sized_contraction: &SizedContraction,
     strategy: OptimizationMethod,
 ) -> ContractionOrder {
-    let tensor_order = match strategy {
-        OptimizationMethod::Naive => naive_order(sized_contraction),
-        OptimizationMethod::Reverse => reverse_order(sized_contraction),
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "generate_optimized_order",
+        subscripts = %sized_contraction.as_einsum_string(),
+        strategy = ?strategy
+    )
+    .entered();
+
+    match strategy {
+        OptimizationMethod::Naive => {
+            generate_path(sized_contraction, &naive_order(sized_contraction))
+        }
+        OptimizationMethod::Reverse => {
+            generate_path(sized_contraction, &reverse_order(sized_contraction))
+        }
+        OptimizationMethod::Greedy => {
+            generate_path_from_pairs(sized_contraction, &greedy_pairs(sized_contraction))
+        }
+        #[cfg(feature = "optimal")]
+        OptimizationMethod::Optimal { memory_limit } => generate_path_from_pairs(
+            sized_contraction,
+            &optimal_pairs(sized_contraction, memory_limit),
+        ),
+        #[cfg(not(feature = "optimal"))]
+        OptimizationMethod::Optimal { .. } => {
+            generate_path_from_pairs(sized_contraction, &greedy_pairs(sized_contraction))
+        }
         _ => panic!("Unsupported optimization method"),
-    };
-    generate_path(sized_contraction, &tensor_order)
+    }
 }
@@ -0,0 +1,234 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_chunked_into`, which performs a contraction one chunk of the output at a
+//! time, for operands and outputs that are too large to be held in memory.
+//!
+//! The operands are only ever read through views, and the output is only written through a
+//! mutable view, so they can be backed by memory-mapped files: with `memmap2`, a read-only
+//! `Mmap` of a file of `f64`s can be viewed as an `ArrayView` with
+//! `ArrayView::from_shape(shape, bytemuck::cast_slice(&mmap))`, and a `MmapMut` as an
+//! `ArrayViewMut` in the same way. The output is split along its first index into chunks, and
+//! for each chunk only the matching slices of the operands that have that index are touched,
+//! so the operating system only needs to keep those pages in memory.
+use crate::optimizers::OperandNumber;
+use crate::{
+    generate_optimized_order, ArrayLike, ContractionOrder, EinsumError, EinsumPath,
+    OptimizationMethod, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Slice};
+
+/// Returns the indices of each input operand of `contraction_order`, as its steps see them.
+pub(crate) fn input_indices(contraction_order: &ContractionOrder) -> Vec<Vec<char>> {
+    match contraction_order {
+        ContractionOrder::Singleton(sc) => sc.contraction.operand_indices.clone(),
+        ContractionOrder::Pairs(order_steps) => {
+            let mut indices: Vec<(usize, Vec<char>)> = Vec::new();
+            for order_step in order_steps.iter() {
+                let operand_nums = [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs];
+                for (operand_num, operand_number) in operand_nums.iter().enumerate() {
+                    if let OperandNumber::Input(pos) = operand_number {
+                        let step_indices = &order_step.sized_contraction.contraction;
+                        indices.push((*pos, step_indices.operand_indices[operand_num].clone()));
+                    }
+                }
+            }
+            indices.sort_by_key(|(pos, _)| *pos);
+            indices.into_iter().map(|(_, indices)| indices).collect()
+        }
+    }
+}
+
+/// Returns the indices of the result of `contraction_order`.
+pub(crate) fn result_indices(contraction_order: &ContractionOrder) -> &[char] {
+    match contraction_order {
+        ContractionOrder::Singleton(sc) => &sc.contraction.output_indices,
+        ContractionOrder::Pairs(order_steps) => {
+            &order_steps
+                .last()
+                .unwrap()
+                .sized_contraction
+                .contraction
+                .output_indices
+        }
+    }
+}
+
+/// Returns a copy of `contraction_order` in which `index` has length `len`.
+pub(crate) fn with_index_len(
+    contraction_order: &ContractionOrder,
+    index: char,
+    len: usize,
+) -> ContractionOrder {
+    let resize = |sc: &mut SizedContraction| {
+        if let Some(index_len) = sc.output_size.get_mut(&index) {
+            *index_len = len;
+        }
+    };
+    let mut contraction_order = contraction_order.clone();
+    match &mut contraction_order {
+        ContractionOrder::Singleton(sc) => resize(sc),
+        ContractionOrder::Pairs(order_steps) => {
+            for order_step in order_steps.iter_mut() {
+                resize(&mut order_step.sized_contraction);
+            }
+        }
+    }
+    contraction_order
+}
+
+/// Returns views of each operand restricted to positions `start..end` of each axis whose index
+/// is `index`, which has length `index_len`. Axes of length 1 that are broadcast along `index`
+/// are left alone.
+pub(crate) fn slice_operands<'a, A>(
+    operands: &[ArrayViewD<'a, A>],
+    operand_indices: &[Vec<char>],
+    index: char,
+    index_len: usize,
+    start: usize,
+    end: usize,
+) -> Vec<ArrayViewD<'a, A>> {
+    operands
+        .iter()
+        .zip(operand_indices.iter())
+        .map(|(operand, indices)| {
+            let mut view = operand.clone();
+            for (axis, &c) in indices.iter().enumerate() {
+                if c == index && view.len_of(Axis(axis)) == index_len {
+                    view.slice_axis_inplace(Axis(axis), Slice::from(start..end));
+                }
+            }
+            view
+        })
+        .collect()
+}
+
+/// Performs `contraction_order` on `operands` `chunk_len` positions of the output index `index`
+/// at a time, writing each chunk into the matching slice of `out`, which has to have the shape
+/// of the result. `contract_chunk` performs the path of a chunk on the slices of the operands,
+/// writing the result into the slice of `out`. If `index` appears more than once in the output,
+/// each chunk only covers the blocks of `out` along its diagonal, so `out` is zeroed first.
+pub(crate) fn contract_in_chunks<A, F>(
+    contraction_order: &ContractionOrder,
+    operands: &[ArrayViewD<A>],
+    index: char,
+    chunk_len: usize,
+    out: &mut ArrayViewMutD<A>,
+    contract_chunk: F,
+) -> Result<(), EinsumError>
+where
+    A: LinalgScalar,
+    F: Fn(&EinsumPath<A>, &[&dyn ArrayLike<A>], &mut ArrayViewMutD<A>) -> Result<(), EinsumError>,
+{
+    let operand_indices = input_indices(contraction_order);
+    let output_indices = result_indices(contraction_order);
+    let index_len = out.len_of(Axis(
+        output_indices.iter().position(|&c| c == index).unwrap(),
+    ));
+    if output_indices.iter().filter(|&&c| c == index).count() > 1 {
+        out.fill(A::zero());
+    }
+
+    // Only the last chunk can be shorter, so at most two paths are needed
+    let mut path: Option<(usize, EinsumPath<A>)> = None;
+    for start in (0..index_len).step_by(chunk_len) {
+        let end = (start + chunk_len).min(index_len);
+        if path.as_ref().map(|(len, _)| *len) != Some(end - start) {
+            let chunk_order = with_index_len(contraction_order, index, end - start);
+            path = Some((end - start, EinsumPath::from_path(&chunk_order)));
+        }
+
+        let chunk_views = slice_operands(operands, &operand_indices, index, index_len, start, end);
+        let chunk_operands: Vec<&dyn ArrayLike<A>> =
+            chunk_views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+        let mut out_chunk = out.view_mut();
+        for (axis, &c) in output_indices.iter().enumerate() {
+            if c == index {
+                out_chunk.slice_axis_inplace(Axis(axis), Slice::from(start..end));
+            }
+        }
+        contract_chunk(&path.as_ref().unwrap().1, &chunk_operands, &mut out_chunk)?;
+    }
+    Ok(())
+}
+
+/// Same as [`einsum_into`](fn.einsum_into.html), but performs the contraction for
+/// `chunk_len` positions of the first output index at a time (skipping indices that appear
+/// more than once in the output, unless they all do), writing each chunk into the matching
+/// slice of `out` before going on to the next one. The intermediate results of each
+/// chunk are freed before the next one starts, and only the slices of the operands that the
+/// chunk needs are read, so the operands and `out` can be memory-mapped arrays larger than the
+/// available memory (see the module documentation). A contraction into a scalar is performed
+/// in a single chunk.
+///
+/// Returns `EinsumError::OutputShapeMismatch` if `out` doesn't have the shape of the result,
+/// `EinsumError::Unsupported` if `chunk_len` is zero, and otherwise the same errors as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 30., 1.).into_shape((10, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let mut out: Array2<f64> = Array::zeros((10, 4));
+/// // Four rows of the product at a time
+/// einsum_chunked_into("ij,jk->ik", &[&a, &b], &mut out.view_mut().into_dyn(), 4).unwrap();
+/// assert_eq!(out, a.dot(&b));
+/// ```
+pub fn einsum_chunked_into<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    out: &mut ArrayViewMutD<A>,
+    chunk_len: usize,
+) -> Result<(), EinsumError> {
+    if chunk_len == 0 {
+        return Err(EinsumError::Unsupported("chunk_len has to be at least 1"));
+    }
+    let sc = SizedContraction::new(input_string, operands)?;
+    let output_shape: Vec<usize> = sc
+        .contraction
+        .output_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect();
+    if out.shape() != output_shape.as_slice() {
+        return Err(EinsumError::OutputShapeMismatch {
+            expected: output_shape,
+            found: out.shape().to_vec(),
+        });
+    }
+
+    let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+    let output_indices = &sc.contraction.output_indices;
+    let chunk_index = output_indices
+        .iter()
+        .find(|&&c| output_indices.iter().filter(|&&d| d == c).count() == 1)
+        .or_else(|| output_indices.first());
+    match chunk_index {
+        Some(&index) => {
+            let input_views: Vec<ArrayViewD<A>> =
+                operands.iter().map(|x| x.into_dyn_view()).collect();
+            contract_in_chunks(
+                &contraction_order,
+                &input_views,
+                index,
+                chunk_len,
+                out,
+                EinsumPath::contract_operands_into,
+            )
+        }
+        None => EinsumPath::from_path(&contraction_order).contract_operands_into(operands, out),
+    }
+}
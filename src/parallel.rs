@@ -0,0 +1,377 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Multithreaded execution, enabled by the `rayon` feature.
+//!
+//! The parallel entry points compile exactly the same `EinsumPath` as their sequential
+//! counterparts; only the execution differs. Matrix multiplications in `TensordotGeneral`
+//! are split into blocks of rows or columns (and matrix-vector products in
+//! `MatrixVectorProduct` into blocks of rows), `StackedTensordotGeneral` contracts the subviews
+//! along its stack axis concurrently, `BatchedMatMul` multiplies the matrices along its first
+//! batch axis concurrently, and the element-wise products are computed in parallel. Work is
+//! done on the current rayon thread pool, which is the global pool unless the call is wrapped
+//! in [`with_num_threads`](fn.with_num_threads.html).
+//!
+//! The number of rows or columns given to each thread by a matrix multiplication depends on the
+//! number of threads, and so can the rounding of its results, e.g. if BLAS picks different
+//! kernels for blocks of different shapes. Inside
+//! [`with_deterministic_reductions`](fn.with_deterministic_reductions.html), the blocks have a
+//! fixed size instead, so the results are bitwise identical whatever the number of threads.
+//!
+//! When the contraction order has independent branches, i.e. a step that contracts two
+//! intermediate results, the steps of the two branches are also performed concurrently, each
+//! branch starting as soon as the thread pool has a thread for it.
+//!
+//! The thread-local settings in effect where a parallel contraction starts (the summation mode,
+//! backend, layout, determinism and the budget of
+//! [`with_max_intermediate_bytes`](fn.with_max_intermediate_bytes.html)) are carried over to the
+//! threads that do its work. A contraction whose intermediate results don't fit the budget is
+//! performed one chunk at a time, with each chunk contracted in parallel.
+use crate::backend::BackendSetting;
+use crate::budget::with_budget;
+use crate::contractors::{broadcast_input, PairContraction, PairContractor};
+use crate::layout::with_layout_setting;
+use crate::optimizers::{OperandNumber, Pair};
+use crate::{
+    cached_order, max_intermediate_bytes, standard_layout_output, summation_mode,
+    with_summation_mode, ArrayLike, EinsumError, EinsumPath, SizedContraction, SummationMode,
+};
+use alloc::vec::Vec;
+use core::cell::Cell;
+use ndarray::prelude::*;
+use ndarray::{CowArray, LinalgScalar};
+use rayon::prelude::*;
+
+pub use rayon::{ThreadPool, ThreadPoolBuildError};
+
+/// The number of rows or columns in each block of a matrix multiplication that is split across
+/// threads inside `with_deterministic_reductions`.
+pub(crate) const DETERMINISTIC_BLOCK_LEN: usize = 64;
+
+thread_local! {
+    static DETERMINISTIC_REDUCTIONS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous setting when dropped, so that it is restored even if `op` panics.
+struct DeterminismGuard(bool);
+
+impl Drop for DeterminismGuard {
+    fn drop(&mut self) {
+        DETERMINISTIC_REDUCTIONS.with(|deterministic| deterministic.set(self.0));
+    }
+}
+
+/// Runs `op` with the parallel contractions it performs on the current thread splitting their
+/// work into blocks whose sizes don't depend on the number of threads, and returns its result.
+/// Each element of a result is then computed by the same operations in the same order however
+/// many threads there are (including one), so the results are bitwise identical. Calls can be
+/// nested.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::from_shape_fn((200, 50), |(i, j)| (i as f64).sin() * j as f64);
+/// let b: Array2<f64> = Array::from_shape_fn((50, 100), |(i, j)| (i as f64 / (j + 1) as f64));
+/// let results: Vec<ArrayD<f64>> = (1..5)
+///     .map(|num_threads| {
+///         with_num_threads(num_threads, || {
+///             with_deterministic_reductions(|| par_einsum("ij,jk->ik", &[&a, &b]).unwrap())
+///         })
+///         .unwrap()
+///     })
+///     .collect();
+/// assert!(results.iter().all(|result| result == &results[0]));
+/// ```
+pub fn with_deterministic_reductions<R, F>(op: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let _guard = DeterminismGuard(
+        DETERMINISTIC_REDUCTIONS.with(|deterministic| deterministic.replace(true)),
+    );
+    op()
+}
+
+/// Returns whether the current thread is inside
+/// [`with_deterministic_reductions`](fn.with_deterministic_reductions.html).
+pub fn deterministic_reductions() -> bool {
+    DETERMINISTIC_REDUCTIONS.with(|deterministic| deterministic.get())
+}
+
+/// The thread-local settings of the executor, which parallel work carries over to the threads
+/// that do it.
+#[derive(Clone)]
+pub(crate) struct ThreadSettings {
+    mode: SummationMode,
+    deterministic: bool,
+    backend: BackendSetting,
+    standard_layout: bool,
+    max_intermediate_bytes: Option<usize>,
+}
+
+impl ThreadSettings {
+    pub(crate) fn current() -> Self {
+        ThreadSettings {
+            mode: summation_mode(),
+            deterministic: deterministic_reductions(),
+            backend: BackendSetting::current(),
+            standard_layout: standard_layout_output(),
+            max_intermediate_bytes: max_intermediate_bytes(),
+        }
+    }
+
+    /// Runs `op` with these settings.
+    pub(crate) fn apply<R>(&self, op: impl FnOnce() -> R) -> R {
+        let _guard = DeterminismGuard(
+            DETERMINISTIC_REDUCTIONS
+                .with(|deterministic| deterministic.replace(self.deterministic)),
+        );
+        self.backend.apply(|| {
+            with_layout_setting(self.standard_layout, || {
+                with_budget(self.max_intermediate_bytes, || {
+                    with_summation_mode(self.mode, op)
+                })
+            })
+        })
+    }
+}
+
+/// Same as [`einsum`](fn.einsum.html), but each pairwise contraction is split across the
+/// current rayon thread pool.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// assert_eq!(
+///     par_einsum("ij,jk->ik", &[&a, &b]).unwrap(),
+///     a.dot(&b).into_dyn()
+/// );
+/// ```
+pub fn par_einsum<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar + Send + Sync,
+{
+    let contraction_order = cached_order(input_string, operands)?;
+    Ok(EinsumPath::from_path(&contraction_order).par_contract_operands(operands))
+}
+
+/// Runs `op` on a new rayon thread pool with `num_threads` threads, so that any parallel
+/// contractions performed inside it use at most that many threads. Passing 0 lets rayon
+/// choose the number of threads, as for the global pool.
+///
+/// Returns an error if the thread pool can't be created.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+/// let b: Array3<f64> = Array::range(0., 40., 1.).into_shape((2, 4, 5)).unwrap();
+/// let product = with_num_threads(2, || par_einsum("bij,bjk->bik", &[&a, &b]))
+///     .unwrap()
+///     .unwrap();
+/// assert_eq!(product, einsum("bij,bjk->bik", &[&a, &b]).unwrap());
+/// ```
+pub fn with_num_threads<R, F>(num_threads: usize, op: F) -> Result<R, ThreadPoolBuildError>
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build()?;
+    Ok(with_thread_pool(&pool, op))
+}
+
+/// Runs `op` on `pool`, so that any parallel contractions performed inside it share that pool
+/// (e.g. an application's own pool) instead of using the global one.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+/// let a: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+/// let b: Array3<f64> = Array::range(0., 40., 1.).into_shape((2, 4, 5)).unwrap();
+/// let product = with_thread_pool(&pool, || par_einsum("bij,bjk->bik", &[&a, &b])).unwrap();
+/// assert_eq!(product, einsum("bij,bjk->bik", &[&a, &b]).unwrap());
+/// ```
+pub fn with_thread_pool<R, F>(pool: &ThreadPool, op: F) -> R
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    // The settings are thread-local, so carry them over to the pool's thread
+    let settings = ThreadSettings::current();
+    pool.install(move || settings.apply(op))
+}
+
+/// Same as [`par_einsum`](fn.par_einsum.html), but the work is done on `pool` instead of the
+/// current thread pool. Unlike calling `par_einsum` inside
+/// [`with_thread_pool`](fn.with_thread_pool.html), this accepts operands that can't be sent to
+/// another thread as `ArrayLike` trait objects.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let operands: [&dyn ArrayLike<f64>; 2] = [&a, &b];
+/// assert_eq!(
+///     par_einsum_in(&pool, "ij,jk->ik", &operands).unwrap(),
+///     a.dot(&b).into_dyn()
+/// );
+/// ```
+pub fn par_einsum_in<A>(
+    pool: &ThreadPool,
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: LinalgScalar + Send + Sync,
+{
+    let contraction_order = cached_order(input_string, operands)?;
+    // Views can be sent to other threads, unlike the `ArrayLike` trait objects
+    let views: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+    Ok(with_thread_pool(pool, || {
+        let operands: Vec<&dyn ArrayLike<A>> =
+            views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+        EinsumPath::from_path(&contraction_order).par_contract_operands(&operands)
+    }))
+}
+
+impl SizedContraction {
+    /// Same as [`contract_many`](struct.SizedContraction.html#method.contract_many), but the
+    /// operand sets are contracted concurrently on the current rayon thread pool. Each thread
+    /// compiles the `EinsumPath` once and reuses it for all the sets it's given.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let matrices: Vec<Array2<f64>> = (0..8).map(|n| Array::eye(3) * n as f64).collect();
+    /// let operand_sets: Vec<[&dyn ArrayLike<f64>; 1]> = matrices.iter().map(|m| [m as _]).collect();
+    /// let operand_sets: Vec<&[&dyn ArrayLike<f64>]> = operand_sets.iter().map(|x| &x[..]).collect();
+    ///
+    /// let sc = SizedContraction::from_string_and_shapes("ii->", &[vec![3, 3]]).unwrap();
+    /// let traces = sc.par_contract_many(&operand_sets);
+    /// assert_eq!(traces, sc.contract_many(&operand_sets));
+    /// assert_eq!(traces[7], arr0(21.).into_dyn());
+    /// ```
+    pub fn par_contract_many<A>(&self, operand_sets: &[&[&dyn ArrayLike<A>]]) -> Vec<ArrayD<A>>
+    where
+        A: LinalgScalar + Send + Sync,
+    {
+        // Views can be sent to other threads, unlike the `ArrayLike` trait objects
+        let view_sets: Vec<Vec<ArrayViewD<A>>> = operand_sets
+            .iter()
+            .map(|operands| {
+                self.assert_sized_for(operands);
+                operands.iter().map(|x| x.into_dyn_view()).collect()
+            })
+            .collect();
+        // The settings are thread-local, so carry them over to the threads that do the work
+        let settings = ThreadSettings::current();
+        view_sets
+            .par_iter()
+            .map_init(
+                || EinsumPath::new(self),
+                |cpc, views| {
+                    let operands: Vec<&dyn ArrayLike<A>> =
+                        views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+                    settings.apply(|| cpc.contract_operands(&operands))
+                },
+            )
+            .collect()
+    }
+}
+
+/// Whether some step of `order_steps` contracts two intermediate results, so that the steps
+/// that produce them can be performed concurrently.
+pub(crate) fn has_independent_branches(order_steps: &[Pair]) -> bool {
+    order_steps.iter().any(|step| {
+        matches!(
+            (&step.operand_nums.lhs, &step.operand_nums.rhs),
+            (
+                OperandNumber::IntermediateResult(_),
+                OperandNumber::IntermediateResult(_)
+            )
+        )
+    })
+}
+
+/// Performs step `step_num` of `order_steps` with its contractor in `steps` and, before it,
+/// the steps that produce its operands, with the two branches of each step performed
+/// concurrently.
+fn contract_subtree<A>(
+    order_steps: &[Pair],
+    steps: &[PairContraction<A>],
+    step_num: usize,
+    inputs: &[ArrayViewD<A>],
+    settings: &ThreadSettings,
+) -> ArrayD<A>
+where
+    A: LinalgScalar + Send + Sync,
+{
+    let step = &order_steps[step_num];
+    let sc = &step.sized_contraction;
+    let operand = |operand_number: &OperandNumber, operand_num| match *operand_number {
+        OperandNumber::Input(pos) => CowArray::from(broadcast_input(&inputs[pos], sc, operand_num)),
+        OperandNumber::IntermediateResult(pos) => {
+            CowArray::from(contract_subtree(order_steps, steps, pos, inputs, settings))
+        }
+    };
+    let (lhs, rhs) = rayon::join(
+        || operand(&step.operand_nums.lhs, 0),
+        || operand(&step.operand_nums.rhs, 1),
+    );
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "contract_pair",
+        step = step_num,
+        subscripts = %sc.as_einsum_string(),
+        lhs_shape = ?lhs.shape(),
+        rhs_shape = ?rhs.shape(),
+    )
+    .entered();
+
+    settings.apply(|| steps[step_num].par_contract_pair(&lhs.view(), &rhs.view()))
+}
+
+/// Performs the steps of `order_steps` on `operands` with the contractors in `steps`, running
+/// independent branches concurrently on the current rayon thread pool.
+pub(crate) fn contract_branches<A>(
+    order_steps: &[Pair],
+    steps: &[PairContraction<A>],
+    operands: &[&dyn ArrayLike<A>],
+) -> ArrayD<A>
+where
+    A: LinalgScalar + Send + Sync,
+{
+    // Views can be sent to other threads, unlike the `ArrayLike` trait objects
+    let inputs: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+    // The settings are thread-local, so carry them over to the threads that do the work
+    let settings = ThreadSettings::current();
+    contract_subtree(
+        order_steps,
+        steps,
+        order_steps.len() - 1,
+        &inputs,
+        &settings,
+    )
+}
@@ -0,0 +1,292 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains the passes that simplify a contraction before `einsum` plans it.
+//!
+//! Indices of length 1 are squeezed out: they don't change how anything is added up, but they
+//! make steps that are really matrix multiplications look like stacked or general ones (e.g.
+//! `bij,bjk->bik` with a batch of 1), so the operands are viewed without those axes and the
+//! axes are put back into the result.
+//!
+//! Indices that always appear next to each other are then fused into one. In `abij,ijcd->abcd`, `a` is always followed by `b`, `i` by `j` and `c` by `d`, so the
+//! contraction is the same as `ai,ic->ac` on the operands with each pair of axes merged into
+//! one. The fused contraction has fewer indices for the planner and the contractors to deal
+//! with, and its matrix multiplications and permutations work on fewer, longer axes. Merging
+//! the axes of a view doesn't copy it, so `einsum` fuses the indices whenever the operands'
+//! memory layouts allow it, and splits the axes of the result back up at the end.
+//!
+//! Neither pass is applied to contractions with an index of length 0.
+use crate::collections::HashMap;
+use crate::out_of_core::{input_indices, result_indices};
+use crate::{
+    generate_optimized_order, ArrayLike, Contraction, ContractionOrder, EinsumPath,
+    OptimizationMethod, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+impl SizedContraction {
+    /// Returns the indices of length 1, in the order in which they first appear.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "bij,bjk->bik",
+    ///     &[vec![1, 2, 3], vec![1, 3, 4]],
+    /// ).unwrap();
+    /// assert_eq!(sc.squeezable_indices(), vec!['b']);
+    /// ```
+    pub fn squeezable_indices(&self) -> Vec<char> {
+        let mut squeezable: Vec<char> = Vec::new();
+        for &c in self.contraction.operand_indices.iter().flatten() {
+            if self.output_size[&c] == 1 && !squeezable.contains(&c) {
+                squeezable.push(c);
+            }
+        }
+        squeezable
+    }
+
+    /// Returns the contraction with the indices `removed` left out.
+    fn without_indices(&self, removed: &[char]) -> SizedContraction {
+        let keep = |indices: &[char]| -> Vec<char> {
+            indices
+                .iter()
+                .filter(|c| !removed.contains(c))
+                .cloned()
+                .collect()
+        };
+        let mut output_size = self.output_size.clone();
+        output_size.retain(|c, _| !removed.contains(c));
+        SizedContraction {
+            contraction: Contraction {
+                operand_indices: self
+                    .contraction
+                    .operand_indices
+                    .iter()
+                    .map(|indices| keep(indices))
+                    .collect(),
+                output_indices: keep(&self.contraction.output_indices),
+                summation_indices: keep(&self.contraction.summation_indices),
+            },
+            output_size,
+        }
+    }
+
+    /// Returns the runs of two or more indices that can be fused into one: wherever the first
+    /// index of a run appears (in an operand or the output) the others follow it, in order, and
+    /// none of them appears anywhere else. Indices that are repeated within an operand or the
+    /// output are never fused.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "abij,ijcd->abcd",
+    ///     &[vec![2, 3, 4, 5], vec![4, 5, 6, 7]],
+    /// ).unwrap();
+    /// assert_eq!(
+    ///     sc.adjacent_index_groups(),
+    ///     vec![vec!['a', 'b'], vec!['i', 'j'], vec!['c', 'd']]
+    /// );
+    /// ```
+    pub fn adjacent_index_groups(&self) -> Vec<Vec<char>> {
+        let index_lists: Vec<&[char]> = self
+            .contraction
+            .operand_indices
+            .iter()
+            .map(|indices| indices.as_slice())
+            .chain(core::iter::once(self.contraction.output_indices.as_slice()))
+            .collect();
+        let is_repeated = |c: char| {
+            index_lists
+                .iter()
+                .any(|indices| indices.iter().filter(|&&d| d == c).count() > 1)
+        };
+        // Whether every c is followed by d and every d is preceded by c
+        let always_follows = |c: char, d: char| {
+            index_lists.iter().all(|indices| {
+                indices.iter().enumerate().all(|(pos, &e)| {
+                    (e != c || indices.get(pos + 1) == Some(&d))
+                        && (e != d || pos > 0 && indices[pos - 1] == c)
+                })
+            })
+        };
+
+        let mut next: HashMap<char, char> = HashMap::new();
+        let mut heads: Vec<char> = Vec::new();
+        for indices in index_lists.iter() {
+            for pair in indices.windows(2) {
+                let (c, d) = (pair[0], pair[1]);
+                if !next.contains_key(&c)
+                    && !is_repeated(c)
+                    && !is_repeated(d)
+                    && always_follows(c, d)
+                {
+                    next.insert(c, d);
+                    heads.push(c);
+                }
+            }
+        }
+        heads.retain(|c| !next.values().any(|d| d == c));
+        heads
+            .into_iter()
+            .map(|head| {
+                let mut group = vec![head];
+                while let Some(&d) = next.get(group.last().unwrap()) {
+                    group.push(d);
+                }
+                group
+            })
+            .collect()
+    }
+
+    /// Returns the contraction with each of `groups` replaced by its first index, whose length
+    /// is the product of the lengths of the group.
+    fn fuse_indices(&self, groups: &[Vec<char>]) -> SizedContraction {
+        let removed: Vec<char> = groups
+            .iter()
+            .flat_map(|group| group[1..].iter().cloned())
+            .collect();
+        let mut fused = self.without_indices(&removed);
+        for group in groups.iter() {
+            let fused_len = group.iter().map(|c| self.output_size[c]).product();
+            fused.output_size.insert(group[0], fused_len);
+        }
+        fused
+    }
+}
+
+/// Returns the contraction performed by all the steps of `contraction_order`.
+fn whole_contraction(contraction_order: &ContractionOrder) -> SizedContraction {
+    match contraction_order {
+        ContractionOrder::Singleton(sc) => sc.clone(),
+        ContractionOrder::Pairs(order_steps) => {
+            let operand_indices = input_indices(contraction_order);
+            let output_indices = result_indices(contraction_order).to_vec();
+            let mut summation_indices: Vec<char> = Vec::new();
+            for &c in operand_indices.iter().flatten() {
+                if !output_indices.contains(&c) && !summation_indices.contains(&c) {
+                    summation_indices.push(c);
+                }
+            }
+            let mut output_size = HashMap::new();
+            for order_step in order_steps.iter() {
+                output_size.extend(order_step.sized_contraction.output_size.clone());
+            }
+            SizedContraction {
+                contraction: Contraction {
+                    operand_indices,
+                    output_indices,
+                    summation_indices,
+                },
+                output_size,
+            }
+        }
+    }
+}
+
+/// Merges the axes of `operand` (whose indices are `indices`) for each of `groups`, or returns
+/// `None` if it has to be broadcast along one of them or its layout doesn't allow merging them
+/// without a copy.
+fn fuse_axes<'a, A>(
+    operand: ArrayViewD<'a, A>,
+    indices: &[char],
+    groups: &[Vec<char>],
+    sc: &SizedContraction,
+) -> Option<ArrayViewD<'a, A>> {
+    // Merging the axes of a group doesn't move the axes before it
+    let mut starts: Vec<(usize, &Vec<char>)> = groups
+        .iter()
+        .filter_map(|group| Some((indices.iter().position(|&c| c == group[0])?, group)))
+        .collect();
+    starts.sort_unstable_by_key(|&(start, _)| core::cmp::Reverse(start));
+
+    let mut fused = operand;
+    for (start, group) in starts {
+        for (offset, c) in group.iter().enumerate() {
+            if fused.len_of(Axis(start + offset)) != sc.output_size[c] {
+                return None;
+            }
+        }
+        for axis in start..start + group.len() - 1 {
+            if !fused.merge_axes(Axis(axis), Axis(axis + 1)) {
+                return None;
+            }
+        }
+        for axis in (start..start + group.len() - 1).rev() {
+            fused.index_axis_inplace(Axis(axis), 0);
+        }
+    }
+    Some(fused)
+}
+
+/// Removes the axes of `operand` (whose indices are `indices`) whose index is in `squeezed`.
+fn squeeze_axes<'a, A>(
+    operand: ArrayViewD<'a, A>,
+    indices: &[char],
+    squeezed: &[char],
+) -> ArrayViewD<'a, A> {
+    let mut squeezed_operand = operand;
+    for (axis, c) in indices.iter().enumerate().rev() {
+        if squeezed.contains(c) {
+            squeezed_operand.index_axis_inplace(Axis(axis), 0);
+        }
+    }
+    squeezed_operand
+}
+
+/// Performs `contraction_order` on `operands` with its indices of length 1 squeezed out and its
+/// adjacent indices fused, returning `None` if neither pass applies.
+pub(crate) fn contract_simplified<A: LinalgScalar>(
+    contraction_order: &ContractionOrder,
+    operands: &[&dyn ArrayLike<A>],
+) -> Option<ArrayD<A>> {
+    let sc = whole_contraction(contraction_order);
+    if sc.output_size.values().any(|&len| len == 0) {
+        return None;
+    }
+    let squeezed = sc.squeezable_indices();
+    let squeezed_sc = sc.without_indices(&squeezed);
+    let squeezed_views: Vec<ArrayViewD<A>> = operands
+        .iter()
+        .zip(sc.contraction.operand_indices.iter())
+        .map(|(operand, indices)| squeeze_axes(operand.into_dyn_view(), indices, &squeezed))
+        .collect();
+
+    let groups = squeezed_sc.adjacent_index_groups();
+    let fused_views = squeezed_views
+        .iter()
+        .zip(squeezed_sc.contraction.operand_indices.iter())
+        .map(|(view, indices)| fuse_axes(view.clone(), indices, &groups, &squeezed_sc))
+        .collect::<Option<Vec<_>>>();
+    let (simplified_sc, simplified_views) = match fused_views {
+        Some(fused_views) if !groups.is_empty() => (squeezed_sc.fuse_indices(&groups), fused_views),
+        _ if !squeezed.is_empty() => (squeezed_sc, squeezed_views),
+        _ => return None,
+    };
+    let simplified_operands: Vec<&dyn ArrayLike<A>> = simplified_views
+        .iter()
+        .map(|x| x as &dyn ArrayLike<A>)
+        .collect();
+
+    let simplified_order = generate_optimized_order(&simplified_sc, OptimizationMethod::Naive);
+    let result = EinsumPath::from_path(&simplified_order).contract_operands(&simplified_operands);
+    let output_shape: Vec<usize> = sc
+        .contraction
+        .output_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect();
+    Some(result.to_shape(output_shape).unwrap().into_owned())
+}
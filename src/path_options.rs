@@ -0,0 +1,154 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `PathOptions`, which overrides the contractor chosen for some steps of a path.
+//!
+//! The contractor for a pair step is chosen from how many of its indices are contracted,
+//! stacked and so on (see `EinsumPath::explain`), which is usually but not always the fastest
+//! choice for the shapes and memory layouts at hand. Forcing a different `PairMethod` for a
+//! step only changes how the simplified operands of that step are contracted; the
+//! simplifications themselves and the rest of the path are unchanged.
+use crate::collections::HashMap;
+use crate::contractors::PairContraction;
+use crate::plan_cache::cached_order;
+use crate::{ArrayLike, ContractionOrder, EinsumError, EinsumPath, EinsumPathSteps, PairMethod};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Options for compiling a path with
+/// [`EinsumPath::from_path_with_options`](struct.EinsumPath.html#method.from_path_with_options).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathOptions {
+    /// The method forced for each pair step, by step number
+    pair_methods: HashMap<usize, PairMethod>,
+}
+
+impl PathOptions {
+    /// Options that don't override anything.
+    pub fn new() -> Self {
+        PathOptions::default()
+    }
+
+    /// Forces step `step` of the path (numbered as in the contraction order) to contract its
+    /// simplified operands with `method`.
+    pub fn with_pair_method(mut self, step: usize, method: PairMethod) -> Self {
+        self.pair_methods.insert(step, method);
+        self
+    }
+
+    /// The method forced for step `step`, if there is one.
+    pub fn pair_method(&self, step: usize) -> Option<PairMethod> {
+        self.pair_methods.get(&step).cloned()
+    }
+}
+
+impl<A> EinsumPath<A> {
+    /// Same as [`from_path`](#method.from_path), but with the contractors forced by `options`.
+    ///
+    /// Returns `EinsumError::UnsupportedPairMethod` if a method is forced for a step that it
+    /// can't perform (e.g. `HadamardProductGeneral` for a matrix multiplication), or for a step
+    /// that isn't a pair step of the path.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let sc = validate_and_size("ij,jk->ik", &[&a, &b]).unwrap();
+    /// let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    ///
+    /// let options = PathOptions::new().with_pair_method(0, PairMethod::StackedTensordotGeneral);
+    /// let path = EinsumPath::from_path_with_options(&order, &options).unwrap();
+    /// assert_eq!(path.steps.to_string(), "step 0: StackedTensordotGeneral");
+    /// assert_eq!(path.contract_operands(&[&a, &b]), a.dot(&b).into_dyn());
+    ///
+    /// let options = PathOptions::new().with_pair_method(0, PairMethod::HadamardProductGeneral);
+    /// assert_eq!(
+    ///     EinsumPath::<f64>::from_path_with_options(&order, &options).unwrap_err(),
+    ///     EinsumError::UnsupportedPairMethod { step: 0, method: PairMethod::HadamardProductGeneral }
+    /// );
+    /// ```
+    pub fn from_path_with_options(
+        contraction_order: &ContractionOrder,
+        options: &PathOptions,
+    ) -> Result<Self, EinsumError> {
+        let num_pair_steps = match contraction_order {
+            ContractionOrder::Singleton(_) => 0,
+            ContractionOrder::Pairs(order_steps) => order_steps.len(),
+        };
+        if let Some(&step) = options
+            .pair_methods
+            .keys()
+            .filter(|&&step| step >= num_pair_steps)
+            .min()
+        {
+            let method = options.pair_methods[&step];
+            return Err(EinsumError::UnsupportedPairMethod { step, method });
+        }
+
+        let order_steps = match contraction_order {
+            ContractionOrder::Singleton(_) => return Ok(EinsumPath::from_path(contraction_order)),
+            ContractionOrder::Pairs(order_steps) => order_steps,
+        };
+        let mut steps = Vec::new();
+        for (step, order_step) in order_steps.iter().enumerate() {
+            let forced_method = options.pair_method(step);
+            match PairContraction::with_method(&order_step.sized_contraction, forced_method) {
+                Some(pair_contraction) => steps.push(pair_contraction),
+                None => {
+                    return Err(EinsumError::UnsupportedPairMethod {
+                        step,
+                        method: forced_method.unwrap(),
+                    })
+                }
+            }
+        }
+        Ok(EinsumPath {
+            contraction_order: contraction_order.clone(),
+            steps: EinsumPathSteps::PairContractions(steps),
+        })
+    }
+}
+
+/// Same as [`einsum`](fn.einsum.html), but with the path compiled by
+/// [`EinsumPath::from_path_with_options`](struct.EinsumPath.html#method.from_path_with_options),
+/// so that the steps are numbered as in the order returned by
+/// [`einsum_path`](fn.einsum_path.html) with `OptimizationMethod::Naive`. The contraction is
+/// always performed step by step.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array3<f64> = Array::range(0., 24., 1.).into_shape((2, 3, 4)).unwrap();
+/// let b: Array3<f64> = Array::range(0., 40., 1.).into_shape((2, 4, 5)).unwrap();
+/// let options = PathOptions::new().with_pair_method(0, PairMethod::BroadcastProductGeneral);
+/// assert!(einsum_with_options("bij,bjk->bik", &[&a, &b], &options).is_err());
+/// let options = PathOptions::new().with_pair_method(0, PairMethod::StackedTensordotGeneral);
+/// assert_eq!(
+///     einsum_with_options("bij,bjk->bik", &[&a, &b], &options).unwrap(),
+///     einsum("bij,bjk->bik", &[&a, &b]).unwrap()
+/// );
+/// ```
+pub fn einsum_with_options<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    options: &PathOptions,
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    Ok(
+        EinsumPath::from_path_with_options(&contraction_order, options)?
+            .contract_operands(operands),
+    )
+}
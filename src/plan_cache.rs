@@ -0,0 +1,259 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An opt-in, process-wide cache of contraction orders used by `einsum` and the other
+//! functions that take an `einsum`-formatted string.
+//!
+//! The cache is keyed by the string and the shapes of the operands, and holds the
+//! `ContractionOrder` produced for them, so a hit skips parsing, validation, and path
+//! optimization. Only the compilation of the order into an `EinsumPath`, which doesn't
+//! depend on anything but the order, is repeated. The cache is disabled until
+//! [`enable_plan_cache`](fn.enable_plan_cache.html) is called; once it holds `capacity`
+//! orders, the least recently used one is evicted to make room for a new one.
+#[cfg(feature = "std")]
+use crate::collections::HashMap;
+use crate::{
+    generate_optimized_order, validate_and_size, ArrayLike, ContractionOrder, EinsumError,
+    OptimizationMethod,
+};
+#[cfg(feature = "std")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(feature = "std")]
+/// Statistics about the plan cache, returned by [`plan_cache_stats`](fn.plan_cache_stats.html).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    /// The number of lookups that found a cached order.
+    pub hits: u64,
+
+    /// The number of lookups that had to compute the order.
+    pub misses: u64,
+
+    /// The number of orders currently cached.
+    pub len: usize,
+
+    /// The maximum number of orders that will be cached, or 0 if the cache is disabled.
+    pub capacity: usize,
+}
+
+#[cfg(feature = "std")]
+type PlanCacheKey = (String, Vec<Vec<usize>>);
+
+#[cfg(feature = "std")]
+struct PlanCacheEntry {
+    contraction_order: ContractionOrder,
+    last_used: u64,
+}
+
+#[cfg(feature = "std")]
+struct PlanCache {
+    entries: HashMap<PlanCacheKey, PlanCacheEntry>,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+    clock: u64,
+}
+
+#[cfg(feature = "std")]
+impl PlanCache {
+    fn new(capacity: usize) -> Self {
+        PlanCache {
+            entries: HashMap::new(),
+            capacity,
+            hits: 0,
+            misses: 0,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &PlanCacheKey) -> Option<ContractionOrder> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.hits += 1;
+                entry.last_used = self.clock;
+                Some(entry.contraction_order.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: PlanCacheKey, contraction_order: ContractionOrder) {
+        self.clock += 1;
+        if !self.entries.contains_key(&key) {
+            self.evict_to(self.capacity - 1);
+        }
+        self.entries.insert(
+            key,
+            PlanCacheEntry {
+                contraction_order,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Removes the least recently used entries until at most `len` remain.
+    fn evict_to(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn stats(&self) -> PlanCacheStats {
+        PlanCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// `None` while the cache is disabled.
+#[cfg(feature = "std")]
+static PLAN_CACHE: Mutex<Option<PlanCache>> = Mutex::new(None);
+
+#[cfg(feature = "std")]
+fn lock_plan_cache() -> MutexGuard<'static, Option<PlanCache>> {
+    // The cache is never left in an inconsistent state, so a panic in another thread
+    // while it held the lock doesn't matter.
+    PLAN_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Enables the plan cache, holding at most `capacity` contraction orders. If the cache is
+/// already enabled, its capacity is changed (evicting the least recently used orders if
+/// necessary) and its contents and statistics are kept. A `capacity` of 0 disables the cache.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// enable_plan_cache(16);
+/// let a: Array3<f64> = Array::ones((4, 2, 3));
+/// let b: Array3<f64> = Array::ones((4, 3, 5));
+/// for _ in 0..3 {
+///     einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+/// }
+/// let stats = plan_cache_stats();
+/// assert_eq!((stats.hits, stats.misses, stats.len), (2, 1, 1));
+/// assert_eq!(stats.capacity, 16);
+/// disable_plan_cache();
+/// assert_eq!(plan_cache_stats(), PlanCacheStats::default());
+/// ```
+#[cfg(feature = "std")]
+pub fn enable_plan_cache(capacity: usize) {
+    let mut plan_cache = lock_plan_cache();
+    if capacity == 0 {
+        *plan_cache = None;
+        return;
+    }
+    match plan_cache.as_mut() {
+        Some(cache) => {
+            cache.evict_to(capacity);
+            cache.capacity = capacity;
+        }
+        None => *plan_cache = Some(PlanCache::new(capacity)),
+    }
+}
+
+/// Disables the plan cache and discards its contents and statistics.
+#[cfg(feature = "std")]
+pub fn disable_plan_cache() {
+    *lock_plan_cache() = None;
+}
+
+/// Discards the contents of the plan cache and resets its statistics, leaving it enabled
+/// (or disabled) with the same capacity.
+#[cfg(feature = "std")]
+pub fn clear_plan_cache() {
+    if let Some(cache) = lock_plan_cache().as_mut() {
+        *cache = PlanCache::new(cache.capacity);
+    }
+}
+
+/// Returns the current statistics of the plan cache. All of the fields are 0 while the
+/// cache is disabled.
+#[cfg(feature = "std")]
+pub fn plan_cache_stats() -> PlanCacheStats {
+    lock_plan_cache()
+        .as_ref()
+        .map(PlanCache::stats)
+        .unwrap_or_default()
+}
+
+/// Computes the order in which `einsum` contracts `operands`.
+fn compute_order<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ContractionOrder, EinsumError> {
+    let sized_contraction = validate_and_size(input_string, operands)?;
+    Ok(generate_optimized_order(
+        &sized_contraction,
+        OptimizationMethod::Naive,
+    ))
+}
+
+/// Returns the order in which `einsum` contracts `operands`. Without `std` there is no cache.
+#[cfg(not(feature = "std"))]
+pub(crate) fn cached_order<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ContractionOrder, EinsumError> {
+    compute_order(input_string, operands)
+}
+
+/// Returns the order in which `einsum` contracts `operands`, from the cache if it is
+/// enabled and holds one for this string and these shapes.
+#[cfg(feature = "std")]
+pub(crate) fn cached_order<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ContractionOrder, EinsumError> {
+    let compute = || compute_order(input_string, operands);
+
+    if lock_plan_cache().is_none() {
+        return compute();
+    }
+
+    let key: PlanCacheKey = (
+        input_string.to_string(),
+        operands
+            .iter()
+            .map(|operand| operand.into_dyn_view().shape().to_vec())
+            .collect(),
+    );
+    if let Some(contraction_order) = lock_plan_cache().as_mut().and_then(|c| c.get(&key)) {
+        return Ok(contraction_order);
+    }
+
+    // The order is computed without holding the lock, so the cache could have been
+    // disabled in the meantime.
+    let contraction_order = compute()?;
+    if let Some(cache) = lock_plan_cache().as_mut() {
+        cache.insert(key, contraction_order.clone());
+    }
+    Ok(contraction_order)
+}
@@ -0,0 +1,134 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_widened`, which performs a contraction in a wider element type than that
+//! of its operands.
+//!
+//! Each operand is converted to the wider type when it is loaded, every multiplication and
+//! summation (including those in the intermediate results) is carried out in the wider type,
+//! and only the final result is converted back. `f32` operands are contracted in `f64`, which
+//! keeps the sums over long axes (say, millions of terms) accurate to the last bit of the `f32`
+//! result. With the `half` feature, `f16` and `bf16` operands are contracted in `f32`:
+//! accumulating in half precision loses all accuracy after a few thousand terms.
+use crate::{cached_order, ArrayLike, EinsumError, EinsumPath};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Element types whose contractions are carried out in the wider type `Wide`.
+pub trait Widen: Copy {
+    /// The type the contraction is carried out in.
+    type Wide: LinalgScalar;
+
+    /// Converts an element of an operand to the wider type.
+    fn widen(self) -> Self::Wide;
+
+    /// Converts an element of the result back from the wider type, rounding if necessary.
+    fn narrow(wide: Self::Wide) -> Self;
+}
+
+impl Widen for f32 {
+    type Wide = f64;
+
+    fn widen(self) -> f64 {
+        self as f64
+    }
+
+    fn narrow(wide: f64) -> Self {
+        wide as f32
+    }
+}
+
+#[cfg(feature = "half")]
+impl Widen for half::f16 {
+    type Wide = f32;
+
+    fn widen(self) -> f32 {
+        self.to_f32()
+    }
+
+    fn narrow(wide: f32) -> Self {
+        half::f16::from_f32(wide)
+    }
+}
+
+#[cfg(feature = "half")]
+impl Widen for half::bf16 {
+    type Wide = f32;
+
+    fn widen(self) -> f32 {
+        self.to_f32()
+    }
+
+    fn narrow(wide: f32) -> Self {
+        half::bf16::from_f32(wide)
+    }
+}
+
+impl<W: LinalgScalar> EinsumPath<W> {
+    /// Performs the contraction on `operands`, whose elements are converted to `W` when they are
+    /// loaded. The result is converted back to the type of the operands.
+    ///
+    /// The path is compiled for the wider type, e.g. `EinsumPath<f32>` for `f16` operands; see
+    /// the [`einsum_widened`](fn.einsum_widened.html) function.
+    pub fn contract_operands_widened<A>(&self, operands: &[&dyn ArrayLike<A>]) -> ArrayD<A>
+    where
+        A: Widen<Wide = W>,
+    {
+        let widened: Vec<ArrayD<W>> = operands
+            .iter()
+            .map(|operand| operand.into_dyn_view().mapv(A::widen))
+            .collect();
+        let widened_operands: Vec<&dyn ArrayLike<W>> = widened
+            .iter()
+            .map(|operand| operand as &dyn ArrayLike<W>)
+            .collect();
+        self.contract_operands(&widened_operands).mapv(A::narrow)
+    }
+}
+
+/// Performs the contraction described by `input_string` in the wider type `A::Wide` and
+/// converts the result back to `A`. Returns an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let v: Array1<f32> = array![16777216., 1., 1.];
+/// // 2^24 + 1 isn't representable in f32 and rounds back down to 2^24
+/// assert_eq!(v.iter().sum::<f32>(), 16777216.);
+/// assert_eq!(einsum_widened("i->", &[&v]).unwrap(), arr0(16777218.).into_dyn());
+/// ```
+///
+/// With the `half` feature:
+///
+/// ```
+/// # #[cfg(feature = "half")] {
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// use half::f16;
+/// let ones: Array1<f16> = Array::from_elem(4096, f16::ONE);
+/// // Summing in f16 gets stuck at 2048, since 2048 + 1 rounds back down to 2048
+/// let naive = ones.iter().fold(f16::ZERO, |acc, &x| acc + x);
+/// assert_eq!(naive, f16::from_f32(2048.));
+/// let total = einsum_widened("i,i->", &[&ones, &ones]).unwrap();
+/// assert_eq!(total, arr0(f16::from_f32(4096.)).into_dyn());
+/// # }
+/// ```
+pub fn einsum_widened<A: Widen>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    Ok(EinsumPath::<A::Wide>::from_path(&contraction_order).contract_operands_widened(operands))
+}
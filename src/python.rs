@@ -0,0 +1,206 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `python` feature, contains the Python extension module `ndarray_einsum_beta`,
+//! built with [maturin](https://www.maturin.rs/) from `pyproject.toml`:
+//!
+//! ```python
+//! import numpy as np
+//! import ndarray_einsum_beta as ne
+//!
+//! a, b = np.random.rand(2, 3), np.random.rand(3, 4)
+//! ne.einsum("ij,jk->ik", a, b)
+//! path, report = ne.einsum_path("ij,jk,kl->il", a, b, np.random.rand(4, 5), optimize="greedy")
+//! expr = ne.contract_expression("ij,jk->ik", (2, 3), (3, 4))
+//! expr(a, b)
+//! ```
+//!
+//! The operands are numpy arrays, which are contracted in place without being copied. They must
+//! either all be `float64` or all be `float32`, and the result has the same type. `optimize` is
+//! one of `"naive"`, `"reverse"`, `"greedy"` (the default) and `"optimal"`. Errors are raised
+//! as `ValueError`s with the message of the `EinsumError`.
+use crate::{
+    einsum, generate_optimized_order, ArrayLike, ContractExpression, EinsumError,
+    OptimizationMethod, PathReport, SizedContraction,
+};
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use numpy::{Element, IntoPyArray, PyReadonlyArrayDyn};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::sync::OnceLock;
+
+fn value_error(e: EinsumError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Parses the `optimize` argument.
+fn optimization_method(optimize: &str) -> PyResult<OptimizationMethod> {
+    match optimize {
+        "naive" => Ok(OptimizationMethod::Naive),
+        "reverse" => Ok(OptimizationMethod::Reverse),
+        "greedy" => Ok(OptimizationMethod::Greedy),
+        "optimal" => Ok(OptimizationMethod::Optimal { memory_limit: None }),
+        _ => Err(PyValueError::new_err(format!(
+            "optimize must be 'naive', 'reverse', 'greedy' or 'optimal', not '{}'",
+            optimize
+        ))),
+    }
+}
+
+/// Borrows every operand as an array of `A`, or returns `None` if one of them isn't one.
+fn extract_all<'py, A: Element>(
+    operands: &Bound<'py, PyTuple>,
+) -> Option<Vec<PyReadonlyArrayDyn<'py, A>>> {
+    operands
+        .iter()
+        .map(|operand| operand.extract().ok())
+        .collect()
+}
+
+/// Calls `contract` with views of `arrays` and converts its result to a numpy array.
+fn contract_arrays<'py, A, F>(
+    py: Python<'py>,
+    arrays: &[PyReadonlyArrayDyn<'py, A>],
+    contract: F,
+) -> PyResult<Bound<'py, PyAny>>
+where
+    A: Element + LinalgScalar,
+    F: FnOnce(&[&dyn ArrayLike<A>]) -> Result<ArrayD<A>, EinsumError>,
+{
+    let views: Vec<ArrayViewD<A>> = arrays.iter().map(|array| array.as_array()).collect();
+    let operands: Vec<&dyn ArrayLike<A>> = views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+    let result = contract(&operands).map_err(value_error)?;
+    Ok(result.into_pyarray(py).into_any())
+}
+
+fn unsupported_operands() -> PyErr {
+    PyTypeError::new_err("operands must all be float64 arrays or all be float32 arrays")
+}
+
+/// Performs the contraction described by `subscripts` on the operands.
+#[pyfunction]
+#[pyo3(name = "einsum", signature = (subscripts, *operands))]
+fn py_einsum<'py>(
+    py: Python<'py>,
+    subscripts: &str,
+    operands: &Bound<'py, PyTuple>,
+) -> PyResult<Bound<'py, PyAny>> {
+    if let Some(arrays) = extract_all::<f64>(operands) {
+        contract_arrays(py, &arrays, |operands| einsum(subscripts, operands))
+    } else if let Some(arrays) = extract_all::<f32>(operands) {
+        contract_arrays(py, &arrays, |operands| einsum(subscripts, operands))
+    } else {
+        Err(unsupported_operands())
+    }
+}
+
+/// Returns the order in which the operands would be contracted, in the format of numpy's
+/// `einsum_path` (which `numpy.einsum` accepts as its `optimize` argument), and a printable
+/// report of its cost.
+#[pyfunction]
+#[pyo3(name = "einsum_path", signature = (subscripts, *operands, optimize = "greedy"))]
+fn py_einsum_path<'py>(
+    py: Python<'py>,
+    subscripts: &str,
+    operands: &Bound<'py, PyTuple>,
+    optimize: &str,
+) -> PyResult<(Bound<'py, PyAny>, String)> {
+    let method = optimization_method(optimize)?;
+    let shapes: Vec<Vec<usize>> = operands
+        .iter()
+        .map(|operand| operand.getattr("shape")?.extract())
+        .collect::<PyResult<_>>()?;
+    let sc = SizedContraction::from_string_and_shapes(subscripts, &shapes).map_err(value_error)?;
+    let contraction_order = generate_optimized_order(&sc, method);
+    let mut path: Vec<PyObject> = vec!["einsum_path".into_pyobject(py)?.into_any().unbind()];
+    for pair in contraction_order.to_explicit_path() {
+        path.push(pair.into_pyobject(py)?.into_any().unbind());
+    }
+    let report = PathReport::new(&sc, &contraction_order);
+    Ok((path.into_pyobject(py)?.into_any(), report.to_string()))
+}
+
+/// A contraction planned once for operands of fixed shapes by `contract_expression`, which can
+/// then be called with operands of those shapes any number of times.
+#[pyclass(name = "ContractExpression", frozen)]
+struct PyContractExpression {
+    subscripts: String,
+    shapes: Vec<Vec<usize>>,
+    optimize: String,
+    expr_f64: ContractExpression<f64>,
+    /// Planned the first time the expression is called with `float32` operands
+    expr_f32: OnceLock<ContractExpression<f32>>,
+}
+
+#[pymethods]
+impl PyContractExpression {
+    #[pyo3(signature = (*operands))]
+    fn __call__<'py>(
+        &self,
+        py: Python<'py>,
+        operands: &Bound<'py, PyTuple>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if let Some(arrays) = extract_all::<f64>(operands) {
+            contract_arrays(py, &arrays, |operands| self.expr_f64.eval(operands))
+        } else if let Some(arrays) = extract_all::<f32>(operands) {
+            let expr_f32 = self.expr_f32.get_or_init(|| {
+                let method = optimization_method(&self.optimize).unwrap();
+                ContractExpression::new(&self.subscripts, &self.shapes, method).unwrap()
+            });
+            contract_arrays(py, &arrays, |operands| expr_f32.eval(operands))
+        } else {
+            Err(unsupported_operands())
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ContractExpression('{}', shapes={:?})",
+            self.expr_f64.sized_contraction().as_einsum_string(),
+            self.shapes
+        )
+    }
+}
+
+/// Plans the contraction described by `subscripts` for operands of the given shapes and
+/// returns a `ContractExpression` that performs it when called with the operands.
+#[pyfunction]
+#[pyo3(name = "contract_expression", signature = (subscripts, *shapes, optimize = "greedy"))]
+fn py_contract_expression(
+    subscripts: &str,
+    shapes: Vec<Vec<usize>>,
+    optimize: &str,
+) -> PyResult<PyContractExpression> {
+    let method = optimization_method(optimize)?;
+    let expr_f64 = ContractExpression::new(subscripts, &shapes, method).map_err(value_error)?;
+    Ok(PyContractExpression {
+        subscripts: subscripts.to_string(),
+        shapes,
+        optimize: optimize.to_string(),
+        expr_f64,
+        expr_f32: OnceLock::new(),
+    })
+}
+
+/// The `ndarray_einsum_beta` Python module.
+#[pymodule]
+fn ndarray_einsum_beta(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(py_einsum, module)?)?;
+    module.add_function(wrap_pyfunction!(py_einsum_path, module)?)?;
+    module.add_function(wrap_pyfunction!(py_contract_expression, module)?)?;
+    module.add_class::<PyContractExpression>()?;
+    Ok(())
+}
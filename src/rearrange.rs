@@ -0,0 +1,298 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `rearrange`, which permutes, splits and merges the axes of a tensor as described
+//! by an [einops](https://einops.rocks/)-style pattern such as `b (h w) c -> b h w c`.
+//!
+//! Each side of the pattern is a list of whitespace-separated groups, one per axis, where a
+//! group is either the name of an axis or a parenthesized list of names, which stands for the
+//! single axis made by merging them (in row-major order). The tensor is reshaped into the axes
+//! named on the left, which are permuted into the order in which they are named on the right
+//! and then merged into the groups of the right.
+use crate::collections::{HashMap, HashSet};
+use crate::EinsumError;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::Data;
+
+/// A group of a pattern: the axes named in it, each with its byte offset in the pattern, and
+/// the byte offset of the group itself.
+#[derive(Debug)]
+struct Group<'a> {
+    position: usize,
+    axes: Vec<(usize, &'a str)>,
+}
+
+/// Adds the name `side[start..end]` to `group`, or as a group of its own if there is no open
+/// group, where `side` starts at byte `position` of the pattern.
+fn push_name<'a>(
+    side: &'a str,
+    position: usize,
+    (start, end): (usize, usize),
+    group: &mut Option<Group<'a>>,
+    groups: &mut Vec<Group<'a>>,
+) -> Result<(), EinsumError> {
+    let name = &side[start..end];
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(EinsumError::Parse {
+            position: position + start,
+            message: "invalid character in axis name",
+        });
+    }
+    match group {
+        Some(group) => group.axes.push((position + start, name)),
+        None => groups.push(Group {
+            position: position + start,
+            axes: vec![(position + start, name)],
+        }),
+    }
+    Ok(())
+}
+
+/// Splits `side` (which starts at byte `position` of the pattern) into its groups, checking
+/// that each name is a letter or underscore followed by any number of letters, digits and
+/// underscores.
+fn parse_groups(side: &str, position: usize) -> Result<Vec<Group<'_>>, EinsumError> {
+    let mut groups = Vec::new();
+    let mut group = None;
+    let mut word_start = None;
+
+    for (offset, c) in side.char_indices() {
+        if c == '_' || c.is_alphanumeric() {
+            word_start.get_or_insert(offset);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            push_name(side, position, (start, offset), &mut group, &mut groups)?;
+        }
+        if c == '(' {
+            if group.is_some() {
+                return Err(EinsumError::Parse {
+                    position: position + offset,
+                    message: "parentheses can't be nested",
+                });
+            }
+            group = Some(Group {
+                position: position + offset,
+                axes: Vec::new(),
+            });
+        } else if c == ')' {
+            match group.take() {
+                Some(closed) => groups.push(closed),
+                None => {
+                    return Err(EinsumError::Parse {
+                        position: position + offset,
+                        message: "unmatched ')'",
+                    })
+                }
+            }
+        } else if !c.is_whitespace() {
+            return Err(EinsumError::Parse {
+                position: position + offset,
+                message: "invalid character in axis name",
+            });
+        }
+    }
+    if let Some(start) = word_start {
+        push_name(side, position, (start, side.len()), &mut group, &mut groups)?;
+    }
+    if let Some(open) = group {
+        return Err(EinsumError::Parse {
+            position: open.position,
+            message: "unmatched '('",
+        });
+    }
+    Ok(groups)
+}
+
+/// Checks that no axis is named twice in `groups`.
+fn check_distinct(groups: &[Group]) -> Result<(), EinsumError> {
+    let mut seen = HashMap::new();
+    for &(position, name) in groups.iter().flat_map(|group| group.axes.iter()) {
+        if seen.insert(name, position).is_some() {
+            return Err(EinsumError::Parse {
+                position,
+                message: "axis is named more than once on the same side of '->'",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Permutes, splits and merges the axes of `tensor` as described by `pattern`, an
+/// [einops](https://einops.rocks/)-style pattern such as `b (h w) c -> b h w c`, which splits
+/// the second axis of a 3-D tensor into two. A parenthesized group on the left splits an axis
+/// into the axes named in it, and one on the right merges the axes named in it; `()` is an axis
+/// of length 1. Every axis has to be named on both sides.
+///
+/// The lengths of the axes that a group on the left splits an axis into are given by
+/// `axis_lengths`, except for one of them, whose length is inferred from the length of the
+/// axis. The result is a new array in standard layout.
+///
+/// Returns `EinsumError::Parse` if `pattern` is malformed, `EinsumError::RankMismatch` if the
+/// number of groups on the left isn't the number of axes of `tensor`,
+/// `EinsumError::InvalidAxisLength` if a length is given for an axis that isn't in the pattern
+/// or the lengths of the axes in a group on the left can't be determined, and
+/// `EinsumError::ShapeMismatch` if an axis of `tensor` doesn't have the length given by its
+/// group.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let t: Array3<f64> = Array::range(0., 36., 1.).into_shape_with_order((2, 6, 3)).unwrap();
+/// let split = rearrange(&t, "b (h w) c -> b h w c", &[("h", 2)]).unwrap();
+/// assert_eq!(split, t.clone().into_shape_with_order((2, 2, 3, 3)).unwrap().into_dyn());
+///
+/// let channels_first = rearrange(&split, "b h w c -> b c h w", &[]).unwrap();
+/// assert_eq!(channels_first, split.clone().permuted_axes(IxDyn(&[0, 3, 1, 2])));
+///
+/// let merged = rearrange(&split, "b h w c -> (b h) (w c) ()", &[]).unwrap();
+/// assert_eq!(merged.shape(), &[4, 9, 1]);
+///
+/// assert_eq!(
+///     rearrange(&t, "b (h w) c -> b h w c", &[("h", 4)]).unwrap_err(),
+///     EinsumError::InvalidAxisLength {
+///         axis: "w".to_string(),
+///         message: "the lengths of the other axes of its group don't divide the length of the axis"
+///     }
+/// );
+/// ```
+pub fn rearrange<A, S, D>(
+    tensor: &ArrayBase<S, D>,
+    pattern: &str,
+    axis_lengths: &[(&str, usize)],
+) -> Result<ArrayD<A>, EinsumError>
+where
+    A: Clone,
+    S: Data<Elem = A>,
+    D: Dimension,
+{
+    let arrow = pattern.find("->").ok_or(EinsumError::Parse {
+        position: pattern.len(),
+        message: "the pattern has no '->'",
+    })?;
+    let left = parse_groups(&pattern[..arrow], 0)?;
+    let right = parse_groups(&pattern[(arrow + 2)..], arrow + 2)?;
+    check_distinct(&left)?;
+    check_distinct(&right)?;
+
+    let left_names: HashSet<&str> = left
+        .iter()
+        .flat_map(|group| group.axes.iter().map(|&(_, name)| name))
+        .collect();
+    let right_names: HashSet<&str> = right
+        .iter()
+        .flat_map(|group| group.axes.iter().map(|&(_, name)| name))
+        .collect();
+    for &(position, name) in right.iter().flat_map(|group| group.axes.iter()) {
+        if !left_names.contains(name) {
+            return Err(EinsumError::Parse {
+                position,
+                message: "axis isn't named on the left of '->'",
+            });
+        }
+    }
+    for &(position, name) in left.iter().flat_map(|group| group.axes.iter()) {
+        if !right_names.contains(name) {
+            return Err(EinsumError::Parse {
+                position,
+                message: "axis isn't named on the right of '->'",
+            });
+        }
+    }
+
+    if left.len() != tensor.ndim() {
+        return Err(EinsumError::RankMismatch {
+            operand: 0,
+            expected: left.len(),
+            found: tensor.ndim(),
+        });
+    }
+
+    let mut lengths: HashMap<&str, usize> = HashMap::new();
+    for &(name, length) in axis_lengths.iter() {
+        if !left_names.contains(name) {
+            return Err(EinsumError::InvalidAxisLength {
+                axis: name.to_string(),
+                message: "the axis isn't in the pattern",
+            });
+        }
+        lengths.insert(name, length);
+    }
+    for (axis, (group, &found)) in left.iter().zip(tensor.shape().iter()).enumerate() {
+        let unknown: Vec<&str> = group
+            .axes
+            .iter()
+            .map(|&(_, name)| name)
+            .filter(|name| !lengths.contains_key(name))
+            .collect();
+        let known: usize = group
+            .axes
+            .iter()
+            .filter_map(|&(_, name)| lengths.get(name))
+            .product();
+        match unknown[..] {
+            [] => {
+                if known != found {
+                    return Err(EinsumError::ShapeMismatch {
+                        operand: 0,
+                        axis,
+                        expected: known,
+                        found,
+                    });
+                }
+            }
+            [name] => {
+                if known == 0 || found % known != 0 {
+                    return Err(EinsumError::InvalidAxisLength {
+                        axis: name.to_string(),
+                        message: "the lengths of the other axes of its group don't divide the length of the axis",
+                    });
+                }
+                lengths.insert(name, found / known);
+            }
+            [_, name, ..] => {
+                return Err(EinsumError::InvalidAxisLength {
+                    axis: name.to_string(),
+                    message: "another axis of its group has an unknown length too",
+                })
+            }
+        }
+    }
+
+    // Split the axes of the tensor into the axes named on the left, permute them into the
+    // order of the right, and merge them into the groups of the right.
+    let names: Vec<&str> = left
+        .iter()
+        .flat_map(|group| group.axes.iter().map(|&(_, name)| name))
+        .collect();
+    let split_shape: Vec<usize> = names.iter().map(|name| lengths[name]).collect();
+    let order: Vec<usize> = right
+        .iter()
+        .flat_map(|group| group.axes.iter())
+        .map(|&(_, name)| names.iter().position(|&n| n == name).unwrap())
+        .collect();
+    let merged_shape: Vec<usize> = right
+        .iter()
+        .map(|group| group.axes.iter().map(|&(_, name)| lengths[name]).product())
+        .collect();
+
+    let split = tensor.to_shape(IxDyn(&split_shape)).unwrap();
+    let permuted = split.view().permuted_axes(IxDyn(&order));
+    Ok(permuted
+        .to_shape(IxDyn(&merged_shape))
+        .unwrap()
+        .into_owned())
+}
@@ -0,0 +1,210 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `ContractorRegistry`, through which callers supply their own `PairContractor`s and
+//! `SingletonContractor`s for the steps they know how to perform better than the built-in ones.
+//!
+//! When a path is compiled with a registry, each step is offered to the matchers registered
+//! for its number of operands, in the order they were registered. The first matcher that
+//! returns a contractor performs the step on its own: the operands are passed to it as they
+//! are (broadcast to the lengths of the step's indices), without the simplifications that the
+//! built-in pair contractors apply first. A step that no matcher accepts gets the contractor
+//! that [`EinsumPath::from_path`](struct.EinsumPath.html#method.from_path) would choose.
+use crate::contractors::{PairContraction, SingletonContraction};
+use crate::plan_cache::cached_order;
+use crate::{
+    ArrayLike, ContractionOrder, EinsumError, EinsumPath, EinsumPathSteps, PairContractor,
+    SingletonContractor, SizedContraction,
+};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+type SingletonMatcher<A> = Box<
+    dyn Fn(&SizedContraction) -> Option<Box<dyn SingletonContractor<A> + Send + Sync>>
+        + Send
+        + Sync,
+>;
+type PairMatcher<A> = Box<
+    dyn Fn(&SizedContraction) -> Option<Box<dyn PairContractor<A> + Send + Sync>> + Send + Sync,
+>;
+
+/// User-supplied contractors for the steps of a path, with the built-in ones as the fallback.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// /// Computes the attention scores `bhqd,bhkd->bhqk` one head at a time
+/// #[derive(Debug)]
+/// struct AttentionScores;
+///
+/// impl PairContractor<f64> for AttentionScores {
+///     fn contract_pair<'a, 'b, 'c, 'd>(
+///         &self,
+///         lhs: &'b ArrayViewD<'a, f64>,
+///         rhs: &'d ArrayViewD<'c, f64>,
+///     ) -> ArrayD<f64>
+///     where
+///         'a: 'b,
+///         'c: 'd,
+///     {
+///         let (b, h, q, _) = lhs.view().into_dimensionality::<Ix4>().unwrap().dim();
+///         let k = rhs.shape()[2];
+///         let mut scores = Array4::zeros((b, h, q, k));
+///         for i in 0..b {
+///             for j in 0..h {
+///                 let queries = lhs.slice(s![i, j, .., ..]).into_dimensionality::<Ix2>().unwrap();
+///                 let keys = rhs.slice(s![i, j, .., ..]).into_dimensionality::<Ix2>().unwrap();
+///                 scores.slice_mut(s![i, j, .., ..]).assign(&queries.dot(&keys.t()));
+///             }
+///         }
+///         scores.into_dyn()
+///     }
+/// }
+///
+/// let mut registry = ContractorRegistry::new();
+/// registry.register_pair_contractor(|sc| {
+///     if sc.as_einsum_string() == "bhqd,bhkd->bhqk" {
+///         Some(Box::new(AttentionScores))
+///     } else {
+///         None
+///     }
+/// });
+///
+/// let queries: Array4<f64> = Array::range(0., 48., 1.).into_shape((1, 2, 3, 8)).unwrap();
+/// let keys: Array4<f64> = Array::range(0., 64., 1.).into_shape((1, 2, 4, 8)).unwrap();
+/// let operands: &[&dyn ArrayLike<f64>] = &[&queries, &keys];
+/// let order = generate_optimized_order(
+///     &SizedContraction::new("bhqd,bhkd->bhqk", operands).unwrap(),
+///     OptimizationMethod::Naive,
+/// );
+/// assert_eq!(
+///     registry.path(&order).steps.to_string(),
+///     "step 0: Custom"
+/// );
+/// assert_eq!(
+///     registry.einsum("bhqd,bhkd->bhqk", operands).unwrap(),
+///     einsum("bhqd,bhkd->bhqk", operands).unwrap()
+/// );
+/// ```
+pub struct ContractorRegistry<A> {
+    singleton_matchers: Vec<SingletonMatcher<A>>,
+    pair_matchers: Vec<PairMatcher<A>>,
+}
+
+impl<A> Default for ContractorRegistry<A> {
+    fn default() -> Self {
+        ContractorRegistry::new()
+    }
+}
+
+impl<A> core::fmt::Debug for ContractorRegistry<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "ContractorRegistry {{ singleton_matchers: {}, pair_matchers: {} }}",
+            self.singleton_matchers.len(),
+            self.pair_matchers.len()
+        )
+    }
+}
+
+impl<A> ContractorRegistry<A> {
+    /// Creates a registry without any matchers, with which every step gets a built-in
+    /// contractor.
+    pub fn new() -> Self {
+        ContractorRegistry {
+            singleton_matchers: Vec::new(),
+            pair_matchers: Vec::new(),
+        }
+    }
+
+    /// Adds a matcher for contractions of a single operand, which is called with the
+    /// contraction and returns the contractor to perform it with, or `None` to leave it to the
+    /// matchers registered after it (and finally the built-in contractors).
+    pub fn register_singleton_contractor<F>(&mut self, matcher: F) -> &mut Self
+    where
+        F: Fn(&SizedContraction) -> Option<Box<dyn SingletonContractor<A> + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.singleton_matchers.push(Box::new(matcher));
+        self
+    }
+
+    /// Adds a matcher for the steps of a path that contract two operands, as for
+    /// [`register_singleton_contractor`](#method.register_singleton_contractor).
+    pub fn register_pair_contractor<F>(&mut self, matcher: F) -> &mut Self
+    where
+        F: Fn(&SizedContraction) -> Option<Box<dyn PairContractor<A> + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.pair_matchers.push(Box::new(matcher));
+        self
+    }
+
+    /// Compiles `contraction_order` as `EinsumPath::from_path` does, except that each step is
+    /// first offered to the registered matchers.
+    pub fn path(&self, contraction_order: &ContractionOrder) -> EinsumPath<A> {
+        let steps = match contraction_order {
+            ContractionOrder::Singleton(sc) => EinsumPathSteps::SingletonContraction(
+                match self
+                    .singleton_matchers
+                    .iter()
+                    .find_map(|matcher| matcher(sc))
+                {
+                    Some(op) => SingletonContraction::custom(op),
+                    None => SingletonContraction::new(sc),
+                },
+            ),
+            ContractionOrder::Pairs(order_steps) => EinsumPathSteps::PairContractions(
+                order_steps
+                    .iter()
+                    .map(|step| {
+                        let sc = &step.sized_contraction;
+                        match self.pair_matchers.iter().find_map(|matcher| matcher(sc)) {
+                            Some(op) => PairContraction::custom(sc, op),
+                            None => PairContraction::new(sc),
+                        }
+                    })
+                    .collect(),
+            ),
+        };
+        EinsumPath {
+            contraction_order: contraction_order.clone(),
+            steps,
+        }
+    }
+
+    /// Performs the contraction as [`einsum`](fn.einsum.html) does, with the path compiled by
+    /// [`path`](#method.path). Unlike `einsum`, the contraction is always performed step by
+    /// step, so that every step goes through the registry, unless the budget set by
+    /// [`with_max_intermediate_bytes`](fn.with_max_intermediate_bytes.html) requires it to be
+    /// performed in chunks, whose paths get the built-in contractors.
+    pub fn einsum(
+        &self,
+        input_string: &str,
+        operands: &[&dyn ArrayLike<A>],
+    ) -> Result<ArrayD<A>, EinsumError>
+    where
+        A: LinalgScalar,
+    {
+        let contraction_order = cached_order(input_string, operands)?;
+        Ok(self.path(&contraction_order).contract_operands(operands))
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `PathReport`, a human-readable summary of a contraction order in the style of the
+//! second value returned by numpy's `einsum_path`, and `ExecutionReport`, which records how long
+//! each step of a path actually took.
+//!
+//! `ExecutionReport` needs a clock, so it isn't available without `std` or on
+//! `wasm32-unknown-unknown`, where `std::time::Instant` panics.
+use crate::optimizers::{get_flop_count, OperandNumber};
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use crate::EinsumPath;
+use crate::{
+    generate_optimized_order, validate_and_size, ArrayLike, ContractionOrder, EinsumError,
+    OptimizationMethod, SizedContraction,
+};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use ndarray::prelude::*;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use ndarray::LinalgScalar;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use std::time::{Duration, Instant};
+
+/// One step of a [`PathReport`](struct.PathReport.html).
+#[derive(Debug, Clone)]
+pub struct PathReportStep {
+    /// The contraction performed at this step, e.g. `ij,jk->ik`
+    pub einsum_string: String,
+
+    /// The number of distinct indices involved in the step
+    pub scaling: usize,
+
+    /// The shape of the tensor produced by the step
+    pub output_shape: Vec<usize>,
+
+    /// The number of floating-point operations needed for the step, as counted by
+    /// [`ContractionOrder::flop_count`](enum.ContractionOrder.html#method.flop_count)
+    pub flop_count: u128,
+
+    /// The contraction that remains to be performed after this step, e.g. `ik,kl->il`
+    pub remaining: String,
+}
+
+/// A summary of the cost of a contraction order compared with contracting all the operands in
+/// a single step, returned by [`einsum_path_report`](fn.einsum_path_report.html). Its `Display`
+/// implementation prints a table like the one returned by numpy's `einsum_path`:
+///
+/// ```text
+///   Complete contraction:  ij,jk,kl->il
+///          Naive scaling:  4
+///      Optimized scaling:  3
+///       Naive FLOP count:  1.800e+04
+///   Optimized FLOP count:  1.600e+03
+///    Theoretical speedup:  11.250
+///   Largest intermediate:  2.000e+01 elements
+/// -----------------------------------------------------------------------------------------------------
+/// scaling      flops                  current            shape                                remaining
+/// -----------------------------------------------------------------------------------------------------
+///    3     1.200e+03                jk,kl->jl          [20, 1]                                ij,jl->il
+///    3     4.000e+02                ij,jl->il          [10, 1]                                   il->il
+/// ```
+#[derive(Debug, Clone)]
+pub struct PathReport {
+    /// The full contraction, e.g. `ij,jk,kl->il`
+    pub einsum_string: String,
+
+    /// The number of distinct indices in the full contraction
+    pub naive_scaling: usize,
+
+    /// The largest number of distinct indices involved in any step
+    pub optimized_scaling: usize,
+
+    /// The number of floating-point operations needed to perform the full contraction in a
+    /// single step
+    pub naive_flop_count: u128,
+
+    /// The number of floating-point operations needed to perform all the steps
+    pub optimized_flop_count: u128,
+
+    /// The number of elements in the largest tensor produced by any of the steps
+    pub largest_intermediate: u128,
+
+    /// The steps in the order they are performed
+    pub steps: Vec<PathReportStep>,
+}
+
+impl PathReport {
+    /// Summarizes how `contraction_order` performs `sized_contraction`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![10, 20], vec![20, 30], vec![30, 1]],
+    /// ).unwrap();
+    /// let order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+    /// let report = PathReport::new(&sc, &order);
+    /// assert_eq!(report.naive_scaling, 4);
+    /// assert_eq!(report.optimized_scaling, 3);
+    /// assert_eq!(report.steps[0].einsum_string, "jk,kl->jl");
+    /// assert_eq!(report.steps[0].output_shape, vec![20, 1]);
+    /// assert_eq!(report.steps[0].remaining, "ij,jl->il");
+    /// assert_eq!(report.speedup(), 11.25);
+    /// ```
+    pub fn new(sized_contraction: &SizedContraction, contraction_order: &ContractionOrder) -> Self {
+        let num_operands = sized_contraction.contraction.operand_indices.len();
+        let output_string: String = sized_contraction
+            .contraction
+            .output_indices
+            .iter()
+            .collect();
+        let step_report =
+            |sc: &SizedContraction, num_terms: usize, remaining: String| PathReportStep {
+                einsum_string: sc.as_einsum_string(),
+                scaling: sc.output_size.len(),
+                output_shape: sc
+                    .contraction
+                    .output_indices
+                    .iter()
+                    .map(|c| sc.output_size[c])
+                    .collect(),
+                flop_count: get_flop_count(sc, num_terms),
+                remaining,
+            };
+
+        let steps = match contraction_order {
+            ContractionOrder::Singleton(sc) => {
+                vec![step_report(
+                    sc,
+                    1,
+                    format!("{}->{}", output_string, output_string),
+                )]
+            }
+            ContractionOrder::Pairs(order_steps) => {
+                // The tensors that are left, as (is an intermediate result, number, indices)
+                let mut remaining: Vec<(bool, usize, Vec<char>)> = sized_contraction
+                    .contraction
+                    .operand_indices
+                    .iter()
+                    .enumerate()
+                    .map(|(i, indices)| (false, i, indices.clone()))
+                    .collect();
+                let key = |operand_num: &OperandNumber| match *operand_num {
+                    OperandNumber::Input(i) => (false, i),
+                    OperandNumber::IntermediateResult(i) => (true, i),
+                };
+
+                let mut steps = Vec::new();
+                for (step_num, step) in order_steps.iter().enumerate() {
+                    let lhs = key(&step.operand_nums.lhs);
+                    let rhs = key(&step.operand_nums.rhs);
+                    remaining.retain(|&(is_intermediate, num, _)| {
+                        (is_intermediate, num) != lhs && (is_intermediate, num) != rhs
+                    });
+                    remaining.push((
+                        true,
+                        step_num,
+                        step.sized_contraction.contraction.output_indices.clone(),
+                    ));
+                    let remaining_inputs: Vec<String> = remaining
+                        .iter()
+                        .map(|(_, _, indices)| indices.iter().collect())
+                        .collect();
+                    let remaining_string =
+                        format!("{}->{}", remaining_inputs.join(","), output_string);
+                    steps.push(step_report(&step.sized_contraction, 2, remaining_string));
+                }
+                steps
+            }
+        };
+
+        PathReport {
+            einsum_string: sized_contraction.as_einsum_string(),
+            naive_scaling: sized_contraction.output_size.len(),
+            optimized_scaling: steps.iter().map(|step| step.scaling).max().unwrap(),
+            naive_flop_count: get_flop_count(sized_contraction, num_operands),
+            optimized_flop_count: contraction_order.flop_count(),
+            largest_intermediate: contraction_order.largest_intermediate(),
+            steps,
+        }
+    }
+
+    /// The ratio of the naive FLOP count to the optimized FLOP count.
+    pub fn speedup(&self) -> f64 {
+        self.naive_flop_count as f64 / self.optimized_flop_count.max(1) as f64
+    }
+}
+
+/// Formats `x` the way Python's `%.3e` does, e.g. `1.200e+04`.
+fn format_scientific(x: u128) -> String {
+    let formatted = format!("{:.3e}", x as f64);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+impl fmt::Display for PathReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rule = "-".repeat(101);
+        writeln!(f, "  Complete contraction:  {}", self.einsum_string)?;
+        writeln!(f, "         Naive scaling:  {}", self.naive_scaling)?;
+        writeln!(f, "     Optimized scaling:  {}", self.optimized_scaling)?;
+        writeln!(
+            f,
+            "      Naive FLOP count:  {}",
+            format_scientific(self.naive_flop_count)
+        )?;
+        writeln!(
+            f,
+            "  Optimized FLOP count:  {}",
+            format_scientific(self.optimized_flop_count)
+        )?;
+        writeln!(f, "   Theoretical speedup:  {:.3}", self.speedup())?;
+        writeln!(
+            f,
+            "  Largest intermediate:  {} elements",
+            format_scientific(self.largest_intermediate)
+        )?;
+        writeln!(f, "{}", rule)?;
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>24} {:>16} {:>40}",
+            "scaling", "flops", "current", "shape", "remaining"
+        )?;
+        write!(f, "{}", rule)?;
+        for step in self.steps.iter() {
+            write!(
+                f,
+                "\n{:>4}    {:>10} {:>24} {:>16} {:>40}",
+                step.scaling,
+                format_scientific(step.flop_count),
+                step.einsum_string,
+                format!("{:?}", step.output_shape),
+                step.remaining
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates and sizes the contraction, chooses an order with `optimization_strategy`, and
+/// summarizes its cost in a [`PathReport`](struct.PathReport.html), which prints like the second
+/// value returned by numpy's `einsum_path`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::zeros((10, 20));
+/// let b: Array2<f64> = Array::zeros((20, 30));
+/// let c: Array2<f64> = Array::zeros((30, 1));
+/// let report = einsum_path_report("ij,jk,kl->il", &[&a, &b, &c], OptimizationMethod::Greedy)
+///     .unwrap();
+/// let printed = report.to_string();
+/// assert!(printed.contains("Optimized FLOP count:  1.600e+03"));
+/// println!("{}", report);
+/// ```
+pub fn einsum_path_report<A>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    optimization_strategy: OptimizationMethod,
+) -> Result<PathReport, EinsumError> {
+    let sized_contraction = validate_and_size(input_string, operands)?;
+    let contraction_order = generate_optimized_order(&sized_contraction, optimization_strategy);
+    Ok(PathReport::new(&sized_contraction, &contraction_order))
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+/// One step of an [`ExecutionReport`](struct.ExecutionReport.html).
+#[derive(Debug, Clone)]
+pub struct StepExecutionReport {
+    /// The contraction performed at this step, e.g. `ij,jk->ik`
+    pub einsum_string: String,
+
+    /// The shape of the tensor produced by the step
+    pub output_shape: Vec<usize>,
+
+    /// How long the step took
+    pub wall_time: Duration,
+
+    /// The estimated number of floating-point operations performed by the step, as counted by
+    /// [`ContractionOrder::flop_count`](enum.ContractionOrder.html#method.flop_count)
+    pub flop_count: u128,
+
+    /// The size in bytes of the array allocated for the result of the step
+    pub allocated_bytes: usize,
+
+    /// The total size in bytes of the results of the steps (including this one) that are
+    /// held in memory while the step is performed: its result, and the intermediate results
+    /// that are its operands or that are still waiting to be used. The operands of the
+    /// contraction and the temporaries used inside a step aren't counted.
+    pub resident_bytes: usize,
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl StepExecutionReport {
+    /// The achieved rate, in billions of floating-point operations per second.
+    pub fn gflops(&self) -> f64 {
+        gflops(self.flop_count, self.wall_time)
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+/// The time taken by each step of a contraction, along with its estimated FLOP count, the
+/// memory allocated for its result and the memory held by intermediate results while it was
+/// performed. Returned by
+/// [`EinsumPath::contract_operands_with_report`](struct.EinsumPath.html#method.contract_operands_with_report).
+#[derive(Debug, Clone)]
+pub struct ExecutionReport {
+    /// The steps in the order they were performed
+    pub steps: Vec<StepExecutionReport>,
+
+    /// How long the whole contraction took
+    pub wall_time: Duration,
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl ExecutionReport {
+    /// The estimated number of floating-point operations performed by all the steps.
+    pub fn flop_count(&self) -> u128 {
+        self.steps
+            .iter()
+            .map(|step| step.flop_count)
+            .fold(0, u128::saturating_add)
+    }
+
+    /// The total size in bytes of the arrays allocated for the results of all the steps.
+    pub fn allocated_bytes(&self) -> usize {
+        self.steps.iter().map(|step| step.allocated_bytes).sum()
+    }
+
+    /// The number of arrays allocated for the results of the steps, which is the number of
+    /// steps whose results aren't empty.
+    pub fn num_allocations(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.allocated_bytes > 0)
+            .count()
+    }
+
+    /// The largest total size in bytes of the results held in memory at once, i.e. the
+    /// largest `resident_bytes` of any step. Intermediate results are freed as soon as the
+    /// step that uses them has been performed.
+    pub fn peak_resident_bytes(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|step| step.resident_bytes)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The achieved rate over the whole contraction, in billions of floating-point operations
+    /// per second.
+    pub fn gflops(&self) -> f64 {
+        gflops(self.flop_count(), self.wall_time)
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+fn gflops(flop_count: u128, wall_time: Duration) -> f64 {
+    flop_count as f64 / wall_time.as_secs_f64().max(f64::MIN_POSITIVE) / 1e9
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rule = "-".repeat(96);
+        writeln!(f, "{}", rule)?;
+        writeln!(
+            f,
+            "{:>4} {:>24} {:>12} {:>10} {:>12} {:>8} {:>9} {:>10}",
+            "step", "current", "time (ms)", "flops", "GFLOP/s", "MiB", "live MiB", "shape"
+        )?;
+        writeln!(f, "{}", rule)?;
+        let mib = |bytes: usize| bytes as f64 / (1 << 20) as f64;
+        for (step_num, step) in self.steps.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4} {:>24} {:>12.3} {:>10} {:>12.3} {:>8.3} {:>9.3} {:>10}",
+                step_num,
+                step.einsum_string,
+                step.wall_time.as_secs_f64() * 1e3,
+                format_scientific(step.flop_count),
+                step.gflops(),
+                mib(step.allocated_bytes),
+                mib(step.resident_bytes),
+                format!("{:?}", step.output_shape)
+            )?;
+        }
+        writeln!(f, "{}", rule)?;
+        write!(
+            f,
+            "{:>4} {:>24} {:>12.3} {:>10} {:>12.3} {:>8.3} {:>9.3}",
+            "",
+            "total",
+            self.wall_time.as_secs_f64() * 1e3,
+            format_scientific(self.flop_count()),
+            self.gflops(),
+            mib(self.allocated_bytes()),
+            mib(self.peak_resident_bytes())
+        )
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl<A> EinsumPath<A> {
+    /// Same as `contract_operands`, but also times each step and returns an
+    /// [`ExecutionReport`](struct.ExecutionReport.html) with the wall time, estimated FLOP
+    /// count, achieved GFLOP/s, and size of the result of every step, along with the memory
+    /// held by the intermediate results while it was performed. Comparing the reports for the
+    /// paths produced by different `OptimizationMethod`s shows which one is actually faster,
+    /// and `peak_resident_bytes` shows whether a path fits in the memory available.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::ones((20, 30));
+    /// let m2: Array2<f64> = Array::ones((30, 40));
+    /// let m3: Array1<f64> = Array::ones(40);
+    /// let path = einsum_path("ij,jk,k->i", &[&m1, &m2, &m3], OptimizationMethod::Naive).unwrap();
+    /// let (result, report) = path.contract_operands_with_report(&[&m1, &m2, &m3]);
+    /// assert_eq!(result, Array::from_elem(20, 1200.).into_dyn());
+    /// assert_eq!(report.steps.len(), 2);
+    /// assert_eq!(report.steps[0].flop_count, 2 * 20 * 30 * 40);
+    /// assert_eq!(report.steps[0].allocated_bytes, 8 * 20 * 40);
+    /// // The result of the first step is freed once the second step has been performed
+    /// assert_eq!(report.peak_resident_bytes(), 8 * 20 * 40 + 8 * 20);
+    /// assert_eq!(report.num_allocations(), 2);
+    /// assert!(report.steps[0].wall_time <= report.wall_time);
+    /// println!("{}", report);
+    /// ```
+    pub fn contract_operands_with_report(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+    ) -> (ArrayD<A>, ExecutionReport)
+    where
+        A: Clone + LinalgScalar,
+    {
+        let element_bytes = core::mem::size_of::<A>();
+        // The contraction of each step, its number of terms, and the intermediate results it
+        // consumes
+        let step_contractions: Vec<(&SizedContraction, usize, Vec<usize>)> =
+            match &self.contraction_order {
+                ContractionOrder::Singleton(sc) => vec![(sc, 1, Vec::new())],
+                ContractionOrder::Pairs(steps) => steps
+                    .iter()
+                    .map(|step| {
+                        let consumed = [&step.operand_nums.lhs, &step.operand_nums.rhs]
+                            .iter()
+                            .filter_map(|operand_num| match operand_num {
+                                OperandNumber::Input(_) => None,
+                                OperandNumber::IntermediateResult(pos) => Some(*pos),
+                            })
+                            .collect();
+                        (&step.sized_contraction, 2, consumed)
+                    })
+                    .collect(),
+            };
+        // The size of the result of each step, or 0 before it is computed and once it has been
+        // freed. If an index has length 0 only the last step is reported.
+        let mut live_bytes: Vec<usize> = vec![0; step_contractions.len()];
+
+        let mut steps = Vec::new();
+        let start = Instant::now();
+        let mut step_start = start;
+        let result = self.execute_with(operands, |step_num, intermediate| {
+            let wall_time = step_start.elapsed();
+            let (sc, num_terms, consumed) = &step_contractions[step_num];
+            let allocated_bytes = intermediate.len() * element_bytes;
+            let resident_bytes = live_bytes.iter().sum::<usize>() + allocated_bytes;
+            for &pos in consumed.iter() {
+                live_bytes[pos] = 0;
+            }
+            live_bytes[step_num] = allocated_bytes;
+            steps.push(StepExecutionReport {
+                einsum_string: sc.as_einsum_string(),
+                output_shape: intermediate.shape().to_vec(),
+                wall_time,
+                flop_count: get_flop_count(sc, *num_terms),
+                allocated_bytes,
+                resident_bytes,
+            });
+            step_start = Instant::now();
+        });
+        let wall_time = start.elapsed();
+
+        (result, ExecutionReport { steps, wall_time })
+    }
+}
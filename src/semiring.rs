@@ -0,0 +1,439 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_semiring`, which performs a contraction over a `Semiring` other than the
+//! usual (+, ×) on numbers.
+//!
+//! The contractors behind `EinsumPath` rely on `LinalgScalar` and on `ndarray`'s matrix
+//! multiplication, so they can't be used here. The contraction order is still chosen by the
+//! optimizer, but each step is performed by a generic loop nest that consults only the
+//! `Semiring`: for every element of the step's output, it adds up the products of the
+//! corresponding input elements over all values of the summed indices.
+use crate::optimizers::OperandNumber;
+use crate::{cached_order, ArrayLike, ContractionOrder, EinsumError, SizedContraction};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use ndarray::prelude::*;
+use num_traits::Float;
+
+/// An algebra to contract over: a set of elements, an associative and commutative addition
+/// with identity `zero`, and an associative multiplication that distributes over it.
+///
+/// The type implementing the trait only names the semiring, so that several semirings can
+/// share an element type.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // The capacity of the widest path through a network is the maximum over the paths
+/// // of the minimum capacity along each one.
+/// struct Bottleneck;
+///
+/// impl Semiring for Bottleneck {
+///     type Element = f64;
+///
+///     fn zero() -> f64 {
+///         0.
+///     }
+///
+///     fn add(lhs: &f64, rhs: &f64) -> f64 {
+///         lhs.max(*rhs)
+///     }
+///
+///     fn mul(lhs: &f64, rhs: &f64) -> f64 {
+///         lhs.min(*rhs)
+///     }
+/// }
+///
+/// let capacity = array![[0., 5., 2.], [0., 0., 4.], [0., 0., 0.]];
+/// let two_hops = einsum_semiring::<Bottleneck>("ij,jk->ik", &[&capacity, &capacity]).unwrap();
+/// assert_eq!(two_hops[[0, 2]], 4.);
+/// ```
+pub trait Semiring {
+    type Element: Clone;
+
+    /// The identity of `add`, and the result of a sum with no terms.
+    fn zero() -> Self::Element;
+
+    fn add(lhs: &Self::Element, rhs: &Self::Element) -> Self::Element;
+
+    fn mul(lhs: &Self::Element, rhs: &Self::Element) -> Self::Element;
+
+    /// Adds up all the terms of one element of the output. The default implementation folds
+    /// `add` over them; a semiring can override it with a more accurate or faster reduction.
+    fn sum(terms: &[Self::Element]) -> Self::Element {
+        terms
+            .iter()
+            .fold(Self::zero(), |sum, term| Self::add(&sum, term))
+    }
+}
+
+/// The max-plus (tropical) semiring on floating-point numbers: addition is `max` and
+/// multiplication is `+`, with `-∞` as the zero.
+///
+/// A contraction over it computes the best score over all assignments of the summed indices,
+/// as in the Viterbi algorithm.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // Log-probabilities of the initial states, of the transitions, and of the observed symbols
+/// let initial = array![-0.5f64, -1.0];
+/// let transition = array![[-0.2, -1.8], [-1.5, -0.3]];
+/// let emission = array![[-0.1, -2.5], [-2.0, -0.2]];
+/// let observed = [0, 1];
+/// let best = einsum_semiring::<MaxPlus<f64>>(
+///     "i,i,ij,j->",
+///     &[&initial, &emission.column(observed[0]), &transition, &emission.column(observed[1])],
+/// )
+/// .unwrap();
+/// // The best path goes from state 0 to state 1
+/// assert!((best.sum() - (-0.5 - 0.1 - 1.8 - 0.2)).abs() < 1e-12);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MaxPlus<T>(PhantomData<T>);
+
+impl<T: Float> Semiring for MaxPlus<T> {
+    type Element = T;
+
+    fn zero() -> T {
+        T::neg_infinity()
+    }
+
+    fn add(lhs: &T, rhs: &T) -> T {
+        lhs.max(*rhs)
+    }
+
+    fn mul(lhs: &T, rhs: &T) -> T {
+        *lhs + *rhs
+    }
+}
+
+/// The min-plus (tropical) semiring on floating-point numbers: addition is `min` and
+/// multiplication is `+`, with `+∞` as the zero.
+///
+/// A contraction over it computes the cheapest total cost over all assignments of the summed
+/// indices, e.g. one relaxation step of an all-pairs shortest path computation.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let inf = f64::INFINITY;
+/// // Edge weights, with 0 on the diagonal so that paths can stop early
+/// let weights = array![[0., 4., inf], [inf, 0., 1.], [2., inf, 0.]];
+/// let two_hops = einsum_semiring::<MinPlus<f64>>("ij,jk->ik", &[&weights, &weights]).unwrap();
+/// assert_eq!(
+///     two_hops,
+///     array![[0., 4., 5.], [3., 0., 1.], [2., 6., 0.]].into_dyn()
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MinPlus<T>(PhantomData<T>);
+
+impl<T: Float> Semiring for MinPlus<T> {
+    type Element = T;
+
+    fn zero() -> T {
+        T::infinity()
+    }
+
+    fn add(lhs: &T, rhs: &T) -> T {
+        lhs.min(*rhs)
+    }
+
+    fn mul(lhs: &T, rhs: &T) -> T {
+        *lhs + *rhs
+    }
+}
+
+/// The log semiring on floating-point numbers, in which each element stands for the logarithm
+/// of a non-negative number: multiplication is `+` and addition is `ln(exp(a) + exp(b))`,
+/// with `-∞` (the logarithm of 0) as the zero. See
+/// [`einsum_logsumexp`](fn.einsum_logsumexp.html).
+///
+/// The terms of each sum are shifted by their maximum before being exponentiated, so that
+/// neither underflows nor overflows.
+#[derive(Debug, Clone, Copy)]
+pub struct LogSumExp<T>(PhantomData<T>);
+
+impl<T: Float> Semiring for LogSumExp<T> {
+    type Element = T;
+
+    fn zero() -> T {
+        T::neg_infinity()
+    }
+
+    fn add(lhs: &T, rhs: &T) -> T {
+        Self::sum(&[*lhs, *rhs])
+    }
+
+    fn mul(lhs: &T, rhs: &T) -> T {
+        *lhs + *rhs
+    }
+
+    fn sum(terms: &[T]) -> T {
+        let max = terms.iter().fold(T::neg_infinity(), |max, &x| max.max(x));
+        // Only happens if there are no terms, all of them are -∞, or one of them is +∞
+        if max.is_infinite() {
+            return max;
+        }
+        max + terms
+            .iter()
+            .fold(T::zero(), |sum, &x| sum + (x - max).exp())
+            .ln()
+    }
+}
+
+/// The Boolean semiring: addition is OR and multiplication is AND, with `false` as the zero.
+/// See [`einsum_bool`](fn.einsum_bool.html).
+#[derive(Debug, Clone, Copy)]
+pub struct Boolean;
+
+impl Semiring for Boolean {
+    type Element = bool;
+
+    fn zero() -> bool {
+        false
+    }
+
+    fn add(lhs: &bool, rhs: &bool) -> bool {
+        *lhs || *rhs
+    }
+
+    fn mul(lhs: &bool, rhs: &bool) -> bool {
+        *lhs && *rhs
+    }
+
+    fn sum(terms: &[bool]) -> bool {
+        terms.iter().any(|&x| x)
+    }
+}
+
+/// Performs the contraction described by `sc` over the semiring `S` with a generic loop nest.
+///
+/// An axis of length 1 whose index has a larger size elsewhere in the contraction is
+/// broadcast, and an output with repeated indices has `S::zero()` off the diagonal.
+pub(crate) fn contract_loop_nest<S: Semiring>(
+    sc: &SizedContraction,
+    operands: &[ArrayViewD<S::Element>],
+) -> ArrayD<S::Element> {
+    let output_indices = &sc.contraction.output_indices;
+    let summation_indices = &sc.contraction.summation_indices;
+
+    // Each index is given a slot in `values`; the distinct output indices come first.
+    let mut slots: Vec<char> = Vec::new();
+    for &c in output_indices.iter().chain(summation_indices) {
+        if !slots.contains(&c) {
+            slots.push(c);
+        }
+    }
+    let slot_of = |c: &char| slots.iter().position(|s| s == c).unwrap();
+    let output_slots: Vec<usize> = output_indices.iter().map(slot_of).collect();
+    let num_output_slots = slots.len() - summation_indices.len();
+    let summed_shape: Vec<usize> = summation_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect();
+
+    // For each axis of each operand, the slot of its index, or None if it's broadcast
+    let operand_slots: Vec<Vec<Option<usize>>> = sc
+        .contraction
+        .operand_indices
+        .iter()
+        .zip(operands)
+        .map(|(indices, operand)| {
+            indices
+                .iter()
+                .zip(operand.shape())
+                .map(|(c, &len)| {
+                    if len == 1 && sc.output_size[c] != 1 {
+                        None
+                    } else {
+                        Some(slot_of(c))
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let operand_element = |operand: usize, values: &[usize]| {
+        let index: Vec<usize> = operand_slots[operand]
+            .iter()
+            .map(|slot| slot.map_or(0, |slot| values[slot]))
+            .collect();
+        operands[operand][IxDyn(&index)].clone()
+    };
+
+    let output_shape: Vec<usize> = output_indices.iter().map(|c| sc.output_size[c]).collect();
+    let mut values = vec![0; slots.len()];
+    let mut terms = Vec::with_capacity(summed_shape.iter().product());
+    Array::from_shape_fn(IxDyn(&output_shape), |output_index| {
+        for (axis, &slot) in output_slots.iter().enumerate() {
+            if slot_assigned_differently(&output_slots, axis, &output_index) {
+                return S::zero();
+            }
+            values[slot] = output_index[axis];
+        }
+
+        terms.clear();
+        for summed_index in ndarray::indices(IxDyn(&summed_shape)) {
+            for (axis, value) in values[num_output_slots..].iter_mut().enumerate() {
+                *value = summed_index[axis];
+            }
+            let product = (1..operands.len()).fold(operand_element(0, &values), |product, i| {
+                S::mul(&product, &operand_element(i, &values))
+            });
+            terms.push(product);
+        }
+        S::sum(&terms)
+    })
+}
+
+/// Returns true if an earlier axis of the output has the same index as `axis` but a different
+/// coordinate in `output_index`, i.e. the element is off the diagonal.
+fn slot_assigned_differently(output_slots: &[usize], axis: usize, output_index: &IxDyn) -> bool {
+    output_slots[..axis]
+        .iter()
+        .enumerate()
+        .any(|(earlier, &slot)| {
+            slot == output_slots[axis] && output_index[earlier] != output_index[axis]
+        })
+}
+
+/// Performs the contraction described by `input_string` over the semiring `S`: the products
+/// and sums of `einsum` are replaced by `S::mul` and `S::add`.
+///
+/// The contraction order is optimized as for `einsum`, but every step is performed by a generic
+/// loop nest instead of the specialized contractors, so this is much slower than `einsum` for
+/// ordinary arithmetic.
+///
+/// Returns an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // Counting, with the addition saturating at 255 instead of overflowing
+/// struct SaturatingCount;
+///
+/// impl Semiring for SaturatingCount {
+///     type Element = u8;
+///
+///     fn zero() -> u8 {
+///         0
+///     }
+///
+///     fn add(lhs: &u8, rhs: &u8) -> u8 {
+///         lhs.saturating_add(*rhs)
+///     }
+///
+///     fn mul(lhs: &u8, rhs: &u8) -> u8 {
+///         lhs.saturating_mul(*rhs)
+///     }
+/// }
+///
+/// let ones: Array1<u8> = Array::ones(300);
+/// let count = einsum_semiring::<SaturatingCount>("i,i->", &[&ones, &ones]).unwrap();
+/// assert_eq!(count, arr0(255).into_dyn());
+/// ```
+pub fn einsum_semiring<S: Semiring>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<S::Element>],
+) -> Result<ArrayD<S::Element>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    let input_views: Vec<ArrayViewD<S::Element>> =
+        operands.iter().map(|x| x.into_dyn_view()).collect();
+
+    Ok(match contraction_order {
+        ContractionOrder::Singleton(sc) => contract_loop_nest::<S>(&sc, &input_views),
+        ContractionOrder::Pairs(order_steps) => {
+            let mut intermediate_results: Vec<Option<ArrayD<S::Element>>> = Vec::new();
+            for order_step in order_steps.iter() {
+                let operand_nums = [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs];
+                let views: Vec<ArrayViewD<S::Element>> = operand_nums
+                    .iter()
+                    .map(|operand_number| match **operand_number {
+                        OperandNumber::Input(pos) => input_views[pos].view(),
+                        OperandNumber::IntermediateResult(pos) => {
+                            intermediate_results[pos].as_ref().unwrap().view()
+                        }
+                    })
+                    .collect();
+                let result = contract_loop_nest::<S>(&order_step.sized_contraction, &views);
+
+                // Intermediate results are only used once, so they can be dropped.
+                for operand_number in operand_nums {
+                    if let OperandNumber::IntermediateResult(pos) = *operand_number {
+                        intermediate_results[pos] = None;
+                    }
+                }
+                intermediate_results.push(Some(result));
+            }
+            intermediate_results.pop().unwrap().unwrap()
+        }
+    })
+}
+
+/// Performs the contraction described by `input_string` on operands holding the logarithms of
+/// non-negative numbers, and returns the logarithm of the result: for `"ij,jk->ik"`,
+/// `out[i, k] = ln(Σ_j exp(a[i, j] + b[j, k]))`. This is the contraction over the
+/// [`LogSumExp`](struct.LogSumExp.html) semiring.
+///
+/// Unlike exponentiating the operands, contracting them with `einsum` and taking the
+/// logarithm of the result, it doesn't underflow for very small probabilities.
+///
+/// Returns an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // exp(-1000) is 0 in f64
+/// let log_a: Array2<f64> = Array::from_elem((2, 3), -1000.);
+/// let log_b: Array2<f64> = Array::from_elem((3, 2), -1000.);
+/// let log_product = einsum_logsumexp("ij,jk->ik", &[&log_a, &log_b]).unwrap();
+/// let expected = -2000. + 3f64.ln();
+/// assert!(log_product.iter().all(|&x| (x - expected).abs() < 1e-9));
+/// ```
+pub fn einsum_logsumexp<A: Float>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+) -> Result<ArrayD<A>, EinsumError> {
+    einsum_semiring::<LogSumExp<A>>(input_string, operands)
+}
+
+/// Performs the contraction described by `input_string` on Boolean operands, with AND in place
+/// of multiplication and OR in place of summation: the result is `true` wherever some
+/// assignment of the summed indices makes all of the corresponding input elements `true`.
+/// This is the contraction over the [`Boolean`](struct.Boolean.html) semiring.
+///
+/// With relations stored as Boolean matrices, `"ij,jk->ik"` composes them and `"ij,jk,kl->il"`
+/// finds the pairs connected by a path of exactly three edges.
+///
+/// Returns an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // 0 -> 1 -> 2 -> 0
+/// let edges = array![[false, true, false], [false, false, true], [true, false, false]];
+/// let two_steps = einsum_bool("ij,jk->ik", &[&edges, &edges]).unwrap();
+/// assert_eq!(
+///     two_steps,
+///     array![[false, false, true], [true, false, false], [false, true, false]].into_dyn()
+/// );
+/// ```
+pub fn einsum_bool(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<bool>],
+) -> Result<ArrayD<bool>, EinsumError> {
+    einsum_semiring::<Boolean>(input_string, operands)
+}
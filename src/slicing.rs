@@ -0,0 +1,157 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_sliced`, which slices a summed index of a contraction (a bond of a tensor
+//! network) and adds up the contractions of the slices.
+//!
+//! The result of a contraction is the sum over each summed index of the contractions with that
+//! index fixed, so a summed index can be split into `k` ranges, the whole path performed for
+//! each range, and the `k` partial results added up. Every intermediate result that has the
+//! index shrinks by a factor of `k`, at the cost of repeating the steps that don't have it
+//! `k` times. Unlike splitting an output index, this also works for contractions into a scalar,
+//! which is what most tensor-network contractions are.
+use crate::out_of_core::{input_indices, result_indices, slice_operands, with_index_len};
+use crate::{
+    generate_optimized_order, ArrayLike, ContractionOrder, EinsumError, EinsumPath,
+    OptimizationMethod, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Zip};
+
+impl ContractionOrder {
+    /// Returns the summed index that, if split into `num_slices` slices, leaves the smallest
+    /// largest intermediate result, breaking ties by the total number of operations over all
+    /// the slices. Returns `None` if the contraction has no summed indices.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->",
+    ///     &[vec![2, 100], vec![100, 100], vec![100, 2]],
+    /// ).unwrap();
+    /// let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    /// // The first step produces an intermediate result with indices i and k
+    /// assert_eq!(order.best_slice_index(10), Some('k'));
+    /// ```
+    pub fn best_slice_index(&self, num_slices: usize) -> Option<char> {
+        let output_indices = result_indices(self);
+        let mut summed_indices: Vec<char> = Vec::new();
+        for &c in input_indices(self).iter().flatten() {
+            if !output_indices.contains(&c) && !summed_indices.contains(&c) {
+                summed_indices.push(c);
+            }
+        }
+
+        let mut best: Option<(char, (u128, u128))> = None;
+        for &index in summed_indices.iter() {
+            let index_len = self.index_len(index);
+            let num_slices = num_slices.clamp(1, index_len.max(1));
+            let sliced = with_index_len(self, index, index_len.div_ceil(num_slices));
+            let cost = (
+                sliced.largest_intermediate(),
+                sliced.flop_count().saturating_mul(num_slices as u128),
+            );
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                best = Some((index, cost));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+
+    /// The length of `index`, which appears in at least one of the steps.
+    fn index_len(&self, index: char) -> usize {
+        self.step_contractions()
+            .iter()
+            .find_map(|sc| sc.output_size.get(&index).cloned())
+            .unwrap()
+    }
+}
+
+/// Performs the contraction specified by `input_string` and `operands` as `num_slices`
+/// contractions of slices of the summed index `index` (or, if `index` is `None`, of the index
+/// chosen by [`ContractionOrder::best_slice_index`]), and returns the sum of their results. The
+/// contraction order is chosen as by `OptimizationMethod::Greedy`, once for all the slices. If
+/// `num_slices` is larger than the length of the index, each slice has length 1.
+///
+/// Returns `EinsumError::InvalidSliceIndex` if `index` isn't a summed index of the contraction,
+/// `EinsumError::Unsupported` if `num_slices` is zero, and otherwise the same errors as `einsum`.
+/// If `index` is `None` and nothing is summed, the contraction is performed as usual.
+///
+/// [`ContractionOrder::best_slice_index`]: enum.ContractionOrder.html#method.best_slice_index
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// let c: Array2<f64> = Array::range(0., 8., 1.).into_shape((4, 2)).unwrap();
+/// let trace = einsum_sliced("ij,jk,ki->", &[&a, &b, &c], Some('k'), 2).unwrap();
+/// assert_eq!(trace, einsum("ij,jk,ki->", &[&a, &b, &c]).unwrap());
+/// let trace = einsum_sliced("ij,jk,ki->", &[&a, &b, &c], None, 3).unwrap();
+/// assert_eq!(trace, einsum("ij,jk,ki->", &[&a, &b, &c]).unwrap());
+/// ```
+pub fn einsum_sliced<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    index: Option<char>,
+    num_slices: usize,
+) -> Result<ArrayD<A>, EinsumError> {
+    if num_slices == 0 {
+        return Err(EinsumError::Unsupported("num_slices has to be at least 1"));
+    }
+    let sc = SizedContraction::new(input_string, operands)?;
+    if let Some(index) = index {
+        if !sc.contraction.summation_indices.contains(&index) {
+            return Err(EinsumError::InvalidSliceIndex { index });
+        }
+    }
+    let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+    let index = match index.or_else(|| contraction_order.best_slice_index(num_slices)) {
+        Some(index) => index,
+        None => return Ok(EinsumPath::from_path(&contraction_order).contract_operands(operands)),
+    };
+
+    let index_len = sc.output_size[&index];
+    if index_len == 0 {
+        return Ok(EinsumPath::from_path(&contraction_order).contract_operands(operands));
+    }
+    let slice_len = index_len.div_ceil(num_slices);
+    let operand_indices = input_indices(&contraction_order);
+    let input_views: Vec<ArrayViewD<A>> = operands.iter().map(|x| x.into_dyn_view()).collect();
+
+    // Only the last slice can be shorter, so at most two paths are needed
+    let mut path: Option<(usize, EinsumPath<A>)> = None;
+    let mut result: Option<ArrayD<A>> = None;
+    for start in (0..index_len).step_by(slice_len) {
+        let end = (start + slice_len).min(index_len);
+        if path.as_ref().map(|(len, _)| *len) != Some(end - start) {
+            let slice_order = with_index_len(&contraction_order, index, end - start);
+            path = Some((end - start, EinsumPath::from_path(&slice_order)));
+        }
+
+        let slice_views =
+            slice_operands(&input_views, &operand_indices, index, index_len, start, end);
+        let sliced: Vec<&dyn ArrayLike<A>> =
+            slice_views.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+        let partial_result = path.as_ref().unwrap().1.contract_operands(&sliced);
+        match &mut result {
+            Some(result) => Zip::from(result)
+                .and(&partial_result)
+                .for_each(|x, &y| *x = *x + y),
+            None => result = Some(partial_result),
+        }
+    }
+    Ok(result.unwrap())
+}
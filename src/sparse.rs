@@ -0,0 +1,753 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `SparseOperand`, a tensor stored as a list of its nonzero elements, and
+//! `einsum_sparse`, which contracts any mixture of sparse and dense operands.
+//!
+//! The contraction order is chosen as for dense operands, and each pairwise step is performed
+//! according to the kinds of its two inputs:
+//!
+//! * Two dense tensors are contracted as by `einsum`, and the result is dense.
+//! * A sparse tensor and a dense one: the dense tensor is first reduced to the indices that
+//!   appear in the sparse tensor or in the output. Then each nonzero element of the sparse
+//!   tensor fixes the values of its indices, and the corresponding subview of the reduced
+//!   dense tensor, scaled by the element, is added to the corresponding subview of the dense
+//!   result. Only the nonzero elements are ever visited.
+//! * Two sparse tensors: the pairs of nonzero elements that agree on the shared indices are
+//!   multiplied together and accumulated into a sparse result.
+//!
+//! The final result is always returned as a dense array.
+//!
+//! With the `sprs` feature, a matrix in compressed row or column format (`sprs::CsMat`) can
+//! be used as an operand too. A step that multiplies it by a dense operand as a matrix-matrix
+//! or matrix-vector product goes through a kernel that walks the compressed storage and adds
+//! scaled rows of the (reshaped) dense operand; any other step converts it to coordinate
+//! format first.
+use crate::collections::HashMap;
+use crate::contractors::broadcast_input;
+use crate::optimizers::OperandNumber;
+use crate::{
+    generate_optimized_order, ContractionOrder, EinsumError, OptimizationMethod, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{Data, LinalgScalar, RawData, Zip};
+
+/// A tensor in coordinate (COO) format: a shape, and a list of elements, each given by its
+/// coordinates and its value. Elements that aren't listed are zero, and elements listed more
+/// than once are summed.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let sparse = SparseOperand::new(vec![2, 3], vec![vec![0, 2], vec![1, 0]], vec![5., 7.]);
+/// assert_eq!(sparse.nnz(), 2);
+/// assert_eq!(sparse.to_dense(), array![[0., 0., 5.], [7., 0., 0.]].into_dyn());
+/// assert_eq!(SparseOperand::from_dense(&sparse.to_dense()).nnz(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseOperand<A> {
+    shape: Vec<usize>,
+    coords: Vec<Vec<usize>>,
+    values: Vec<A>,
+}
+
+impl<A> SparseOperand<A> {
+    /// Creates a sparse tensor of shape `shape` whose element at `coords[n]` is `values[n]`.
+    ///
+    /// Panics if `coords` and `values` have different lengths, or if some coordinates don't
+    /// have one entry per dimension or are out of bounds.
+    pub fn new(shape: Vec<usize>, coords: Vec<Vec<usize>>, values: Vec<A>) -> Self {
+        assert_eq!(
+            coords.len(),
+            values.len(),
+            "each value needs one set of coordinates"
+        );
+        for coord in coords.iter() {
+            assert!(
+                coord.len() == shape.len() && coord.iter().zip(&shape).all(|(&i, &len)| i < len),
+                "coordinates {:?} are out of bounds for shape {:?}",
+                coord,
+                shape
+            );
+        }
+        SparseOperand {
+            shape,
+            coords,
+            values,
+        }
+    }
+
+    /// Creates a sparse tensor holding the nonzero elements of `array`.
+    pub fn from_dense<S, D>(array: &ArrayBase<S, D>) -> Self
+    where
+        A: LinalgScalar,
+        S: Data<Elem = A>,
+        D: Dimension,
+    {
+        let array = array.view().into_dyn();
+        let (coords, values) = array
+            .indexed_iter()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(coord, &value)| (coord.as_array_view().to_vec(), value))
+            .unzip();
+        SparseOperand {
+            shape: array.shape().to_vec(),
+            coords,
+            values,
+        }
+    }
+
+    /// Creates a sparse tensor holding the stored elements of `matrix`.
+    #[cfg(feature = "sprs")]
+    pub fn from_csmat(matrix: &sprs::CsMat<A>) -> Self
+    where
+        A: Clone,
+    {
+        let (coords, values) = matrix
+            .iter()
+            .map(|(value, (row, col))| (vec![row, col], value.clone()))
+            .unzip();
+        SparseOperand {
+            shape: vec![matrix.rows(), matrix.cols()],
+            coords,
+            values,
+        }
+    }
+
+    /// Returns the dense array with the same elements.
+    pub fn to_dense(&self) -> ArrayD<A>
+    where
+        A: LinalgScalar,
+    {
+        let mut dense = Array::zeros(IxDyn(&self.shape));
+        for (coord, &value) in self.coords.iter().zip(&self.values) {
+            let elem = &mut dense[IxDyn(coord)];
+            *elem = *elem + value;
+        }
+        dense
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// The number of elements stored, which includes any duplicates or explicit zeros.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn coords(&self) -> &[Vec<usize>] {
+        &self.coords
+    }
+
+    pub fn values(&self) -> &[A] {
+        &self.values
+    }
+}
+
+/// A borrowed operand of `einsum_sparse`, either dense or sparse.
+#[derive(Debug)]
+pub enum OperandRef<'a, A> {
+    Dense(ArrayViewD<'a, A>),
+    Sparse(&'a SparseOperand<A>),
+    #[cfg(feature = "sprs")]
+    CsMat(&'a sprs::CsMat<A>),
+}
+
+impl<A> OperandRef<'_, A> {
+    fn reborrow(&self) -> OperandRef<'_, A> {
+        match self {
+            OperandRef::Dense(view) => OperandRef::Dense(view.view()),
+            OperandRef::Sparse(sparse) => OperandRef::Sparse(sparse),
+            #[cfg(feature = "sprs")]
+            OperandRef::CsMat(matrix) => OperandRef::CsMat(matrix),
+        }
+    }
+
+    fn shape(&self) -> Vec<usize> {
+        match self {
+            OperandRef::Dense(view) => view.shape().to_vec(),
+            OperandRef::Sparse(sparse) => sparse.shape().to_vec(),
+            #[cfg(feature = "sprs")]
+            OperandRef::CsMat(matrix) => vec![matrix.rows(), matrix.cols()],
+        }
+    }
+}
+
+/// The operands accepted by [`einsum_sparse`](fn.einsum_sparse.html): all `ArrayBase`
+/// variants, as for `ArrayLike`, and `SparseOperand`.
+pub trait EinsumOperand<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A>;
+}
+
+impl<A, S, D> EinsumOperand<A> for ArrayBase<S, D>
+where
+    S: Data<Elem = A>,
+    D: Dimension,
+{
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::Dense(self.view().into_dyn())
+    }
+}
+
+impl<A> EinsumOperand<A> for SparseOperand<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::Sparse(self)
+    }
+}
+
+#[cfg(feature = "sprs")]
+impl<A> EinsumOperand<A> for sprs::CsMat<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        OperandRef::CsMat(self)
+    }
+}
+
+/// The result of a step, which is sparse only if both of its inputs were.
+enum Intermediate<A> {
+    Dense(ArrayD<A>),
+    Sparse(SparseOperand<A>),
+}
+
+impl<A> Intermediate<A> {
+    fn as_operand_ref(&self) -> OperandRef<'_, A> {
+        match self {
+            Intermediate::Dense(dense) => OperandRef::Dense(dense.view()),
+            Intermediate::Sparse(sparse) => OperandRef::Sparse(sparse),
+        }
+    }
+
+    fn into_dense(self) -> ArrayD<A>
+    where
+        A: LinalgScalar,
+    {
+        match self {
+            Intermediate::Dense(dense) => dense,
+            Intermediate::Sparse(sparse) => sparse.to_dense(),
+        }
+    }
+}
+
+/// Returns the indices in the order of their first appearance, without repeats.
+fn distinct(indices: &[char]) -> Vec<char> {
+    let mut distinct = Vec::new();
+    for &c in indices {
+        if !distinct.contains(&c) {
+            distinct.push(c);
+        }
+    }
+    distinct
+}
+
+/// How to read the values of the distinct indices of a sparse operand from the coordinates
+/// of one of its elements.
+struct IndexReader {
+    /// The distinct indices, in order of first appearance.
+    indices: Vec<char>,
+
+    /// For each distinct index, the first axis it labels.
+    first_axes: Vec<usize>,
+
+    /// The axes labeled by an index that also labels an earlier axis, along with that axis.
+    repeated_axes: Vec<(usize, usize)>,
+}
+
+impl IndexReader {
+    fn new(operand_indices: &[char]) -> Self {
+        let indices = distinct(operand_indices);
+        let first_axes: Vec<usize> = indices
+            .iter()
+            .map(|c| operand_indices.iter().position(|d| d == c).unwrap())
+            .collect();
+        let repeated_axes = operand_indices
+            .iter()
+            .enumerate()
+            .filter_map(|(axis, c)| {
+                let first_axis = first_axes[indices.iter().position(|d| d == c).unwrap()];
+                (first_axis != axis).then_some((axis, first_axis))
+            })
+            .collect();
+        IndexReader {
+            indices,
+            first_axes,
+            repeated_axes,
+        }
+    }
+
+    fn position(&self, c: &char) -> Option<usize> {
+        self.indices.iter().position(|d| d == c)
+    }
+
+    /// Returns the values of the distinct indices, or None if the element is off the diagonal
+    /// of some repeated index and so doesn't contribute to the contraction.
+    fn read(&self, coord: &[usize]) -> Option<Vec<usize>> {
+        if self
+            .repeated_axes
+            .iter()
+            .any(|&(axis, first_axis)| coord[axis] != coord[first_axis])
+        {
+            return None;
+        }
+        Some(self.first_axes.iter().map(|&axis| coord[axis]).collect())
+    }
+}
+
+/// Fixes the coordinates along some of the axes of `array`, given as `(axis, coordinate)`
+/// pairs sorted by decreasing axis.
+fn fix_axes<S: RawData>(
+    mut array: ArrayBase<S, IxDyn>,
+    fixed: impl Iterator<Item = (usize, usize)>,
+) -> ArrayBase<S, IxDyn> {
+    for (axis, coordinate) in fixed {
+        array = array.index_axis_move(Axis(axis), coordinate);
+    }
+    array
+}
+
+/// Sums a sparse tensor into a dense result containing the distinct output indices.
+fn contract_sparse_singleton<A: LinalgScalar>(
+    sparse: &SparseOperand<A>,
+    operand_indices: &[char],
+    output_indices: &[char],
+    sc: &SizedContraction,
+) -> ArrayD<A> {
+    let reader = IndexReader::new(operand_indices);
+    let output_positions: Vec<usize> = output_indices
+        .iter()
+        .map(|c| reader.position(c).unwrap())
+        .collect();
+    let output_shape: Vec<usize> = output_indices.iter().map(|c| sc.output_size[c]).collect();
+
+    let mut result = Array::zeros(IxDyn(&output_shape));
+    for (coord, &value) in sparse.coords().iter().zip(sparse.values()) {
+        if let Some(values) = reader.read(coord) {
+            let output_coord: Vec<usize> = output_positions.iter().map(|&p| values[p]).collect();
+            let elem = &mut result[IxDyn(&output_coord)];
+            *elem = *elem + value;
+        }
+    }
+    result
+}
+
+/// Contracts a sparse tensor with a dense one into a dense result containing the distinct
+/// output indices, visiting only the stored elements of the sparse tensor.
+fn contract_sparse_dense<A: LinalgScalar>(
+    sparse: &SparseOperand<A>,
+    sparse_indices: &[char],
+    dense: &ArrayViewD<A>,
+    dense_indices: &[char],
+    output_indices: &[char],
+    sc: &SizedContraction,
+) -> Result<ArrayD<A>, EinsumError> {
+    let reader = IndexReader::new(sparse_indices);
+
+    // Reduce the dense tensor to the indices it shares with the sparse one or the output,
+    // summing the rest and taking the diagonal of any repeated ones.
+    let reduced_indices: Vec<char> = distinct(dense_indices)
+        .into_iter()
+        .filter(|c| reader.position(c).is_some() || output_indices.contains(c))
+        .collect();
+    let reduced = sc
+        .subset(&[dense_indices.to_vec()], &reduced_indices)?
+        .contract_operands(&[dense]);
+
+    // Each element of the sparse tensor fixes the axes of `reduced` and of the result whose
+    // indices come from the sparse tensor; the rest (which are output indices) are free.
+    let mut reduced_fixed: Vec<(usize, usize)> = reduced_indices
+        .iter()
+        .enumerate()
+        .filter_map(|(axis, c)| reader.position(c).map(|p| (axis, p)))
+        .collect();
+    reduced_fixed.reverse();
+    let mut output_fixed: Vec<(usize, usize)> = output_indices
+        .iter()
+        .enumerate()
+        .filter_map(|(axis, c)| reader.position(c).map(|p| (axis, p)))
+        .collect();
+    output_fixed.reverse();
+    let free_reduced: Vec<char> = reduced_indices
+        .iter()
+        .filter(|c| reader.position(c).is_none())
+        .cloned()
+        .collect();
+    let permutation: Vec<usize> = output_indices
+        .iter()
+        .filter(|c| reader.position(c).is_none())
+        .map(|c| free_reduced.iter().position(|d| d == c).unwrap())
+        .collect();
+
+    let output_shape: Vec<usize> = output_indices.iter().map(|c| sc.output_size[c]).collect();
+    let mut result = Array::zeros(IxDyn(&output_shape));
+    for (coord, &value) in sparse.coords().iter().zip(sparse.values()) {
+        if let Some(values) = reader.read(coord) {
+            let reduced_subview = fix_axes(
+                reduced.view(),
+                reduced_fixed.iter().map(|&(axis, p)| (axis, values[p])),
+            )
+            .permuted_axes(permutation.as_slice());
+            let output_subview = fix_axes(
+                result.view_mut(),
+                output_fixed.iter().map(|&(axis, p)| (axis, values[p])),
+            );
+            Zip::from(output_subview)
+                .and(&reduced_subview)
+                .for_each(|out_elem, &x| *out_elem = *out_elem + value * x);
+        }
+    }
+    Ok(result)
+}
+
+/// Contracts two sparse tensors into a sparse result containing the distinct output indices,
+/// multiplying only the pairs of stored elements that agree on the shared indices.
+fn contract_sparse_pair<A: LinalgScalar>(
+    lhs: &SparseOperand<A>,
+    lhs_indices: &[char],
+    rhs: &SparseOperand<A>,
+    rhs_indices: &[char],
+    output_indices: &[char],
+    sc: &SizedContraction,
+) -> SparseOperand<A> {
+    let lhs_reader = IndexReader::new(lhs_indices);
+    let rhs_reader = IndexReader::new(rhs_indices);
+    let shared: Vec<char> = lhs_reader
+        .indices
+        .iter()
+        .filter(|c| rhs_reader.position(c).is_some())
+        .cloned()
+        .collect();
+    let lhs_shared: Vec<usize> = shared
+        .iter()
+        .map(|c| lhs_reader.position(c).unwrap())
+        .collect();
+    let rhs_shared: Vec<usize> = shared
+        .iter()
+        .map(|c| rhs_reader.position(c).unwrap())
+        .collect();
+    // Whether each output index is read from the LHS or the RHS element, and where
+    let output_sources: Vec<(bool, usize)> = output_indices
+        .iter()
+        .map(|c| match lhs_reader.position(c) {
+            Some(p) => (true, p),
+            None => (false, rhs_reader.position(c).unwrap()),
+        })
+        .collect();
+
+    let mut rhs_by_shared: HashMap<Vec<usize>, Vec<(Vec<usize>, A)>> = HashMap::new();
+    for (coord, &value) in rhs.coords().iter().zip(rhs.values()) {
+        if let Some(values) = rhs_reader.read(coord) {
+            let key = rhs_shared.iter().map(|&p| values[p]).collect();
+            rhs_by_shared.entry(key).or_default().push((values, value));
+        }
+    }
+
+    // Keep the elements of the result in order of their first appearance
+    let mut positions: HashMap<Vec<usize>, usize> = HashMap::new();
+    let mut coords = Vec::new();
+    let mut values = Vec::new();
+    for (coord, &lhs_value) in lhs.coords().iter().zip(lhs.values()) {
+        let Some(lhs_values) = lhs_reader.read(coord) else {
+            continue;
+        };
+        let key: Vec<usize> = lhs_shared.iter().map(|&p| lhs_values[p]).collect();
+        for (rhs_values, rhs_value) in rhs_by_shared.get(&key).into_iter().flatten() {
+            let output_coord: Vec<usize> = output_sources
+                .iter()
+                .map(|&(from_lhs, p)| {
+                    if from_lhs {
+                        lhs_values[p]
+                    } else {
+                        rhs_values[p]
+                    }
+                })
+                .collect();
+            let product = lhs_value * *rhs_value;
+            match positions.get(&output_coord) {
+                Some(&n) => values[n] = values[n] + product,
+                None => {
+                    positions.insert(output_coord.clone(), values.len());
+                    coords.push(output_coord);
+                    values.push(product);
+                }
+            }
+        }
+    }
+
+    SparseOperand {
+        shape: output_indices.iter().map(|c| sc.output_size[c]).collect(),
+        coords,
+        values,
+    }
+}
+
+/// Performs a step with at least one `CsMat` operand: with the matrix kernel if the step is a
+/// matrix product with a dense operand, and otherwise by converting the matrices to
+/// coordinate format.
+#[cfg(feature = "sprs")]
+fn contract_csmat_step<A: LinalgScalar>(
+    sc: &SizedContraction,
+    operands: &[OperandRef<A>],
+) -> Result<Intermediate<A>, EinsumError> {
+    let operand_indices = &sc.contraction.operand_indices;
+    let product = match operands {
+        [OperandRef::CsMat(matrix), OperandRef::Dense(dense)] => contract_csmat_dense(
+            matrix,
+            &operand_indices[0],
+            &broadcast_input(dense, sc, 1),
+            &operand_indices[1],
+            sc,
+        )?,
+        [OperandRef::Dense(dense), OperandRef::CsMat(matrix)] => contract_csmat_dense(
+            matrix,
+            &operand_indices[1],
+            &broadcast_input(dense, sc, 0),
+            &operand_indices[0],
+            sc,
+        )?,
+        _ => None,
+    };
+    if let Some(product) = product {
+        return Ok(Intermediate::Dense(product));
+    }
+
+    let converted: Vec<Option<SparseOperand<A>>> = operands
+        .iter()
+        .map(|operand| match operand {
+            OperandRef::CsMat(matrix) => Some(SparseOperand::from_csmat(matrix)),
+            _ => None,
+        })
+        .collect();
+    let operands: Vec<OperandRef<A>> = operands
+        .iter()
+        .zip(&converted)
+        .map(|(operand, converted)| match converted {
+            Some(sparse) => OperandRef::Sparse(sparse),
+            None => operand.reborrow(),
+        })
+        .collect();
+    contract_step(sc, &operands)
+}
+
+/// Contracts a sparse matrix with a dense tensor if the step is a matrix product: one index of
+/// the matrix is contracted with the dense tensor, and the other is an output index that the
+/// dense tensor doesn't have. Returns `None` for any other step.
+///
+/// The dense tensor is reduced to a matrix whose rows are indexed by the contracted index and
+/// whose columns are indexed by its output indices. Then each stored element `(r, c, value)` of
+/// the (possibly transposed) sparse matrix adds `value` times row `c` of that matrix to row `r`
+/// of the product.
+#[cfg(feature = "sprs")]
+fn contract_csmat_dense<A: LinalgScalar>(
+    matrix: &sprs::CsMat<A>,
+    matrix_indices: &[char],
+    dense: &ArrayViewD<A>,
+    dense_indices: &[char],
+    sc: &SizedContraction,
+) -> Result<Option<ArrayD<A>>, EinsumError> {
+    let output_indices = &sc.contraction.output_indices;
+    let is_contracted = |c: &char| dense_indices.contains(c) && !output_indices.contains(c);
+    let is_free = |c: &char| output_indices.contains(c) && !dense_indices.contains(c);
+    let (row_index, col_index, matrix) = match *matrix_indices {
+        [a, b] if is_free(&a) && is_contracted(&b) => (a, b, matrix.view()),
+        [a, b] if is_contracted(&a) && is_free(&b) => (b, a, matrix.transpose_view()),
+        _ => return Ok(None),
+    };
+    if distinct(output_indices) != *output_indices {
+        return Ok(None);
+    }
+
+    let dense_output_indices: Vec<char> = distinct(dense_indices)
+        .into_iter()
+        .filter(|c| output_indices.contains(c))
+        .collect();
+    let mut reduced_indices = vec![col_index];
+    reduced_indices.extend(dense_output_indices.iter());
+    let reduced = sc
+        .subset(&[dense_indices.to_vec()], &reduced_indices)?
+        .contract_operands(&[dense]);
+    let len_columns: usize = dense_output_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .product();
+    let reduced = reduced.as_standard_layout();
+    let reduced = reduced
+        .view()
+        .into_shape_with_order((sc.output_size[&col_index], len_columns))
+        .unwrap();
+
+    let mut product = Array2::zeros((sc.output_size[&row_index], len_columns));
+    for (&value, (row, col)) in matrix.iter() {
+        product.row_mut(row).scaled_add(value, &reduced.row(col));
+    }
+
+    let mut product_indices = vec![row_index];
+    product_indices.extend(dense_output_indices.iter());
+    let product_shape: Vec<usize> = product_indices.iter().map(|c| sc.output_size[c]).collect();
+    let product = product
+        .into_shape_with_order(IxDyn(&product_shape))
+        .unwrap();
+    Ok(Some(
+        sc.subset(&[product_indices], output_indices)?
+            .contract_operands(&[&product]),
+    ))
+}
+
+/// Performs one step of the contraction; `operands` holds one or two tensors.
+fn contract_step<A: LinalgScalar>(
+    sc: &SizedContraction,
+    operands: &[OperandRef<A>],
+) -> Result<Intermediate<A>, EinsumError> {
+    let operand_indices = &sc.contraction.operand_indices;
+    let output_indices = &sc.contraction.output_indices;
+    // The sparse kernels produce each output index once; a repeated one is embedded afterwards.
+    let distinct_output = distinct(output_indices);
+
+    #[cfg(feature = "sprs")]
+    if operands.iter().any(|x| matches!(x, OperandRef::CsMat(_))) {
+        return contract_csmat_step(sc, operands);
+    }
+
+    let result = match operands {
+        [OperandRef::Dense(dense)] => {
+            return Ok(Intermediate::Dense(sc.contract_operands(&[dense])))
+        }
+        [OperandRef::Dense(lhs), OperandRef::Dense(rhs)] => {
+            return Ok(Intermediate::Dense(sc.contract_operands(&[lhs, rhs])))
+        }
+        [OperandRef::Sparse(sparse)] => Intermediate::Dense(contract_sparse_singleton(
+            sparse,
+            &operand_indices[0],
+            &distinct_output,
+            sc,
+        )),
+        [OperandRef::Sparse(sparse), OperandRef::Dense(dense)] => {
+            Intermediate::Dense(contract_sparse_dense(
+                sparse,
+                &operand_indices[0],
+                &broadcast_input(dense, sc, 1),
+                &operand_indices[1],
+                &distinct_output,
+                sc,
+            )?)
+        }
+        [OperandRef::Dense(dense), OperandRef::Sparse(sparse)] => {
+            Intermediate::Dense(contract_sparse_dense(
+                sparse,
+                &operand_indices[1],
+                &broadcast_input(dense, sc, 0),
+                &operand_indices[0],
+                &distinct_output,
+                sc,
+            )?)
+        }
+        [OperandRef::Sparse(lhs), OperandRef::Sparse(rhs)] => {
+            Intermediate::Sparse(contract_sparse_pair(
+                lhs,
+                &operand_indices[0],
+                rhs,
+                &operand_indices[1],
+                &distinct_output,
+                sc,
+            ))
+        }
+        _ => unreachable!(),
+    };
+
+    if distinct_output == *output_indices {
+        Ok(result)
+    } else {
+        let embedding = sc.subset(&[distinct_output], output_indices)?;
+        Ok(Intermediate::Dense(
+            embedding.contract_operands(&[&result.into_dense()]),
+        ))
+    }
+}
+
+/// Performs the contraction described by `input_string` on operands that can each be dense
+/// (any `ArrayBase`) or sparse (a [`SparseOperand`](struct.SparseOperand.html)), returning a
+/// dense result. The work done for a sparse operand is proportional to its number of stored
+/// elements rather than to its size: a product with a dense operand only touches the subviews
+/// selected by the stored elements, and a product of two sparse operands only the pairs of
+/// elements that line up.
+///
+/// Returns `EinsumError::Unsupported` if a sparse operand has an axis of length 1 that would
+/// have to be broadcast, or an error for the same reasons as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let n = 1000;
+/// // A 1000 x 1000 matrix with three nonzero elements
+/// let sparse = SparseOperand::new(
+///     vec![n, n],
+///     vec![vec![0, 1], vec![2, 2], vec![999, 0]],
+///     vec![2., 3., 4.],
+/// );
+/// let dense: Array2<f64> = Array::from_shape_fn((n, 3), |(i, j)| (i + j) as f64);
+/// let product = einsum_sparse("ij,jk->ik", &[&sparse, &dense]).unwrap();
+/// assert_eq!(product, einsum("ij,jk->ik", &[&sparse.to_dense(), &dense]).unwrap());
+/// assert_eq!(product.slice(s![999, ..]), array![0., 4., 8.]);
+/// ```
+pub fn einsum_sparse<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn EinsumOperand<A>],
+) -> Result<ArrayD<A>, EinsumError> {
+    let operands: Vec<OperandRef<A>> = operands.iter().map(|x| x.as_operand_ref()).collect();
+    let operand_shapes: Vec<Vec<usize>> = operands.iter().map(|x| x.shape()).collect();
+    let sc = SizedContraction::from_string_and_shapes(input_string, &operand_shapes)?;
+
+    for (operand, indices) in operands.iter().zip(&sc.contraction.operand_indices) {
+        let is_sparse = !matches!(operand, OperandRef::Dense(_));
+        if is_sparse
+            && indices
+                .iter()
+                .zip(operand.shape())
+                .any(|(c, len)| len != sc.output_size[c])
+        {
+            return Err(EinsumError::Unsupported(
+                "a sparse operand can't be broadcast",
+            ));
+        }
+    }
+
+    let result = match generate_optimized_order(&sc, OptimizationMethod::Greedy) {
+        ContractionOrder::Singleton(sc) => contract_step(&sc, &operands)?,
+        ContractionOrder::Pairs(order_steps) => {
+            let mut intermediate_results: Vec<Option<Intermediate<A>>> = Vec::new();
+            for order_step in order_steps.iter() {
+                let operand_nums = [&order_step.operand_nums.lhs, &order_step.operand_nums.rhs];
+                let step_operands: Vec<OperandRef<A>> = operand_nums
+                    .iter()
+                    .map(|operand_number| match **operand_number {
+                        OperandNumber::Input(pos) => operands[pos].reborrow(),
+                        OperandNumber::IntermediateResult(pos) => {
+                            intermediate_results[pos].as_ref().unwrap().as_operand_ref()
+                        }
+                    })
+                    .collect();
+                let result = contract_step(&order_step.sized_contraction, &step_operands)?;
+
+                // Intermediate results are only used once, so they can be dropped.
+                for operand_number in operand_nums {
+                    if let OperandNumber::IntermediateResult(pos) = *operand_number {
+                        intermediate_results[pos] = None;
+                    }
+                }
+                intermediate_results.push(Some(result));
+            }
+            intermediate_results.pop().unwrap().unwrap()
+        }
+    };
+    Ok(result.into_dense())
+}
@@ -0,0 +1,243 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `SummationMode`, which selects how the executor adds up long sums.
+//!
+//! By default, reductions are done by `ndarray`'s `sum_axis` and matrix multiplications by
+//! `general_mat_mul` and `general_mat_vec_mul` (or BLAS), which accumulate naively; with
+//! floating-point elements the rounding error of a sum of `n` terms can grow linearly with `n`.
+//! Inside [`with_summation_mode`](fn.with_summation_mode.html), the `Summation` step and the
+//! reductions over the contracted axes of `TensordotGeneral`, `StackedTensordotGeneral`,
+//! `BatchedMatMul`, `MatrixVectorProduct` and `DotProduct` use pairwise or Kahan-compensated
+//! summation instead, at the cost of speed. The mode doesn't change the contraction order or
+//! the compiled `EinsumPath`.
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use ndarray::linalg::{general_mat_mul, general_mat_vec_mul};
+use ndarray::prelude::*;
+use ndarray::{Data, LinalgScalar};
+
+/// How the elements of a reduction are added up. Set with
+/// [`with_summation_mode`](fn.with_summation_mode.html).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SummationMode {
+    /// Accumulate the terms in order, using `ndarray`'s own reductions and matrix
+    /// multiplication (and BLAS, if it's enabled).
+    #[default]
+    Standard,
+
+    /// Split the terms in half recursively and add up the halves, which bounds the rounding
+    /// error by O(log n) instead of O(n) for almost no extra arithmetic.
+    Pairwise,
+
+    /// Accumulate the terms in order with Kahan's compensated summation, which carries the
+    /// rounding error of each addition into the next and makes the error essentially
+    /// independent of n, at the cost of four operations per term.
+    Kahan,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static SUMMATION_MODE: Cell<SummationMode> = const { Cell::new(SummationMode::Standard) };
+}
+
+/// Restores the previous mode when dropped, so that it is restored even if `op` panics.
+#[cfg(feature = "std")]
+struct ModeGuard(SummationMode);
+
+#[cfg(feature = "std")]
+impl Drop for ModeGuard {
+    fn drop(&mut self) {
+        SUMMATION_MODE.with(|mode| mode.set(self.0));
+    }
+}
+
+/// Runs `op` with the executor using `mode` for its reductions, and returns its result. The mode
+/// applies to the contractions performed by `op` on the current thread, including the work they
+/// hand to rayon; calls can be nested.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array1<f32> = Array::from_elem(1 << 20, 0.1);
+/// let exact = (1 << 20) as f64 * 0.1f32 as f64;
+/// for mode in [SummationMode::Pairwise, SummationMode::Kahan] {
+///     let sum = with_summation_mode(mode, || einsum("i->", &[&a])).unwrap();
+///     assert!((sum.sum() as f64 - exact).abs() < 0.01);
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn with_summation_mode<R, F>(mode: SummationMode, op: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let _guard = ModeGuard(SUMMATION_MODE.with(|current| current.replace(mode)));
+    op()
+}
+
+/// Returns the mode set by the innermost enclosing
+/// [`with_summation_mode`](fn.with_summation_mode.html) on this thread, or
+/// `SummationMode::Standard` outside of one.
+#[cfg(feature = "std")]
+pub fn summation_mode() -> SummationMode {
+    SUMMATION_MODE.with(|mode| mode.get())
+}
+
+/// Returns `SummationMode::Standard`, since the mode can only be changed with `std`.
+#[cfg(not(feature = "std"))]
+pub fn summation_mode() -> SummationMode {
+    SummationMode::Standard
+}
+
+/// Below this many terms, pairwise summation just adds the terms in order.
+const PAIRWISE_BLOCK_SIZE: usize = 8;
+
+fn pairwise_sum<A: LinalgScalar>(terms: &[A]) -> A {
+    if terms.len() <= PAIRWISE_BLOCK_SIZE {
+        terms.iter().fold(A::zero(), |sum, &term| sum + term)
+    } else {
+        let (left, right) = terms.split_at(terms.len() / 2);
+        pairwise_sum(left) + pairwise_sum(right)
+    }
+}
+
+fn kahan_sum<A: LinalgScalar>(terms: &[A]) -> A {
+    let mut sum = A::zero();
+    let mut compensation = A::zero();
+    for &term in terms {
+        let corrected = term - compensation;
+        let new_sum = sum + corrected;
+        compensation = (new_sum - sum) - corrected;
+        sum = new_sum;
+    }
+    sum
+}
+
+/// Adds up `terms` as specified by `mode`.
+fn sum_terms<A: LinalgScalar>(mode: SummationMode, terms: &[A]) -> A {
+    match mode {
+        SummationMode::Standard => terms.iter().fold(A::zero(), |sum, &term| sum + term),
+        SummationMode::Pairwise => pairwise_sum(terms),
+        SummationMode::Kahan => kahan_sum(terms),
+    }
+}
+
+/// Sums `tensor` over its last `num_summed_axes` axes, as specified by `mode`.
+pub(crate) fn sum_trailing_axes<A: LinalgScalar>(
+    mode: SummationMode,
+    tensor: &ArrayViewD<A>,
+    num_summed_axes: usize,
+) -> ArrayD<A> {
+    let output_shape = &tensor.shape()[..(tensor.ndim() - num_summed_axes)];
+    let len_output: usize = output_shape.iter().product();
+    let len_summed: usize = tensor.shape()[output_shape.len()..].iter().product();
+
+    let standard = tensor.as_standard_layout();
+    let matrix = standard
+        .view()
+        .into_shape_with_order((len_output, len_summed))
+        .unwrap();
+    matrix
+        .rows()
+        .into_iter()
+        .map(|row| sum_terms(mode, row.as_slice().unwrap()))
+        .collect::<Array1<A>>()
+        .into_shape_with_order(IxDyn(output_shape))
+        .unwrap()
+}
+
+/// Same as `general_mat_mul`, computing `out = alpha * lhs.dot(rhs) + beta * out`, but with
+/// each dot product summed as specified by `mode`.
+pub(crate) fn mat_mul<A, S, T>(
+    mode: SummationMode,
+    alpha: A,
+    lhs: &ArrayBase<S, Ix2>,
+    rhs: &ArrayBase<T, Ix2>,
+    beta: A,
+    out: &mut ArrayViewMut2<A>,
+) where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    T: Data<Elem = A>,
+{
+    if mode == SummationMode::Standard {
+        general_mat_mul(alpha, lhs, rhs, beta, out);
+        return;
+    }
+
+    let rhs_columns = rhs.t().as_standard_layout().into_owned();
+    let mut products = Vec::with_capacity(lhs.ncols());
+    for (lhs_row, mut out_row) in lhs.rows().into_iter().zip(out.rows_mut()) {
+        for (rhs_column, out_elem) in rhs_columns.rows().into_iter().zip(out_row.iter_mut()) {
+            products.clear();
+            products.extend(lhs_row.iter().zip(rhs_column).map(|(&l, &r)| l * r));
+            let dot = alpha * sum_terms(mode, &products);
+            // As in `general_mat_mul`, the existing contents are ignored when beta is zero
+            *out_elem = if beta.is_zero() {
+                dot
+            } else {
+                dot + beta * *out_elem
+            };
+        }
+    }
+}
+
+/// Same as `general_mat_vec_mul`, computing `out = alpha * lhs.dot(rhs) + beta * out`, but with
+/// each dot product summed as specified by `mode`.
+pub(crate) fn mat_vec_mul<A, S, T>(
+    mode: SummationMode,
+    alpha: A,
+    lhs: &ArrayBase<S, Ix2>,
+    rhs: &ArrayBase<T, Ix1>,
+    beta: A,
+    out: &mut ArrayViewMut1<A>,
+) where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    T: Data<Elem = A>,
+{
+    if mode == SummationMode::Standard {
+        general_mat_vec_mul(alpha, lhs, rhs, beta, out);
+        return;
+    }
+
+    for (lhs_row, out_elem) in lhs.rows().into_iter().zip(out.iter_mut()) {
+        let product = alpha * dot(mode, &lhs_row, rhs);
+        // As in `general_mat_vec_mul`, the existing contents are ignored when beta is zero
+        *out_elem = if beta.is_zero() {
+            product
+        } else {
+            product + beta * *out_elem
+        };
+    }
+}
+
+/// The dot product of `lhs` and `rhs`, summed as specified by `mode`.
+pub(crate) fn dot<A, S, T>(
+    mode: SummationMode,
+    lhs: &ArrayBase<S, Ix1>,
+    rhs: &ArrayBase<T, Ix1>,
+) -> A
+where
+    A: LinalgScalar,
+    S: Data<Elem = A>,
+    T: Data<Elem = A>,
+{
+    if mode == SummationMode::Standard {
+        return lhs.dot(rhs);
+    }
+    let products: Vec<A> = lhs.iter().zip(rhs.iter()).map(|(&l, &r)| l * r).collect();
+    sum_terms(mode, &products)
+}
@@ -0,0 +1,315 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `IndexSymmetry`, which declares that an operand is symmetric or antisymmetric
+//! under swapping two of its axes, and `einsum_symmetric`, which uses the declarations to
+//! sum over only one triangle of each such pair of indices.
+//!
+//! If `A[ij] = A[ji]` and both `i` and `j` are summed over, then
+//! `sum_ij A[ij] B[ij] = sum_(i <= j) A[ij] (B[ij] + B[ji])` (without the second term when
+//! `i = j`), and similarly with a minus sign and without the diagonal when `A` is
+//! antisymmetric. So the two indices are replaced by a single index that runs over the
+//! `n (n + 1) / 2` pairs with `i <= j` (or the `n (n - 1) / 2` pairs with `i < j`): `A` is
+//! packed into its unique triangle, and the one other operand that has both indices is folded
+//! onto it. The rewritten contraction is then planned and performed as usual, which roughly
+//! halves the work of the steps that sum over the pair.
+//!
+//! A declaration can only be used this way when both indices are summed over, each appears
+//! once in the symmetric operand, and at most one other operand has either of them (in which
+//! case it has to have both, once each). Declarations that don't satisfy this are ignored:
+//! they never change the result.
+use crate::{
+    generate_optimized_order, ArrayLike, Contraction, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{CowArray, LinalgScalar};
+
+/// Whether an operand is unchanged or negated when two of its axes are swapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymmetryKind {
+    /// `A[..i..j..] = A[..j..i..]`
+    Symmetric,
+
+    /// `A[..i..j..] = -A[..j..i..]`, so `A` is zero wherever `i = j`.
+    Antisymmetric,
+}
+
+/// Declares that operand `operand` is symmetric or antisymmetric (according to `kind`) under
+/// swapping its axes `axes.0` and `axes.1`. The declaration isn't checked against the
+/// contents of the operand; if it's wrong, so is the result of `einsum_symmetric`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexSymmetry {
+    pub operand: usize,
+    pub axes: (usize, usize),
+    pub kind: SymmetryKind,
+}
+
+impl IndexSymmetry {
+    /// Declares that operand `operand` is unchanged by swapping its axes `axes.0` and `axes.1`.
+    pub fn symmetric(operand: usize, axes: (usize, usize)) -> Self {
+        IndexSymmetry {
+            operand,
+            axes,
+            kind: SymmetryKind::Symmetric,
+        }
+    }
+
+    /// Declares that operand `operand` is negated by swapping its axes `axes.0` and `axes.1`.
+    pub fn antisymmetric(operand: usize, axes: (usize, usize)) -> Self {
+        IndexSymmetry {
+            operand,
+            axes,
+            kind: SymmetryKind::Antisymmetric,
+        }
+    }
+}
+
+/// Returns the subview of `tensor` with axis `axes.0` fixed at `i` and axis `axes.1` fixed at `j`.
+fn fix_pair<'a, A>(
+    tensor: &ArrayViewD<'a, A>,
+    axes: (usize, usize),
+    i: usize,
+    j: usize,
+) -> ArrayViewD<'a, A> {
+    let tensor = tensor.clone();
+    if axes.0 > axes.1 {
+        tensor
+            .index_axis_move(Axis(axes.0), i)
+            .index_axis_move(Axis(axes.1), j)
+    } else {
+        tensor
+            .index_axis_move(Axis(axes.1), j)
+            .index_axis_move(Axis(axes.0), i)
+    }
+}
+
+/// Replaces axes `axes.0` and `axes.1` of `tensor` by a single last axis over `pairs`. Element
+/// `k` along the new axis is the subview at `pairs[k] = (i, j)`; if `mirror` is `Some(sign)`,
+/// `sign` times the subview at `(j, i)` is added to it when `i != j`.
+fn pack_pair<A: LinalgScalar>(
+    tensor: &ArrayViewD<A>,
+    axes: (usize, usize),
+    pairs: &[(usize, usize)],
+    mirror: Option<A>,
+) -> ArrayD<A> {
+    let mut packed_shape: Vec<usize> = tensor
+        .shape()
+        .iter()
+        .enumerate()
+        .filter(|&(axis, _)| axis != axes.0 && axis != axes.1)
+        .map(|(_, &len)| len)
+        .collect();
+    packed_shape.push(pairs.len());
+    let last_axis = Axis(packed_shape.len() - 1);
+
+    let mut packed = ArrayD::zeros(packed_shape);
+    for (k, &(i, j)) in pairs.iter().enumerate() {
+        let mut subview = packed.index_axis_mut(last_axis, k);
+        subview.assign(&fix_pair(tensor, axes, i, j));
+        if let Some(sign) = mirror {
+            if i != j {
+                subview.scaled_add(sign, &fix_pair(tensor, axes, j, i));
+            }
+        }
+    }
+    packed
+}
+
+/// Returns the axes of `indices` at which `first` and `second` appear, if each appears exactly once.
+fn find_pair(indices: &[char], first: char, second: char) -> Option<(usize, usize)> {
+    let find_once = |c: char| {
+        let mut positions = indices.iter().enumerate().filter(|&(_, &x)| x == c);
+        match (positions.next(), positions.next()) {
+            (Some((axis, _)), None) => Some(axis),
+            _ => None,
+        }
+    };
+    Some((find_once(first)?, find_once(second)?))
+}
+
+/// Removes the axes `axes` from `indices` and appends `packed_index`.
+fn pack_indices(indices: &mut Vec<char>, axes: (usize, usize), packed_index: char) {
+    *indices = indices
+        .iter()
+        .enumerate()
+        .filter(|&(axis, _)| axis != axes.0 && axis != axes.1)
+        .map(|(_, &c)| c)
+        .collect();
+    indices.push(packed_index);
+}
+
+/// Performs the contraction specified by `input_string`, using the symmetries declared in
+/// `symmetries` to sum over only the unique triangle of each pair of symmetric indices when
+/// they're both summed over (see the module documentation for when a declaration can be
+/// used). The result is the same as that of `einsum`, up to rounding.
+///
+/// Returns `EinsumError::InvalidSymmetry` if a declaration names an operand or an axis that
+/// doesn't exist, the same axis twice, or two axes of different lengths.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// // A Coulomb-like contraction J[kl] = sum_ij V[ijkl] D[ij], with V symmetric in (i, j)
+/// let n = 4;
+/// let v = Array::from_shape_fn((n, n, n, n), |(i, j, k, l)| ((i + j) * k + l) as f64);
+/// let d = Array::from_shape_fn((n, n), |(i, j)| (i * j) as f64 + 1.);
+/// let j = einsum_symmetric(
+///     "ijkl,ij->kl",
+///     &[&v, &d],
+///     &[IndexSymmetry::symmetric(0, (0, 1))],
+/// )
+/// .unwrap();
+/// assert_eq!(j, einsum("ijkl,ij->kl", &[&v, &d]).unwrap());
+/// ```
+pub fn einsum_symmetric<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    symmetries: &[IndexSymmetry],
+) -> Result<ArrayD<A>, EinsumError> {
+    let sc = SizedContraction::new(input_string, operands)?;
+
+    let mut declared_indices = Vec::with_capacity(symmetries.len());
+    for symmetry in symmetries.iter() {
+        let invalid = EinsumError::InvalidSymmetry {
+            operand: symmetry.operand,
+            axes: symmetry.axes,
+        };
+        let (first, second) = symmetry.axes;
+        let indices = match sc.contraction.operand_indices.get(symmetry.operand) {
+            Some(indices) if first != second && first < indices.len() && second < indices.len() => {
+                indices
+            }
+            _ => return Err(invalid),
+        };
+        let shape = operands[symmetry.operand].into_dyn_view().shape().to_vec();
+        if shape[first] != shape[second] {
+            return Err(invalid);
+        }
+        declared_indices.push((indices[first], indices[second]));
+    }
+
+    let mut operand_indices = sc.contraction.operand_indices.clone();
+    let mut summation_indices = sc.contraction.summation_indices.clone();
+    let mut output_size = sc.output_size.clone();
+    let mut tensors: Vec<CowArray<A, IxDyn>> = operands
+        .iter()
+        .map(|x| CowArray::from(x.into_dyn_view()))
+        .collect();
+
+    for (symmetry, &(first, second)) in symmetries.iter().zip(&declared_indices) {
+        // An earlier declaration may have packed one of the indices already
+        let is_summed = |c| summation_indices.contains(&c);
+        if first == second || !is_summed(first) || !is_summed(second) {
+            continue;
+        }
+        let axes = match find_pair(&operand_indices[symmetry.operand], first, second) {
+            Some(axes) => axes,
+            None => continue,
+        };
+        let others: Vec<usize> = (0..operand_indices.len())
+            .filter(|&n| {
+                n != symmetry.operand
+                    && operand_indices[n]
+                        .iter()
+                        .any(|&c| c == first || c == second)
+            })
+            .collect();
+        let other = match others[..] {
+            [] => None,
+            [other] => match find_pair(&operand_indices[other], first, second) {
+                Some(other_axes) => Some((other, other_axes)),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let packed_index = match ('a'..='z')
+            .chain('A'..='Z')
+            .find(|c| !output_size.contains_key(c))
+        {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let n = output_size[&first];
+        let (pairs, sign): (Vec<(usize, usize)>, A) = match symmetry.kind {
+            SymmetryKind::Symmetric => (
+                (0..n).flat_map(|i| (i..n).map(move |j| (i, j))).collect(),
+                A::one(),
+            ),
+            SymmetryKind::Antisymmetric => (
+                (0..n)
+                    .flat_map(|i| ((i + 1)..n).map(move |j| (i, j)))
+                    .collect(),
+                A::zero() - A::one(),
+            ),
+        };
+
+        // Axes of length 1 are broadcast to the full lengths before the pairs are picked out
+        let full_shape =
+            |indices: &[char]| -> Vec<usize> { indices.iter().map(|c| output_size[c]).collect() };
+        let operand_shape = full_shape(&operand_indices[symmetry.operand]);
+        let packed = pack_pair(
+            &tensors[symmetry.operand].broadcast(operand_shape).unwrap(),
+            axes,
+            &pairs,
+            None,
+        );
+        tensors[symmetry.operand] = CowArray::from(packed);
+        pack_indices(&mut operand_indices[symmetry.operand], axes, packed_index);
+        match other {
+            Some((other, other_axes)) => {
+                let other_shape = full_shape(&operand_indices[other]);
+                let folded = pack_pair(
+                    &tensors[other].broadcast(other_shape).unwrap(),
+                    other_axes,
+                    &pairs,
+                    Some(sign),
+                );
+                tensors[other] = CowArray::from(folded);
+                pack_indices(&mut operand_indices[other], other_axes, packed_index);
+            }
+            None => {
+                // Nothing else depends on the pair, so each off-diagonal element of the triangle
+                // just stands in for itself and its mirror image.
+                let weights: Array1<A> = pairs
+                    .iter()
+                    .map(|&(i, j)| if i == j { A::one() } else { A::one() + sign })
+                    .collect();
+                tensors.push(CowArray::from(weights.into_dyn()));
+                operand_indices.push(vec![packed_index]);
+            }
+        }
+
+        summation_indices.retain(|&c| c != first && c != second);
+        summation_indices.push(packed_index);
+        output_size.remove(&first);
+        output_size.remove(&second);
+        output_size.insert(packed_index, pairs.len());
+    }
+
+    let packed_sc = SizedContraction {
+        contraction: Contraction {
+            operand_indices,
+            output_indices: sc.contraction.output_indices.clone(),
+            summation_indices,
+        },
+        output_size,
+    };
+    let contraction_order = generate_optimized_order(&packed_sc, OptimizationMethod::Greedy);
+    let tensor_refs: Vec<&dyn ArrayLike<A>> =
+        tensors.iter().map(|x| x as &dyn ArrayLike<A>).collect();
+    Ok(EinsumPath::from_path(&contraction_order).contract_operands(&tensor_refs))
+}
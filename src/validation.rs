@@ -21,23 +21,133 @@
 //! to perform the full contraction.
 //!
 //!
+use crate::collections::{HashMap, HashSet};
+use crate::optimizers::generate_path_from_pairs;
 use crate::{
-    generate_optimized_order, ArrayLike, ContractionOrder, EinsumPath, OptimizationMethod,
+    generate_optimized_order, label_index, ArrayLike, ContractionOrder, EinsumError, EinsumPath,
+    OptimizationMethod,
 };
-use lazy_static::lazy_static;
+use alloc::string::String;
+use alloc::vec::Vec;
 use ndarray::prelude::*;
 use ndarray::LinalgScalar;
-use regex::Regex;
-use std::collections::{HashMap, HashSet};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-/// The result of running an `einsum`-formatted string through the regex.
+/// The prefix that marks an `einsum`-formatted string whose labels are words separated by
+/// whitespace instead of single letters, e.g. `labels: batch chan_in, chan_in chan_out`.
+pub const LABELS_PREFIX: &str = "labels:";
+
+/// The literal used in `einsum`-formatted strings to stand in for any number of
+/// broadcast axes, e.g. `...ij,...jk->...ik`.
+const ELLIPSIS: &str = "...";
+
+/// The result of parsing an `einsum`-formatted string.
 #[derive(Debug)]
 struct EinsumParse {
     operand_indices: Vec<String>,
     output_indices: Option<String>,
+    /// The label that each index stands for, if the string was labelled
+    labels: HashMap<char, String>,
+}
+
+impl EinsumParse {
+    fn has_ellipsis(&self) -> bool {
+        self.operand_indices.iter().any(|s| s.contains(ELLIPSIS))
+            || self
+                .output_indices
+                .as_ref()
+                .is_some_and(|s| s.contains(ELLIPSIS))
+    }
+
+    /// Replaces each ellipsis with generated labels for the axes it covers, which requires
+    /// knowing the number of dimensions of each operand.
+    ///
+    /// As in numpy, the axes covered by the ellipses are aligned from the right: in
+    /// `...ij,...jk->...ik` with operands of shapes `[5, 2, 3]` and `[3, 4]`, the ellipsis
+    /// covers one axis of the first operand and no axes of the second.
+    fn expand_ellipses(&self, operand_shapes: &[Vec<usize>]) -> Result<EinsumParse, EinsumError> {
+        if self.operand_indices.len() != operand_shapes.len() {
+            return Err(EinsumError::OperandCountMismatch {
+                expected: self.operand_indices.len(),
+                found: operand_shapes.len(),
+            });
+        }
+
+        let mut ellipsis_ndims = Vec::new();
+        for (operand_num, (indices, operand_shape)) in
+            self.operand_indices.iter().zip(operand_shapes).enumerate()
+        {
+            if indices.contains(ELLIPSIS) {
+                let num_named = indices.chars().count() - ELLIPSIS.len();
+                if operand_shape.len() < num_named {
+                    return Err(EinsumError::RankMismatch {
+                        operand: operand_num,
+                        expected: num_named,
+                        found: operand_shape.len(),
+                    });
+                }
+                ellipsis_ndims.push(operand_shape.len() - num_named);
+            } else {
+                ellipsis_ndims.push(0);
+            }
+        }
+        let total_ellipsis_ndim = ellipsis_ndims.iter().cloned().max().unwrap_or(0);
+
+        let used_indices: HashSet<char> = self
+            .operand_indices
+            .iter()
+            .chain(self.output_indices.iter())
+            .flat_map(|s| s.chars())
+            .collect();
+        // Uppercase letters read best in `as_einsum_string`, but they can be indices too, so
+        // once the unused ones run out the labels continue with the `char`s from U+0100 on
+        let ellipsis_indices: Vec<char> = ('A'..='Z')
+            .chain((0x100..).filter_map(char::from_u32))
+            .filter(|c| !used_indices.contains(c))
+            .take(total_ellipsis_ndim)
+            .collect();
+
+        let operand_indices = self
+            .operand_indices
+            .iter()
+            .zip(ellipsis_ndims.iter())
+            .map(|(indices, &ndim)| {
+                let covered: String = ellipsis_indices[(total_ellipsis_ndim - ndim)..]
+                    .iter()
+                    .collect();
+                indices.replace(ELLIPSIS, &covered)
+            })
+            .collect();
+        let covered: String = ellipsis_indices.iter().collect();
+        let output_indices = match &self.output_indices {
+            Some(s) => s.replace(ELLIPSIS, &covered),
+            None => {
+                // Implicit case: as in numpy, the broadcast axes come first, followed by the
+                // (alphabetically sorted) named indices that only appear once.
+                let named_operand_indices: Vec<String> = self
+                    .operand_indices
+                    .iter()
+                    .map(|s| s.replace(ELLIPSIS, ""))
+                    .collect();
+                let mut output_indices = covered;
+                output_indices.extend(implicit_output_indices(&named_operand_indices));
+                output_indices
+            }
+        };
+
+        Ok(EinsumParse {
+            operand_indices,
+            output_indices: Some(output_indices),
+            labels: self.labels.clone(),
+        })
+    }
+
+    /// Names the index of an `IndexLengthMismatch` by its label, if the string was labelled.
+    fn relabel(&self, error: EinsumError) -> EinsumError {
+        error.with_label(|c| self.labels.get(&c).cloned())
+    }
 }
 
 /// A `Contraction` contains the result of parsing an `einsum`-formatted string.
@@ -72,34 +182,62 @@ pub struct Contraction {
 
 impl Contraction {
     /// Validates and creates a `Contraction` from an `einsum`-formatted string.
-    pub fn new(input_string: &str) -> Result<Self, &'static str> {
-        let p = parse_einsum_string(input_string).ok_or("Invalid string")?;
+    ///
+    /// Strings containing an ellipsis (`...`) can't be turned into a `Contraction` on their own,
+    /// since the number of axes covered by each ellipsis depends on the operands. Use
+    /// [SizedContraction::new()](struct.SizedContraction.html#method.new) or
+    /// [SizedContraction::from_string_and_shapes()](struct.SizedContraction.html#method.from_string_and_shapes)
+    /// for those instead.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// assert!(Contraction::new("...ij,...jk->...ik").is_err());
+    /// ```
+    ///
+    /// The indices are letters as defined by `char::is_alphabetic`, so uppercase letters and the
+    /// letters of other alphabets can be used as well as `a`-`z`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let c = Contraction::new("μν,νρ->μρ").unwrap();
+    /// assert_eq!(c.summation_indices, vec!['ν']);
+    /// assert_eq!(Contraction::new("iJ,Jk").unwrap().output_indices, vec!['i', 'k']);
+    /// ```
+    ///
+    /// If the string starts with [`LABELS_PREFIX`](constant.LABELS_PREFIX.html) (`labels:`),
+    /// the labels are the words that whitespace separates instead of single letters, so that
+    /// indices can have descriptive names. A label is a letter or an underscore followed by
+    /// letters, digits and underscores, and `...` is an ellipsis. Each label is stored as a
+    /// `char` (the distinct labels, in sorted order, become `a`, `b`, ...), so the implicit
+    /// output is sorted by label. Without the prefix, whitespace is an error.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let c = Contraction::new("labels: batch chan_in, chan_in chan_out -> batch chan_out")
+    ///     .unwrap();
+    /// assert_eq!(c.operand_indices, vec![vec!['a', 'b'], vec!['b', 'c']]);
+    /// assert_eq!(c.output_indices, vec!['a', 'c']);
+    /// assert_eq!(c.summation_indices, vec!['b']);
+    /// assert!(Contraction::new("ij, jk -> ik").is_err());
+    /// ```
+    pub fn new(input_string: &str) -> Result<Self, EinsumError> {
+        let p = parse_einsum_string(input_string)?;
+        if p.has_ellipsis() {
+            return Err(EinsumError::Unsupported("ellipsis requires operand shapes"));
+        }
         Contraction::from_parse(&p)
     }
 
     /// If output_indices has been specified in the parse (i.e. explicit case),
     /// e.g. "ij,jk->ik", simply converts the strings to `Vec<char>`s and passes
-    /// them to Contraction::from_indices. If the output indices haven't been specified,
+    /// them to Contraction::from_char_indices. If the output indices haven't been specified,
     /// e.g. "ij,jk", figures out which ones aren't duplicated and hence summed over,
     /// sorts them alphabetically, and uses those as the output indices.
-    fn from_parse(parse: &EinsumParse) -> Result<Self, &'static str> {
+    fn from_parse(parse: &EinsumParse) -> Result<Self, EinsumError> {
         let requested_output_indices: Vec<char> = match &parse.output_indices {
             Some(s) => s.chars().collect(),
-            _ => {
-                // Handle implicit case, e.g. nothing to the right of the arrow
-                let mut input_indices = HashMap::new();
-                for c in parse.operand_indices.iter().flat_map(|s| s.chars()) {
-                    *input_indices.entry(c).or_insert(0) += 1;
-                }
-
-                let mut unique_indices: Vec<char> = input_indices
-                    .iter()
-                    .filter(|(_, &v)| v == 1)
-                    .map(|(&k, _)| k)
-                    .collect();
-                unique_indices.sort();
-                unique_indices
-            }
+            // Handle implicit case, e.g. nothing to the right of the arrow
+            _ => implicit_output_indices(&parse.operand_indices),
         };
 
         let operand_indices: Vec<Vec<char>> = parse
@@ -107,44 +245,37 @@ impl Contraction {
             .iter()
             .map(|x| x.chars().collect::<Vec<char>>())
             .collect();
-        Contraction::from_indices(&operand_indices, &requested_output_indices)
+        Contraction::from_char_indices(&operand_indices, &requested_output_indices)
     }
 
     /// Validates and creates a `Contraction` from a slice of `Vec<char>`s containing
     /// the operand indices, and a slice of `char` containing the desired output indices.
-    fn from_indices(
+    pub(crate) fn from_char_indices(
         operand_indices: &[Vec<char>],
         output_indices: &[char],
-    ) -> Result<Self, &'static str> {
+    ) -> Result<Self, EinsumError> {
         let mut input_char_counts = HashMap::new();
         for &c in operand_indices.iter().flat_map(|operand| operand.iter()) {
             *input_char_counts.entry(c).or_insert(0) += 1;
         }
 
-        let mut distinct_output_indices = HashMap::new();
-        for &c in output_indices.iter() {
-            *distinct_output_indices.entry(c).or_insert(0) += 1;
-        }
-        for (&c, &n) in distinct_output_indices.iter() {
-            // No duplicates
-            if n > 1 {
-                return Err("Requested output has duplicate index");
-            }
-
+        // Repeated output indices are allowed; the result is embedded along the diagonal
+        let distinct_output_indices: HashSet<char> = output_indices.iter().cloned().collect();
+        for &c in distinct_output_indices.iter() {
             // Must be in inputs
-            if input_char_counts.get(&c).is_none() {
-                return Err("Requested output contains an index not found in inputs");
+            if !input_char_counts.contains_key(&c) {
+                return Err(EinsumError::OutputIndexNotInInputs { index: c });
             }
         }
 
         let mut summation_indices: Vec<char> = input_char_counts
             .keys()
-            .filter(|&c| distinct_output_indices.get(c).is_none())
+            .filter(|&c| !distinct_output_indices.contains(c))
             .cloned()
             .collect();
         summation_indices.sort();
 
-        let cloned_operand_indices: Vec<Vec<char>> = operand_indices.iter().cloned().collect();
+        let cloned_operand_indices: Vec<Vec<char>> = operand_indices.to_vec();
 
         Ok(Contraction {
             operand_indices: cloned_operand_indices,
@@ -163,37 +294,83 @@ trait OutputSizeMethods {
     fn from_contraction_and_shapes(
         contraction: &Contraction,
         operand_shapes: &[Vec<usize>],
-    ) -> Result<OutputSize, &'static str>;
+    ) -> Result<OutputSize, EinsumError>;
 }
 impl OutputSizeMethods for OutputSize {
     /// Build the HashMap containing the axis lengths
+    ///
+    /// As in numpy, an index can have length 1 in some operands and a different length in
+    /// others, in which case the longer length is used and the operands with length 1 are
+    /// broadcast. Repeated copies of an index within a single operand must match exactly.
     fn from_contraction_and_shapes(
         contraction: &Contraction,
         operand_shapes: &[Vec<usize>],
-    ) -> Result<Self, &'static str> {
+    ) -> Result<Self, EinsumError> {
         // Check that len(operand_indices) == len(operands)
         if contraction.operand_indices.len() != operand_shapes.len() {
-            return Err(
-                "number of operands in contraction does not match number of operands supplied",
-            );
+            return Err(EinsumError::OperandCountMismatch {
+                expected: contraction.operand_indices.len(),
+                found: operand_shapes.len(),
+            });
         }
 
         let mut index_lengths: OutputSize = HashMap::new();
+        // The operand and axis that each length in `index_lengths` was taken from
+        let mut length_sources: HashMap<char, (usize, usize)> = HashMap::new();
 
-        for (indices, operand_shape) in contraction.operand_indices.iter().zip(operand_shapes) {
+        for (operand_num, (indices, operand_shape)) in contraction
+            .operand_indices
+            .iter()
+            .zip(operand_shapes)
+            .enumerate()
+        {
             // Check that len(operand_indices[i]) == len(operands[i].shape())
             if indices.len() != operand_shape.len() {
-                return Err(
-                    "number of indices in one or more operands does not match dimensions of operand",
-                );
+                return Err(EinsumError::RankMismatch {
+                    operand: operand_num,
+                    expected: indices.len(),
+                    found: operand_shape.len(),
+                });
             }
 
-            // Check that whenever there are multiple copies of an index,
-            // operands[i].shape()[m] == operands[j].shape()[n]
-            for (&c, &n) in indices.iter().zip(operand_shape) {
+            // Check that whenever there are multiple copies of an index within an operand,
+            // operands[i].shape()[m] == operands[i].shape()[n]
+            let mut operand_lengths: HashMap<char, (usize, usize)> = HashMap::new();
+            for (axis, (&c, &n)) in indices.iter().zip(operand_shape).enumerate() {
+                let (first_axis, existing_n) = *operand_lengths.entry(c).or_insert((axis, n));
+                if existing_n != n {
+                    return Err(EinsumError::IndexLengthMismatch {
+                        index: String::from(c),
+                        first_operand: operand_num,
+                        first_axis,
+                        expected: existing_n,
+                        operand: operand_num,
+                        axis,
+                        found: n,
+                    });
+                }
+            }
+
+            // Check that whenever there are copies of an index in different operands,
+            // operands[i].shape()[m] == operands[j].shape()[n] or one of them is 1
+            for (axis, (&c, &n)) in indices.iter().zip(operand_shape).enumerate() {
                 let existing_n = index_lengths.entry(c).or_insert(n);
+                let source = length_sources.entry(c).or_insert((operand_num, axis));
                 if *existing_n != n {
-                    return Err("repeated index with different size");
+                    if *existing_n == 1 {
+                        *existing_n = n;
+                        *source = (operand_num, axis);
+                    } else if n != 1 {
+                        return Err(EinsumError::IndexLengthMismatch {
+                            index: String::from(c),
+                            first_operand: source.0,
+                            first_axis: source.1,
+                            expected: *existing_n,
+                            operand: operand_num,
+                            axis,
+                            found: n,
+                        });
+                    }
                 }
             }
         }
@@ -235,7 +412,7 @@ impl SizedContraction {
         &self,
         new_operand_indices: &[Vec<char>],
         new_output_indices: &[char],
-    ) -> Result<Self, &'static str> {
+    ) -> Result<Self, EinsumError> {
         // Make sure all chars in new_operand_indices are in self
         let all_operand_indices: HashSet<char> = new_operand_indices
             .iter()
@@ -244,13 +421,18 @@ impl SizedContraction {
             .collect();
         if all_operand_indices
             .iter()
-            .any(|c| self.output_size.get(c).is_none())
+            .any(|c| !self.output_size.contains_key(c))
         {
-            return Err("Character found in new_operand_indices but not in self.output_size");
+            let index = *all_operand_indices
+                .iter()
+                .find(|c| !self.output_size.contains_key(c))
+                .unwrap();
+            return Err(EinsumError::UnknownIndex { index });
         }
 
         // Validate what they asked for and compute summation_indices
-        let new_contraction = Contraction::from_indices(new_operand_indices, new_output_indices)?;
+        let new_contraction =
+            Contraction::from_char_indices(new_operand_indices, new_output_indices)?;
 
         // Clone output_size, omitting unused characters
         let new_output_size: OutputSize = self
@@ -269,8 +451,8 @@ impl SizedContraction {
     fn from_contraction_and_shapes(
         contraction: &Contraction,
         operand_shapes: &[Vec<usize>],
-    ) -> Result<Self, &'static str> {
-        let output_size = OutputSize::from_contraction_and_shapes(&contraction, operand_shapes)?;
+    ) -> Result<Self, EinsumError> {
+        let output_size = OutputSize::from_contraction_and_shapes(contraction, operand_shapes)?;
 
         Ok(SizedContraction {
             contraction: contraction.clone(),
@@ -294,7 +476,7 @@ impl SizedContraction {
     pub fn from_contraction_and_operands<A>(
         contraction: &Contraction,
         operands: &[&dyn ArrayLike<A>],
-    ) -> Result<Self, &'static str> {
+    ) -> Result<Self, EinsumError> {
         let operand_shapes = get_operand_shapes(operands);
 
         SizedContraction::from_contraction_and_shapes(contraction, &operand_shapes)
@@ -302,6 +484,16 @@ impl SizedContraction {
 
     /// Create a SizedContraction from an `einsum`-formatted input string and a slice
     /// of `Vec<usize>`s containing the shapes of each operand.
+    ///
+    /// If the string contains an ellipsis, each one is replaced by generated uppercase
+    /// labels for the axes it covers. Operands may have different numbers of these axes,
+    /// which are aligned from the right. If the output isn't specified, they are placed at
+    /// the front of the output, ahead of the implicit output indices.
+    ///
+    /// As in numpy, an axis of length 1 is broadcast against an axis of any length that
+    /// has the same index in another operand, whether or not the index is covered by an
+    /// ellipsis. Copies of an index within one operand must have the same length.
+    ///
     /// ```
     /// # use ndarray_einsum_beta::*;
     /// # use ndarray::prelude::*;
@@ -313,12 +505,52 @@ impl SizedContraction {
     /// assert_eq!(sc.output_size[&'k'], 4);
     /// assert_eq!(sc.output_size[&'j'], 3);
     /// ```
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "...ij,...jk->...ik",
+    ///     &[vec![5, 1, 2, 3], vec![6, 3, 4]]
+    /// ).unwrap();
+    /// assert_eq!(sc.as_einsum_string(), "ABij,Bjk->ABik");
+    /// assert_eq!(sc.output_size[&'A'], 5);
+    /// assert_eq!(sc.output_size[&'B'], 6);
+    ///
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "...ij,...jk",
+    ///     &[vec![5, 1, 2, 3], vec![6, 3, 4]]
+    /// ).unwrap();
+    /// assert_eq!(sc.as_einsum_string(), "ABij,Bjk->ABik");
+    ///
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,ij->ij",
+    ///     &[vec![3, 1], vec![3, 4]]
+    /// ).unwrap();
+    /// assert_eq!(sc.output_size[&'j'], 4);
+    /// ```
     pub fn from_string_and_shapes(
         input_string: &str,
         operand_shapes: &[Vec<usize>],
-    ) -> Result<Self, &'static str> {
-        let contraction = validate(input_string)?;
+    ) -> Result<Self, EinsumError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "validate_and_size",
+            subscripts = input_string,
+            shapes = ?operand_shapes
+        )
+        .entered();
+
+        let p = parse_einsum_string(input_string)?;
+        if !p.has_ellipsis() {
+            let contraction = Contraction::from_parse(&p)?;
+            return SizedContraction::from_contraction_and_shapes(&contraction, operand_shapes)
+                .map_err(|e| p.relabel(e));
+        }
+
+        let expanded = p.expand_ellipses(operand_shapes)?;
+        let contraction = Contraction::from_parse(&expanded)?;
         SizedContraction::from_contraction_and_shapes(&contraction, operand_shapes)
+            .map_err(|e| expanded.relabel(e))
     }
 
     /// Create a SizedContraction from an `einsum`-formatted input string and a list
@@ -334,10 +566,7 @@ impl SizedContraction {
     /// assert_eq!(sc.output_size[&'k'], 4);
     /// assert_eq!(sc.output_size[&'j'], 3);
     /// ```
-    pub fn new<A>(
-        input_string: &str,
-        operands: &[&dyn ArrayLike<A>],
-    ) -> Result<Self, &'static str> {
+    pub fn new<A>(input_string: &str, operands: &[&dyn ArrayLike<A>]) -> Result<Self, EinsumError> {
         let operand_shapes = get_operand_shapes(operands);
 
         SizedContraction::from_string_and_shapes(input_string, &operand_shapes)
@@ -358,10 +587,202 @@ impl SizedContraction {
         &self,
         operands: &[&dyn ArrayLike<A>],
     ) -> ArrayD<A> {
-        let cpc = EinsumPath::new(&self);
+        let cpc = EinsumPath::new(self);
         cpc.contract_operands(operands)
     }
 
+    /// Same as `contract_operands`, but writes the result into `out`. Returns an error if
+    /// `out` doesn't have the shape of the result.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::ones((2, 3));
+    /// let m2: Array2<f64> = Array::ones((3, 4));
+    /// let mut out: ArrayD<f64> = Array::zeros(IxDyn(&[2, 4]));
+    /// let sc = validate_and_size("ij,jk->ik", &[&m1, &m2]).unwrap();
+    /// sc.contract_operands_into(&[&m1, &m2], &mut out.view_mut()).unwrap();
+    /// assert_eq!(out, Array::from_elem(IxDyn(&[2, 4]), 3.));
+    /// ```
+    pub fn contract_operands_into<A: Clone + LinalgScalar>(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError> {
+        let cpc = EinsumPath::new(self);
+        cpc.contract_operands_into(operands, out)
+    }
+
+    /// Computes `out = alpha * result + beta * out`, where `result` is what `contract_operands`
+    /// would return. Returns an error if `out` doesn't have the shape of the result.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::ones((2, 3));
+    /// let m2: Array2<f64> = Array::ones((3, 4));
+    /// let mut out: ArrayD<f64> = Array::ones(IxDyn(&[2, 4]));
+    /// let sc = validate_and_size("ij,jk->ik", &[&m1, &m2]).unwrap();
+    /// sc.contract_operands_acc(&[&m1, &m2], 0.5, -1., &mut out.view_mut()).unwrap();
+    /// assert_eq!(out, Array::from_elem(IxDyn(&[2, 4]), 0.5));
+    /// ```
+    pub fn contract_operands_acc<A: Clone + LinalgScalar>(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        alpha: A,
+        beta: A,
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError> {
+        let cpc = EinsumPath::new(self);
+        cpc.contract_operands_acc(operands, alpha, beta, out)
+    }
+
+    /// Performs the contraction on each of several sets of operands, all of which have to have
+    /// the shapes the contraction was sized for (or be broadcastable to them), and returns the
+    /// results in the same order. The `EinsumPath` is compiled once and reused for every set, so
+    /// running the same contraction many times (e.g. over Monte-Carlo samples or mini-batches)
+    /// doesn't repeat the planning and dispatch of `einsum`.
+    ///
+    /// Panics if a set doesn't have one operand per input of the contraction, or if an operand
+    /// doesn't have the shape expected for it.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let samples: Vec<(Array2<f64>, Array1<f64>)> = (0..3)
+    ///     .map(|n| (Array::from_elem((2, 3), n as f64), Array::ones(3)))
+    ///     .collect();
+    /// let operand_sets: Vec<[&dyn ArrayLike<f64>; 2]> =
+    ///     samples.iter().map(|(m, v)| [m as &dyn ArrayLike<f64>, v]).collect();
+    /// let operand_sets: Vec<&[&dyn ArrayLike<f64>]> = operand_sets.iter().map(|x| &x[..]).collect();
+    ///
+    /// let sc = SizedContraction::from_string_and_shapes("ij,j->i", &[vec![2, 3], vec![3]]).unwrap();
+    /// let results = sc.contract_many(&operand_sets);
+    /// assert_eq!(results[2], arr1(&[6., 6.]).into_dyn());
+    /// ```
+    pub fn contract_many<A: Clone + LinalgScalar>(
+        &self,
+        operand_sets: &[&[&dyn ArrayLike<A>]],
+    ) -> Vec<ArrayD<A>> {
+        let cpc = EinsumPath::new(self);
+        operand_sets
+            .iter()
+            .map(|operands| {
+                self.assert_sized_for(operands);
+                cpc.contract_operands(operands)
+            })
+            .collect()
+    }
+
+    /// Panics unless `operands` has one operand per input, each of which has (or can be broadcast
+    /// to) the shape given by the axis lengths of the contraction.
+    pub(crate) fn assert_sized_for<A>(&self, operands: &[&dyn ArrayLike<A>]) {
+        assert_eq!(
+            operands.len(),
+            self.contraction.operand_indices.len(),
+            "the contraction takes {} operands",
+            self.contraction.operand_indices.len()
+        );
+        for (operand_num, (operand, indices)) in operands
+            .iter()
+            .zip(&self.contraction.operand_indices)
+            .enumerate()
+        {
+            let shape = operand.into_dyn_view().shape().to_vec();
+            let expected: Vec<usize> = indices.iter().map(|c| self.output_size[c]).collect();
+            assert!(
+                shape.len() == expected.len()
+                    && shape
+                        .iter()
+                        .zip(&expected)
+                        .all(|(&found, &expected)| found == expected || found == 1),
+                "operand {} has shape {:?} but the contraction expects {:?}",
+                operand_num,
+                shape,
+                expected
+            );
+        }
+    }
+
+    /// Builds the contraction order described by `path`, a list of pairs of positions in the
+    /// format used by opt_einsum (e.g. the first element returned by `opt_einsum.contract_path`)
+    /// and cotengra: at each step, the two tensors to contract are identified by their
+    /// positions in the list of remaining tensors, both are removed from the list, and the
+    /// result of contracting them is appended to the end. The inverse is
+    /// [`ContractionOrder::to_explicit_path`](enum.ContractionOrder.html#method.to_explicit_path).
+    ///
+    /// Returns an error unless `path` has one step fewer than the number of operands and every
+    /// step contracts two different remaining tensors. A single operand takes an empty path.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let m3: Array2<f64> = Array::range(0., 20., 1.).into_shape((4, 5)).unwrap();
+    /// let sc = validate_and_size("ij,jk,kl->il", &[&m1, &m2, &m3]).unwrap();
+    /// let order = sc.with_explicit_path(&[(1, 2), (0, 1)]).unwrap();
+    /// assert_eq!(order.to_explicit_path(), vec![(1, 2), (0, 1)]);
+    /// let path = EinsumPath::from_path(&order);
+    /// assert_eq!(path.contract_operands(&[&m1, &m2, &m3]), m1.dot(&m2).dot(&m3).into_dyn());
+    ///
+    /// assert_eq!(
+    ///     sc.with_explicit_path(&[(1, 2), (0, 2)]).unwrap_err(),
+    ///     EinsumError::InvalidPathStep { step: 1, pair: (0, 2), remaining: 2 }
+    /// );
+    /// ```
+    pub fn with_explicit_path(
+        &self,
+        path: &[(usize, usize)],
+    ) -> Result<ContractionOrder, EinsumError> {
+        let num_operands = self.contraction.operand_indices.len();
+        if path.len() + 1 != num_operands {
+            return Err(EinsumError::PathLengthMismatch {
+                expected: num_operands - 1,
+                found: path.len(),
+            });
+        }
+        for (step, &pair) in path.iter().enumerate() {
+            let remaining = num_operands - step;
+            if pair.0 == pair.1 || pair.0 >= remaining || pair.1 >= remaining {
+                return Err(EinsumError::InvalidPathStep {
+                    step,
+                    pair,
+                    remaining,
+                });
+            }
+        }
+
+        Ok(generate_path_from_pairs(self, path))
+    }
+
+    /// Shorthand for `generate_optimized_order(self, strategy).flop_count()`; see
+    /// [`ContractionOrder::flop_count`](enum.ContractionOrder.html#method.flop_count).
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![10, 20], vec![20, 30], vec![30, 1]],
+    /// ).unwrap();
+    /// assert!(sc.flop_count(OptimizationMethod::Reverse) < sc.flop_count(OptimizationMethod::Naive));
+    /// ```
+    pub fn flop_count(&self, strategy: OptimizationMethod) -> u128 {
+        generate_optimized_order(self, strategy).flop_count()
+    }
+
+    /// Shorthand for `generate_optimized_order(self, strategy).largest_intermediate()`; see
+    /// [`ContractionOrder::largest_intermediate`](enum.ContractionOrder.html#method.largest_intermediate).
+    pub fn largest_intermediate(&self, strategy: OptimizationMethod) -> u128 {
+        generate_optimized_order(self, strategy).largest_intermediate()
+    }
+
+    /// Shorthand for `generate_optimized_order(self, strategy).total_intermediate_bytes::<A>()`; see
+    /// [`ContractionOrder::total_intermediate_bytes`](enum.ContractionOrder.html#method.total_intermediate_bytes).
+    pub fn total_intermediate_bytes<A>(&self, strategy: OptimizationMethod) -> u128 {
+        generate_optimized_order(self, strategy).total_intermediate_bytes::<A>()
+    }
+
     /// Show as an `einsum`-formatted string.
     ///
     /// ```
@@ -373,12 +794,12 @@ impl SizedContraction {
     /// assert_eq!(sc.as_einsum_string(), "ij,jk->ik");
     /// ```
     pub fn as_einsum_string(&self) -> String {
-        assert!(self.contraction.operand_indices.len() > 0);
+        assert!(!self.contraction.operand_indices.is_empty());
         let mut s: String = self.contraction.operand_indices[0]
             .iter()
             .cloned()
             .collect();
-        for op in (&self.contraction.operand_indices[1..]).iter() {
+        for op in self.contraction.operand_indices[1..].iter() {
             s.push(',');
             for &c in op.iter() {
                 s.push(c);
@@ -392,36 +813,242 @@ impl SizedContraction {
     }
 }
 
-/// Runs an input string through a regex and convert it to an EinsumParse.
-fn parse_einsum_string(input_string: &str) -> Option<EinsumParse> {
-    lazy_static! {
-        // Unwhitespaced version:
-        // ^([a-z]+)((?:,[a-z]+)*)(?:->([a-z]*))?$
-        static ref RE: Regex = Regex::new(r"(?x)
-            ^
-            (?P<first_operand>[a-z]+)
-            (?P<more_operands>(?:,[a-z]+)*)
-            (?:->(?P<output>[a-z]*))?
-            $
-            ").unwrap();
+/// Returns the output indices for an implicit-mode string (one without `->`): the indices
+/// that appear exactly once across all the operands, sorted alphabetically.
+fn implicit_output_indices(operand_indices: &[String]) -> Vec<char> {
+    let mut input_indices = HashMap::new();
+    for c in operand_indices.iter().flat_map(|s| s.chars()) {
+        *input_indices.entry(c).or_insert(0) += 1;
     }
-    let captures = RE.captures(input_string)?;
+
+    let mut unique_indices: Vec<char> = input_indices
+        .iter()
+        .filter(|(_, &v)| v == 1)
+        .map(|(&k, _)| k)
+        .collect();
+    unique_indices.sort();
+    unique_indices
+}
+
+/// Splits an input string into its operands and (optional) output and converts it to an
+/// EinsumParse, checking along the way that each piece consists only of valid indices.
+///
+/// If the string starts with `LABELS_PREFIX`, its labels are the words that whitespace
+/// separates, as in `labels: batch chan_in, chan_in chan_out -> batch chan_out`, and the rest
+/// of it is parsed by `parse_labelled_string` instead.
+fn parse_einsum_string(input_string: &str) -> Result<EinsumParse, EinsumError> {
+    if let Some(labelled_string) = input_string.strip_prefix(LABELS_PREFIX) {
+        return parse_labelled_string(labelled_string, LABELS_PREFIX.len());
+    }
+    let (operands_string, output_string) = match input_string.find("->") {
+        Some(pos) => (
+            &input_string[..pos],
+            Some((pos + 2, &input_string[(pos + 2)..])),
+        ),
+        None => (input_string, None),
+    };
+
     let mut operand_indices = Vec::new();
-    let output_indices = captures.name("output").map(|s| String::from(s.as_str()));
+    let mut position = 0;
+    for operand_string in operands_string.split(',') {
+        operand_indices.push(parse_indices(operand_string, position)?);
+        position += operand_string.len() + 1;
+    }
+
+    let output_indices = match output_string {
+        Some((position, s)) => Some(parse_indices(s, position)?),
+        None => None,
+    };
+
+    Ok(EinsumParse {
+        operand_indices,
+        output_indices,
+        labels: HashMap::new(),
+    })
+}
+
+/// Splits `subscripts` into its whitespace-separated words, each with its byte offset in the
+/// full string, given that `subscripts` starts at byte `position`.
+fn split_words(subscripts: &str, position: usize) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (offset, c) in subscripts.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push((position + word_start, &subscripts[word_start..offset]));
+                start = None;
+            }
+            (false, None) => start = Some(offset),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((position + word_start, &subscripts[word_start..]));
+    }
+    words
+}
 
-    operand_indices.push(String::from(&captures["first_operand"]));
-    for s in (&captures["more_operands"]).split(',').skip(1) {
-        operand_indices.push(String::from(s));
+/// Checks that the words of `subscripts` (which starts at byte `position` of the full string)
+/// are labels or an ellipsis, with at most one ellipsis, and returns them.
+fn parse_labels(subscripts: &str, position: usize) -> Result<Vec<(usize, &str)>, EinsumError> {
+    let words = split_words(subscripts, position);
+
+    let mut found_ellipsis = false;
+    for &(word_position, word) in words.iter() {
+        if word == ELLIPSIS {
+            if found_ellipsis {
+                return Err(EinsumError::Parse {
+                    position: word_position,
+                    message: "only one ellipsis is allowed per operand",
+                });
+            }
+            found_ellipsis = true;
+            continue;
+        }
+        for (offset, c) in word.char_indices() {
+            let is_valid = c == '_' || c.is_alphabetic() || (offset > 0 && c.is_ascii_digit());
+            if !is_valid {
+                return Err(EinsumError::Parse {
+                    position: word_position + offset,
+                    message: "invalid character in label",
+                });
+            }
+        }
     }
+    Ok(words)
+}
 
-    Some(EinsumParse {
-        operand_indices: operand_indices,
-        output_indices: output_indices,
+/// Parses a string whose labels are the words separated by whitespace, e.g.
+/// `batch chan_in, chan_in chan_out -> batch chan_out`, which starts at byte `position` of the
+/// full string (after `LABELS_PREFIX`). A label is a letter or underscore followed by any
+/// number of letters, digits and underscores, and `...` on its own is an ellipsis.
+///
+/// The rest of the library identifies indices by `char`, so the labels of the operands are
+/// sorted and each one is replaced by the `char` that [`label_index`](fn.label_index.html)
+/// gives for its position (`a` for the first one, `b` for the second, and so on). The implicit
+/// output, in which the indices are sorted, is then sorted by label. Errors that name an index
+/// are given the label back by `EinsumParse::relabel`.
+fn parse_labelled_string(input_string: &str, position: usize) -> Result<EinsumParse, EinsumError> {
+    let (operands_string, output_string) = match input_string.find("->") {
+        Some(pos) => (
+            &input_string[..pos],
+            Some((position + pos + 2, &input_string[(pos + 2)..])),
+        ),
+        None => (input_string, None),
+    };
+
+    let mut operand_labels = Vec::new();
+    let mut position = position;
+    for operand_string in operands_string.split(',') {
+        operand_labels.push(parse_labels(operand_string, position)?);
+        position += operand_string.len() + 1;
+    }
+    let output_labels = match output_string {
+        Some((position, s)) => Some(parse_labels(s, position)?),
+        None => None,
+    };
+
+    let mut distinct_labels: Vec<&str> = operand_labels
+        .iter()
+        .flatten()
+        .map(|&(_, label)| label)
+        .filter(|&label| label != ELLIPSIS)
+        .collect();
+    distinct_labels.sort_unstable();
+    distinct_labels.dedup();
+    let indices: HashMap<&str, char> = distinct_labels
+        .iter()
+        .enumerate()
+        .map(|(n, &label)| (label, label_index(n).unwrap()))
+        .collect();
+    let to_indices = |labels: &[(usize, &str)]| -> Result<String, EinsumError> {
+        let mut s = String::new();
+        for &(position, label) in labels.iter() {
+            if label == ELLIPSIS {
+                s.push_str(ELLIPSIS);
+            } else {
+                match indices.get(label) {
+                    Some(&c) => s.push(c),
+                    None => {
+                        return Err(EinsumError::Parse {
+                            position,
+                            message: "requested output contains a label not found in inputs",
+                        })
+                    }
+                }
+            }
+        }
+        Ok(s)
+    };
+
+    Ok(EinsumParse {
+        operand_indices: operand_labels
+            .iter()
+            .map(|labels| to_indices(labels))
+            .collect::<Result<Vec<String>, EinsumError>>()?,
+        output_indices: match &output_labels {
+            Some(labels) => Some(to_indices(labels)?),
+            None => None,
+        },
+        labels: indices
+            .iter()
+            .map(|(&label, &c)| (c, String::from(label)))
+            .collect(),
     })
 }
 
+/// Checks the subscripts of a single operand or of the output: letters and at most one
+/// ellipsis, or if `labelled`, labels separated by whitespace.
+pub(crate) fn check_subscripts(subscripts: &str, labelled: bool) -> Result<(), EinsumError> {
+    if labelled {
+        parse_labels(subscripts, 0).map(|_| ())
+    } else {
+        parse_indices(subscripts, 0).map(|_| ())
+    }
+}
+
+/// Checks that `indices` consists of letters (as defined by `char::is_alphabetic`, so `A`-`Z`
+/// and letters of other alphabets such as `μ` are allowed) and at most one ellipsis.
+/// `position` is the byte offset of `indices` in the full string, used for error reporting.
+/// Empty indices are allowed: they are those of a 0-d operand (or a scalar output).
+pub(crate) fn parse_indices(indices: &str, position: usize) -> Result<String, EinsumError> {
+    let mut found_ellipsis = false;
+    let mut offset = 0;
+    while offset < indices.len() {
+        let rest = &indices[offset..];
+        if rest.starts_with(ELLIPSIS) {
+            if found_ellipsis {
+                return Err(EinsumError::Parse {
+                    position: position + offset,
+                    message: "only one ellipsis is allowed per operand",
+                });
+            }
+            found_ellipsis = true;
+            offset += ELLIPSIS.len();
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+        if !c.is_alphabetic() {
+            return Err(EinsumError::Parse {
+                position: position + offset,
+                message: if c == '.' {
+                    "incomplete ellipsis"
+                } else if c.is_whitespace() {
+                    "whitespace in indices (multi-character labels need the prefix \"labels:\")"
+                } else {
+                    "invalid character in indices"
+                },
+            });
+        }
+        offset += c.len_utf8();
+    }
+
+    Ok(String::from(indices))
+}
+
 /// Wrapper around [Contraction::new()](struct.Contraction.html#method.new).
-pub fn validate(input_string: &str) -> Result<Contraction, &'static str> {
+pub fn validate(input_string: &str) -> Result<Contraction, EinsumError> {
     Contraction::new(input_string)
 }
 
@@ -437,16 +1064,65 @@ fn get_operand_shapes<A>(operands: &[&dyn ArrayLike<A>]) -> Vec<Vec<usize>> {
 pub fn validate_and_size<A>(
     input_string: &str,
     operands: &[&dyn ArrayLike<A>],
-) -> Result<SizedContraction, &'static str> {
+) -> Result<SizedContraction, EinsumError> {
     SizedContraction::new(input_string, operands)
 }
 
+/// Same as [`validate_and_size`](fn.validate_and_size.html), but takes the shapes of the
+/// operands instead of the operands, so that the contraction (and the path to perform it) can be
+/// prepared before the data arrives. Wrapper around
+/// [SizedContraction::from_string_and_shapes()](struct.SizedContraction.html#method.from_string_and_shapes).
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let sc = validate_and_size_from_shapes("ij,jk->ik", &[&[2, 3], &[3, 4]]).unwrap();
+/// let path = EinsumPath::<f64>::new(&sc);
+///
+/// // Later, once the operands exist
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let b: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+/// assert_eq!(path.contract_operands(&[&a, &b]), a.dot(&b).into_dyn());
+/// ```
+pub fn validate_and_size_from_shapes(
+    input_string: &str,
+    operand_shapes: &[&[usize]],
+) -> Result<SizedContraction, EinsumError> {
+    let operand_shapes: Vec<Vec<usize>> = operand_shapes.iter().map(|s| s.to_vec()).collect();
+    SizedContraction::from_string_and_shapes(input_string, &operand_shapes)
+}
+
+/// Returns the shape of the result of the contraction specified by `input_string` of operands
+/// with the shapes `operand_shapes`, without needing the operands themselves, so that the
+/// shape of a result can be known before there is any data to contract.
+///
+/// Returns the same errors as [`einsum`](fn.einsum.html) would for operands of these shapes.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// assert_eq!(output_shape("bij,bjk->bik", &[&[8, 2, 3], &[8, 3, 4]]).unwrap(), vec![8, 2, 4]);
+/// assert_eq!(output_shape("...ij->...ji", &[&[5, 6, 2, 3]]).unwrap(), vec![5, 6, 3, 2]);
+/// assert!(output_shape("ij,jk->ik", &[&[2, 3], &[4, 5]]).is_err());
+/// ```
+pub fn output_shape(
+    input_string: &str,
+    operand_shapes: &[&[usize]],
+) -> Result<Vec<usize>, EinsumError> {
+    let sc = validate_and_size_from_shapes(input_string, operand_shapes)?;
+    Ok(sc
+        .contraction
+        .output_indices
+        .iter()
+        .map(|c| sc.output_size[c])
+        .collect())
+}
+
 /// Create a [SizedContraction](struct.SizedContraction.html) and then optimize the order in which pairs of inputs will be contracted.
 pub fn validate_and_optimize_order<A>(
     input_string: &str,
     operands: &[&dyn ArrayLike<A>],
     optimization_strategy: OptimizationMethod,
-) -> Result<ContractionOrder, &'static str> {
+) -> Result<ContractionOrder, EinsumError> {
     let sc = validate_and_size(input_string, operands)?;
     Ok(generate_optimized_order(&sc, optimization_strategy))
 }
@@ -0,0 +1,190 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_view`, which borrows the operand instead of copying it when the result of
+//! a contraction is just the operand with its axes rearranged or one of its diagonals,
+//! `einsum_shared`, which does the same for operands that are `ArcArray`s by sharing their data,
+//! and `einsum_view_singleton`, which always returns a view or an error.
+use crate::contractors::singleton_viewer;
+use crate::passes::contract_simplified;
+use crate::plan_cache::cached_order;
+use crate::{
+    standard_layout_output, ArrayLike, ContractionOrder, EinsumError, EinsumPath, SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::{ArcArray, CowArray, LinalgScalar};
+
+/// If the single operand of `sc` has distinct indices and the output is a permutation of them,
+/// returns the axis of the operand for each output axis.
+fn output_permutation(sc: &SizedContraction) -> Option<Vec<usize>> {
+    let input_indices = &sc.contraction.operand_indices[0];
+    let output_indices = &sc.contraction.output_indices;
+    if output_indices.len() != input_indices.len() {
+        return None;
+    }
+    output_indices
+        .iter()
+        .map(|&c| {
+            let mut positions = input_indices
+                .iter()
+                .enumerate()
+                .filter(|(_, &d)| d == c)
+                .map(|(pos, _)| pos);
+            match (positions.next(), positions.next()) {
+                (Some(pos), None) => Some(pos),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Returns the result of the singleton contraction `sc` of `view` as a view of it, or `None` if
+/// it isn't one: if `sc` sums over an index or repeats an output index, or takes a diagonal of an
+/// operand that isn't contiguous.
+fn singleton_view<'a, A: LinalgScalar>(
+    sc: &SizedContraction,
+    view: &ArrayViewD<'a, A>,
+) -> Option<ArrayViewD<'a, A>> {
+    let viewer = singleton_viewer(sc)?;
+    if output_permutation(sc).is_none()
+        && !view.is_empty()
+        && view.as_slice_memory_order().is_none()
+    {
+        return None;
+    }
+    Some(viewer.view_singleton(view))
+}
+
+/// Same as [`einsum`](fn.einsum.html), but if the contraction has a single operand and only
+/// permutes its axes (e.g. `ij->ji`, or `ij->ij`) or takes a (generalized) diagonal of a
+/// contiguous operand (e.g. `ii->i`), the result is a view of the operand with its strides
+/// rearranged instead of a copy. Otherwise the contraction is performed as by `einsum` and the
+/// result is owned, as it also is inside
+/// [`with_standard_layout_output`](fn.with_standard_layout_output.html) if the view wouldn't
+/// be in standard layout.
+///
+/// Like `einsum`, this accepts operands of any storage, including `CowArray`s and `ArcArray`s.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// let a_t = einsum_view("ij->ji", &[&a]).unwrap();
+/// assert!(a_t.is_view());
+/// assert_eq!(a_t, a.t().into_dyn());
+///
+/// let row_sums = einsum_view("ij->i", &[&a]).unwrap();
+/// assert!(row_sums.is_owned());
+/// assert_eq!(row_sums, a.sum_axis(Axis(1)).into_dyn());
+///
+/// let b = CowArray::from(Array::range(0., 9., 1.).into_shape((3, 3)).unwrap());
+/// let diagonal = einsum_view("ii->i", &[&b]).unwrap();
+/// assert!(diagonal.is_view());
+/// assert_eq!(diagonal, b.diag().into_dyn());
+/// ```
+pub fn einsum_view<'a, A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&'a dyn ArrayLike<A>],
+) -> Result<CowArray<'a, A, IxDyn>, EinsumError> {
+    let contraction_order = cached_order(input_string, operands)?;
+    if let ContractionOrder::Singleton(sc) = &contraction_order {
+        if let Some(view) = singleton_view(sc, &operands[0].into_dyn_view()) {
+            if !standard_layout_output() || view.is_standard_layout() {
+                return Ok(CowArray::from(view));
+            }
+        }
+    }
+    let result = contract_simplified(&contraction_order, operands)
+        .unwrap_or_else(|| EinsumPath::from_path(&contraction_order).contract_operands(operands));
+    Ok(CowArray::from(result))
+}
+
+/// Same as [`einsum`](fn.einsum.html), but for operands that are `ArcArray`s, and if the
+/// contraction has a single operand and only permutes its axes, the result shares the data of
+/// the operand instead of copying it. Otherwise (and inside
+/// [`with_standard_layout_output`](fn.with_standard_layout_output.html) if the permuted axes
+/// wouldn't be in standard layout) the contraction is performed as by `einsum` and the result
+/// has data of its own.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap().into_dyn().into_shared();
+/// let a_t = einsum_shared("ij->ji", &[&a]).unwrap();
+/// assert_eq!(a_t.as_ptr(), a.as_ptr());
+/// assert_eq!(a_t, a.t());
+///
+/// let b = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap().into_dyn().into_shared();
+/// assert_eq!(
+///     einsum_shared("ij,jk->ik", &[&a, &b]).unwrap(),
+///     einsum("ij,jk->ik", &[&a, &b]).unwrap()
+/// );
+/// ```
+pub fn einsum_shared<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&ArcArray<A, IxDyn>],
+) -> Result<ArcArray<A, IxDyn>, EinsumError> {
+    let array_likes: Vec<&dyn ArrayLike<A>> =
+        operands.iter().map(|&x| x as &dyn ArrayLike<A>).collect();
+    let contraction_order = cached_order(input_string, &array_likes)?;
+    if let ContractionOrder::Singleton(sc) = &contraction_order {
+        if let Some(permutation) = output_permutation(sc) {
+            let shared = operands[0].clone().permuted_axes(IxDyn(&permutation));
+            if !standard_layout_output() || shared.is_standard_layout() {
+                return Ok(shared);
+            }
+        }
+    }
+    let result = contract_simplified(&contraction_order, &array_likes).unwrap_or_else(|| {
+        EinsumPath::from_path(&contraction_order).contract_operands(&array_likes)
+    });
+    Ok(result.into_shared())
+}
+
+/// Returns the result of the singleton contraction `input_string` of `operand` as a view of
+/// `operand`, without copying anything. This works for contractions that only take a
+/// (generalized) diagonal of the operand and permute its axes, such as `ii->i`, `iij->ji` or
+/// `ij->ji`: the diagonal of an axis of length `n` is the axis of length `n` whose stride is
+/// the sum of the strides of the axes it runs along.
+///
+/// Returns `EinsumError::Unsupported` if the contraction sums over an index or repeats an
+/// output index (as in `ii->` or `i->ii`), or if it takes a diagonal of an operand whose
+/// elements aren't contiguous in memory (reversed axes are fine); these can be performed by
+/// [`einsum`](fn.einsum.html) instead. Otherwise returns the same errors as `einsum`.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f64> = Array::range(0., 9., 1.).into_shape((3, 3)).unwrap();
+/// let diagonal = einsum_view_singleton("ii->i", &a).unwrap();
+/// assert_eq!(diagonal, a.diag().into_dyn());
+/// assert_eq!(diagonal.as_ptr(), a.as_ptr());
+///
+/// assert!(einsum_view_singleton("ii->", &a).is_err());
+/// ```
+pub fn einsum_view_singleton<'a, A: LinalgScalar>(
+    input_string: &str,
+    operand: &'a dyn ArrayLike<A>,
+) -> Result<ArrayViewD<'a, A>, EinsumError> {
+    let sc = SizedContraction::new(input_string, &[operand])?;
+    if singleton_viewer::<A>(&sc).is_none() {
+        return Err(EinsumError::Unsupported(
+            "only diagonals and permutations of an operand can be returned as views",
+        ));
+    }
+    singleton_view(&sc, &operand.into_dyn_view()).ok_or(EinsumError::Unsupported(
+        "a diagonal can only be returned as a view of a contiguous operand",
+    ))
+}
@@ -0,0 +1,130 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `einsum_vmap`, which maps a contraction over a leading batch axis of some of its
+//! operands, like JAX's `vmap`.
+//!
+//! Instead of looping over the batch, the contraction is rewritten with a new index for the
+//! batch axis, added at the front of the flagged operands and of the output, and performed
+//! once. A pairwise step between two batched tensors then has the batch index in both inputs
+//! and in the output, so it is performed by `BatchedMatMul` or `StackedTensordotGeneral` (or
+//! by a Hadamard product, if nothing is summed), while the unbatched operands are shared by
+//! every element of the batch without being copied.
+use crate::{
+    generate_optimized_order, ArrayLike, Contraction, EinsumError, EinsumPath, OptimizationMethod,
+    SizedContraction,
+};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Performs the contraction specified by `input_string` separately for each position along
+/// axis 0 of the operands flagged in `batched`, and stacks the results along axis 0 of the
+/// output. `input_string` describes one element of the batch: it doesn't mention the batch
+/// axis, and the flagged operands have one more axis than their subscripts. Operands that
+/// aren't flagged are used as they are for every element of the batch.
+///
+/// Returns `EinsumError::BatchFlagCountMismatch` if `batched` doesn't have one flag per
+/// operand, `EinsumError::Unsupported` if no operand is flagged,
+/// `EinsumError::RankMismatch` if a flagged operand has no axes and
+/// `EinsumError::ShapeMismatch` if the flagged operands have batches of different lengths.
+/// Otherwise, the errors are those of `einsum` for one element of the batch.
+///
+/// ```
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let xs: Array2<f64> = Array::range(0., 12., 1.).into_shape((4, 3)).unwrap();
+/// let w: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+/// // A matrix-vector product for each of the 4 rows of xs
+/// let ys = einsum_vmap("ij,j->i", &[&w, &xs], &[false, true]).unwrap();
+/// assert_eq!(ys, xs.dot(&w.t()).into_dyn());
+/// ```
+pub fn einsum_vmap<A: LinalgScalar>(
+    input_string: &str,
+    operands: &[&dyn ArrayLike<A>],
+    batched: &[bool],
+) -> Result<ArrayD<A>, EinsumError> {
+    if batched.len() != operands.len() {
+        return Err(EinsumError::BatchFlagCountMismatch {
+            expected: operands.len(),
+            found: batched.len(),
+        });
+    }
+
+    let mut batch_len: Option<usize> = None;
+    let mut element_shapes = Vec::with_capacity(operands.len());
+    for (operand_num, (operand, &is_batched)) in operands.iter().zip(batched).enumerate() {
+        let shape = operand.into_dyn_view().shape().to_vec();
+        if !is_batched {
+            element_shapes.push(shape);
+            continue;
+        }
+        match (shape.first(), batch_len) {
+            (None, _) => {
+                return Err(EinsumError::RankMismatch {
+                    operand: operand_num,
+                    expected: 1,
+                    found: 0,
+                })
+            }
+            (Some(&len), Some(expected)) if len != expected => {
+                return Err(EinsumError::ShapeMismatch {
+                    operand: operand_num,
+                    axis: 0,
+                    expected,
+                    found: len,
+                })
+            }
+            (Some(&len), _) => batch_len = Some(len),
+        }
+        element_shapes.push(shape[1..].to_vec());
+    }
+    let batch_len = match batch_len {
+        Some(len) => len,
+        None => {
+            return Err(EinsumError::Unsupported(
+                "einsum_vmap needs at least one batched operand",
+            ))
+        }
+    };
+
+    // Size the contraction of one element of the batch, then add the batch index
+    let element_sc = SizedContraction::from_string_and_shapes(input_string, &element_shapes)?;
+    let batch_index = ('a'..='z')
+        .chain('A'..='Z')
+        .chain((0x100..).filter_map(char::from_u32))
+        .find(|c| !element_sc.output_size.contains_key(c))
+        .unwrap();
+    let mut operand_indices = element_sc.contraction.operand_indices.clone();
+    for (indices, &is_batched) in operand_indices.iter_mut().zip(batched) {
+        if is_batched {
+            indices.insert(0, batch_index);
+        }
+    }
+    let mut output_indices = element_sc.contraction.output_indices.clone();
+    output_indices.insert(0, batch_index);
+    let mut output_size = element_sc.output_size.clone();
+    output_size.insert(batch_index, batch_len);
+    let sc = SizedContraction {
+        contraction: Contraction {
+            operand_indices,
+            output_indices,
+            summation_indices: element_sc.contraction.summation_indices.clone(),
+        },
+        output_size,
+    };
+
+    let contraction_order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+    Ok(EinsumPath::from_path(&contraction_order).contract_operands(operands))
+}
@@ -0,0 +1,437 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `wgpu` feature, contains `WgpuExecutor`, which runs a
+//! [`KernelProgram`](struct.KernelProgram.html) on a GPU with [wgpu](https://crates.io/crates/wgpu)
+//! compute shaders.
+//!
+//! Each kernel is one dispatch of a shader with one thread per element of its output:
+//! `StridedCopy` gathers the element at the offset given by the strides, `SumTrailing` sums a
+//! contiguous run of elements, and `BatchedGemm` computes a dot product of a row and a column.
+//! The operands are uploaded once per call, every kernel of the program is recorded into a
+//! single command buffer, and only the result is copied back. An intermediate buffer is
+//! handed to a later kernel once the last kernel that reads it has run, so the intermediate
+//! results stay on the GPU without all being alive at once.
+//!
+//! WGSL has no 64-bit floats on most GPUs, so only contractions of `f32`s are supported. The
+//! methods are `async`, since wgpu is; outside of the browser they can be run to completion
+//! with e.g. `pollster::block_on`.
+use crate::plan_cache::cached_order;
+use crate::{ArrayLike, BufferId, EinsumError, Kernel, KernelProgram};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use ndarray::prelude::*;
+use std::sync::Mutex;
+use wgpu::util::DeviceExt;
+
+/// The shaders, with one entry point per kind of kernel. The parameters of each dispatch are
+/// given as an array of `u32`s.
+const SHADERS: &str = r#"
+@group(0) @binding(0) var<storage, read> lhs: array<f32>;
+@group(0) @binding(1) var<storage, read> rhs: array<f32>;
+@group(0) @binding(2) var<storage, read_write> result: array<f32>;
+@group(0) @binding(3) var<storage, read> params: array<u32>;
+
+const WORKGROUP_SIZE: u32 = 64u;
+
+fn element(id: vec3<u32>, groups: vec3<u32>) -> u32 {
+    return id.y * groups.x * WORKGROUP_SIZE + id.x;
+}
+
+// params: the number of elements, the rank, the shape and the strides
+@compute @workgroup_size(64)
+fn strided_copy(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let p = element(id, groups);
+    if (p >= params[0]) {
+        return;
+    }
+    let rank = params[1];
+    var rest = p;
+    var offset = 0u;
+    for (var axis = rank; axis > 0u; axis--) {
+        let len = params[1u + axis];
+        offset += (rest % len) * params[1u + rank + axis];
+        rest /= len;
+    }
+    result[p] = lhs[offset];
+}
+
+// params: the number of elements kept and the number summed into each
+@compute @workgroup_size(64)
+fn sum_trailing(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let p = element(id, groups);
+    if (p >= params[0]) {
+        return;
+    }
+    let num_summed = params[1];
+    var total = 0.0;
+    for (var i = 0u; i < num_summed; i++) {
+        total += lhs[p * num_summed + i];
+    }
+    result[p] = total;
+}
+
+// params: batch, m, n and k
+@compute @workgroup_size(64)
+fn batched_gemm(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let p = element(id, groups);
+    let m = params[1];
+    let n = params[2];
+    let k = params[3];
+    if (p >= params[0] * m * n) {
+        return;
+    }
+    let matrix = p / (m * n);
+    let row = (p / n) % m;
+    let column = p % n;
+    var total = 0.0;
+    for (var i = 0u; i < k; i++) {
+        total += lhs[(matrix * m + row) * k + i] * rhs[(matrix * k + i) * n + column];
+    }
+    result[p] = total;
+}
+"#;
+
+/// The number of threads of a workgroup, as in `SHADERS`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The most workgroups a dispatch can have along one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Runs `KernelProgram`s of `f32`s on a GPU with wgpu compute shaders.
+///
+/// ```no_run
+/// # use ndarray_einsum_beta::*;
+/// # use ndarray::prelude::*;
+/// let a: Array2<f32> = Array::range(0., 6., 1.).into_shape_with_order((2, 3)).unwrap();
+/// let b: Array2<f32> = Array::range(0., 12., 1.).into_shape_with_order((3, 4)).unwrap();
+/// pollster::block_on(async {
+///     let executor = WgpuExecutor::new().await.unwrap();
+///     let product = executor.einsum("ij,jk->ik", &[&a, &b]).await.unwrap();
+///     assert_eq!(product, a.dot(&b).into_dyn());
+/// });
+/// ```
+#[derive(Debug)]
+pub struct WgpuExecutor {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    strided_copy: wgpu::ComputePipeline,
+    sum_trailing: wgpu::ComputePipeline,
+    batched_gemm: wgpu::ComputePipeline,
+}
+
+impl WgpuExecutor {
+    /// Creates an executor on the default adapter of a new wgpu instance, with the largest
+    /// limits the adapter supports.
+    ///
+    /// Returns `EinsumError::Unsupported` if there is no adapter or its device can't be created.
+    pub async fn new() -> Result<Self, EinsumError> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .map_err(|_| EinsumError::Unsupported("no wgpu adapter is available"))?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                required_limits: adapter.limits(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|_| EinsumError::Unsupported("the wgpu device couldn't be created"))?;
+        Ok(WgpuExecutor::from_device(device, queue))
+    }
+
+    /// Creates an executor that runs on `device`, submitting to `queue`.
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("einsum kernels"),
+            source: wgpu::ShaderSource::Wgsl(SHADERS.into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        WgpuExecutor {
+            strided_copy: pipeline("strided_copy"),
+            sum_trailing: pipeline("sum_trailing"),
+            batched_gemm: pipeline("batched_gemm"),
+            device,
+            queue,
+        }
+    }
+
+    /// Runs `program` on the GPU and returns the result. The operands are broadcast to the
+    /// shapes of `inputs` first, as by [`KernelProgram::execute`].
+    ///
+    /// Returns `EinsumError::Unsupported` if a buffer has more than `u32::MAX` elements or the
+    /// result can't be read back from the GPU. Panics if an operand can't be broadcast to the
+    /// shape of its input.
+    ///
+    /// [`KernelProgram::execute`]: struct.KernelProgram.html#method.execute
+    pub async fn execute(
+        &self,
+        program: &KernelProgram,
+        operands: &[&dyn ArrayLike<f32>],
+    ) -> Result<ArrayD<f32>, EinsumError> {
+        assert_eq!(operands.len(), program.inputs.len());
+        let num_buffers = program.inputs.len() + program.kernels.len();
+        if (0..num_buffers).any(|buffer| program.buffer_len(buffer) > u32::MAX as usize) {
+            return Err(EinsumError::Unsupported(
+                "a buffer has more elements than a shader can index",
+            ));
+        }
+        let mut buffers: Vec<Option<wgpu::Buffer>> = operands
+            .iter()
+            .zip(program.inputs.iter())
+            .map(|(operand, shape)| {
+                let view = operand.into_dyn_view();
+                let elements = view.broadcast(shape.clone()).unwrap();
+                let contents: Vec<u8> = elements.iter().flat_map(|x| x.to_ne_bytes()).collect();
+                Some(self.upload(&contents))
+            })
+            .collect();
+
+        // Each buffer is reused once the last kernel that reads it has run. The kernels are
+        // recorded into one command buffer, which wgpu runs in order.
+        let last_uses = program.last_uses();
+        let mut free: Vec<wgpu::Buffer> = Vec::new();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        for (kernel_num, kernel) in program.kernels.iter().enumerate() {
+            let size = byte_size(kernel.output_len());
+            let output = match free.iter().position(|buffer| buffer.size() >= size) {
+                Some(position) => free.swap_remove(position),
+                None => self.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+            };
+            let buffer = |id: &BufferId| buffers[*id].as_ref().unwrap();
+            let (pipeline, inputs, params) = match kernel {
+                Kernel::StridedCopy {
+                    input,
+                    shape,
+                    strides,
+                } => {
+                    let mut params = vec![kernel.output_len(), shape.len()];
+                    params.extend(shape.iter().chain(strides.iter()));
+                    (&self.strided_copy, vec![buffer(input)], params)
+                }
+                Kernel::SumTrailing {
+                    input,
+                    shape,
+                    num_axes,
+                } => {
+                    let num_summed = shape[shape.len() - num_axes..].iter().product();
+                    let params = vec![kernel.output_len(), num_summed];
+                    (&self.sum_trailing, vec![buffer(input)], params)
+                }
+                Kernel::BatchedGemm {
+                    lhs,
+                    rhs,
+                    batch,
+                    m,
+                    n,
+                    k,
+                } => (
+                    &self.batched_gemm,
+                    vec![buffer(lhs), buffer(rhs)],
+                    vec![*batch, *m, *n, *k],
+                ),
+            };
+            self.dispatch(
+                &mut encoder,
+                pipeline,
+                &inputs,
+                &output,
+                &params,
+                kernel.output_len(),
+            );
+
+            for input in kernel.inputs() {
+                if last_uses[input] == Some(kernel_num) && input != program.output {
+                    if let Some(buffer) = buffers[input].take() {
+                        free.push(buffer);
+                    }
+                }
+            }
+            buffers.push(Some(output));
+        }
+
+        let output = buffers[program.output].as_ref().unwrap();
+        let output_len = program.buffer_len(program.output);
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: byte_size(output_len),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(output, 0, &staging, 0, byte_size(output_len));
+        self.queue.submit(Some(encoder.finish()));
+
+        let mapped = Mapped::default();
+        let state = mapped.0.clone();
+        staging.map_async(wgpu::MapMode::Read, .., move |status| {
+            let mut state = state.lock().unwrap();
+            state.0 = Some(status.is_ok());
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        });
+        // On WebGPU this doesn't block, and the browser completes the mapping instead
+        let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+        let unreadable = EinsumError::Unsupported("the result couldn't be read back from the GPU");
+        if !mapped.await {
+            return Err(unreadable);
+        }
+        let range = staging
+            .slice(..)
+            .get_mapped_range()
+            .map_err(|_| unreadable.clone())?;
+        let elements: Vec<f32> = range[..output_len * 4]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        Ok(ArrayD::from_shape_vec(program.output_shape.clone(), elements).unwrap())
+    }
+
+    /// Performs the contraction described by `input_string` on the GPU, lowering the order
+    /// `einsum` would use with
+    /// [`ContractionOrder::lower`](enum.ContractionOrder.html#method.lower).
+    ///
+    /// Returns the errors of `einsum`, and `EinsumError::Unsupported` if a step can't be
+    /// lowered or the result can't be read back from the GPU.
+    pub async fn einsum(
+        &self,
+        input_string: &str,
+        operands: &[&dyn ArrayLike<f32>],
+    ) -> Result<ArrayD<f32>, EinsumError> {
+        let program = cached_order(input_string, operands)?.lower()?;
+        self.execute(&program, operands).await
+    }
+
+    /// Creates a storage buffer holding `contents`.
+    fn upload(&self, contents: &[u8]) -> wgpu::Buffer {
+        // wgpu can't bind an empty buffer
+        let contents = if contents.is_empty() {
+            &[0; 4][..]
+        } else {
+            contents
+        };
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+    }
+
+    /// Records a dispatch of `pipeline` with one thread per element of `output`, the first
+    /// `len` elements of which it writes.
+    fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        inputs: &[&wgpu::Buffer],
+        output: &wgpu::Buffer,
+        params: &[usize],
+        len: usize,
+    ) {
+        let params: Vec<u8> = params
+            .iter()
+            .flat_map(|&x| (x as u32).to_ne_bytes())
+            .collect();
+        let params = self.upload(&params);
+        // `lhs`, `rhs`, `result` and `params`, leaving out `rhs` if the shader doesn't use it
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: inputs[0].as_entire_binding(),
+        }];
+        if let Some(rhs) = inputs.get(1) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: rhs.as_entire_binding(),
+            });
+        }
+        entries.push(wgpu::BindGroupEntry {
+            binding: 2,
+            resource: output.as_entire_binding(),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 3,
+            resource: params.as_entire_binding(),
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let workgroups = (len as u32).div_ceil(WORKGROUP_SIZE);
+        if workgroups == 0 {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let x = workgroups.min(MAX_WORKGROUPS);
+        pass.dispatch_workgroups(x, workgroups.div_ceil(x), 1);
+    }
+}
+
+/// The size in bytes of a buffer of `len` `f32`s, which is at least 4 since wgpu can't bind an
+/// empty buffer.
+fn byte_size(len: usize) -> u64 {
+    4 * len.max(1) as u64
+}
+
+/// Resolves to whether the mapping of a buffer succeeded, once its callback has run.
+#[derive(Default)]
+struct Mapped(Arc<Mutex<(Option<bool>, Option<Waker>)>>);
+
+impl Future for Mapped {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let mut state = self.0.lock().unwrap();
+        match state.0 {
+            Some(succeeded) => Poll::Ready(succeeded),
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2019 Jared Samet
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Contains `EinsumWorkspace`, which owns the buffers that the intermediate results of a path
+//! are written into, so that performing the same contraction repeatedly doesn't allocate them
+//! every time.
+//!
+//! For small tensors, allocating and freeing the intermediate results can take longer than
+//! the arithmetic. `EinsumPath::contract_operands_with_workspace` writes the result of each
+//! step except the last into a buffer of the workspace, and the result of the last step into
+//! the output array supplied by the caller, with `contract_and_assign_pair`. The buffers are
+//! kept for the next call, so once a workspace has been used with a path (or created for it
+//! with `EinsumWorkspace::for_path`), performing the path again doesn't allocate any
+//! intermediate results.
+use crate::budget::budget_chunking;
+use crate::contractors::{broadcast_input, PairContractor, SingletonContractor};
+use crate::optimizers::OperandNumber;
+use crate::{ArrayLike, ContractionOrder, EinsumError, EinsumPath, EinsumPathSteps};
+use alloc::vec::Vec;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+
+/// Reusable buffers for the intermediate results of an [`EinsumPath`](struct.EinsumPath.html),
+/// passed to
+/// [`EinsumPath::contract_operands_with_workspace`](struct.EinsumPath.html#method.contract_operands_with_workspace).
+///
+/// A workspace can be used with any path: a buffer whose shape doesn't match the step it's
+/// needed for is replaced by one that does. Using one workspace per path avoids replacing them.
+///
+/// A workspace only removes the allocations of the intermediate results, which are the large
+/// ones; performing a path with one isn't free of allocations. Each call still allocates the
+/// list of operand views and of the steps of the path, and the shape of the output; each step
+/// allocates a few small vectors of axis numbers (such as the inverse of the permutation of its
+/// output); and each matrix multiplication allocates the packing buffers of `matrixmultiply`
+/// (or whatever BLAS allocates, with the `blas` feature), after copying any operand that can't
+/// be viewed as a matrix without one. For a chain of three 64x64 matrix multiplications that is
+/// 13 allocations, of which the packing buffers are the only ones larger than a few hundred
+/// bytes.
+#[derive(Debug, Clone)]
+pub struct EinsumWorkspace<A> {
+    /// The buffer for the result of each step of the path except the last
+    buffers: Vec<ArrayD<A>>,
+}
+
+impl<A> Default for EinsumWorkspace<A> {
+    fn default() -> Self {
+        EinsumWorkspace::new()
+    }
+}
+
+impl<A> EinsumWorkspace<A> {
+    /// Creates a workspace without any buffers; they are allocated the first time it is used.
+    pub fn new() -> Self {
+        EinsumWorkspace {
+            buffers: Vec::new(),
+        }
+    }
+
+    /// The total number of elements in the buffers of the workspace.
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.len()).sum()
+    }
+
+    /// Whether the workspace has no buffers, or only empty ones.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<A: Clone + LinalgScalar> EinsumWorkspace<A> {
+    /// Creates a workspace with a buffer for the result of every step of `path` but the last.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let sc = SizedContraction::from_string_and_shapes(
+    ///     "ij,jk,kl->il",
+    ///     &[vec![2, 3], vec![3, 4], vec![4, 5]],
+    /// ).unwrap();
+    /// let path = EinsumPath::<f64>::from_path(&sc.with_explicit_path(&[(0, 1), (0, 1)]).unwrap());
+    /// // The first step has a result of shape [2, 4]
+    /// assert_eq!(EinsumWorkspace::for_path(&path).len(), 8);
+    /// ```
+    pub fn for_path(path: &EinsumPath<A>) -> Self {
+        let mut workspace = EinsumWorkspace::new();
+        workspace.prepare(path);
+        workspace
+    }
+
+    /// Makes sure there is a buffer of the right shape for the result of every step of `path`
+    /// but the last.
+    fn prepare(&mut self, path: &EinsumPath<A>) {
+        let order_steps = match &path.contraction_order {
+            ContractionOrder::Singleton(_) => return,
+            ContractionOrder::Pairs(order_steps) => order_steps,
+        };
+        let num_buffers = order_steps.len() - 1;
+        self.buffers.truncate(num_buffers);
+        for (step_num, order_step) in order_steps[..num_buffers].iter().enumerate() {
+            let sc = &order_step.sized_contraction;
+            let shape = || -> Vec<usize> {
+                sc.contraction
+                    .output_indices
+                    .iter()
+                    .map(|c| sc.output_size[c])
+                    .collect()
+            };
+            let has_shape = |buffer: &ArrayD<A>| {
+                buffer.ndim() == sc.contraction.output_indices.len()
+                    && sc
+                        .contraction
+                        .output_indices
+                        .iter()
+                        .zip(buffer.shape())
+                        .all(|(c, &len)| sc.output_size[c] == len)
+            };
+            match self.buffers.get_mut(step_num) {
+                Some(buffer) if has_shape(buffer) => {}
+                Some(buffer) => *buffer = ArrayD::zeros(shape()),
+                None => self.buffers.push(ArrayD::zeros(shape())),
+            }
+        }
+    }
+}
+
+impl<A> EinsumPath<A> {
+    /// Same as [`contract_operands_into`](#method.contract_operands_into), except that the
+    /// intermediate results are written into the buffers of `workspace` instead of newly
+    /// allocated arrays. The workspace keeps them for the next call, so contracting new
+    /// operands of the same shapes with the same workspace doesn't allocate any intermediate
+    /// results. The temporaries that some steps need internally (such as the packing buffers
+    /// of `matrixmultiply`, a copy of an operand whose axes can't be viewed as a matrix, or the
+    /// sum of a singleton contraction) are still allocated.
+    ///
+    /// If the budget set by [`with_max_intermediate_bytes`](fn.with_max_intermediate_bytes.html)
+    /// requires the path to be performed in chunks, the workspace isn't used and this is the
+    /// same as `contract_operands_into`.
+    ///
+    /// Returns an error if `out` doesn't have the shape of the result, or if `operands` can't be
+    /// contracted with this path, as for `try_contract_operands`.
+    ///
+    /// ```
+    /// # use ndarray_einsum_beta::*;
+    /// # use ndarray::prelude::*;
+    /// let m1: Array2<f64> = Array::range(0., 6., 1.).into_shape((2, 3)).unwrap();
+    /// let m2: Array2<f64> = Array::range(0., 12., 1.).into_shape((3, 4)).unwrap();
+    /// let m3: Array2<f64> = Array::range(0., 20., 1.).into_shape((4, 5)).unwrap();
+    /// let path = einsum_path("ij,jk,kl->il", &[&m1, &m2, &m3], OptimizationMethod::Greedy).unwrap();
+    /// let mut workspace = EinsumWorkspace::for_path(&path);
+    /// let mut out: Array2<f64> = Array::zeros((2, 5));
+    /// for _ in 0..3 {
+    ///     path.contract_operands_with_workspace(
+    ///         &[&m1, &m2, &m3],
+    ///         &mut workspace,
+    ///         &mut out.view_mut().into_dyn(),
+    ///     ).unwrap();
+    ///     assert_eq!(out, m1.dot(&m2).dot(&m3));
+    /// }
+    /// ```
+    pub fn contract_operands_with_workspace(
+        &self,
+        operands: &[&dyn ArrayLike<A>],
+        workspace: &mut EinsumWorkspace<A>,
+        out: &mut ArrayViewMutD<A>,
+    ) -> Result<(), EinsumError>
+    where
+        A: Clone + LinalgScalar,
+    {
+        self.check_operands(operands)?;
+        self.check_output_shape(out)?;
+        if self.has_zero_length_index() {
+            out.fill(A::zero());
+            return Ok(());
+        }
+        if budget_chunking::<A>(&self.contraction_order).is_some() {
+            return self.contract_operands_into(operands, out);
+        }
+        workspace.prepare(self);
+        match (&self.steps, &self.contraction_order) {
+            (EinsumPathSteps::SingletonContraction(c), ContractionOrder::Singleton(_)) => {
+                out.assign(&c.contract_singleton(&operands[0].into_dyn_view()));
+            }
+            (EinsumPathSteps::PairContractions(steps), ContractionOrder::Pairs(order_steps)) => {
+                let input_views: Vec<ArrayViewD<A>> =
+                    operands.iter().map(|x| x.into_dyn_view()).collect();
+                let num_steps = steps.len();
+                for (step_num, (step, order_step)) in
+                    steps.iter().zip(order_steps.iter()).enumerate()
+                {
+                    let sc = &order_step.sized_contraction;
+                    // The results of the earlier steps are read while this one is written
+                    let (earlier_results, later_results) =
+                        workspace.buffers.split_at_mut(step_num.min(num_steps - 1));
+                    let operand_view = |operand_num: &OperandNumber, position| match *operand_num {
+                        OperandNumber::Input(pos) => {
+                            broadcast_input(&input_views[pos], sc, position)
+                        }
+                        OperandNumber::IntermediateResult(pos) => earlier_results[pos].view(),
+                    };
+                    let lhs = operand_view(&order_step.operand_nums.lhs, 0);
+                    let rhs = operand_view(&order_step.operand_nums.rhs, 1);
+                    if step_num == num_steps - 1 {
+                        step.contract_and_assign_pair(&lhs, &rhs, out);
+                    } else {
+                        step.contract_and_assign_pair(&lhs, &rhs, &mut later_results[0].view_mut());
+                    }
+                }
+            }
+            _ => panic!(), // steps and contraction_order don't match
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use ndarray::prelude::*;
-use ndarray::Data;
+use ndarray::{Data, Zip};
 use ndarray_einsum_beta::*;
+use ndarray_rand::rand_distr::Uniform;
 use ndarray_rand::RandomExt;
-use rand::distributions::Uniform;
 const TOL: f64 = 1e-10;
 
 trait AllClose {
@@ -217,7 +217,7 @@ fn tp18() {
 
 #[test]
 fn bad_parses_1() {
-    for s in vec!["->i", "i,", "->", "i,,,j->k"].iter() {
+    for s in ["->i", "i,,,j->k", "i,j->i)"].iter() {
         let contraction_result = Contraction::new(s);
         assert!(contraction_result.is_err());
     }
@@ -225,12 +225,203 @@ fn bad_parses_1() {
 
 #[test]
 fn bad_outputs_1() {
-    for s in vec!["i,j,k,l,m->p", "i,j->ijj"].iter() {
+    for s in ["i,j,k,l,m->p", "i,j->iik"].iter() {
         let contraction_result = Contraction::new(s);
         assert!(contraction_result.is_err());
     }
 }
 
+#[test]
+fn bad_parses_report_positions() {
+    for &(s, position) in [
+        ("ij,jk->i)", 8),
+        ("ij,j.k->ik", 4),
+        ("ij->i->j", 5),
+        ("...i...->i", 4),
+    ]
+    .iter()
+    {
+        match Contraction::new(s) {
+            Err(EinsumError::Parse { position: p, .. }) => assert_eq!(p, position),
+            other => panic!("Unexpected result for {}: {:?}", s, other),
+        }
+    }
+}
+
+#[test]
+fn bad_outputs_report_index() {
+    assert_eq!(
+        Contraction::new("i,j->ik").unwrap_err(),
+        EinsumError::OutputIndexNotInInputs { index: 'k' }
+    );
+}
+
+#[test]
+fn bad_shapes_report_details() {
+    assert_eq!(
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![2, 3]]).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+    assert_eq!(
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![2, 3], vec![3, 4, 5]])
+            .unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 1,
+            expected: 2,
+            found: 3
+        }
+    );
+    assert_eq!(
+        SizedContraction::from_string_and_shapes(
+            "ij,jk,ki->",
+            &[vec![2, 3], vec![3, 4], vec![4, 5]]
+        )
+        .unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "i".to_string(),
+            first_operand: 0,
+            first_axis: 0,
+            expected: 2,
+            operand: 2,
+            axis: 1,
+            found: 5
+        }
+    );
+    assert_eq!(
+        SizedContraction::from_string_and_shapes("jij->i", &[vec![2, 3, 4]]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 0,
+            first_axis: 0,
+            expected: 2,
+            operand: 0,
+            axis: 2,
+            found: 4
+        }
+    );
+
+    // A length of 1 is broadcast, so the mismatch is reported against the first other length
+    let err = SizedContraction::from_string_and_shapes(
+        "ij,jk,jl->",
+        &[vec![2, 1], vec![4, 3], vec![5, 6]],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 1,
+            first_axis: 0,
+            expected: 4,
+            operand: 2,
+            axis: 0,
+            found: 5
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "index 'j' has length 4 on axis 0 of operand 1 but length 5 on axis 0 of operand 2"
+    );
+}
+
+#[test]
+fn ellipsis_parses_1() {
+    let sc = SizedContraction::from_string_and_shapes(
+        "...ij,...jk->...ik",
+        &[vec![2, 3, 4], vec![2, 4, 5]],
+    )
+    .unwrap();
+    assert_eq!(
+        sc.contraction.operand_indices,
+        &[vec!['A', 'i', 'j'], vec!['A', 'j', 'k']]
+    );
+    assert_eq!(sc.contraction.output_indices, &['A', 'i', 'k']);
+    assert_eq!(sc.contraction.summation_indices, &['j']);
+    assert_eq!(sc.output_size[&'A'], 2);
+}
+
+#[test]
+fn ellipsis_parses_2() {
+    // Ellipses are aligned from the right and can appear anywhere in an operand
+    let sc =
+        SizedContraction::from_string_and_shapes("i...j,...->...", &[vec![2, 3, 4, 5], vec![4]])
+            .unwrap();
+    assert_eq!(
+        sc.contraction.operand_indices,
+        &[vec!['i', 'A', 'B', 'j'], vec!['B']]
+    );
+    assert_eq!(sc.contraction.output_indices, &['A', 'B']);
+    assert_eq!(sc.contraction.summation_indices, &['i', 'j']);
+}
+
+#[test]
+fn ellipsis_parses_3() {
+    // An ellipsis covering no axes is allowed
+    let sc = SizedContraction::from_string_and_shapes("...ij->...ji", &[vec![2, 3]]).unwrap();
+    assert_eq!(sc.as_einsum_string(), "ij->ji");
+}
+
+#[test]
+fn ellipsis_parses_implicit_1() {
+    let sc =
+        SizedContraction::from_string_and_shapes("...ij,...jk", &[vec![2, 3, 4], vec![7, 2, 4, 5]])
+            .unwrap();
+    assert_eq!(
+        sc.contraction.operand_indices,
+        &[vec!['B', 'i', 'j'], vec!['A', 'B', 'j', 'k']]
+    );
+    assert_eq!(sc.contraction.output_indices, &['A', 'B', 'i', 'k']);
+    assert_eq!(sc.contraction.summation_indices, &['j']);
+}
+
+#[test]
+fn ellipsis_parses_implicit_2() {
+    // Repeated named indices are summed; the broadcast axes are always kept
+    let sc =
+        SizedContraction::from_string_and_shapes("b...a,a...c", &[vec![2, 3, 4], vec![4, 3, 5]])
+            .unwrap();
+    assert_eq!(sc.as_einsum_string(), "bAa,aAc->Abc");
+}
+
+#[test]
+fn bad_ellipsis_parses_1() {
+    for s in [
+        "....ij->ij",
+        "i..j->ij",
+        "...i...->i",
+        "ij->i...j...",
+        "..,ij->ij",
+    ]
+    .iter()
+    {
+        assert!(SizedContraction::from_string_and_shapes(s, &[vec![2, 3]]).is_err());
+    }
+}
+
+#[test]
+fn bad_ellipsis_shapes_1() {
+    // Too few dimensions for the named indices
+    assert!(SizedContraction::from_string_and_shapes("...ijk->...", &[vec![2, 3]]).is_err());
+
+    // Broadcast axes must either match or have length 1
+    assert!(SizedContraction::from_string_and_shapes(
+        "...ij,...jk->...ik",
+        &[vec![2, 3, 4], vec![3, 4, 5]]
+    )
+    .is_err());
+
+    // Axes with the same index in one operand aren't broadcast against each other
+    assert!(SizedContraction::from_string_and_shapes("ii,i->i", &[vec![1, 3], vec![3]]).is_err());
+}
+
+#[test]
+fn ellipsis_requires_shapes() {
+    assert!(Contraction::new("...ij,...jk->...ik").is_err());
+}
+
 fn rand_array<Sh, D: Dimension>(shape: Sh) -> ArrayBase<ndarray::OwnedRepr<f64>, D>
 where
     Sh: ShapeBuilder<Dim = D>,
@@ -297,7 +488,7 @@ fn it_collapses_a_singleton_with_noncontiguous_strides() {
     let output_shape = vec![4, 4];
     let strides = vec![17, 4];
     let square =
-        ArrayView::from_shape(IxDyn(&output_shape).strides(IxDyn(&strides)), &data_slice).unwrap();
+        ArrayView::from_shape(IxDyn(&output_shape).strides(IxDyn(&strides)), data_slice).unwrap();
 
     let correct_answer = arr0(square.diag().sum());
 
@@ -1164,3 +1355,4931 @@ fn it_contracts_four_matrices() {
     let dotted = ep.contract_operands(&[&op1, &op2, &op3, &op4]);
     assert!(correct_answer.my_all_close(&dotted, TOL));
 }
+
+#[test]
+fn it_contracts_with_an_ellipsis() {
+    let lhs = rand_array((2, 3, 4));
+    let rhs = rand_array((2, 4, 5));
+
+    let mut correct_answer: Array3<f64> = Array::zeros((2, 3, 5));
+    for b in 0..2 {
+        correct_answer
+            .index_axis_mut(Axis(0), b)
+            .assign(&lhs.index_axis(Axis(0), b).dot(&rhs.index_axis(Axis(0), b)));
+    }
+
+    let lib_output = einsum("...ij,...jk->...ik", &[&lhs, &rhs]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+}
+
+#[test]
+fn it_broadcasts_an_ellipsis_with_fewer_dimensions() {
+    let lhs = rand_array((6, 2, 3, 4));
+    let rhs = rand_array((4, 5));
+
+    let mut correct_answer: Array4<f64> = Array::zeros((6, 2, 3, 5));
+    for a in 0..6 {
+        for b in 0..2 {
+            let lhs_matrix: ArrayView2<f64> = lhs.slice(s![a, b, .., ..]);
+            correct_answer
+                .slice_mut(s![a, b, .., ..])
+                .assign(&lhs_matrix.dot(&rhs));
+        }
+    }
+
+    let lib_output = einsum("...ij,...jk->...ik", &[&lhs, &rhs]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+}
+
+#[test]
+fn it_broadcasts_length_one_ellipsis_axes() {
+    let lhs = rand_array((2, 1, 3, 4));
+    let rhs = rand_array((5, 4, 6));
+
+    let mut correct_answer: Array4<f64> = Array::zeros((2, 5, 3, 6));
+    for a in 0..2 {
+        for b in 0..5 {
+            let lhs_matrix: ArrayView2<f64> = lhs.slice(s![a, 0, .., ..]);
+            let rhs_matrix: ArrayView2<f64> = rhs.slice(s![b, .., ..]);
+            correct_answer
+                .slice_mut(s![a, b, .., ..])
+                .assign(&lhs_matrix.dot(&rhs_matrix));
+        }
+    }
+
+    let lib_output = einsum("...ij,...jk->...ik", &[&lhs, &rhs]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+
+    let path = einsum_path(
+        "...ij,...jk->...ik",
+        &[&lhs, &rhs],
+        OptimizationMethod::Reverse,
+    )
+    .unwrap();
+    assert!(correct_answer.my_all_close(&path.contract_operands(&[&lhs, &rhs]), TOL));
+}
+
+#[test]
+fn it_broadcasts_length_one_named_axes() {
+    let column = rand_array((3, 1));
+    let matrix = rand_array((3, 4));
+
+    // Element-wise product
+    let correct_answer = &column * &matrix;
+    let lib_output = einsum("ij,ij->ij", &[&column, &matrix]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+    let lib_output = einsum("ij,ij->ij", &[&matrix, &column]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+
+    // A broadcast axis that is summed over
+    let row = rand_array((1, 4));
+    let correct_answer = column.dot(&row.sum_axis(Axis(1)).insert_axis(Axis(0)));
+    let lib_output = einsum("ij,kj->ik", &[&column, &row]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+
+    // Several steps, with the broadcast operand in different positions of the order
+    let other = rand_array((4, 5));
+    let correct_answer = (&column * &matrix).dot(&other);
+    for method in [
+        OptimizationMethod::Naive,
+        OptimizationMethod::Reverse,
+        OptimizationMethod::Greedy,
+    ] {
+        let path = einsum_path("ij,ij,jk->ik", &[&column, &matrix, &other], method).unwrap();
+        let lib_output = path.contract_operands(&[&column, &matrix, &other]);
+        assert!(correct_answer.my_all_close(&lib_output, TOL));
+    }
+
+    // Lengths other than 1 still have to match
+    assert_eq!(
+        einsum("ij,ij->ij", &[&matrix, &rand_array((3, 2))]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 0,
+            first_axis: 1,
+            expected: 4,
+            operand: 1,
+            axis: 1,
+            found: 2
+        }
+    );
+}
+
+#[test]
+fn it_diagonalizes_and_sums_with_an_ellipsis() {
+    let op = rand_array((2, 3, 4, 4));
+
+    let mut diagonals: Array3<f64> = Array::zeros((2, 3, 4));
+    for a in 0..2 {
+        for b in 0..3 {
+            for i in 0..4 {
+                diagonals[[a, b, i]] = op[[a, b, i, i]];
+            }
+        }
+    }
+    let lib_output = einsum("...ii->...i", &[&op]).unwrap();
+    assert!(diagonals.my_all_close(&lib_output, TOL));
+
+    // Axes covered by the ellipsis on the inputs but not the output are summed over
+    let traces = diagonals.sum_axis(Axis(0)).sum_axis(Axis(0));
+    let lib_output = einsum("...ii->i", &[&op]).unwrap();
+    assert!(traces.my_all_close(&lib_output, TOL));
+}
+
+#[test]
+fn it_embeds_repeated_output_indices() {
+    let v = rand_array(4);
+    let correct_answer = Array2::from_diag(&v);
+    let lib_output = einsum("i->ii", &[&v]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+
+    let m = rand_array((3, 4));
+    let mut correct_answer: Array3<f64> = Array::zeros((3, 3, 4));
+    for i in 0..3 {
+        for j in 0..4 {
+            correct_answer[[i, i, j]] = m[[i, j]];
+        }
+    }
+    let lib_output = einsum("ij->iij", &[&m]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+    let lib_output = einsum("ji->iji", &[&m.t()]).unwrap();
+    assert!(correct_answer
+        .view()
+        .permuted_axes([0, 2, 1])
+        .my_all_close(&lib_output, TOL));
+
+    // The final step of a pairwise contraction
+    let other = rand_array((4, 5));
+    let product = m.dot(&other);
+    let mut correct_answer: Array3<f64> = Array::zeros((3, 5, 3));
+    for i in 0..3 {
+        for k in 0..5 {
+            correct_answer[[i, k, i]] = product[[i, k]];
+        }
+    }
+    let lib_output = einsum("ij,jk->iki", &[&m, &other]).unwrap();
+    assert!(correct_answer.my_all_close(&lib_output, TOL));
+    let path = einsum_path("ij,jk->iki", &[&m, &other], OptimizationMethod::Greedy).unwrap();
+    assert_eq!(path.output_shape(), vec![3, 5, 3]);
+
+    // Writing into an existing array zeroes the off-diagonal elements, whatever its layout
+    let mut out: Array3<f64> = Array::ones((3, 3, 5));
+    let mut out_view = out.view_mut().permuted_axes([0, 2, 1]).into_dyn();
+    einsum_into("ij,jk->iki", &[&m, &other], &mut out_view).unwrap();
+    assert!(correct_answer.my_all_close(&out_view, TOL));
+    let mut out: Array3<f64> = Array::ones((3, 5, 3));
+    einsum_into("ij,jk->iki", &[&m, &other], &mut out.view_mut().into_dyn()).unwrap();
+    assert!(correct_answer.my_all_close(&out, TOL));
+    einsum_acc(
+        "ij,jk->iki",
+        &[&m, &other],
+        2.,
+        1.,
+        &mut out.view_mut().into_dyn(),
+    )
+    .unwrap();
+    assert!((&correct_answer * 3.).my_all_close(&out, TOL));
+
+    let sc = validate_and_size("ij,jk->iki", &[&m, &other]).unwrap();
+    assert_eq!(sc.as_einsum_string(), "ij,jk->iki");
+}
+
+#[test]
+fn it_contracts_an_implicit_ellipsis() {
+    let lhs = rand_array((2, 3, 4));
+    let rhs = rand_array((2, 4, 5));
+
+    let explicit = einsum("...ij,...jk->...ik", &[&lhs, &rhs]).unwrap();
+    let implicit = einsum("...ij,...jk", &[&lhs, &rhs]).unwrap();
+    assert!(explicit.my_all_close(&implicit, TOL));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_contracts_pairs_in_parallel() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((3, 5, 6));
+    let m4 = rand_array((6, 3));
+    let v = rand_array(7);
+    let w = rand_array(6);
+    for (s, operands) in [
+        ("ijk,jkl->il", vec![&m1 as &dyn ArrayLike<f64>, &m2]), // TensordotGeneral
+        ("ijk,jkl->li", vec![&m1, &m2]),                        // output permutation
+        ("ijk,ikl->ijl", vec![&m1, &m3]),                       // BatchedMatMul
+        ("ijk,ijk->i", vec![&m1, &m1]),                         // StackedTensordotGeneral
+        ("ji,j->i", vec![&m4, &w]),                             // MatrixVectorProduct
+        ("ijk,ijk->ijk", vec![&m1, &m1]),                       // HadamardProductGeneral
+        ("ijk,kji->ijk", vec![&m1, &m1.t()]),                   // permuted Hadamard
+        ("l,ijk->kji", vec![&v, &m1]),                          // ScalarMatrixProductGeneral
+        ("ijk,l->ijk", vec![&m1, &v]),                          // MatrixScalarProductGeneral
+        ("li,ijk,jkl->", vec![&m4, &m1, &m2]),                  // several steps
+    ] {
+        let sequential = einsum(s, &operands).unwrap();
+        let parallel = par_einsum(s, &operands).unwrap();
+        assert!(sequential.my_all_close(&parallel, TOL), "{}", s);
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_splits_wide_and_tall_matrix_products() {
+    let tall = rand_array((37, 5));
+    let wide = rand_array((5, 41));
+    let narrow = rand_array((5, 3));
+
+    let lib_output = par_einsum("ij,jk->ik", &[&tall, &narrow]).unwrap();
+    assert!(tall.dot(&narrow).my_all_close(&lib_output, TOL));
+
+    let short: Array2<f64> = tall.slice(s![..3, ..]).to_owned();
+    let lib_output = par_einsum("ij,jk->ik", &[&short, &wide]).unwrap();
+    assert!(short.dot(&wide).my_all_close(&lib_output, TOL));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_limits_the_number_of_threads() {
+    let lhs = rand_array((8, 6, 7));
+    let rhs = rand_array((8, 7, 5));
+    let correct_answer = einsum("bij,bjk->bik", &[&lhs, &rhs]).unwrap();
+
+    for num_threads in [1, 3] {
+        let lib_output = with_num_threads(num_threads, || {
+            assert_eq!(rayon::current_num_threads(), num_threads);
+            par_einsum("bij,bjk->bik", &[&lhs, &rhs]).unwrap()
+        })
+        .unwrap();
+        assert!(correct_answer.my_all_close(&lib_output, TOL));
+    }
+}
+
+#[test]
+fn it_contracts_with_a_greedy_order() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let m3 = rand_array((5, 6));
+    let m4 = rand_array((6, 3, 2));
+    let m5 = rand_array((2, 7));
+    let operands: [&dyn ArrayLike<f64>; 5] = [&m1, &m2, &m3, &m4, &m5];
+
+    for s in [
+        "ij,jk,kl,lim,mn->n",
+        "ij,jk,kl,lim,mn->in",
+        "ij,jk,kl,lim,mn->",
+        "ij,jk,kl,lim,mn->nmlkji",
+    ] {
+        let naive_output = einsum(s, &operands).unwrap();
+        let path = einsum_path(s, &operands, OptimizationMethod::Greedy).unwrap();
+        let greedy_output = path.contract_operands(&operands);
+        assert!(naive_output.my_all_close(&greedy_output, TOL), "{}", s);
+    }
+}
+
+#[test]
+fn greedy_order_avoids_outer_products() {
+    let m1 = rand_array((10, 2));
+    let m2 = rand_array((11, 12));
+    let m3 = rand_array((2, 11));
+    let sc = validate_and_size("ab,cd,bc->ad", &[&m1, &m2, &m3]).unwrap();
+
+    // The naive order starts with the 10x2x11x12 outer product of the first two operands,
+    // while contracting the last two first shrinks 132 + 22 elements down to 24
+    match generate_optimized_order(&sc, OptimizationMethod::Greedy) {
+        ContractionOrder::Pairs(steps) => {
+            assert_eq!(steps.len(), 2);
+            assert_eq!(steps[0].sized_contraction.as_einsum_string(), "cd,bc->db");
+            assert_eq!(steps[1].sized_contraction.as_einsum_string(), "ab,db->ad");
+        }
+        _ => panic!(),
+    }
+
+    let path = einsum_path("ab,cd,bc->ad", &[&m1, &m2, &m3], OptimizationMethod::Greedy).unwrap();
+    let correct_answer = m1.dot(&m3).dot(&m2);
+    assert!(correct_answer.my_all_close(&path.contract_operands(&[&m1, &m2, &m3]), TOL));
+}
+
+#[test]
+fn it_contracts_with_an_optimal_order() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let m3 = rand_array((5, 6));
+    let m4 = rand_array((6, 3, 2));
+    let m5 = rand_array((2, 7));
+    let m6 = rand_array((7, 4));
+    let operands: [&dyn ArrayLike<f64>; 6] = [&m1, &m2, &m3, &m4, &m5, &m6];
+
+    for s in [
+        "ij,jk,kl,lim,mn,nj->",
+        "ij,jk,kl,lim,mn,nj->in",
+        "ij,jk,kl,lim,mn,nj->jnl",
+    ] {
+        let naive_output = einsum(s, &operands).unwrap();
+        for memory_limit in [None, Some(1), Some(30)] {
+            let path =
+                einsum_path(s, &operands, OptimizationMethod::Optimal { memory_limit }).unwrap();
+            let optimal_output = path.contract_operands(&operands);
+            assert!(naive_output.my_all_close(&optimal_output, TOL), "{}", s);
+        }
+    }
+}
+
+#[cfg(feature = "optimal")]
+#[test]
+fn optimal_order_respects_the_memory_limit() {
+    let m1 = rand_array((1, 2));
+    let m2 = rand_array((2, 100));
+    let m3 = rand_array((100, 2));
+    let sc = validate_and_size("ij,jk,kl->il", &[&m1, &m2, &m3]).unwrap();
+    let step_strings = |memory_limit| match generate_optimized_order(
+        &sc,
+        OptimizationMethod::Optimal { memory_limit },
+    ) {
+        ContractionOrder::Pairs(steps) => steps
+            .iter()
+            .map(|step| step.sized_contraction.as_einsum_string())
+            .collect::<Vec<_>>(),
+        _ => panic!(),
+    };
+
+    // Contracting the first two operands first takes 1*2*100 + 1*100*2 = 400 multiplications
+    // but has a 1x100 intermediate; contracting the last two first takes 2*100*2 + 1*2*2 = 404
+    // multiplications with only a 2x2 intermediate.
+    assert_eq!(step_strings(None), ["ij,jk->ik", "ik,kl->il"]);
+    assert_eq!(step_strings(Some(100)), ["ij,jk->ik", "ik,kl->il"]);
+    assert_eq!(step_strings(Some(50)), ["jk,kl->jl", "ij,jl->il"]);
+
+    // Nothing fits in one element, so the order with the smallest intermediate is used
+    assert_eq!(step_strings(Some(1)), ["jk,kl->jl", "ij,jl->il"]);
+}
+
+#[test]
+fn it_accepts_operands_as_iterators_and_arguments() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let m3 = rand_array(5);
+    let correct_answer = einsum("ij,jk,k->i", &[&m1, &m2, &m3]).unwrap();
+
+    let views = vec![
+        m1.view().into_dyn(),
+        m2.view().into_dyn(),
+        m3.view().into_dyn(),
+    ];
+    assert!(correct_answer.my_all_close(&einsum_iter("ij,jk,k->i", views).unwrap(), TOL));
+    let from_iterator = einsum_iter(
+        "ij,jk,k->i",
+        [&m1, &m2]
+            .iter()
+            .map(|m| m.view().into_dyn())
+            .chain(Some(m3.view().into_dyn())),
+    );
+    assert!(correct_answer.my_all_close(&from_iterator.unwrap(), TOL));
+    let result = einsum3("ij,jk,k->i", &m1, &m2.view(), &m3).unwrap();
+    assert!(correct_answer.my_all_close(&result, TOL));
+    let result = einsum2("ij,jk->ik", &m1, &m2.t().t()).unwrap();
+    assert!(result.my_all_close(&einsum("ij,jk->ik", &[&m1, &m2]).unwrap(), TOL));
+
+    assert_eq!(
+        einsum2("ij,jk->ik", &m1, &m1),
+        einsum("ij,jk->ik", &[&m1, &m1])
+    );
+    assert!(einsum_iter("ij,jk->ik", vec![m1.view(), m1.view()]).is_err());
+    assert!(einsum_iter("i->i", Vec::<ArrayViewD<f64>>::new()).is_err());
+}
+
+#[test]
+fn it_contracts_raw_buffers() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let correct_answer = einsum("ij,jk->ik", &[&m1, &m2]).unwrap();
+    let result = einsum_from_raw(
+        "ij,jk->ik",
+        &[
+            (m1.as_slice().unwrap(), &[3, 4]),
+            (m2.as_slice().unwrap(), &[4, 5]),
+        ],
+    )
+    .unwrap();
+    assert!(correct_answer.my_all_close(&result, TOL));
+
+    let scalar = [2.];
+    let result = einsum_from_raw(
+        ",ij->ij",
+        &[(&scalar, &[]), (m1.as_slice().unwrap(), &[3, 4])],
+    );
+    assert!(result.unwrap().my_all_close(&(&m1 * 2.), TOL));
+
+    for (shape, found) in [
+        (vec![3, 5], 12),
+        (vec![2, 3], 12),
+        (vec![usize::MAX, 3], 12),
+    ] {
+        assert_eq!(
+            einsum_from_raw("ij->", &[(m1.as_slice().unwrap(), &shape)]).unwrap_err(),
+            EinsumError::BufferLengthMismatch {
+                operand: 0,
+                shape,
+                found
+            }
+        );
+    }
+    assert!(einsum_from_raw("ij->", &[(m1.as_slice().unwrap(), &[4, 3])]).is_ok());
+}
+
+#[test]
+fn it_computes_traces_diagonals_outer_and_hadamard_products_directly() {
+    let square = rand_array((4, 4));
+    let reversed = square.slice(s![..;-1, ..;2]);
+    let u = rand_array(4);
+    let v = rand_array(3);
+    let m = rand_array((4, 3));
+    let b = rand_array((2, 4, 3));
+
+    let correct_trace = einsum("ii", &[&square]).unwrap();
+    assert!(correct_trace.my_all_close(&arr0(trace(&square).unwrap()).into_dyn(), TOL));
+    assert!(einsum("ii->i", &[&square])
+        .unwrap()
+        .my_all_close(&diag(&square).unwrap().into_dyn(), TOL));
+    assert!(einsum("ii->i", &[&square.t()])
+        .unwrap()
+        .my_all_close(&diag(&square.t()).unwrap().into_dyn(), TOL));
+    assert_eq!(diag(&Array2::<f64>::zeros((0, 0))).unwrap().len(), 0);
+    assert!(trace(&reversed).is_err());
+    assert_eq!(
+        diag(&m).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 1,
+            expected: 4,
+            found: 3
+        }
+    );
+
+    assert!(einsum("i,j->ij", &[&u, &v])
+        .unwrap()
+        .my_all_close(&outer(&u, &v).into_dyn(), TOL));
+
+    let correct_answer = einsum("ij,ij->ij", &[&m, &m.mapv(|x| x + 1.)]).unwrap();
+    let result = hadamard(&m, &m.mapv(|x| x + 1.)).unwrap();
+    assert!(correct_answer.my_all_close(&result.into_dyn(), TOL));
+    let row = m.slice(s![..1, ..]);
+    assert!(einsum("ij,ij->ij", &[&row, &m])
+        .unwrap()
+        .my_all_close(&hadamard(&row, &m).unwrap().into_dyn(), TOL));
+    assert_eq!(
+        hadamard(&b.view().into_dyn(), &m.view().into_dyn()).unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 1,
+            expected: 3,
+            found: 2
+        }
+    );
+    assert_eq!(
+        hadamard(&m, &m.t()).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: 0,
+            expected: 4,
+            found: 3
+        }
+    );
+}
+
+#[test]
+fn it_multiplies_batches_of_matrices() {
+    let a = rand_array((2, 3, 4, 5));
+    let b = rand_array((3, 5, 6));
+    let correct_answer = einsum("...ij,...jk->...ik", &[&a, &b]).unwrap();
+    let result = batch_matmul(&a, &b).unwrap();
+    assert_eq!(result.shape(), &[2, 3, 4, 6]);
+    assert!(correct_answer.my_all_close(&result, TOL));
+
+    // A batch axis of length 1 is broadcast
+    let c = rand_array((2, 1, 5, 6));
+    let result = batch_matmul(&a, &c).unwrap();
+    assert_eq!(result.shape(), &[2, 3, 4, 6]);
+    let rhs: ArrayView2<f64> = c.slice(s![1, 0, .., ..]);
+    for batch in 0..3 {
+        let lhs: ArrayView2<f64> = a.slice(s![1, batch, .., ..]);
+        assert!(lhs
+            .dot(&rhs)
+            .into_dyn()
+            .my_all_close(&result.slice(s![1, batch, .., ..]).into_dyn(), TOL));
+    }
+
+    let m1 = rand_array((4, 5));
+    let m2 = rand_array((5, 6));
+    assert!(m1
+        .dot(&m2)
+        .into_dyn()
+        .my_all_close(&batch_matmul(&m1, &m2).unwrap(), TOL));
+    assert!(batch_matmul(&m1.t(), &a.slice(s![.., .., .., ..4]))
+        .unwrap()
+        .my_all_close(
+            &einsum("ji,...jk->...ik", &[&m1, &a.slice(s![.., .., .., ..4])]).unwrap(),
+            TOL
+        ));
+
+    assert_eq!(
+        batch_matmul(&a, &rand_array(5)).unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 1,
+            expected: 2,
+            found: 1
+        }
+    );
+    assert_eq!(
+        batch_matmul(&a, &m1).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: 0,
+            expected: 5,
+            found: 4
+        }
+    );
+    assert_eq!(
+        batch_matmul(&a, &rand_array((4, 5, 6))).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: 0,
+            expected: 3,
+            found: 4
+        }
+    );
+}
+
+#[test]
+fn it_computes_bilinear_maps() {
+    for (i, j) in [(3, 4), (4, 3)] {
+        let x = rand_array((5, i));
+        let a = rand_array((i, j, 2));
+        let y = rand_array((5, j));
+        let correct_answer = einsum("bi,ijk,bj->bk", &[&x, &a, &y]).unwrap();
+        let result = bilinear(&x, &a, &y).unwrap();
+        assert!(correct_answer.my_all_close(&result.into_dyn(), TOL));
+    }
+
+    let x = rand_array((5, 3));
+    let a = rand_array((3, 4, 2));
+    assert_eq!(
+        bilinear(&x, &a, &rand_array((5, 3))).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 1,
+            first_axis: 1,
+            expected: 4,
+            operand: 2,
+            axis: 1,
+            found: 3
+        }
+    );
+}
+
+#[test]
+fn it_computes_partial_traces() {
+    // rho = a ⊗ b ⊗ c for matrices of dimensions 2, 3 and 2
+    let a = rand_array((2, 2));
+    let b = rand_array((3, 3));
+    let c = rand_array((2, 2));
+    let rho: Array2<f64> = einsum("ij,kl,mn->ikmjln", &[&a, &b, &c])
+        .unwrap()
+        .into_shape_clone((12, 12))
+        .unwrap();
+    let (trace_a, trace_b, trace_c) = (a.diag().sum(), b.diag().sum(), c.diag().sum());
+
+    let ac = einsum("ij,mn->imjn", &[&a, &c])
+        .unwrap()
+        .into_shape_clone((4, 4))
+        .unwrap();
+    let result = partial_trace(&rho, &[2, 3, 2], &[1]).unwrap();
+    assert!(result.my_all_close(&(&ac * trace_b), TOL));
+    let result = partial_trace(&rho, &[2, 3, 2], &[2, 0]).unwrap();
+    assert!(result.my_all_close(&(&b * (trace_a * trace_c)), TOL));
+    let result = partial_trace(&rho, &[2, 3, 2], &[]).unwrap();
+    assert!(result.my_all_close(&rho, TOL));
+
+    assert_eq!(
+        partial_trace(&rho, &[2, 3, 2], &[1, 1]).unwrap_err(),
+        EinsumError::InvalidSubsystem {
+            subsystem: 1,
+            message: "it is listed more than once"
+        }
+    );
+    assert_eq!(
+        partial_trace(&rho, &[2, 3, 3], &[0]).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 0,
+            expected: 18,
+            found: 12
+        }
+    );
+}
+
+#[test]
+fn it_computes_kronecker_products() {
+    let a = rand_array((2, 3));
+    let b = rand_array((4, 5));
+    let result = kron(&a, &b);
+    assert_eq!(result.dim(), (8, 15));
+    for ((i, j), &x) in a.indexed_iter() {
+        let block = result.slice(s![i * 4..(i + 1) * 4, j * 5..(j + 1) * 5]);
+        assert!(block.my_all_close(&(&b * x), TOL));
+    }
+
+    // (a ⊗ b)(c ⊗ d) = ac ⊗ bd
+    let c = rand_array((3, 2));
+    let d = rand_array((5, 3));
+    let lhs = kron(&a, &b).dot(&kron(&c, &d));
+    let rhs = kron(&a.dot(&c), &b.dot(&d));
+    assert!(lhs.my_all_close(&rhs, TOL));
+}
+
+#[test]
+fn it_rearranges_axes() {
+    let t = rand_array((2, 12, 3));
+    let correct_answer = t
+        .clone()
+        .into_shape_with_order((2, 4, 3, 3))
+        .unwrap()
+        .permuted_axes([0, 3, 2, 1]);
+    let result = rearrange(&t, "b (h w) c -> b c w h", &[("h", 4)]).unwrap();
+    assert!(result.my_all_close(&correct_answer.clone().into_dyn(), TOL));
+    let result = rearrange(&t, "b (h w) c -> b c w h", &[("w", 3)]).unwrap();
+    assert!(result.my_all_close(&correct_answer.clone().into_dyn(), TOL));
+
+    // Merging the axes back works on a non-contiguous view too
+    let result = rearrange(&correct_answer.view(), "b c w h -> b (h w) c", &[]).unwrap();
+    assert!(result.my_all_close(&t.clone().into_dyn(), TOL));
+    let result = rearrange(&t.t(), "c (h w) b -> () b (c w) h", &[("h", 4), ("w", 3)]).unwrap();
+    assert_eq!(result.shape(), &[1, 2, 9, 4]);
+    assert_eq!(result[[0, 1, 5, 2]], t[[1, 2 * 3 + 2, 1]]);
+
+    assert_eq!(
+        rearrange(&t, "b (h w) c -> b h c", &[]).unwrap_err(),
+        EinsumError::Parse {
+            position: 5,
+            message: "axis isn't named on the right of '->'"
+        }
+    );
+    assert_eq!(
+        rearrange(&t, "b (h w c -> b h w c", &[]).unwrap_err(),
+        EinsumError::Parse {
+            position: 2,
+            message: "unmatched '('"
+        }
+    );
+    assert_eq!(
+        rearrange(&t, "b h w c -> b h w c", &[]).unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 0,
+            expected: 4,
+            found: 3
+        }
+    );
+    assert_eq!(
+        rearrange(&t, "b (h w) c -> b h w c", &[]).unwrap_err(),
+        EinsumError::InvalidAxisLength {
+            axis: "w".to_string(),
+            message: "another axis of its group has an unknown length too"
+        }
+    );
+    assert_eq!(
+        rearrange(&t, "b (h w) c -> b h w c", &[("h", 4), ("w", 4)]).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 1,
+            expected: 16,
+            found: 12
+        }
+    );
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn it_contracts_nalgebra_matrices_and_vectors() {
+    use nalgebra::{DMatrix, DVector, RowDVector};
+
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let v = rand_array(5);
+    let m1_nalgebra = DMatrix::from_row_slice(3, 4, m1.as_slice().unwrap());
+    let m2_nalgebra = DMatrix::from_row_slice(4, 5, m2.as_slice().unwrap());
+    let v_nalgebra = DVector::from_column_slice(v.as_slice().unwrap());
+    let v_row = RowDVector::from_row_slice(v.as_slice().unwrap());
+    for input_string in ["ij,jk->ik", "ij,jk->ki", "ij,jk->"] {
+        let correct_answer = einsum(input_string, &[&m1, &m2]).unwrap();
+        let result = einsum(input_string, &[&m1_nalgebra, &m2_nalgebra]).unwrap();
+        assert!(
+            correct_answer.my_all_close(&result, TOL),
+            "{}",
+            input_string
+        );
+        let result = einsum(input_string, &[&m1_nalgebra, &m2]).unwrap();
+        assert!(
+            correct_answer.my_all_close(&result, TOL),
+            "{}",
+            input_string
+        );
+    }
+    for operand in [&v_nalgebra as &dyn ArrayLike<f64>, &v_row] {
+        let result = einsum("ij,jk,k->i", &[&m1, &m2_nalgebra, operand]).unwrap();
+        assert!(m1.dot(&m2).dot(&v).my_all_close(&result, TOL));
+    }
+
+    let product = einsum_dmatrix("ij,jk->ik", &[&m1, &m2]).unwrap();
+    assert!((product - &m1_nalgebra * &m2_nalgebra).amax() < TOL);
+    let transposed = einsum_dmatrix("ij,jk->ki", &[&m1_nalgebra, &m2_nalgebra]).unwrap();
+    assert!((transposed - (&m1_nalgebra * &m2_nalgebra).transpose()).amax() < TOL);
+    let vector = einsum_dvector("ij,jk,k->i", &[&m1, &m2, &v_nalgebra]).unwrap();
+    assert!((vector - &m1_nalgebra * &m2_nalgebra * &v_nalgebra).amax() < TOL);
+    assert_eq!(
+        einsum_dmatrix("ij,jk,k->i", &[&m1, &m2, &v]).unwrap_err(),
+        EinsumError::OutputRankMismatch {
+            expected: 1,
+            found: 2
+        }
+    );
+
+    let sparse = SparseOperand::from_dense(&m1);
+    let result = einsum_sparse("ij,jk->ik", &[&sparse, &m2_nalgebra]).unwrap();
+    assert!(m1.dot(&m2).my_all_close(&result, TOL));
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn it_contracts_through_the_c_interface() {
+    use ndarray_einsum_beta::capi::*;
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let subscripts = CString::new("ij,jk->ki").unwrap();
+    let ndims = [2, 2];
+    let shapes = [[3, 4].as_ptr(), [4, 5].as_ptr()];
+    let mut plan = ptr::null_mut();
+    unsafe {
+        assert_eq!(
+            einsum_plan_create(
+                subscripts.as_ptr(),
+                2,
+                ndims.as_ptr(),
+                shapes.as_ptr(),
+                &mut plan
+            ),
+            EINSUM_OK
+        );
+        assert_eq!(einsum_plan_output_ndim(plan), 2);
+        let mut output_shape = [0; 2];
+        einsum_plan_output_shape(plan, output_shape.as_mut_ptr());
+        assert_eq!(output_shape, [5, 3]);
+
+        // Row-major operands and output
+        let correct_answer = einsum("ij,jk->ki", &[&m1, &m2]).unwrap();
+        let operands = [m1.as_ptr(), m2.as_ptr()];
+        let mut out = Array2::<f64>::zeros((5, 3));
+        let status = einsum_plan_execute_f64(
+            plan,
+            operands.as_ptr(),
+            ptr::null(),
+            out.as_mut_ptr(),
+            ptr::null(),
+        );
+        assert_eq!(status, EINSUM_OK);
+        assert!(correct_answer.my_all_close(&out, TOL));
+
+        // A reversed operand and a column-major output
+        let reversed = m1.slice(s![..;-1, ..]);
+        let correct_answer = einsum("ij,jk->ki", &[&reversed, &m2]).unwrap();
+        let operands = [reversed.as_ptr(), m2.as_ptr()];
+        let strides = [reversed.strides().as_ptr(), ptr::null()];
+        let mut out = Array2::<f64>::zeros((3, 5));
+        let status = einsum_plan_execute_f64(
+            plan,
+            operands.as_ptr(),
+            strides.as_ptr(),
+            out.as_mut_ptr(),
+            [1, 5].as_ptr(),
+        );
+        assert_eq!(status, EINSUM_OK);
+        assert!(correct_answer.my_all_close(&out.t(), TOL));
+
+        // The same plan contracts f32 operands
+        let m1_f32 = m1.mapv(|x| x as f32);
+        let m2_f32 = m2.mapv(|x| x as f32);
+        let operands = [m1_f32.as_ptr(), m2_f32.as_ptr()];
+        let mut out = Array2::<f32>::zeros((5, 3));
+        let status = einsum_plan_execute_f32(
+            plan,
+            operands.as_ptr(),
+            ptr::null(),
+            out.as_mut_ptr(),
+            ptr::null(),
+        );
+        assert_eq!(status, EINSUM_OK);
+        let correct_answer = einsum("ij,jk->ki", &[&m1_f32, &m2_f32]).unwrap();
+        assert!(correct_answer.abs_diff_eq(&out.into_dyn(), 1e-4));
+
+        let status =
+            einsum_plan_execute_f64(plan, ptr::null(), ptr::null(), ptr::null_mut(), ptr::null());
+        assert_eq!(status, EINSUM_ERROR_INVALID_ARGUMENT);
+        einsum_plan_destroy(plan);
+
+        let shapes = [[3, 4].as_ptr(), [5, 5].as_ptr()];
+        let status = einsum_plan_create(
+            subscripts.as_ptr(),
+            2,
+            ndims.as_ptr(),
+            shapes.as_ptr(),
+            &mut plan,
+        );
+        assert_eq!(status, EINSUM_ERROR_CONTRACTION);
+        assert_eq!(
+            CStr::from_ptr(einsum_last_error()).to_str().unwrap(),
+            "index 'j' has length 4 on axis 1 of operand 0 but length 5 on axis 0 of operand 1"
+        );
+    }
+}
+
+#[test]
+fn it_contracts_into_an_output_array() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((3, 5, 6));
+    let m4 = rand_array((6, 3));
+    for (s, operands) in [
+        ("ijk->ki", vec![&m1 as &dyn ArrayLike<f64>]),
+        ("ijk,jkl->il", vec![&m1, &m2]),
+        ("ijk,jkl->li", vec![&m1, &m2]),
+        ("ijk,ikl->ijl", vec![&m1, &m3]),
+        ("ijk,ijk->ikj", vec![&m1, &m1]),
+        ("li,ijk,jkl->k", vec![&m4, &m1, &m2]),
+    ] {
+        let correct_answer = einsum(s, &operands).unwrap();
+
+        // Fill with garbage first to make sure everything gets overwritten
+        let mut out = Array::from_elem(correct_answer.shape(), f64::NAN);
+        einsum_into(s, &operands, &mut out.view_mut()).unwrap();
+        assert!(correct_answer.my_all_close(&out, TOL), "{}", s);
+
+        // The output doesn't need to be in standard layout
+        let reversed_shape: Vec<usize> = correct_answer.shape().iter().rev().cloned().collect();
+        let mut reversed = Array::from_elem(reversed_shape, f64::NAN);
+        einsum_into(s, &operands, &mut reversed.view_mut().reversed_axes()).unwrap();
+        assert!(correct_answer.my_all_close(&reversed.t(), TOL), "{}", s);
+    }
+}
+
+#[test]
+fn it_returns_the_output_in_the_requested_order() {
+    use ndarray::Order;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the strides of the output matrices it multiplies into
+    #[derive(Default)]
+    struct OutputStrideRecorder(Mutex<Vec<Vec<isize>>>);
+
+    impl Backend<f64> for OutputStrideRecorder {
+        fn mat_mul(
+            &self,
+            alpha: f64,
+            lhs: &ArrayView2<f64>,
+            rhs: &ArrayView2<f64>,
+            beta: f64,
+            out: &mut ArrayViewMut2<f64>,
+        ) {
+            self.0.lock().unwrap().push(out.strides().to_vec());
+            Backend::<f64>::mat_mul(&CpuBackend, alpha, lhs, rhs, beta, out)
+        }
+    }
+
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((6, 2));
+    for (s, operands) in [
+        ("ijk->ki", vec![&m1 as &dyn ArrayLike<f64>]),
+        ("ijk,jkl->il", vec![&m1, &m2]),
+        ("ijk,jkl->li", vec![&m1, &m2]),
+        ("ijk,jkl->kil", vec![&m1, &m2]),
+        ("ijk,jkl,lm->im", vec![&m1, &m2, &m3]),
+    ] {
+        let correct_answer = einsum(s, &operands).unwrap();
+        let row_major = einsum_in_order(s, &operands, Order::RowMajor).unwrap();
+        assert!(correct_answer.my_all_close(&row_major, TOL), "{}", s);
+        assert!(row_major.is_standard_layout(), "{}", s);
+        let column_major = einsum_in_order(s, &operands, Order::ColumnMajor).unwrap();
+        assert!(correct_answer.my_all_close(&column_major, TOL), "{}", s);
+        assert!(column_major.t().is_standard_layout(), "{}", s);
+    }
+
+    // The final matrix multiplication writes the column-major result directly
+    let m4 = rand_array((2, 3, 4));
+    let m5 = rand_array((4, 5, 6));
+    let recorder = Arc::new(OutputStrideRecorder::default());
+    let result = with_backend(recorder.clone(), || {
+        einsum_in_order("ijk,klm->ijlm", &[&m4, &m5], Order::ColumnMajor)
+    })
+    .unwrap();
+    assert!(result.my_all_close(&einsum("ijk,klm->ijlm", &[&m4, &m5]).unwrap(), TOL));
+    assert_eq!(*recorder.0.lock().unwrap(), vec![vec![1, 6]]);
+}
+
+#[test]
+fn it_returns_standard_layout_output_when_asked_to() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((5, 6, 2));
+    let m3 = rand_array((2, 3));
+    for (s, operands) in [
+        ("ijk->kji", vec![&m1 as &dyn ArrayLike<f64>]),
+        ("iji->ji", vec![&m2.slice(s![..2, .., ..]).to_owned()]),
+        ("ijk,klm->mli", vec![&m1, &m2]),
+        ("ijk,klm,mi->lkj", vec![&m1, &m2, &m3]),
+    ] {
+        let correct_answer = einsum(s, &operands).unwrap();
+        assert!(!standard_layout_output());
+        let result = with_standard_layout_output(|| {
+            assert!(standard_layout_output());
+            einsum(s, &operands).unwrap()
+        });
+        assert!(!standard_layout_output());
+        assert!(result.is_standard_layout(), "{}", s);
+        assert!(correct_answer.my_all_close(&result, TOL), "{}", s);
+
+        let path = einsum_path(s, &operands, OptimizationMethod::Naive).unwrap();
+        let result = with_standard_layout_output(|| path.contract_operands(&operands));
+        assert!(result.is_standard_layout(), "{}", s);
+        assert!(correct_answer.my_all_close(&result, TOL), "{}", s);
+
+        #[cfg(feature = "rayon")]
+        {
+            let result = with_standard_layout_output(|| par_einsum(s, &operands).unwrap());
+            assert!(result.is_standard_layout(), "{}", s);
+            assert!(correct_answer.my_all_close(&result, TOL), "{}", s);
+        }
+    }
+}
+
+#[test]
+fn it_rejects_an_output_array_of_the_wrong_shape() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let mut out = Array::zeros(IxDyn(&[3, 4]));
+    assert_eq!(
+        einsum_into("ij,jk->ik", &[&m1, &m2], &mut out.view_mut()),
+        Err(EinsumError::OutputShapeMismatch {
+            expected: vec![3, 5],
+            found: vec![3, 4]
+        })
+    );
+}
+
+#[test]
+fn it_rejects_operands_a_path_was_not_planned_for() {
+    let m1 = rand_array((3, 4));
+    let m2 = rand_array((4, 5));
+    let path = einsum_path("ij,jk->ik", &[&m1, &m2], OptimizationMethod::Naive).unwrap();
+    assert!(path
+        .try_contract_operands(&[&m1, &m2])
+        .unwrap()
+        .my_all_close(&m1.dot(&m2), TOL));
+    assert_eq!(
+        path.try_contract_operands(&[&m1]).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+    assert_eq!(
+        path.try_contract_operands(&[&m1, &rand_array((4, 5, 1))])
+            .unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 1,
+            expected: 2,
+            found: 3
+        }
+    );
+    assert_eq!(
+        path.try_contract_operands(&[&m1, &rand_array((4, 6))])
+            .unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: 1,
+            expected: 5,
+            found: 6
+        }
+    );
+    let mut out = Array::zeros(IxDyn(&[3, 5]));
+    assert_eq!(
+        path.contract_operands_into(&[&m2, &m1], &mut out.view_mut()),
+        Err(EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 0,
+            expected: 3,
+            found: 4
+        })
+    );
+
+    // Axes of length 1 are broadcast between operands but not within a singleton contraction
+    let column = rand_array((1, 4));
+    let correct_answer = column.broadcast((3, 4)).unwrap().dot(&m2);
+    assert!(path
+        .try_contract_operands(&[&column, &m2])
+        .unwrap()
+        .my_all_close(&correct_answer, TOL));
+    let path = einsum_path("ij->ji", &[&m1], OptimizationMethod::Naive).unwrap();
+    assert_eq!(
+        path.try_contract_operands(&[&column]).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 0,
+            expected: 3,
+            found: 1
+        }
+    );
+}
+
+#[test]
+fn it_accumulates_into_an_output_array() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((3, 5, 6));
+    let m4 = rand_array((6, 3));
+    let (alpha, beta) = (0.5, -2.);
+    for (s, operands) in [
+        ("ijk->ki", vec![&m1 as &dyn ArrayLike<f64>]),
+        ("ijk,jkl->il", vec![&m1, &m2]),
+        ("ijk,jkl->li", vec![&m1, &m2]),
+        ("ijk,ikl->ijl", vec![&m1, &m3]),
+        ("ijk,ijk->ikj", vec![&m1, &m1]),
+        ("li,ijk,jkl->k", vec![&m4, &m1, &m2]),
+    ] {
+        let result = einsum(s, &operands).unwrap();
+        let initial = rand_array(result.shape());
+        let correct_answer = alpha * &result + beta * &initial;
+
+        let mut out = initial.clone();
+        einsum_acc(s, &operands, alpha, beta, &mut out.view_mut()).unwrap();
+        assert!(correct_answer.my_all_close(&out, TOL), "{}", s);
+
+        // Not in standard layout
+        let mut reversed = initial.t().to_owned();
+        einsum_acc(
+            s,
+            &operands,
+            alpha,
+            beta,
+            &mut reversed.view_mut().reversed_axes(),
+        )
+        .unwrap();
+        assert!(correct_answer.my_all_close(&reversed.t(), TOL), "{}", s);
+
+        // With beta = 0, the original contents (even NaNs) are ignored
+        let mut garbage = Array::from_elem(result.shape(), f64::NAN);
+        einsum_acc(s, &operands, alpha, 0., &mut garbage.view_mut()).unwrap();
+        assert!((alpha * &result).my_all_close(&garbage, TOL), "{}", s);
+    }
+}
+
+#[test]
+fn it_evaluates_a_prepared_expression() {
+    let expr = contract_expression(
+        "...ij,jk,kl->...il",
+        &[vec![2, 3, 4], vec![4, 5], vec![5, 6]],
+    )
+    .unwrap();
+    for _ in 0..3 {
+        let m1 = rand_array((2, 3, 4));
+        let m2 = rand_array((4, 5));
+        let m3 = rand_array((5, 6));
+        let correct_answer = einsum("...ij,jk,kl->...il", &[&m1, &m2, &m3]).unwrap();
+        let result = expr.eval(&[&m1, &m2, &m3]).unwrap();
+        assert!(correct_answer.my_all_close(&result, TOL));
+
+        let mut out = Array::zeros((2, 3, 6));
+        expr.eval_into(&[&m1, &m2, &m3], &mut out.view_mut().into_dyn())
+            .unwrap();
+        assert!(correct_answer.my_all_close(&out, TOL));
+    }
+
+    let m1 = rand_array((2, 3, 4));
+    let m2 = rand_array((4, 5));
+    assert_eq!(
+        expr.eval(&[&m1, &m2]).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 3,
+            found: 2
+        }
+    );
+    assert_eq!(
+        expr.eval(&[&m1, &m2, &m1]).unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 2,
+            expected: 2,
+            found: 3
+        }
+    );
+    // A shape that einsum would accept still has to match the planned shapes
+    let m4 = rand_array((1, 3, 4));
+    let m3 = rand_array((5, 6));
+    assert_eq!(
+        expr.eval(&[&m4, &m2, &m3]).unwrap_err(),
+        EinsumError::ShapeMismatch {
+            operand: 0,
+            axis: 0,
+            expected: 2,
+            found: 1
+        }
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn it_round_trips_plans_through_serde() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((6, 2));
+    let operands: [&dyn ArrayLike<f64>; 3] = [&m1, &m2, &m3];
+    let s = "ijk,jkl,lm->im";
+    let correct_answer = einsum(s, &operands).unwrap();
+
+    let contraction = Contraction::new(s).unwrap();
+    let json = serde_json::to_string(&contraction).unwrap();
+    let contraction: Contraction = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        contraction.operand_indices,
+        Contraction::new(s).unwrap().operand_indices
+    );
+    assert_eq!(contraction.output_indices, vec!['i', 'm']);
+    assert_eq!(contraction.summation_indices, vec!['j', 'k', 'l']);
+
+    let sc = validate_and_size(s, &operands).unwrap();
+    let json = serde_json::to_string(&sc).unwrap();
+    let deserialized_sc: SizedContraction = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized_sc.as_einsum_string(), s);
+    assert_eq!(deserialized_sc.output_size, sc.output_size);
+    assert!(correct_answer.my_all_close(&deserialized_sc.contract_operands(&operands), TOL));
+
+    let order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+    let json = serde_json::to_string(&order).unwrap();
+    let deserialized_order: ContractionOrder = serde_json::from_str(&json).unwrap();
+    let result = EinsumPath::from_path(&deserialized_order).contract_operands(&operands);
+    assert!(correct_answer.my_all_close(&result, TOL));
+
+    let path: EinsumPath<f64> = EinsumPath::from_path(&order);
+    let json = serde_json::to_string(&path).unwrap();
+    let deserialized_path: EinsumPath<f64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(format!("{:?}", deserialized_path), format!("{:?}", path));
+    assert!(correct_answer.my_all_close(&deserialized_path.contract_operands(&operands), TOL));
+
+    let method: OptimizationMethod =
+        serde_json::from_str(r#"{"Optimal":{"memory_limit":100}}"#).unwrap();
+    assert!(matches!(
+        method,
+        OptimizationMethod::Optimal {
+            memory_limit: Some(100)
+        }
+    ));
+}
+
+#[test]
+fn it_imports_and_exports_explicit_paths() {
+    let m1 = rand_array((2, 3));
+    let m2 = rand_array((3, 4));
+    let m3 = rand_array((4, 5));
+    let m4 = rand_array((5, 2));
+    let operands: [&dyn ArrayLike<f64>; 4] = [&m1, &m2, &m3, &m4];
+    let s = "ij,jk,kl,lm->im";
+    let correct_answer = einsum(s, &operands).unwrap();
+    let sc = validate_and_size(s, &operands).unwrap();
+
+    for path in [
+        vec![(0, 1), (0, 1), (0, 1)],
+        vec![(2, 3), (0, 1), (0, 1)],
+        vec![(3, 2), (1, 0), (1, 0)],
+        vec![(1, 2), (2, 0), (0, 1)],
+    ] {
+        let order = sc.with_explicit_path(&path).unwrap();
+        assert_eq!(order.to_explicit_path(), path);
+        let result = EinsumPath::from_path(&order).contract_operands(&operands);
+        assert!(correct_answer.my_all_close(&result, TOL), "{:?}", path);
+    }
+
+    // Paths chosen by the optimizers survive a round trip
+    for method in [
+        OptimizationMethod::Naive,
+        OptimizationMethod::Reverse,
+        OptimizationMethod::Greedy,
+        OptimizationMethod::Optimal { memory_limit: None },
+    ] {
+        let order = generate_optimized_order(&sc, method);
+        let path = order.to_explicit_path();
+        let reimported = sc.with_explicit_path(&path).unwrap();
+        assert_eq!(reimported.to_explicit_path(), path);
+        let result = EinsumPath::from_path(&reimported).contract_operands(&operands);
+        assert!(correct_answer.my_all_close(&result, TOL), "{:?}", path);
+    }
+
+    let singleton = validate_and_size("ij->ji", &[&m1]).unwrap();
+    let order = singleton.with_explicit_path(&[]).unwrap();
+    assert!(order.to_explicit_path().is_empty());
+
+    assert_eq!(
+        sc.with_explicit_path(&[(0, 1), (0, 1)]).unwrap_err(),
+        EinsumError::PathLengthMismatch {
+            expected: 3,
+            found: 2
+        }
+    );
+    assert_eq!(
+        sc.with_explicit_path(&[(0, 1), (1, 1), (0, 1)])
+            .unwrap_err(),
+        EinsumError::InvalidPathStep {
+            step: 1,
+            pair: (1, 1),
+            remaining: 3
+        }
+    );
+    assert_eq!(
+        sc.with_explicit_path(&[(0, 4), (0, 1), (0, 1)])
+            .unwrap_err(),
+        EinsumError::InvalidPathStep {
+            step: 0,
+            pair: (0, 4),
+            remaining: 4
+        }
+    );
+}
+
+#[cfg(not(feature = "optimal"))]
+#[test]
+fn optimal_order_is_the_greedy_order_without_the_optimal_feature() {
+    let sc = SizedContraction::from_string_and_shapes(
+        "ij,jk,kl->il",
+        &[vec![1, 2], vec![2, 100], vec![100, 2]],
+    )
+    .unwrap();
+    let explicit_path = |method| generate_optimized_order(&sc, method).to_explicit_path();
+    assert_eq!(
+        explicit_path(OptimizationMethod::Optimal { memory_limit: None }),
+        explicit_path(OptimizationMethod::Greedy)
+    );
+}
+
+#[test]
+fn it_estimates_flops_and_memory() {
+    let sc = SizedContraction::from_string_and_shapes(
+        "ab,bc,cd,de->ae",
+        &[vec![2, 100], vec![100, 3], vec![3, 100], vec![100, 4]],
+    )
+    .unwrap();
+    let naive = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    // ab,bc->ac; ac,cd->ad; ad,de->ae
+    assert_eq!(
+        naive.flop_count(),
+        2 * (2 * 100 * 3) + 2 * (2 * 3 * 100) + 2 * (2 * 100 * 4)
+    );
+    assert_eq!(naive.largest_intermediate(), 2 * 100);
+    assert_eq!(
+        naive.total_intermediate_bytes::<f64>(),
+        8 * (2 * 3 + 2 * 100 + 2 * 4)
+    );
+
+    let optimal = generate_optimized_order(&sc, OptimizationMethod::Optimal { memory_limit: None });
+    assert!(optimal.flop_count() <= naive.flop_count());
+    assert_eq!(
+        sc.flop_count(OptimizationMethod::Optimal { memory_limit: None }),
+        optimal.flop_count()
+    );
+    assert_eq!(
+        sc.largest_intermediate(OptimizationMethod::Naive),
+        naive.largest_intermediate()
+    );
+    assert_eq!(
+        sc.total_intermediate_bytes::<f32>(OptimizationMethod::Naive),
+        naive.total_intermediate_bytes::<f64>() / 2
+    );
+
+    // An outer product has no summed indices, so each element takes one operation
+    let outer = SizedContraction::from_string_and_shapes("i,j->ij", &[vec![3], vec![4]]).unwrap();
+    assert_eq!(outer.flop_count(OptimizationMethod::Naive), 12);
+    let trace = SizedContraction::from_string_and_shapes("ii->", &[vec![5, 5]]).unwrap();
+    let order = generate_optimized_order(&trace, OptimizationMethod::Naive);
+    assert_eq!(order.flop_count(), 2 * 5);
+    assert_eq!(order.largest_intermediate(), 1);
+
+    // Sizes that don't fit in a usize saturate instead of overflowing
+    let huge = SizedContraction::from_string_and_shapes(
+        "ab,cd->abcd",
+        &[vec![1 << 40, 1 << 40], vec![1 << 40, 1 << 40]],
+    )
+    .unwrap();
+    assert_eq!(
+        huge.largest_intermediate(OptimizationMethod::Naive),
+        u128::MAX
+    );
+    assert_eq!(
+        huge.total_intermediate_bytes::<f64>(OptimizationMethod::Naive),
+        u128::MAX
+    );
+}
+
+#[test]
+fn it_reports_on_a_path() {
+    let m1 = rand_array((2, 100));
+    let m2 = rand_array((100, 3));
+    let m3 = rand_array((3, 100));
+    let m4 = rand_array((100, 4));
+    let operands: [&dyn ArrayLike<f64>; 4] = [&m1, &m2, &m3, &m4];
+    let s = "ab,bc,cd,de->ae";
+
+    let report = einsum_path_report(s, &operands, OptimizationMethod::Naive).unwrap();
+    assert_eq!(report.einsum_string, s);
+    assert_eq!(report.naive_scaling, 5);
+    assert_eq!(report.optimized_scaling, 3);
+    assert_eq!(report.naive_flop_count, 2 * 100 * 3 * 100 * 4 * 4);
+    let order = validate_and_optimize_order(s, &operands, OptimizationMethod::Naive).unwrap();
+    assert_eq!(report.optimized_flop_count, order.flop_count());
+    assert_eq!(report.largest_intermediate, order.largest_intermediate());
+    assert_eq!(report.steps.len(), 3);
+    assert_eq!(report.steps[0].flop_count, 2 * 2 * 100 * 3);
+    // The naive order doesn't fix the order of the indices of the intermediate results
+    let intermediate: String = report.steps[1].remaining[3..5].to_string();
+    assert!(intermediate == "ad" || intermediate == "da");
+    assert_eq!(
+        report.steps[1].remaining,
+        format!("de,{}->ae", intermediate)
+    );
+    assert_eq!(
+        report.steps[2].einsum_string,
+        format!("{},de->ae", intermediate)
+    );
+    assert_eq!(report.steps[2].output_shape, vec![2, 4]);
+    assert_eq!(report.steps[2].remaining, "ae->ae");
+    assert_eq!(
+        report.speedup(),
+        report.naive_flop_count as f64 / report.optimized_flop_count as f64
+    );
+
+    let printed = report.to_string();
+    assert!(printed.starts_with("  Complete contraction:  ab,bc,cd,de->ae\n"));
+    assert!(printed.contains("\n      Naive FLOP count:  9.600e+05\n"));
+    assert_eq!(printed.lines().count(), 7 + 3 + report.steps.len());
+
+    let singleton =
+        einsum_path_report("ii->i", &[&m2.dot(&m3)], OptimizationMethod::Naive).unwrap();
+    assert_eq!(singleton.steps.len(), 1);
+    assert_eq!(singleton.steps[0].einsum_string, "ii->i");
+    assert_eq!(singleton.steps[0].output_shape, vec![100]);
+    assert_eq!(singleton.speedup(), 1.);
+}
+
+#[test]
+fn it_exports_a_path_to_dot() {
+    let m1 = rand_array((2, 3, 3));
+    let m2 = rand_array((3, 4));
+    let m3 = rand_array((4, 5));
+    let path = einsum_path("ijj,jk,kl->il", &[&m1, &m2, &m3], OptimizationMethod::Naive).unwrap();
+    let dot = path.to_dot();
+    assert!(dot.starts_with("digraph einsum {\n"));
+    assert!(dot.ends_with("\n}"));
+    for line in [
+        r#"    input0 [shape=box, label="input 0\nijj\n[2, 3, 3]"];"#,
+        "    input0 -> step0 [label=\"lhs\"];",
+        "    input1 -> step0 [label=\"rhs\"];",
+        "    step0 -> step1 [label=\"lhs\"];",
+        "    input2 -> step1 [label=\"rhs\"];",
+        r#"    input2 [shape=box, label="input 2\nkl\n[4, 5]"];"#,
+    ] {
+        assert!(dot.lines().any(|l| l == line), "{}\n{}", line, dot);
+    }
+    // The diagonal of the first operand is taken before it's contracted
+    let step0 = dot.lines().find(|l| l.starts_with("    step0 [")).unwrap();
+    assert!(
+        step0.contains(r"\nlhs: Diagonalization (ijj->"),
+        "{}",
+        step0
+    );
+    assert!(!step0.contains("peripheries"));
+    let step1 = dot.lines().find(|l| l.starts_with("    step1 [")).unwrap();
+    assert!(step1.starts_with("    step1 [peripheries=2, "));
+    assert!(step1.ends_with(r#"\n[2, 5]\n80 flops"];"#), "{}", step1);
+
+    let path = einsum_path("ii->i", &[&m2.t().dot(&m2)], OptimizationMethod::Naive).unwrap();
+    assert_eq!(
+        path.to_dot(),
+        r#"digraph einsum {
+    input0 [shape=box, label="input 0\nii\n[4, 4]"];
+    input0 -> step0;
+    step0 [peripheries=2, label="step 0\nii->i\nDiagonalization\n[4]\n4 flops"];
+}"#
+    );
+}
+
+#[test]
+fn it_calls_back_after_each_step() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((6, 2));
+    let m4 = rand_array((2, 3));
+    let operands: [&dyn ArrayLike<f64>; 4] = [&m1, &m2, &m3, &m4];
+    let s = "ijk,jkl,lm,mi->i";
+    let correct_answer = einsum(s, &operands).unwrap();
+
+    for method in [OptimizationMethod::Naive, OptimizationMethod::Greedy] {
+        let path = einsum_path(s, &operands, method).unwrap();
+        let mut intermediates = Vec::new();
+        let result = path.execute_with(&operands, |step_index, intermediate| {
+            intermediates.push((step_index, intermediate.clone()));
+        });
+        assert!(correct_answer.my_all_close(&result, TOL));
+        assert_eq!(
+            intermediates.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert!(result.my_all_close(&intermediates[2].1, TOL));
+
+        // Each intermediate is the result of the contraction performed at that step
+        if let ContractionOrder::Pairs(steps) = &path.contraction_order {
+            for ((_, intermediate), step) in intermediates.iter().zip(steps) {
+                let expected_shape: Vec<usize> = step
+                    .sized_contraction
+                    .contraction
+                    .output_indices
+                    .iter()
+                    .map(|c| step.sized_contraction.output_size[c])
+                    .collect();
+                assert_eq!(intermediate.shape(), expected_shape.as_slice());
+            }
+        } else {
+            panic!("expected a pairwise path");
+        }
+    }
+
+    let path = einsum_path("ijk->kj", &[&m1], OptimizationMethod::Naive).unwrap();
+    let mut calls = 0;
+    let result = path.execute_with(&[&m1], |step_index, intermediate| {
+        assert_eq!(step_index, 0);
+        assert_eq!(intermediate.shape(), &[5, 4]);
+        calls += 1;
+    });
+    assert_eq!(calls, 1);
+    assert!(result.my_all_close(&einsum("ijk->kj", &[&m1]).unwrap(), TOL));
+}
+
+#[test]
+fn it_reports_on_an_execution() {
+    let m1 = rand_array((30, 40));
+    let m2 = rand_array((40, 50));
+    let m3 = rand_array((50, 20));
+    let operands: [&dyn ArrayLike<f64>; 3] = [&m1, &m2, &m3];
+    let s = "ij,jk,kl->il";
+    let path = einsum_path(s, &operands, OptimizationMethod::Greedy).unwrap();
+    let (result, report) = path.contract_operands_with_report(&operands);
+    assert!(result.my_all_close(&einsum(s, &operands).unwrap(), TOL));
+
+    assert_eq!(report.steps.len(), 2);
+    assert_eq!(report.flop_count(), path.contraction_order.flop_count());
+    assert_eq!(report.steps[1].output_shape, vec![30, 20]);
+    assert_eq!(report.steps[1].allocated_bytes, 8 * 30 * 20);
+    assert_eq!(
+        report.allocated_bytes() as u128,
+        path.contraction_order.total_intermediate_bytes::<f64>()
+    );
+    let step_time: std::time::Duration = report.steps.iter().map(|step| step.wall_time).sum();
+    assert!(step_time <= report.wall_time);
+    assert!(report.gflops() > 0.);
+    assert!(report.steps.iter().all(|step| step.gflops() > 0.));
+
+    let printed = report.to_string();
+    assert_eq!(printed.lines().count(), 3 + report.steps.len() + 2);
+    assert!(printed.lines().last().unwrap().contains("total"));
+
+    let path = einsum_path("ij->j", &[&m1], OptimizationMethod::Naive).unwrap();
+    let (_, report) = path.contract_operands_with_report(&[&m1]);
+    assert_eq!(report.steps.len(), 1);
+    assert_eq!(report.steps[0].einsum_string, "ij->j");
+    assert_eq!(report.steps[0].flop_count, 2 * 30 * 40);
+    assert_eq!(report.steps[0].allocated_bytes, 8 * 40);
+    assert_eq!(report.peak_resident_bytes(), 8 * 40);
+    assert_eq!(report.num_allocations(), 1);
+}
+
+#[test]
+fn it_reports_the_memory_held_by_intermediate_results() {
+    let m1 = rand_array((2, 3));
+    let m2 = rand_array((3, 4));
+    let m3 = rand_array((4, 5));
+    let m4 = rand_array((5, 6));
+    let operands: [&dyn ArrayLike<f64>; 4] = [&m1, &m2, &m3, &m4];
+    let s = "ij,jk,kl,lm->im";
+    let sc = SizedContraction::new(s, &operands).unwrap();
+    let expected = einsum(s, &operands).unwrap();
+
+    // ((m1 m2) m3) m4: each intermediate result is freed by the next step
+    let path = EinsumPath::from_path(&sc.with_explicit_path(&[(0, 1), (0, 2), (0, 1)]).unwrap());
+    // (m1 m2) (m3 m4): the first result is held while the second one is computed
+    let balanced_path =
+        EinsumPath::from_path(&sc.with_explicit_path(&[(0, 1), (0, 1), (0, 1)]).unwrap());
+    let (result, report) = path.contract_operands_with_report(&operands);
+    assert!(result.my_all_close(&expected, TOL));
+    let resident: Vec<usize> = report
+        .steps
+        .iter()
+        .map(|step| step.resident_bytes)
+        .collect();
+    assert_eq!(resident, vec![8 * 8, 8 * (8 + 10), 8 * (10 + 12)]);
+    assert_eq!(report.peak_resident_bytes(), 8 * 22);
+    assert_eq!(report.allocated_bytes(), 8 * (8 + 10 + 12));
+    assert_eq!(report.num_allocations(), 3);
+
+    let (result, report) = balanced_path.contract_operands_with_report(&operands);
+    assert!(result.my_all_close(&expected, TOL));
+    let resident: Vec<usize> = report
+        .steps
+        .iter()
+        .map(|step| step.resident_bytes)
+        .collect();
+    assert_eq!(resident, vec![8 * 8, 8 * (8 + 24), 8 * (8 + 24 + 12)]);
+    assert_eq!(report.peak_resident_bytes(), 8 * 44);
+    assert!(report
+        .to_string()
+        .lines()
+        .nth(1)
+        .unwrap()
+        .contains("live MiB"));
+
+    let empty: Array2<f64> = Array::zeros((0, 3));
+    let path = einsum_path("ij,jk->ik", &[&empty, &m2], OptimizationMethod::Naive).unwrap();
+    let (_, report) = path.contract_operands_with_report(&[&empty, &m2]);
+    assert_eq!(report.num_allocations(), 0);
+    assert_eq!(report.peak_resident_bytes(), 0);
+
+    // Only the last step, which consumes an intermediate result, is reported
+    let empty: Array2<f64> = Array::zeros((0, 4));
+    let vector = rand_array(4);
+    let square = rand_array((3, 3));
+    let operands: [&dyn ArrayLike<f64>; 3] = [&empty, &vector, &square];
+    let path = einsum_path("ab,b,ij->b", &operands, OptimizationMethod::Naive).unwrap();
+    let (result, report) = path.contract_operands_with_report(&operands);
+    assert_eq!(result, ArrayD::<f64>::zeros(IxDyn(&[4])));
+    assert_eq!(report.steps.len(), 1);
+    assert_eq!(report.steps[0].output_shape, vec![4]);
+    assert_eq!(report.peak_resident_bytes(), 8 * 4);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn it_emits_tracing_spans() {
+    use std::fmt::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    struct SpanRecorder(Arc<Mutex<Vec<String>>>);
+
+    struct FieldRecorder(String);
+
+    impl Visit for FieldRecorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            write!(self.0, " {}={:?}", field.name(), value).unwrap();
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = FieldRecorder(span.metadata().name().to_string());
+            span.record(&mut fields);
+            let mut spans = self.0.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let m1 = rand_array((2, 3));
+    let m2 = rand_array((3, 4));
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let result = tracing::subscriber::with_default(SpanRecorder(spans.clone()), || {
+        einsum("ij,jk->ik", &[&m1, &m2]).unwrap()
+    });
+    assert!(result.my_all_close(&m1.dot(&m2), TOL));
+
+    let spans = spans.lock().unwrap();
+    assert_eq!(
+        spans.as_slice(),
+        &[
+            "validate_and_size subscripts=\"ij,jk->ik\" shapes=[[2, 3], [3, 4]]",
+            "generate_optimized_order subscripts=ij,jk->ik strategy=Naive",
+            "compile_path num_steps=1",
+            "contract_pair step=0 subscripts=ij,jk->ik lhs_shape=[2, 3] rhs_shape=[3, 4] kernel=TensordotGeneral",
+        ]
+    );
+}
+
+#[test]
+fn it_returns_fixed_dimension_outputs() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let operands: [&dyn ArrayLike<f64>; 2] = [&m1, &m2];
+
+    let result: Array2<f64> = einsum_dim("ijk,jkl->il", &operands).unwrap();
+    assert!(result.my_all_close(&einsum("ijk,jkl->il", &operands).unwrap(), TOL));
+    let result: Array3<f64> = einsum_dim("ijk,jkl->lik", &operands).unwrap();
+    assert_eq!(result.dim(), (6, 3, 5));
+    let result: Array1<f64> = einsum_dim("ijk->j", &[&m1]).unwrap();
+    assert!(result.my_all_close(&m1.sum_axis(Axis(2)).sum_axis(Axis(0)), TOL));
+    let result: Array0<f64> = einsum_dim("ijk,ijk", &[&m1, &m1]).unwrap();
+    assert!((result.into_scalar() - (&m1 * &m1).sum()).abs() < TOL);
+    let result: ArrayD<f64> = einsum_dim("ijk,jkl->il", &operands).unwrap();
+    assert_eq!(result.shape(), &[3, 6]);
+
+    assert_eq!(
+        einsum_dim::<Ix3, _>("ijk,jkl->il", &operands).unwrap_err(),
+        EinsumError::OutputRankMismatch {
+            expected: 2,
+            found: 3
+        }
+    );
+    // Other errors are reported as usual
+    assert!(matches!(
+        einsum_dim::<Ix2, _>("ijk,jkm->il", &operands).unwrap_err(),
+        EinsumError::OutputIndexNotInInputs { index: 'l' }
+    ));
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn it_contracts_with_the_macro() {
+    let m1 = rand_array((3, 4, 5));
+    let m2 = rand_array((4, 5, 6));
+    let m3 = rand_array((6, 3));
+    assert!(einsum!("ijk,jkl,li->", m1, m2, m3)
+        .unwrap()
+        .my_all_close(&einsum("ijk,jkl,li->", &[&m1, &m2, &m3]).unwrap(), TOL));
+    assert!(einsum!("ijk,jkl", m1, m2.view(),)
+        .unwrap()
+        .my_all_close(&einsum("ijk,jkl->il", &[&m1, &m2]).unwrap(), TOL));
+    assert!(einsum!("...jk->...kj", m1)
+        .unwrap()
+        .my_all_close(&m1.view().permuted_axes([0, 2, 1]), TOL));
+
+    // The dimensionality of the output is known at compile time
+    let scalar: Array0<f64> = einsum!("ijk,jkl,li", m1, m2, m3).unwrap();
+    let matrix: Array2<f64> = einsum!("ijk,jkl", m1, m2).unwrap();
+    let tensor: Array<f64, Ix6> = einsum!("ijk,lm,no->ijklmn", m1, m3, m3).unwrap();
+    let dynamic: ArrayD<f64> = einsum!("ijk,lm,no->ijklmno", m1, m3, m3).unwrap();
+    let broadcast: ArrayD<f64> = einsum!("...jk->...", m1).unwrap();
+    let embedded: Array4<f64> = einsum!("ijk->ijki", m1).unwrap();
+    assert_eq!(scalar.ndim() + matrix.ndim() + tensor.ndim(), 8);
+    assert_eq!(embedded.shape(), &[3, 4, 5, 3]);
+    assert_eq!(dynamic.ndim(), 7);
+    assert_eq!(broadcast.shape(), &[3]);
+
+    // Mismatched shapes are still caught at run time
+    assert_eq!(
+        einsum!("ij,jk->ik", m3, m3).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 0,
+            first_axis: 1,
+            expected: 3,
+            operand: 1,
+            axis: 0,
+            found: 6
+        }
+    );
+}
+
+#[test]
+fn it_contracts_conjugated_operands() {
+    use num_complex::Complex64;
+
+    fn rand_complex_array(shape: &[usize]) -> ArrayD<Complex64> {
+        let re = rand_array(IxDyn(shape));
+        let im = rand_array(IxDyn(shape));
+        Zip::from(&re)
+            .and(&im)
+            .map_collect(|&re, &im| Complex64::new(re, im))
+    }
+    fn complex_all_close(lhs: &ArrayD<Complex64>, rhs: &ArrayD<Complex64>) -> bool {
+        lhs.mapv(|z| z.re).my_all_close(&rhs.mapv(|z| z.re), TOL)
+            && lhs.mapv(|z| z.im).my_all_close(&rhs.mapv(|z| z.im), TOL)
+    }
+
+    let psi = rand_complex_array(&[4]);
+    let o = rand_complex_array(&[4, 4]);
+    let phi = rand_complex_array(&[4, 3]);
+    let m = rand_complex_array(&[3, 5]);
+    let operands = [&psi, &o, &phi, &m];
+    let input_string = "i,ij,jk,kl->l";
+
+    // Every combination of flags, under each order
+    for flags in 0..16 {
+        let conj: Vec<bool> = (0..4).map(|i| flags & (1 << i) != 0).collect();
+        let conjugated: Vec<ArrayD<Complex64>> = operands
+            .iter()
+            .zip(&conj)
+            .map(|(&op, &c)| if c { op.mapv(|z| z.conj()) } else { op.clone() })
+            .collect();
+        let correct_answer = einsum(
+            input_string,
+            &[
+                &conjugated[0],
+                &conjugated[1],
+                &conjugated[2],
+                &conjugated[3],
+            ],
+        )
+        .unwrap();
+
+        for method in [
+            OptimizationMethod::Naive,
+            OptimizationMethod::Reverse,
+            OptimizationMethod::Greedy,
+        ] {
+            let path = einsum_path(input_string, &[&psi, &o, &phi, &m], method).unwrap();
+            let lib_output = path
+                .contract_operands_conj(&[&psi, &o, &phi, &m], &conj)
+                .unwrap();
+            assert!(complex_all_close(&correct_answer, &lib_output));
+        }
+    }
+
+    // A single operand
+    let lib_output = einsum_conj("ij->ji", &[&o], &[true]).unwrap();
+    assert!(complex_all_close(&o.t().mapv(|z| z.conj()), &lib_output));
+
+    // Real numbers are their own conjugates
+    let real = rand_array((3, 4));
+    assert_eq!(
+        einsum_conj("ij,jk->ik", &[&real, &real.t()], &[true, true]).unwrap(),
+        einsum("ij,jk->ik", &[&real, &real.t()]).unwrap()
+    );
+
+    assert_eq!(
+        einsum_conj("i,ij,j->", &[&psi, &o, &psi], &[true]).unwrap_err(),
+        EinsumError::ConjugationFlagCountMismatch {
+            expected: 3,
+            found: 1
+        }
+    );
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn it_accumulates_half_precision_in_single_precision() {
+    use half::{bf16, f16};
+
+    let m1 = rand_array((3, 64, 5));
+    let m2 = rand_array((64, 5, 6));
+    let m3 = rand_array((6, 4));
+    let [m1, m2, m3] = [m1.into_dyn(), m2.into_dyn(), m3.into_dyn()];
+
+    // The same contraction in f32 on the rounded operands is the reference
+    let half_operands: Vec<ArrayD<f16>> = [&m1, &m2, &m3]
+        .iter()
+        .map(|m| m.mapv(f16::from_f64))
+        .collect();
+    let single_operands: Vec<ArrayD<f32>> = half_operands
+        .iter()
+        .map(|m| m.mapv(|x| x.to_f32()))
+        .collect();
+    let correct_answer = einsum(
+        "ijk,jkl,lm->im",
+        &[
+            &single_operands[0],
+            &single_operands[1],
+            &single_operands[2],
+        ],
+    )
+    .unwrap();
+    let lib_output = einsum_widened(
+        "ijk,jkl,lm->im",
+        &[&half_operands[0], &half_operands[1], &half_operands[2]],
+    )
+    .unwrap();
+    // Only the final rounding to f16, with a relative error of at most 2^-11, is lost
+    Zip::from(&lib_output)
+        .and(&correct_answer)
+        .for_each(|&lib, &correct| {
+            assert!((lib.to_f32() - correct).abs() <= correct.abs() / 1024. + 1e-3);
+        });
+
+    let path = einsum_path(
+        "ijk,jkl,lm->im",
+        &[
+            &single_operands[0],
+            &single_operands[1],
+            &single_operands[2],
+        ],
+        OptimizationMethod::Greedy,
+    )
+    .unwrap();
+    let lib_output =
+        path.contract_operands_widened(&[&half_operands[0], &half_operands[1], &half_operands[2]]);
+    assert_eq!(lib_output.shape(), &[3, 4]);
+
+    // bf16 has even fewer bits of precision: 256 + 1 rounds back down to 256
+    let ones: Array1<bf16> = Array::from_elem(1024, bf16::ONE);
+    assert_eq!(
+        einsum_widened("i->", &[&ones]).unwrap(),
+        arr0(bf16::from_f32(1024.)).into_dyn()
+    );
+}
+
+#[test]
+fn it_accumulates_single_precision_in_double_precision() {
+    let v32 = rand_array(100_000).mapv(|x| x as f32);
+    let m32 = rand_array((100_000, 3)).mapv(|x| x as f32);
+    let v64 = v32.mapv(|x| x as f64);
+    let m64 = m32.mapv(|x| x as f64);
+
+    // The result is that of the contraction in f64, rounded once
+    let check = |input_string: &str,
+                 operands32: &[&dyn ArrayLike<f32>],
+                 operands64: &[&dyn ArrayLike<f64>]| {
+        let correct_answer = einsum(input_string, operands64).unwrap().mapv(|x| x as f32);
+        assert_eq!(
+            einsum_widened(input_string, operands32).unwrap(),
+            correct_answer
+        );
+    };
+    check("i,i->", &[&v32, &v32], &[&v64, &v64]);
+    check("i,ij->j", &[&v32, &m32], &[&v64, &m64]);
+    check("ij->j", &[&m32], &[&m64]);
+}
+
+#[test]
+fn it_sums_with_compensation() {
+    let v32 = rand_array(100_000).mapv(|x| (x + 5.) as f32);
+    let m32 = rand_array((100_000, 3)).mapv(|x| (x + 5.) as f32);
+    let s32 = rand_array((2, 3, 50_000)).mapv(|x| (x + 5.) as f32);
+    let t32 = rand_array((2, 50_000, 4)).mapv(|x| (x + 5.) as f32);
+    let v64 = v32.mapv(|x| x as f64);
+    let m64 = m32.mapv(|x| x as f64);
+    let s64 = s32.mapv(|x| x as f64);
+    let t64 = t32.mapv(|x| x as f64);
+
+    // The terms are positive so there's no cancellation, which the reference in f64 would
+    // resolve but products rounded to f32 couldn't
+    let check = |input_string: &str,
+                 operands32: &[&dyn ArrayLike<f32>],
+                 operands64: &[&dyn ArrayLike<f64>]| {
+        let correct_answer = einsum(input_string, operands64).unwrap();
+        assert_eq!(
+            with_summation_mode(SummationMode::Standard, || einsum(input_string, operands32))
+                .unwrap(),
+            einsum(input_string, operands32).unwrap()
+        );
+        for mode in [SummationMode::Pairwise, SummationMode::Kahan] {
+            let result = with_summation_mode(mode, || einsum(input_string, operands32)).unwrap();
+            assert!(result
+                .iter()
+                .zip(correct_answer.iter())
+                .all(|(&x, &y)| (x as f64 - y).abs() <= 1e-6 * y.abs()));
+        }
+    };
+    check("i->", &[&v32], &[&v64]);
+    check("ij->j", &[&m32], &[&m64]);
+    check("i,i->", &[&v32, &v32], &[&v64, &v64]);
+    check("i,ij->j", &[&v32, &m32], &[&v64, &m64]);
+    check("bij,bjk->bik", &[&s32, &t32], &[&s64, &t64]);
+
+    // The mode is restored on the way out, including from nested calls
+    with_summation_mode(SummationMode::Kahan, || {
+        with_summation_mode(SummationMode::Pairwise, || {
+            assert_eq!(summation_mode(), SummationMode::Pairwise)
+        });
+        assert_eq!(summation_mode(), SummationMode::Kahan);
+    });
+    assert_eq!(summation_mode(), SummationMode::Standard);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_sums_with_compensation_in_parallel() {
+    let s32 = rand_array((3, 4, 50_000)).mapv(|x| x as f32);
+    let t32 = rand_array((3, 50_000, 2)).mapv(|x| x as f32);
+    for input_string in ["bij,bjk->bik", "aij,bjk->aibk"] {
+        for mode in [SummationMode::Pairwise, SummationMode::Kahan] {
+            let sequential =
+                with_summation_mode(mode, || einsum(input_string, &[&s32, &t32])).unwrap();
+            let parallel = with_summation_mode(mode, || {
+                with_num_threads(2, || par_einsum(input_string, &[&s32, &t32]).unwrap())
+            })
+            .unwrap();
+            assert_eq!(parallel, sequential);
+        }
+    }
+}
+
+#[test]
+fn it_contracts_over_a_semiring() {
+    struct PlusTimes;
+
+    impl Semiring for PlusTimes {
+        type Element = f64;
+
+        fn zero() -> f64 {
+            0.
+        }
+
+        fn add(lhs: &f64, rhs: &f64) -> f64 {
+            lhs + rhs
+        }
+
+        fn mul(lhs: &f64, rhs: &f64) -> f64 {
+            lhs * rhs
+        }
+    }
+
+    let v = rand_array(3);
+    let m = rand_array((3, 3));
+    let row = rand_array((1, 3));
+    let t1 = rand_array((2, 3, 4));
+    let t2 = rand_array((2, 4, 3));
+    let t3 = rand_array((3, 3, 3));
+    let cases: [(&str, Vec<&dyn ArrayLike<f64>>); 10] = [
+        ("ij->ji", vec![&m]),
+        ("ii->", vec![&m]),
+        ("i->ii", vec![&v]),
+        ("i,i->", vec![&v, &v]),
+        ("ij,jk->ik", vec![&m, &m]),
+        ("iij,j->i", vec![&t3, &v]),
+        ("bij,bjk->bik", vec![&t1, &t2]),
+        ("...ij,...jk->...ik", vec![&t1, &t2]),
+        ("ij,jk,kl,l->i", vec![&m, &m, &m, &v]),
+        ("ij,ij->ij", vec![&row, &m]),
+    ];
+    for (input_string, operands) in cases.iter() {
+        assert!(einsum_semiring::<PlusTimes>(input_string, operands)
+            .unwrap()
+            .my_all_close(&einsum(input_string, operands).unwrap(), TOL));
+    }
+
+    assert_eq!(
+        einsum_semiring::<PlusTimes>("ij,jk->ik", &[&m, &t1]).unwrap_err(),
+        einsum("ij,jk->ik", &[&m, &t1]).unwrap_err()
+    );
+}
+
+#[test]
+fn it_contracts_over_tropical_semirings() {
+    let a = rand_array((4, 5));
+    let b = rand_array((5, 3));
+    let c = rand_array((3, 2));
+
+    // Compare against the best over all assignments of the summed indices
+    let mut min_plus = Array::from_elem((4, 2), f64::INFINITY);
+    let mut max_plus = Array::from_elem((4, 2), f64::NEG_INFINITY);
+    for ((i, l), (min_elem, max_elem)) in ndarray::indices((4, 2))
+        .into_iter()
+        .zip(min_plus.iter_mut().zip(max_plus.iter_mut()))
+    {
+        for j in 0..5 {
+            for k in 0..3 {
+                let score = a[[i, j]] + b[[j, k]] + c[[k, l]];
+                *min_elem = min_elem.min(score);
+                *max_elem = max_elem.max(score);
+            }
+        }
+    }
+
+    assert!(
+        einsum_semiring::<MinPlus<f64>>("ij,jk,kl->il", &[&a, &b, &c])
+            .unwrap()
+            .my_all_close(&min_plus, TOL)
+    );
+    assert!(
+        einsum_semiring::<MaxPlus<f64>>("ij,jk,kl->il", &[&a, &b, &c])
+            .unwrap()
+            .my_all_close(&max_plus, TOL)
+    );
+
+    // A sum with no terms is the zero of the semiring
+    let empty: Array1<f32> = Array::zeros(0);
+    assert_eq!(
+        einsum_semiring::<MaxPlus<f32>>("i->", &[&empty]).unwrap(),
+        arr0(f32::NEG_INFINITY).into_dyn()
+    );
+}
+
+#[test]
+fn it_contracts_in_the_log_domain() {
+    let a = rand_array((3, 4));
+    let b = rand_array((4, 5));
+    let v = rand_array(5);
+
+    let cases: [(&str, Vec<&dyn ArrayLike<f64>>); 2] =
+        [("ij,jk->ik", vec![&a, &b]), ("ij,jk,k->", vec![&a, &b, &v])];
+    for (input_string, operands) in cases {
+        let exp_operands: Vec<ArrayD<f64>> = operands
+            .iter()
+            .map(|x| x.into_dyn_view().mapv(f64::exp))
+            .collect();
+        let exp_refs: Vec<&dyn ArrayLike<f64>> = exp_operands
+            .iter()
+            .map(|x| x as &dyn ArrayLike<f64>)
+            .collect();
+        let correct_answer = einsum(input_string, &exp_refs).unwrap().mapv(f64::ln);
+        assert!(einsum_logsumexp(input_string, &operands)
+            .unwrap()
+            .my_all_close(&correct_answer, TOL));
+    }
+
+    // Shifting by a constant doesn't lose precision, where exponentiating would underflow
+    let shifted = einsum_logsumexp("ij,jk->ik", &[&(&a - 800.), &(&b - 800.)]).unwrap();
+    let unshifted = einsum_logsumexp("ij,jk->ik", &[&a, &b]).unwrap();
+    assert!((shifted + 1600.).my_all_close(&unshifted, 1e-9));
+
+    // Log-probabilities of 0
+    let zeros: Array2<f64> = Array::from_elem((2, 2), f64::NEG_INFINITY);
+    assert_eq!(
+        einsum_logsumexp("ij,jk->ik", &[&zeros, &a.slice(s![..2, ..2])]).unwrap(),
+        zeros.into_dyn()
+    );
+}
+
+#[test]
+fn it_contracts_boolean_operands() {
+    let a = rand_array((4, 5)).mapv(|x| x > 0.);
+    let b = rand_array((5, 3)).mapv(|x| x > 2.);
+    let a_numeric = a.mapv(|x| x as u8 as f64);
+    let b_numeric = b.mapv(|x| x as u8 as f64);
+
+    // True exactly where the numeric contraction of the 0/1 matrices is positive
+    let correct_answer = einsum("ij,jk->ik", &[&a_numeric, &b_numeric])
+        .unwrap()
+        .mapv(|x| x > 0.);
+    assert_eq!(einsum_bool("ij,jk->ik", &[&a, &b]).unwrap(), correct_answer);
+
+    let any_row = einsum_bool("ij->i", &[&a]).unwrap();
+    let expected: Array1<bool> = a
+        .rows()
+        .into_iter()
+        .map(|row| row.iter().any(|&x| x))
+        .collect();
+    assert_eq!(any_row, expected.into_dyn());
+}
+
+#[test]
+fn it_detects_integer_overflow() {
+    let m: Array2<i64> = Array::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as i64 - 5);
+    let n: Array2<i64> = Array::from_shape_fn((4, 2), |(i, j)| (i * 2 + j) as i64 * 3);
+    assert_eq!(
+        einsum_checked("ij,jk->ik", &[&m, &n]).unwrap(),
+        einsum("ij,jk->ik", &[&m, &n]).unwrap()
+    );
+    assert_eq!(
+        einsum_checked("ij,jk,ik->", &[&m, &n, &m.slice(s![.., ..2])]).unwrap(),
+        einsum("ij,jk,ik->", &[&m, &n, &m.slice(s![.., ..2])]).unwrap()
+    );
+
+    // Overflow in a product, and in a sum of products that each fit
+    let big: Array1<i32> = array![i32::MAX / 2 + 1, 1];
+    assert_eq!(
+        einsum_checked("i,i->i", &[&big, &array![2, 2]]).unwrap_err(),
+        EinsumError::IntegerOverflow
+    );
+    assert_eq!(
+        einsum_checked("i,i->", &[&big, &array![1, 1]]).unwrap(),
+        arr0(i32::MAX / 2 + 2).into_dyn()
+    );
+    assert_eq!(
+        einsum_checked("i,j->", &[&big, &array![1, 1]]).unwrap_err(),
+        EinsumError::IntegerOverflow
+    );
+    assert_eq!(
+        einsum_checked("i,i->", &[&array![u8::MAX, 1], &array![1u8, 1]]).unwrap_err(),
+        EinsumError::IntegerOverflow
+    );
+}
+
+#[test]
+fn it_contracts_integers_with_each_arithmetic() {
+    let m: Array2<i8> = Array::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as i8 * 10 - 50);
+    let n: Array2<i8> = Array::from_shape_fn((4, 2), |(i, j)| (i * 2 + j) as i8 * 3);
+    let m_wide = m.mapv(i64::from);
+    let n_wide = n.mapv(i64::from);
+    let exact = einsum("ij,jk->ik", &[&m_wide, &n_wide]).unwrap();
+    assert!(exact.iter().any(|&x| x > i64::from(i8::MAX)));
+
+    // Wrapping gives the exact result modulo 256 whatever the intermediate overflows
+    assert_eq!(
+        einsum_integer("ij,jk->ik", &[&m, &n], IntegerArithmetic::Wrapping).unwrap(),
+        exact.mapv(|x| x as i8)
+    );
+    assert_eq!(
+        einsum_integer("ij,jk->ik", &[&m, &n], IntegerArithmetic::Checked).unwrap_err(),
+        EinsumError::IntegerOverflow
+    );
+
+    // The terms of each sum have the same sign, so saturation is order-independent
+    let positive = m.mapv(|x| x.saturating_abs());
+    let exact = einsum("ij,jk->ik", &[&positive.mapv(i64::from), &n_wide]).unwrap();
+    assert_eq!(
+        einsum_integer("ij,jk->ik", &[&positive, &n], IntegerArithmetic::Saturating).unwrap(),
+        exact.mapv(|x| x.min(i64::from(i8::MAX)) as i8)
+    );
+
+    // Without overflow, every mode agrees with einsum
+    let small: Array2<i8> = Array::from_shape_fn((2, 3), |(i, j)| i as i8 - j as i8);
+    for arithmetic in [
+        IntegerArithmetic::Wrapping,
+        IntegerArithmetic::Saturating,
+        IntegerArithmetic::Checked,
+    ] {
+        assert_eq!(
+            einsum_integer("ij,kj->ik", &[&small, &small], arithmetic).unwrap(),
+            einsum("ij,kj->ik", &[&small, &small]).unwrap()
+        );
+    }
+}
+
+#[test]
+fn it_contracts_sparse_operands() {
+    // About two thirds of the elements are zero
+    let sparsify = |a: ArrayD<f64>| a.mapv(|x| if x.abs() < 1.7 { x } else { 0. });
+    let v = sparsify(rand_array(4).into_dyn());
+    let m = sparsify(rand_array((4, 4)).into_dyn());
+    let r = sparsify(rand_array((4, 5)).into_dyn());
+    let t = sparsify(rand_array((4, 4, 5)).into_dyn());
+    let b1 = sparsify(rand_array((3, 4, 5)).into_dyn());
+    let b2 = sparsify(rand_array((3, 5, 2)).into_dyn());
+    let row = rand_array((1, 4)).into_dyn();
+
+    let cases: [(&str, Vec<&ArrayD<f64>>); 12] = [
+        ("ii->i", vec![&m]),
+        ("ij->", vec![&r]),
+        ("ij,jk->ik", vec![&m, &r]),
+        ("ij,ij->ij", vec![&m, &m]),
+        ("ij,kj->ikj", vec![&m, &m]),
+        ("iik,jk->ij", vec![&t, &r]),
+        ("ijk,j->ik", vec![&t, &v]),
+        ("i,i->ii", vec![&v, &v]),
+        ("bij,bjk->bik", vec![&b1, &b2]),
+        ("ij,jk,kl->il", vec![&m, &m, &r]),
+        ("ij,jk,k,i->", vec![&m, &m, &v, &v]),
+        ("ij,ij->ij", vec![&row, &m]),
+    ];
+    for (input_string, dense_operands) in cases.iter() {
+        let correct_answer = einsum(
+            input_string,
+            &dense_operands
+                .iter()
+                .map(|x| *x as &dyn ArrayLike<f64>)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let sparse_operands: Vec<SparseOperand<f64>> = dense_operands
+            .iter()
+            .map(|x| SparseOperand::from_dense(*x))
+            .collect();
+
+        // Every combination of dense and sparse operands, except sparse broadcasting
+        for mask in 0..(1 << dense_operands.len()) {
+            let operands: Vec<&dyn EinsumOperand<f64>> = (0..dense_operands.len())
+                .map(|i| {
+                    if mask & (1 << i) != 0 {
+                        &sparse_operands[i] as &dyn EinsumOperand<f64>
+                    } else {
+                        dense_operands[i] as &dyn EinsumOperand<f64>
+                    }
+                })
+                .collect();
+            if *dense_operands[0] == row && mask & 1 != 0 {
+                assert_eq!(
+                    einsum_sparse(input_string, &operands).unwrap_err(),
+                    EinsumError::Unsupported("a sparse operand can't be broadcast")
+                );
+            } else {
+                assert!(einsum_sparse(input_string, &operands)
+                    .unwrap()
+                    .my_all_close(&correct_answer, TOL));
+            }
+        }
+    }
+
+    // Duplicate elements are summed
+    let duplicated = SparseOperand::new(vec![2], vec![vec![1], vec![0], vec![1]], vec![1., 2., 3.]);
+    assert_eq!(
+        einsum_sparse("i,i->i", &[&duplicated, &array![1., 1.]]).unwrap(),
+        array![2., 4.].into_dyn()
+    );
+    assert_eq!(
+        einsum_sparse("i,j->ij", &[&duplicated, &duplicated]).unwrap(),
+        array![[4., 8.], [8., 16.]].into_dyn()
+    );
+
+    assert_eq!(
+        einsum_sparse("ij,jk->ik", &[&SparseOperand::from_dense(&m), &v]).unwrap_err(),
+        einsum("ij,jk->ik", &[&m, &v]).unwrap_err()
+    );
+}
+
+#[cfg(feature = "sprs")]
+#[test]
+fn it_contracts_csmat_operands() {
+    let sparsify = |a: Array2<f64>| a.mapv(|x| if x.abs() < 1.7 { x } else { 0. });
+    let to_triplets = |a: &Array2<f64>| {
+        let mut triplets = sprs::TriMat::new(a.dim());
+        for ((i, j), &x) in a.indexed_iter().filter(|(_, &x)| x != 0.) {
+            triplets.add_triplet(i, j, x);
+        }
+        triplets
+    };
+    let m = sparsify(rand_array((4, 5)));
+    let n = sparsify(rand_array((5, 5)));
+    let v = rand_array(5);
+    let w = rand_array(4);
+    let d = rand_array((5, 3));
+    let t = rand_array((5, 2, 3));
+
+    for (m_sparse, n_sparse) in [
+        (to_triplets(&m).to_csr(), to_triplets(&n).to_csr()),
+        (to_triplets(&m).to_csc(), to_triplets(&n).to_csc()),
+    ] {
+        let check = |input_string: &str,
+                     dense_operands: &[&dyn ArrayLike<f64>],
+                     operands: &[&dyn EinsumOperand<f64>]| {
+            assert!(einsum_sparse(input_string, operands)
+                .unwrap()
+                .my_all_close(&einsum(input_string, dense_operands).unwrap(), TOL));
+        };
+        check("ij,jk->ik", &[&m, &d], &[&m_sparse, &d]);
+        check("ij,jk->ki", &[&m, &d], &[&m_sparse, &d]);
+        check("ij,j->i", &[&m, &v], &[&m_sparse, &v]);
+        check("i,ij->j", &[&w, &m], &[&w, &m_sparse]);
+        check("jk,ij->ki", &[&d, &n], &[&d, &n_sparse]);
+        check("ij,jkl->lik", &[&m, &t], &[&m_sparse, &t]);
+        check("ij,jk->ik", &[&m, &n], &[&m_sparse, &n_sparse]);
+        check("ii,ij->j", &[&n, &d], &[&n_sparse, &d]);
+        check("ij,jk,k->ij", &[&m, &n, &v], &[&m_sparse, &n_sparse, &v]);
+    }
+}
+
+#[test]
+fn it_contracts_block_sparse_tensors() {
+    // Only the blocks whose positions add up to an even number are stored, as if each block
+    // were a symmetry sector with a parity
+    let block_sparse = |block_sizes: Vec<Vec<usize>>| {
+        let shape: Vec<usize> = block_sizes.iter().map(|x| x.iter().sum()).collect();
+        let dense = BlockSparseTensor::from_dense(&rand_array(IxDyn(&shape)), block_sizes.clone());
+        let mut tensor = BlockSparseTensor::new(block_sizes);
+        for (coords, block) in dense.blocks() {
+            if coords.iter().sum::<usize>() % 2 == 0 {
+                tensor.insert_block(coords.to_vec(), block.clone());
+            }
+        }
+        tensor
+    };
+    let (i, j, k) = (vec![2, 3], vec![1, 2, 2], vec![3, 1]);
+    let m = block_sparse(vec![i.clone(), i.clone()]);
+    let r = block_sparse(vec![i.clone(), j.clone()]);
+    let s = block_sparse(vec![j.clone(), k.clone()]);
+    let t = block_sparse(vec![i.clone(), i.clone(), j.clone()]);
+    let v = block_sparse(vec![j.clone()]);
+    let w = block_sparse(vec![k.clone()]);
+
+    let cases: [(&str, Vec<&BlockSparseTensor<f64>>); 10] = [
+        ("ii->i", vec![&m]),
+        ("ij->ji", vec![&r]),
+        ("ij->", vec![&r]),
+        ("j->jj", vec![&v]),
+        ("ij,jk->ik", vec![&r, &s]),
+        ("ij,ij->", vec![&r, &r]),
+        ("iij,j->ij", vec![&t, &v]),
+        ("ij,kj->ikj", vec![&r, &r]),
+        ("ij,jk,k->i", vec![&r, &s, &w]),
+        ("ik,kj,jl->il", vec![&m, &r, &s]),
+    ];
+    for (input_string, operands) in cases.iter() {
+        let dense_operands: Vec<ArrayD<f64>> = operands.iter().map(|x| x.to_dense()).collect();
+        let correct_answer = einsum(
+            input_string,
+            &dense_operands
+                .iter()
+                .map(|x| x as &dyn ArrayLike<f64>)
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let result = einsum_block_sparse(input_string, operands).unwrap();
+        assert!(result.to_dense().my_all_close(&correct_answer, TOL));
+    }
+
+    // The product of two tensors with even parity has even parity too
+    let product = einsum_block_sparse("ij,jk->ik", &[&r, &s]).unwrap();
+    assert!(product
+        .blocks()
+        .all(|(coords, _)| (coords[0] + coords[1]) % 2 == 0));
+
+    let mismatched = block_sparse(vec![vec![3, 2], j.clone()]);
+    assert_eq!(
+        einsum_block_sparse("ij,ij->", &[&r, &mismatched]).unwrap_err(),
+        EinsumError::BlockStructureMismatch {
+            operand: 1,
+            axis: 0
+        }
+    );
+}
+
+#[test]
+fn it_contracts_with_index_symmetries() {
+    let a = rand_array((4, 4));
+    let sym = &a + &a.t();
+    let antisym = &a - &a.t();
+    let b = rand_array((4, 4));
+    let t = rand_array((4, 4, 3));
+    let v = rand_array((4, 4, 4, 4));
+    let v = &v + &v.view().permuted_axes([1, 0, 2, 3]);
+    let v = &v + &v.view().permuted_axes([0, 1, 3, 2]);
+    let ellipsis = rand_array((2, 4, 4));
+    let row = b.row(0);
+
+    let symmetric = IndexSymmetry::symmetric;
+    let antisymmetric = IndexSymmetry::antisymmetric;
+    let check =
+        |input_string: &str, operands: &[&dyn ArrayLike<f64>], symmetries: &[IndexSymmetry]| {
+            let correct_answer = einsum(input_string, operands).unwrap();
+            let result = einsum_symmetric(input_string, operands, symmetries).unwrap();
+            assert!(result.my_all_close(&correct_answer, TOL));
+        };
+    check("ij,ij->", &[&sym, &b], &[symmetric(0, (0, 1))]);
+    check("ij->", &[&sym], &[symmetric(0, (1, 0))]);
+    check("ij->", &[&antisym], &[antisymmetric(0, (0, 1))]);
+    check("ji,ijk->k", &[&antisym, &t], &[antisymmetric(0, (0, 1))]);
+    check("ijkl,ij->kl", &[&v, &b], &[symmetric(0, (0, 1))]);
+    check(
+        "ijkl,ij,kl->",
+        &[&v, &sym, &b],
+        &[symmetric(0, (0, 1)), symmetric(0, (2, 3))],
+    );
+    // Not summed over, or shared by two other operands: the declarations are ignored
+    check("ij,jk->ik", &[&sym, &b], &[symmetric(0, (0, 1))]);
+    check("ij,ij,ij->", &[&sym, &b, &b], &[symmetric(0, (0, 1))]);
+    check("ij,i->", &[&sym, &row], &[symmetric(0, (0, 1))]);
+    check("...ij,ij->...", &[&ellipsis, &sym], &[symmetric(1, (0, 1))]);
+    // Axes of length 1 broadcast against the symmetric pair
+    let broadcast_row = rand_array((1, 4));
+    let ones = Array2::<f64>::ones((1, 1));
+    check("ij,ij->", &[&sym, &broadcast_row], &[symmetric(0, (0, 1))]);
+    check(
+        "ij,ij->",
+        &[&broadcast_row, &antisym],
+        &[antisymmetric(1, (1, 0))],
+    );
+    check("ij,ij->", &[&ones, &b], &[symmetric(0, (0, 1))]);
+
+    let rectangular = rand_array((4, 3));
+    for (operands, symmetry) in [
+        (vec![&sym], symmetric(1, (0, 1))),
+        (vec![&sym], symmetric(0, (0, 2))),
+        (vec![&sym], symmetric(0, (1, 1))),
+        (vec![&rectangular], antisymmetric(0, (0, 1))),
+    ] {
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        assert_eq!(
+            einsum_symmetric("ij->", &operands, &[symmetry]).unwrap_err(),
+            EinsumError::InvalidSymmetry {
+                operand: symmetry.operand,
+                axes: symmetry.axes
+            }
+        );
+    }
+}
+
+#[test]
+fn it_contracts_ncon_networks() {
+    let a = rand_array((2, 3, 4));
+    let b = rand_array((4, 3, 5));
+    let m = rand_array((5, 5));
+    let v = rand_array(2);
+
+    let check = |tensors: &[&dyn ArrayLike<f64>],
+                 connects: &[&[i32]],
+                 output_order: Option<&[i32]>,
+                 input_string: &str| {
+        let correct_answer = einsum(input_string, tensors).unwrap();
+        let result = ncon(tensors, connects, output_order).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    };
+    check(&[&a, &b], &[&[-1, 1, 2], &[2, 1, -2]], None, "ijk,kjl->il");
+    check(&[&a, &b], &[&[-2, 1, 2], &[2, 1, -1]], None, "ijk,kjl->li");
+    check(
+        &[&a, &b],
+        &[&[-1, 1, 2], &[2, 1, -2]],
+        Some(&[-2, -1]),
+        "ijk,kjl->li",
+    );
+    check(&[&m], &[&[1, 1]], None, "ii->");
+    check(&[&a, &v], &[&[-3, -1, -2], &[-4]], None, "ijk,l->jkil");
+    check(
+        &[&a, &b, &m, &v],
+        &[&[3, 1, 2], &[2, 1, 4], &[4, -1], &[3]],
+        None,
+        "ijk,kjl,lm,i->m",
+    );
+
+    let invalid = |connects: &[&[i32]], output_order: Option<&[i32]>| match ncon(
+        &[&a, &b],
+        connects,
+        output_order,
+    )
+    .unwrap_err()
+    {
+        EinsumError::InvalidNconLabel { label, .. } => label,
+        err => panic!("unexpected error {:?}", err),
+    };
+    assert_eq!(invalid(&[&[-1, 0, 2], &[2, 1, -2]], None), 0);
+    assert_eq!(invalid(&[&[-1, 1, 2], &[2, 3, -2]], None), 1);
+    assert_eq!(invalid(&[&[-1, 1, 2], &[2, 1, -1]], None), -1);
+    assert_eq!(invalid(&[&[-1, 1, 2], &[2, 1, -2]], Some(&[-1, 1])), 1);
+    assert_eq!(invalid(&[&[-1, 1, 2], &[2, 1, -2]], Some(&[-1])), -2);
+    assert_eq!(invalid(&[&[-1, 1, 2], &[2, 1, -2]], Some(&[-1, -1])), -2);
+    assert_eq!(
+        ncon(&[&a], &[&[-1, 1, 2], &[2, 1, -2]], None).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+    assert_eq!(
+        ncon(&[&a, &b], &[&[-1, 1, 7], &[1, 7, -2]], None)
+            .unwrap_err()
+            .to_string(),
+        "index '1' has length 3 on axis 1 of operand 0 but length 4 on axis 0 of operand 1"
+    );
+}
+
+#[test]
+fn it_contracts_many_strings_at_once() {
+    let a = rand_array((3, 4));
+    let b = rand_array((4, 5));
+    let c = rand_array((5, 3));
+    let d = rand_array((1, 5));
+
+    let input_strings = [
+        "ij,jk,ki->",
+        "ij,jk,ki->i",
+        "ab,bc,ca->ab",
+        "ij,jk,ki->ik",
+        "ij,jk,ki->ki",
+        "ij,jk,ki->ik",
+        "ij,jk,ki->iik",
+        "ij,jk,kl->il",
+        "ij,jk,ki",
+    ];
+    let operands: [&dyn ArrayLike<f64>; 3] = [&a, &b, &c];
+    let results = einsum_many(&input_strings, &operands).unwrap();
+    assert_eq!(results.len(), input_strings.len());
+    for (input_string, result) in input_strings.iter().zip(&results) {
+        let correct_answer = einsum(input_string, &operands).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    }
+
+    // Broadcast operands, and a singleton contraction
+    let operands: [&dyn ArrayLike<f64>; 2] = [&d, &b];
+    let input_strings = ["ij,kj->ik", "ij,kj->k", "ij,kj->ki"];
+    let results = einsum_many(&input_strings, &operands).unwrap();
+    for (input_string, result) in input_strings.iter().zip(&results) {
+        let correct_answer = einsum(input_string, &operands).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    }
+    let results = einsum_many(&["ij->ji", "ij->j", "ij->ji"], &[&a]).unwrap();
+    assert!(results[0].my_all_close(&a.t(), TOL));
+    assert!(results[1].my_all_close(&a.sum_axis(Axis(0)), TOL));
+    assert!(results[2].my_all_close(&a.t(), TOL));
+
+    assert_eq!(
+        einsum_many(&["ij,jk->ik", "ij,jl->il"], &[&a, &c]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 0,
+            first_axis: 1,
+            expected: 4,
+            operand: 1,
+            axis: 0,
+            found: 5
+        }
+    );
+}
+
+#[test]
+fn it_computes_gradients_with_respect_to_operands() {
+    let m = rand_array((3, 4));
+    let n = rand_array((4, 5));
+    let p = rand_array((5, 2));
+    let s = rand_array((3, 3));
+    let t = rand_array((3, 3, 4));
+    let v = rand_array(3);
+    let row = rand_array((1, 4));
+    let b1 = rand_array((2, 3, 4));
+    let b2 = rand_array((1, 4, 5));
+
+    // The contraction is linear in each operand, so the gradient with respect to an operand,
+    // dotted with any perturbation of it, is the upstream gradient dotted with the contraction
+    // of the perturbation.
+    let check = |input_string: &str, operands: &[&ArrayD<f64>]| {
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let output = einsum(input_string, &operands).unwrap();
+        let grad_output = rand_array(output.raw_dim());
+        let grads = einsum_backward(input_string, &operands, &grad_output).unwrap();
+        assert_eq!(grads.len(), operands.len());
+        for (operand_num, grad) in grads.iter().enumerate() {
+            let operand_shape = operands[operand_num].into_dyn_view().raw_dim();
+            assert_eq!(grad.raw_dim(), operand_shape);
+            let perturbation = rand_array(operand_shape);
+            let mut perturbed = operands.clone();
+            perturbed[operand_num] = &perturbation;
+            let directional_derivative =
+                (&grad_output * &einsum(input_string, &perturbed).unwrap()).sum();
+            assert!((directional_derivative - (grad * &perturbation).sum()).abs() < TOL);
+        }
+    };
+    let m = m.into_dyn();
+    let n = n.into_dyn();
+    let p = p.into_dyn();
+    let s = s.into_dyn();
+    let t = t.into_dyn();
+    let v = v.into_dyn();
+    let row = row.into_dyn();
+    let b1 = b1.into_dyn();
+    let b2 = b2.into_dyn();
+    check("ij,jk->ik", &[&m, &n]);
+    check("ij,jk,kl->il", &[&m, &n, &p]);
+    check("ij,jk,kl->", &[&m, &n, &p]);
+    check("ij->ji", &[&m]);
+    check("ij->i", &[&m]);
+    check("ij->", &[&m]);
+    check("ii->", &[&s]);
+    check("ii->i", &[&s]);
+    check("iik,i->k", &[&t, &v]);
+    check("i->ii", &[&v]);
+    check("ij,ij->ij", &[&row, &m]);
+    check("...ij,...jk->...ik", &[&b1, &b2]);
+    check("ij,kl->ijkl", &[&m, &s]);
+
+    assert_eq!(
+        einsum_backward("ij,jk->ik", &[&m, &n], &Array::zeros((3, 4))).unwrap_err(),
+        EinsumError::OutputShapeMismatch {
+            expected: vec![3, 5],
+            found: vec![3, 4]
+        }
+    );
+}
+
+#[test]
+fn it_differentiates_with_dual_numbers() {
+    let m = rand_array((3, 3)).into_dyn();
+    let n = rand_array((3, 4)).into_dyn();
+    let t = rand_array((3, 3, 4)).into_dyn();
+    let b1 = rand_array((2, 3, 4)).into_dyn();
+    let b2 = rand_array((2, 4, 3)).into_dyn();
+    let row = rand_array((1, 3)).into_dyn();
+
+    fn as_operands<'a>(arrays: &[&'a ArrayD<f64>]) -> Vec<&'a dyn ArrayLike<f64>> {
+        arrays.iter().map(|x| *x as &dyn ArrayLike<f64>).collect()
+    }
+
+    // One contraction for each singleton and pair contractor
+    let cases: [(&str, Vec<&ArrayD<f64>>); 14] = [
+        ("ij->ij", vec![&m]),
+        ("ij->ji", vec![&m]),
+        ("ij->i", vec![&n]),
+        ("ii->i", vec![&m]),
+        ("iij->ji", vec![&t]),
+        ("iij->j", vec![&t]),
+        ("ij,jk->ik", vec![&m, &n]),
+        ("ij,ij->ij", vec![&m, &m]),
+        ("ij,kl->ijkl", vec![&m, &n]),
+        ("ij,ij->", vec![&n, &n]),
+        ("bij,bjk->bik", vec![&b1, &b2]),
+        ("iij,ij->j", vec![&t, &n]),
+        ("ij,jk,kl->il", vec![&m, &m, &n]),
+        ("ij,ij->ij", vec![&row, &m]),
+    ];
+    for (input_string, primals) in cases.iter() {
+        let tangents: Vec<ArrayD<f64>> = primals.iter().map(|x| rand_array(x.raw_dim())).collect();
+        let (value, derivative) = einsum_jvp(
+            input_string,
+            &as_operands(primals),
+            &as_operands(&tangents.iter().collect::<Vec<_>>()),
+        )
+        .unwrap();
+        assert!(value.my_all_close(&einsum(input_string, &as_operands(primals)).unwrap(), TOL));
+
+        // The product rule: the sum over operands of the contraction with that operand
+        // replaced by its tangent
+        let mut correct_derivative = ArrayD::zeros(value.raw_dim());
+        for (operand_num, tangent) in tangents.iter().enumerate() {
+            let mut operands = primals.clone();
+            operands[operand_num] = tangent;
+            correct_derivative += &einsum(input_string, &as_operands(&operands)).unwrap();
+        }
+        assert!(derivative.my_all_close(&correct_derivative, TOL));
+    }
+
+    assert_eq!(
+        einsum_jvp("ij,jk->ik", &[&m, &n], &[&m, &m]).unwrap_err(),
+        EinsumError::TangentShapeMismatch {
+            operand: 1,
+            expected: vec![3, 4],
+            found: vec![3, 3]
+        }
+    );
+    assert_eq!(
+        einsum_jvp("ij,jk->ik", &[&m, &n], &[&m]).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+}
+
+#[test]
+fn it_contracts_many_operand_sets() {
+    let lhs: Vec<Array3<f64>> = (0..5).map(|_| rand_array((2, 3, 4))).collect();
+    let rhs: Vec<Array2<f64>> = (0..5).map(|_| rand_array((4, 5))).collect();
+    let broadcast_rhs = rand_array((1, 5));
+    let operand_sets: Vec<[&dyn ArrayLike<f64>; 2]> = lhs
+        .iter()
+        .zip(&rhs)
+        .map(|(l, r)| [l as &dyn ArrayLike<f64>, r])
+        .chain(std::iter::once([
+            &lhs[0] as &dyn ArrayLike<f64>,
+            &broadcast_rhs,
+        ]))
+        .collect();
+    let operand_sets: Vec<&[&dyn ArrayLike<f64>]> = operand_sets.iter().map(|x| &x[..]).collect();
+
+    let sc = SizedContraction::from_string_and_shapes("bij,jk->bik", &[vec![2, 3, 4], vec![4, 5]])
+        .unwrap();
+    let results = sc.contract_many(&operand_sets);
+    assert_eq!(results.len(), operand_sets.len());
+    for (operands, result) in operand_sets.iter().zip(&results) {
+        let correct_answer = einsum("bij,jk->bik", operands).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    }
+    assert!(sc.contract_many::<f64>(&[]).is_empty());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_contracts_many_operand_sets_in_parallel() {
+    let matrices: Vec<Array2<f64>> = (0..20).map(|_| rand_array((6, 6))).collect();
+    let operand_sets: Vec<[&dyn ArrayLike<f64>; 2]> = matrices
+        .iter()
+        .zip(matrices.iter().rev())
+        .map(|(a, b)| [a as &dyn ArrayLike<f64>, b])
+        .collect();
+    let operand_sets: Vec<&[&dyn ArrayLike<f64>]> = operand_sets.iter().map(|x| &x[..]).collect();
+
+    let sc =
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![6, 6], vec![6, 6]]).unwrap();
+    let results = sc.par_contract_many(&operand_sets);
+    for (operands, result) in operand_sets.iter().zip(&results) {
+        let correct_answer = einsum("ij,jk->ik", operands).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    }
+}
+
+#[test]
+#[should_panic(expected = "operand 1 has shape [4, 6] but the contraction expects [4, 5]")]
+fn it_rejects_a_mismatched_operand_set() {
+    let sc =
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![3, 4], vec![4, 5]]).unwrap();
+    let m = rand_array((3, 4));
+    let n = rand_array((4, 6));
+    sc.contract_many(&[&[&m, &n]]);
+}
+
+#[test]
+fn it_maps_contractions_over_a_batch_axis() {
+    let m = rand_array((3, 4)).into_dyn();
+    let n = rand_array((4, 5)).into_dyn();
+    let ms = rand_array((2, 3, 4)).into_dyn();
+    let ns = rand_array((2, 4, 5)).into_dyn();
+    let squares = rand_array((2, 3, 3)).into_dyn();
+    let ts = rand_array((2, 3, 5, 4)).into_dyn();
+
+    let check = |input_string: &str, operands: &[&ArrayD<f64>], batched: &[bool]| {
+        let dyn_operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let result = einsum_vmap(input_string, &dyn_operands, batched).unwrap();
+
+        // Loop over the batch, performing one contraction per element
+        let batch_len = operands
+            .iter()
+            .zip(batched)
+            .find(|(_, &is_batched)| is_batched)
+            .unwrap()
+            .0
+            .shape()[0];
+        let mut elements = Vec::new();
+        for b in 0..batch_len {
+            let views: Vec<ArrayViewD<f64>> = operands
+                .iter()
+                .zip(batched)
+                .map(|(x, &is_batched)| {
+                    if is_batched {
+                        x.index_axis(Axis(0), b)
+                    } else {
+                        x.view()
+                    }
+                })
+                .collect();
+            let element_operands: Vec<&dyn ArrayLike<f64>> =
+                views.iter().map(|x| x as &dyn ArrayLike<f64>).collect();
+            elements.push(einsum(input_string, &element_operands).unwrap());
+        }
+        let element_views: Vec<ArrayViewD<f64>> = elements.iter().map(|x| x.view()).collect();
+        let correct_answer = ndarray::stack(Axis(0), &element_views).unwrap();
+        assert!(result.my_all_close(&correct_answer, TOL));
+    };
+
+    check("ij,jk->ik", &[&ms, &ns], &[true, true]);
+    check("ij,jk->ik", &[&ms, &n], &[true, false]);
+    check("ij,jk->ik", &[&m, &ns], &[false, true]);
+    check("ij,jk->ki", &[&ms, &ns], &[true, true]);
+    check("ii->i", &[&squares], &[true]);
+    check("ij,ij->", &[&ms, &m], &[true, false]);
+    check("...j,jk->...k", &[&ts, &n], &[true, false]);
+    check("ij,jk", &[&ms, &ns], &[true, true]);
+
+    // Errors
+    let operands: [&dyn ArrayLike<f64>; 2] = [&ms, &ns];
+    assert!(matches!(
+        einsum_vmap("ij,jk->ik", &operands, &[true]),
+        Err(EinsumError::BatchFlagCountMismatch {
+            expected: 2,
+            found: 1
+        })
+    ));
+    assert!(matches!(
+        einsum_vmap("ij,jk->ik", &[&m, &n], &[false, false]),
+        Err(EinsumError::Unsupported(_))
+    ));
+    assert!(matches!(
+        einsum_vmap("->", &[&arr0(1.).into_dyn()], &[true]),
+        Err(EinsumError::RankMismatch {
+            operand: 0,
+            expected: 1,
+            found: 0
+        })
+    ));
+    let short_batch = rand_array((3, 4, 5)).into_dyn();
+    assert!(matches!(
+        einsum_vmap("ij,jk->ik", &[&ms, &short_batch], &[true, true]),
+        Err(EinsumError::ShapeMismatch {
+            operand: 1,
+            axis: 0,
+            expected: 2,
+            found: 3
+        })
+    ));
+}
+
+#[test]
+fn it_contracts_in_chunks_of_the_output() {
+    let m = rand_array((7, 4));
+    let n = rand_array((4, 5));
+    let square = rand_array((7, 7));
+    let column = rand_array((7, 1));
+    let t = rand_array((3, 7, 4));
+
+    let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>]| {
+        let correct_answer = einsum(input_string, operands).unwrap();
+        for &chunk_len in [1, 2, 3, 7, 10].iter() {
+            // Every element of `out` has to be overwritten
+            let mut out = ArrayD::from_elem(correct_answer.raw_dim(), 17.);
+            einsum_chunked_into(input_string, operands, &mut out.view_mut(), chunk_len).unwrap();
+            assert!(out.my_all_close(&correct_answer, TOL));
+        }
+    };
+    check("ij,jk->ik", &[&m, &n]);
+    check("ij,jk->ki", &[&m, &n]);
+    check("ii->i", &[&square]);
+    check("ij,ij->i", &[&column, &square]);
+    check("bij,jk->ikb", &[&t, &n]);
+    check("...j,jk->...k", &[&t, &n]);
+    check("ij,ij->", &[&m, &m]);
+    check("ij->iij", &[&m]);
+    check("ij->jij", &[&m]);
+    check("i->ii", &[&column.column(0)]);
+    check("ii->ii", &[&square]);
+
+    let mut out = ArrayD::zeros(IxDyn(&[7, 5]));
+    assert!(matches!(
+        einsum_chunked_into("ij,jk->ik", &[&m, &n], &mut out.view_mut(), 0),
+        Err(EinsumError::Unsupported(_))
+    ));
+    let mut out = ArrayD::zeros(IxDyn(&[5, 7]));
+    assert!(matches!(
+        einsum_chunked_into("ij,jk->ik", &[&m, &n], &mut out.view_mut(), 2),
+        Err(EinsumError::OutputShapeMismatch { .. })
+    ));
+}
+
+#[test]
+fn it_splits_contractions_to_fit_a_memory_budget() {
+    let a = rand_array(10).into_dyn();
+    let b = rand_array(20).into_dyn();
+    let m = rand_array((10, 20)).into_dyn();
+    let n = rand_array((20, 30)).into_dyn();
+    let p = rand_array((30, 5)).into_dyn();
+    let row = rand_array((1, 20)).into_dyn();
+
+    let check = |input_string: &str, operands: &[&ArrayD<f64>]| {
+        let dyn_operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let path = einsum_path(input_string, &dyn_operands, OptimizationMethod::Naive).unwrap();
+        let correct_answer = path.contract_operands(&dyn_operands);
+        for &max_bytes in [0, 8, 100, 1000, 5000, 1 << 20].iter() {
+            let result =
+                with_max_intermediate_bytes(max_bytes, || path.contract_operands(&dyn_operands));
+            assert!(result.my_all_close(&correct_answer, TOL));
+            let result = with_max_intermediate_bytes(max_bytes, || {
+                einsum(input_string, &dyn_operands).unwrap()
+            });
+            assert!(result.my_all_close(&correct_answer, TOL));
+
+            let mut out = ArrayD::from_elem(correct_answer.raw_dim(), 17.);
+            with_max_intermediate_bytes(max_bytes, || {
+                path.contract_operands_into(&dyn_operands, &mut out.view_mut())
+            })
+            .unwrap();
+            assert!(out.my_all_close(&correct_answer, TOL));
+            with_max_intermediate_bytes(max_bytes, || {
+                path.contract_operands_acc(&dyn_operands, 2., -1., &mut out.view_mut())
+            })
+            .unwrap();
+            assert!(out.my_all_close(&correct_answer, TOL));
+            let mut workspace = EinsumWorkspace::new();
+            out.fill(17.);
+            with_max_intermediate_bytes(max_bytes, || {
+                path.contract_operands_with_workspace(
+                    &dyn_operands,
+                    &mut workspace,
+                    &mut out.view_mut(),
+                )
+            })
+            .unwrap();
+            assert!(out.my_all_close(&correct_answer, TOL));
+        }
+    };
+    check("i,j,ij->i", &[&a, &b, &m]);
+    check("i,j,ij->ji", &[&a, &b, &m]);
+    check("ij,jk,kl->il", &[&m, &n, &p]);
+    check("ij,jk,kl->li", &[&m, &n, &p]);
+    check("ij,jk,kl->", &[&m, &n, &p]);
+    check("i,j,ij->", &[&a, &b, &m]);
+    check("ij,ij,jk->ik", &[&row, &m, &n]);
+    check("ij->ji", &[&m]);
+
+    assert_eq!(max_intermediate_bytes(), None);
+    with_max_intermediate_bytes(100, || {
+        assert_eq!(max_intermediate_bytes(), Some(100));
+        with_max_intermediate_bytes(10, || assert_eq!(max_intermediate_bytes(), Some(10)));
+        assert_eq!(max_intermediate_bytes(), Some(100));
+    });
+    assert_eq!(max_intermediate_bytes(), None);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_splits_parallel_contractions_to_fit_a_memory_budget() {
+    let a = rand_array(10).into_dyn();
+    let b = rand_array(20).into_dyn();
+    let m = rand_array((10, 20)).into_dyn();
+    let n = rand_array((20, 30)).into_dyn();
+    let p = rand_array((30, 5)).into_dyn();
+    let q = rand_array((5, 10)).into_dyn();
+
+    // The operands are passed to the thread pool as arrays, since `ArrayLike` isn't `Sync`
+    fn dyn_operands<'a>(operands: &[&'a ArrayD<f64>]) -> Vec<&'a dyn ArrayLike<f64>> {
+        operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect()
+    }
+    let check = |input_string: &str, operands: &[&ArrayD<f64>]| {
+        let path = einsum_path(
+            input_string,
+            &dyn_operands(operands),
+            OptimizationMethod::Naive,
+        )
+        .unwrap();
+        let correct_answer = path.contract_operands(&dyn_operands(operands));
+        for &max_bytes in [0, 8, 100, 1000, 5000, 1 << 20].iter() {
+            let result = with_max_intermediate_bytes(max_bytes, || {
+                with_num_threads(3, || path.par_contract_operands(&dyn_operands(operands))).unwrap()
+            });
+            assert!(result.my_all_close(&correct_answer, TOL));
+            let result = with_max_intermediate_bytes(max_bytes, || {
+                with_num_threads(3, || par_einsum(input_string, &dyn_operands(operands)))
+                    .unwrap()
+                    .unwrap()
+            });
+            assert!(result.my_all_close(&correct_answer, TOL));
+        }
+    };
+    check("i,j,ij->i", &[&a, &b, &m]);
+    check("ij,jk,kl->li", &[&m, &n, &p]);
+    // Independent branches, which are otherwise performed concurrently
+    check("ij,jk,kl,lm->im", &[&m, &n, &p, &q]);
+
+    // The budget is carried over to the threads that perform the steps
+    use std::sync::{Arc, Mutex};
+
+    /// Performs a step as usual, recording the budget it was performed with
+    #[derive(Debug)]
+    struct BudgetRecorder(SizedContraction, Arc<Mutex<Vec<Option<usize>>>>);
+
+    impl PairContractor<f64> for BudgetRecorder {
+        fn contract_pair<'a, 'b, 'c, 'd>(
+            &self,
+            lhs: &'b ArrayViewD<'a, f64>,
+            rhs: &'d ArrayViewD<'c, f64>,
+        ) -> ArrayD<f64>
+        where
+            'a: 'b,
+            'c: 'd,
+        {
+            self.1.lock().unwrap().push(max_intermediate_bytes());
+            self.0.contract_operands(&[lhs, rhs])
+        }
+    }
+
+    let budgets = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = ContractorRegistry::new();
+    let recorded = budgets.clone();
+    registry.register_pair_contractor(move |sc| {
+        Some(Box::new(BudgetRecorder(sc.clone(), recorded.clone())))
+    });
+    let operands = [&m, &n, &p, &q];
+    let sc = SizedContraction::new("ij,jk,kl,lm->im", &dyn_operands(&operands)).unwrap();
+    let path = registry.path(&generate_optimized_order(&sc, OptimizationMethod::Naive));
+    let result = with_max_intermediate_bytes(1 << 20, || {
+        with_num_threads(3, || path.par_contract_operands(&dyn_operands(&operands))).unwrap()
+    });
+    assert!(result.my_all_close(&sc.contract_operands(&dyn_operands(&operands)), TOL));
+    assert_eq!(*budgets.lock().unwrap(), vec![Some(1 << 20); 3]);
+}
+
+#[test]
+fn it_slices_summed_indices() {
+    let m = rand_array((3, 4)).into_dyn();
+    let n = rand_array((4, 5)).into_dyn();
+    let p = rand_array((5, 3)).into_dyn();
+    let square = rand_array((4, 4)).into_dyn();
+    let row = rand_array((1, 4)).into_dyn();
+
+    let check = |input_string: &str, operands: &[&ArrayD<f64>], index: Option<char>| {
+        let dyn_operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let correct_answer = einsum(input_string, &dyn_operands).unwrap();
+        for &num_slices in [1, 2, 3, 4, 10].iter() {
+            let result = einsum_sliced(input_string, &dyn_operands, index, num_slices).unwrap();
+            assert!(result.my_all_close(&correct_answer, TOL));
+        }
+    };
+    check("ij,jk,ki->", &[&m, &n, &p], Some('j'));
+    check("ij,jk,ki->", &[&m, &n, &p], Some('k'));
+    check("ij,jk,ki->", &[&m, &n, &p], None);
+    check("ij,jk->ik", &[&m, &n], Some('j'));
+    check("ij,jk,kl->il", &[&m, &n, &p], None);
+    check("jj,jk->k", &[&square, &n], Some('j'));
+    check("ij,jk->k", &[&row, &n], Some('j'));
+    check("ij->i", &[&m], Some('j'));
+    check("ij->ji", &[&m], None);
+
+    let operands: [&dyn ArrayLike<f64>; 2] = [&m, &n];
+    assert!(matches!(
+        einsum_sliced("ij,jk->ik", &operands, Some('i'), 2),
+        Err(EinsumError::InvalidSliceIndex { index: 'i' })
+    ));
+    assert!(matches!(
+        einsum_sliced("ij,jk->ik", &operands, Some('z'), 2),
+        Err(EinsumError::InvalidSliceIndex { index: 'z' })
+    ));
+    assert!(matches!(
+        einsum_sliced("ij,jk->ik", &operands, Some('j'), 0),
+        Err(EinsumError::Unsupported(_))
+    ));
+
+    // The planner slices the bond that the large intermediate result (with indices a, c) has
+    let sc = SizedContraction::from_string_and_shapes(
+        "ab,bc,cd,da->",
+        &[vec![2, 50], vec![50, 50], vec![50, 2], vec![2, 2]],
+    )
+    .unwrap();
+    let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    assert_eq!(order.best_slice_index(5), Some('c'));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_contracts_independent_branches_concurrently() {
+    let a = rand_array((2, 30));
+    let b = rand_array((30, 2));
+    let c = rand_array((2, 30));
+    let d = rand_array((30, 2));
+    let e = rand_array((2, 30));
+    let f = rand_array((30, 2));
+
+    // The product of a and b and the product of c and d don't depend on each other
+    let path = einsum_path(
+        "ab,bc,cd,de->ae",
+        &[&a, &b, &c, &d],
+        OptimizationMethod::Optimal { memory_limit: None },
+    )
+    .unwrap();
+    assert_eq!(
+        path.contraction_order.to_explicit_path(),
+        vec![(0, 1), (0, 1), (0, 1)]
+    );
+    let correct_answer = a.dot(&b).dot(&c).dot(&d).into_dyn();
+    assert!(path
+        .par_contract_operands(&[&a, &b, &c, &d])
+        .my_all_close(&correct_answer, TOL));
+    let result = with_num_threads(2, || {
+        with_summation_mode(SummationMode::Kahan, || {
+            par_einsum("ab,bc,cd,de->ae", &[&a, &b, &c, &d]).unwrap()
+        })
+    })
+    .unwrap();
+    assert!(result.my_all_close(&correct_answer, TOL));
+
+    let operands: [&dyn ArrayLike<f64>; 6] = [&a, &b, &c, &d, &e, &f];
+    for method in [
+        OptimizationMethod::Optimal { memory_limit: None },
+        OptimizationMethod::Greedy,
+    ] {
+        let path = einsum_path("ab,bc,cd,de,ef,fg->ag", &operands, method).unwrap();
+        assert!(path
+            .par_contract_operands(&operands)
+            .my_all_close(&path.contract_operands(&operands), TOL));
+    }
+    let result = par_einsum("ab,bc,cd,de,ef,fa->", &operands).unwrap();
+    assert!(result.my_all_close(&einsum("ab,bc,cd,de,ef,fa->", &operands).unwrap(), TOL));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_contracts_on_a_caller_provided_thread_pool() {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(3)
+        .build()
+        .unwrap();
+    let a = rand_array((4, 20, 30));
+    let b = rand_array((4, 30, 10));
+    let operands: [&dyn ArrayLike<f64>; 2] = [&a, &b];
+    let correct_answer = einsum("bij,bjk->bik", &operands).unwrap();
+
+    let result = par_einsum_in(&pool, "bij,bjk->bik", &operands).unwrap();
+    assert!(result.my_all_close(&correct_answer, TOL));
+    let (result, num_threads) = with_thread_pool(&pool, || {
+        (
+            par_einsum("bij,bjk->bik", &[&a, &b]).unwrap(),
+            rayon::current_num_threads(),
+        )
+    });
+    assert!(result.my_all_close(&correct_answer, TOL));
+    assert_eq!(num_threads, 3);
+
+    // The summation mode carries over to the pool
+    let mode = with_summation_mode(SummationMode::Pairwise, || {
+        with_thread_pool(&pool, summation_mode)
+    });
+    assert_eq!(mode, SummationMode::Pairwise);
+    assert!(matches!(
+        par_einsum_in(&pool, "ij,jk->ik", &operands),
+        Err(EinsumError::RankMismatch { .. })
+    ));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_reduces_deterministically_whatever_the_number_of_threads() {
+    let a = rand_array((150, 40));
+    let b = rand_array((40, 130));
+    let c = rand_array((130, 70));
+    let d = rand_array((70, 150));
+    let t = rand_array((3, 150, 40));
+
+    fn as_operands<'a>(arrays: &[&'a Array2<f64>]) -> Vec<&'a dyn ArrayLike<f64>> {
+        arrays.iter().map(|x| *x as &dyn ArrayLike<f64>).collect()
+    }
+    let matrices = [&a, &b, &c, &d];
+    for input_string in ["ij,jk->ik", "ij,jk,kl,li->", "ij,jk,kl,lm->im"] {
+        let num_operands = input_string.matches(',').count() + 1;
+        let results: Vec<ArrayD<f64>> = [1, 2, 3, 7]
+            .iter()
+            .map(|&num_threads| {
+                with_num_threads(num_threads, || {
+                    with_deterministic_reductions(|| {
+                        par_einsum(input_string, &as_operands(&matrices[..num_operands])).unwrap()
+                    })
+                })
+                .unwrap()
+            })
+            .collect();
+        for result in results.iter() {
+            assert_eq!(result, &results[0]);
+        }
+        let correct_answer = einsum(input_string, &as_operands(&matrices[..num_operands])).unwrap();
+        assert!(results[0].my_all_close(&correct_answer, TOL));
+    }
+    let stacked = with_deterministic_reductions(|| par_einsum("bij,jk->bik", &[&t, &b]).unwrap());
+    assert!(stacked.my_all_close(&einsum("bij,jk->bik", &[&t, &b]).unwrap(), TOL));
+
+    // The setting carries over to other thread pools
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    assert!(!deterministic_reductions());
+    with_deterministic_reductions(|| {
+        assert!(deterministic_reductions());
+        assert!(with_thread_pool(&pool, deterministic_reductions));
+    });
+    assert!(!deterministic_reductions());
+}
+
+#[test]
+fn it_lowers_contraction_orders_to_kernel_programs() {
+    let m = rand_array((3, 3)).into_dyn();
+    let n = rand_array((3, 4)).into_dyn();
+    let t = rand_array((3, 3, 4)).into_dyn();
+    let b1 = rand_array((2, 3, 4)).into_dyn();
+    let b2 = rand_array((2, 4, 3)).into_dyn();
+    let row = rand_array((1, 3)).into_dyn();
+    let v = rand_array(4).into_dyn();
+
+    let check = |input_string: &str, operands: &[&ArrayD<f64>]| {
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let correct_answer = einsum(input_string, &operands).unwrap();
+        for method in [OptimizationMethod::Naive, OptimizationMethod::Greedy] {
+            let path = einsum_path(input_string, &operands, method).unwrap();
+            let program = path.contraction_order.lower().unwrap();
+            assert_eq!(program.inputs.len(), operands.len());
+            assert_eq!(program.output_shape, correct_answer.shape());
+            assert_eq!(program.buffer_len(program.output), correct_answer.len());
+            assert_eq!(program.last_uses()[program.output], None);
+            assert!(program
+                .execute(&operands)
+                .my_all_close(&correct_answer, TOL));
+        }
+    };
+    check("ij->ij", &[&m]);
+    check("ij->ji", &[&m]);
+    check("ij->i", &[&n]);
+    check("ii->i", &[&m]);
+    check("ii->", &[&m]);
+    check("iij->ji", &[&t]);
+    check("ij,jk->ik", &[&m, &n]);
+    check("ij,ij->ij", &[&m, &m]);
+    check("ij,kl->ijkl", &[&m, &n]);
+    check("ij,ij->", &[&n, &n]);
+    check("bij,bjk->bik", &[&b1, &b2]);
+    check("bij,bjk->kbi", &[&b1, &b2]);
+    check("iij,ij->j", &[&t, &n]);
+    check("ij,jk,kl->il", &[&m, &m, &n]);
+    check("ij,ij->ij", &[&row, &m]);
+    check("ij,k->i", &[&n, &v]);
+    check(
+        "ij,j,jk,k->",
+        &[&m, &row.index_axis(Axis(0), 0).to_owned(), &n, &v],
+    );
+
+    // Operands with zero-length axes
+    let empty = ArrayD::<f64>::zeros(IxDyn(&[0, 4]));
+    let empty_batch = ArrayD::<f64>::zeros(IxDyn(&[0, 3, 4]));
+    let other_empty_batch = ArrayD::<f64>::zeros(IxDyn(&[0, 4, 3]));
+    check("ab->b", &[&empty]);
+    check("ab->ba", &[&empty]);
+    check("ab,bc->ac", &[&empty, &n.t().to_owned()]);
+    check("ij,jk->ik", &[&n, &empty.t().to_owned()]);
+    check("bij,bjk->bik", &[&empty_batch, &other_empty_batch]);
+
+    let path = einsum_path("ij->iij", &[&m], OptimizationMethod::Naive).unwrap();
+    assert!(matches!(
+        path.contraction_order.lower(),
+        Err(EinsumError::Unsupported(_))
+    ));
+}
+
+#[cfg(feature = "wgpu")]
+#[test]
+fn it_runs_kernel_programs_on_the_gpu() {
+    let executor = match pollster::block_on(WgpuExecutor::new()) {
+        Ok(executor) => executor,
+        Err(_) => {
+            eprintln!("skipping: no wgpu adapter is available");
+            return;
+        }
+    };
+    let single = |shape: &[usize]| rand_array(IxDyn(shape)).mapv(|x| x as f32);
+    let m = single(&[3, 3]);
+    let n = single(&[3, 4]);
+    let t = single(&[3, 3, 4]);
+    let b1 = single(&[2, 3, 4]);
+    let b2 = single(&[2, 4, 3]);
+    let row = single(&[1, 3]);
+    let v = single(&[4]);
+    let large = single(&[300, 500]);
+    let long = single(&[2100]);
+
+    let check = |input_string: &str, operands: &[&ArrayD<f32>]| {
+        let operands: Vec<&dyn ArrayLike<f32>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f32>).collect();
+        let correct_answer = einsum(input_string, &operands).unwrap();
+        let result = pollster::block_on(executor.einsum(input_string, &operands)).unwrap();
+        assert_eq!(result.shape(), correct_answer.shape(), "{}", input_string);
+        // The GPU sums in a different order
+        let tolerance = 1e-5 * correct_answer.fold(1., |max: f32, x| max.max(x.abs()));
+        assert!(
+            result.abs_diff_eq(&correct_answer, tolerance),
+            "{}",
+            input_string
+        );
+    };
+    check("ij->ij", &[&m]);
+    check("ij->ji", &[&m]);
+    check("ij->i", &[&n]);
+    check("ii->i", &[&m]);
+    check("ii->", &[&m]);
+    check("iij->ji", &[&t]);
+    check("ij,jk->ik", &[&m, &n]);
+    check("ij,kl->ijkl", &[&m, &n]);
+    check("bij,bjk->kbi", &[&b1, &b2]);
+    check("iij,ij->j", &[&t, &n]);
+    check("ij,jk,kl->il", &[&m, &m, &n]);
+    check("ij,ij->ij", &[&row, &m]);
+    check("ij,k->i", &[&n, &v]);
+    check("ij,kj->ik", &[&large, &large]);
+    // More threads than fit in one row of workgroups
+    check("i,j->ij", &[&long, &long]);
+    check("ab->b", &[&single(&[0, 4])]);
+
+    assert!(matches!(
+        pollster::block_on(executor.einsum("ij->iij", &[&m])),
+        Err(EinsumError::Unsupported(_))
+    ));
+}
+
+#[cfg(feature = "cuda")]
+#[test]
+fn it_runs_kernel_programs_on_a_cuda_device() {
+    let executor = match CudaExecutor::new(0) {
+        Ok(executor) => executor,
+        Err(_) => {
+            eprintln!("skipping: no CUDA device is available");
+            return;
+        }
+    };
+    let m = rand_array((3, 3)).into_dyn();
+    let n = rand_array((3, 4)).into_dyn();
+    let t = rand_array((3, 3, 4)).into_dyn();
+    let b1 = rand_array((2, 3, 4)).into_dyn();
+    let b2 = rand_array((2, 4, 3)).into_dyn();
+    let row = rand_array((1, 3)).into_dyn();
+    let v = rand_array(4).into_dyn();
+    let empty = rand_array((3, 0)).into_dyn();
+    let large = rand_array((300, 500)).into_dyn();
+
+    let check = |input_string: &str, operands: &[&ArrayD<f64>]| {
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let correct_answer = einsum(input_string, &operands).unwrap();
+        let result = executor.einsum(input_string, &operands).unwrap();
+        assert_eq!(result.shape(), correct_answer.shape(), "{}", input_string);
+        assert!(
+            result.my_all_close(&correct_answer, TOL),
+            "{}",
+            input_string
+        );
+    };
+    check("ij->ij", &[&m]);
+    check("ij->ji", &[&m]);
+    check("ij->i", &[&n]);
+    check("ii->i", &[&m]);
+    check("ii->", &[&m]);
+    check("iij->ji", &[&t]);
+    check("ij,jk->ik", &[&m, &n]);
+    check("ij,kl->ijkl", &[&m, &n]);
+    check("bij,bjk->kbi", &[&b1, &b2]);
+    check("iij,ij->j", &[&t, &n]);
+    check("ij,jk,kl->il", &[&m, &m, &n]);
+    check("ij,ij->ij", &[&row, &m]);
+    check("ij,k->i", &[&n, &v]);
+    check("ij,kj->ik", &[&empty, &empty]);
+    check("ij,kj->ik", &[&large, &large]);
+
+    // Single and batched GEMMs, each lowered to one cuBLAS call
+    for (input_string, operands) in [
+        ("ij,jk->ik", [&large.t().to_owned(), &large]),
+        ("bij,bjk->bik", [&b1, &b2]),
+    ] {
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|x| *x as &dyn ArrayLike<f64>).collect();
+        let path = einsum_path(input_string, &operands, OptimizationMethod::Naive).unwrap();
+        let program = path.contraction_order.lower().unwrap();
+        assert!(matches!(program.kernels[..], [Kernel::BatchedGemm { .. }]));
+        let result = executor.einsum(input_string, &operands).unwrap();
+        assert!(result.my_all_close(&einsum(input_string, &operands).unwrap(), TOL));
+    }
+
+    let (m32, n32) = (m.mapv(|x| x as f32), n.mapv(|x| x as f32));
+    let result = executor.einsum("ij,jk->ki", &[&m32, &n32]).unwrap();
+    let correct_answer = einsum("ij,jk->ki", &[&m32, &n32]).unwrap();
+    assert!(result.abs_diff_eq(&correct_answer, 1e-4));
+}
+
+/// Counts the calls to each primitive, and otherwise does what `CpuBackend` does
+#[derive(Default)]
+struct CountingBackend {
+    mat_muls: std::sync::atomic::AtomicUsize,
+    permutations: std::sync::atomic::AtomicUsize,
+    sums: std::sync::atomic::AtomicUsize,
+}
+
+impl Backend<f64> for CountingBackend {
+    fn mat_mul(
+        &self,
+        alpha: f64,
+        lhs: &ArrayView2<f64>,
+        rhs: &ArrayView2<f64>,
+        beta: f64,
+        out: &mut ArrayViewMut2<f64>,
+    ) {
+        use std::sync::atomic::Ordering;
+        self.mat_muls.fetch_add(1, Ordering::Relaxed);
+        Backend::<f64>::mat_mul(&CpuBackend, alpha, lhs, rhs, beta, out)
+    }
+
+    fn permute(&self, tensor: &ArrayViewD<f64>, permutation: &[usize]) -> ArrayD<f64> {
+        use std::sync::atomic::Ordering;
+        self.permutations.fetch_add(1, Ordering::Relaxed);
+        Backend::<f64>::permute(&CpuBackend, tensor, permutation)
+    }
+
+    fn sum_trailing_axes(&self, tensor: &ArrayViewD<f64>, num_axes: usize) -> ArrayD<f64> {
+        use std::sync::atomic::Ordering;
+        self.sums.fetch_add(1, Ordering::Relaxed);
+        Backend::<f64>::sum_trailing_axes(&CpuBackend, tensor, num_axes)
+    }
+}
+
+#[test]
+fn it_performs_primitives_through_the_current_backend() {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    let a = rand_array((3, 4));
+    let b = rand_array((4, 5));
+    let t = rand_array((3, 4, 5));
+
+    let backend = Arc::new(CountingBackend::default());
+    let counts = |backend: &CountingBackend| {
+        (
+            backend.mat_muls.load(Ordering::Relaxed),
+            backend.permutations.load(Ordering::Relaxed),
+            backend.sums.load(Ordering::Relaxed),
+        )
+    };
+    let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>], expected| {
+        let before = counts(&backend);
+        let result = with_backend(backend.clone(), || einsum(input_string, operands)).unwrap();
+        let after = counts(&backend);
+        assert!(result.my_all_close(&einsum(input_string, operands).unwrap(), TOL));
+        assert_eq!(
+            (after.0 - before.0, after.1 - before.1, after.2 - before.2),
+            expected
+        );
+    };
+    check("ij,jk->ik", &[&a, &b], (1, 1, 0));
+    check("ij,jk->ki", &[&a, &b], (1, 1, 0));
+    check("ijk->kji", &[&t], (0, 1, 0));
+    check("ijk->i", &[&t], (0, 0, 1));
+
+    // Other element types, and contractions outside of `with_backend`, use `CpuBackend`
+    let before = counts(&backend);
+    with_backend(backend.clone(), || {
+        einsum("ij,jk->ik", &[&a.mapv(|x| x as f32), &b.mapv(|x| x as f32)]).unwrap()
+    });
+    einsum("ij,jk->ik", &[&a, &b]).unwrap();
+    assert_eq!(counts(&backend), before);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn it_carries_the_backend_over_to_worker_threads() {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    let a = rand_array((200, 30));
+    let b = rand_array((30, 40));
+    let backend = Arc::new(CountingBackend::default());
+    let result = with_num_threads(4, || {
+        with_backend(backend.clone(), || par_einsum("ij,jk->ik", &[&a, &b]))
+    })
+    .unwrap()
+    .unwrap();
+    assert!(result.my_all_close(&a.dot(&b).into_dyn(), TOL));
+    assert!(backend.mat_muls.load(Ordering::Relaxed) > 1);
+}
+
+#[cfg(feature = "faer")]
+#[test]
+fn it_multiplies_matrices_with_faer_like_the_default_backend() {
+    use num_complex::Complex64;
+    use std::sync::Arc;
+
+    let a = rand_array((30, 40));
+    let b = rand_array((40, 20));
+    let t = rand_array((5, 30, 6));
+    let u = rand_array((6, 5, 7));
+    let reversed = a.slice(s![..;-1, ..]);
+
+    for backend in [FaerBackend::default(), FaerBackend::new(faer::Par::Seq)] {
+        let backend = Arc::new(backend);
+        let as_f64: Arc<dyn Backend<f64>> = backend.clone();
+        let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>]| {
+            let result = with_backend(as_f64.clone(), || einsum(input_string, operands)).unwrap();
+            assert!(
+                result.my_all_close(&einsum(input_string, operands).unwrap(), TOL),
+                "{}",
+                input_string
+            );
+        };
+        check("ij,jk->ik", &[&a, &b]);
+        check("ij,jk->ki", &[&a, &b]);
+        check("ij,ik->jk", &[&a, &a]);
+        check("ij,jk->ik", &[&reversed, &b]);
+        check("bij,jbk->bik", &[&t, &u]);
+        check("bij,jbk->ik", &[&t, &u]);
+
+        // Accumulating scales the existing contents of the output by beta
+        let path = einsum_path("ij,jk->ik", &[&a, &b], OptimizationMethod::Naive).unwrap();
+        for beta in [0., 1., 3.] {
+            let mut expected = ArrayD::from_elem(vec![30, 20], 2.);
+            path.contract_operands_acc(&[&a, &b], 0.5, beta, &mut expected.view_mut())
+                .unwrap();
+            let mut out = ArrayD::from_elem(vec![30, 20], 2.);
+            with_backend(as_f64.clone(), || {
+                path.contract_operands_acc(&[&a, &b], 0.5, beta, &mut out.view_mut())
+            })
+            .unwrap();
+            assert!(out.my_all_close(&expected, TOL), "{}", beta);
+        }
+
+        // Other element types faer supports
+        let (a32, b32) = (a.mapv(|x| x as f32), b.mapv(|x| x as f32));
+        let result =
+            with_backend::<f32, _, _>(backend.clone(), || einsum("ij,jk->ik", &[&a32, &b32]));
+        assert!(result.unwrap().abs_diff_eq(&a32.dot(&b32).into_dyn(), 1e-4));
+        let (ac, bc) = (a.mapv(|x| Complex64::new(x, -x)), b.mapv(Complex64::from));
+        let result =
+            with_backend::<Complex64, _, _>(backend.clone(), || einsum("ij,jk->ik", &[&ac, &bc]))
+                .unwrap();
+        let expected = ac.dot(&bc).into_dyn();
+        assert!(Zip::from(&result)
+            .and(&expected)
+            .all(|x, y| (x - y).norm() < TOL));
+    }
+}
+
+#[test]
+fn it_multiplies_permuted_operands_without_copying_them() {
+    use std::sync::{Arc, Mutex};
+
+    /// Records the strides of the matrices it multiplies
+    #[derive(Default)]
+    struct StrideRecorder(Mutex<Vec<(Vec<isize>, Vec<isize>)>>);
+
+    impl Backend<f64> for StrideRecorder {
+        fn mat_mul(
+            &self,
+            alpha: f64,
+            lhs: &ArrayView2<f64>,
+            rhs: &ArrayView2<f64>,
+            beta: f64,
+            out: &mut ArrayViewMut2<f64>,
+        ) {
+            let strides = (lhs.strides().to_vec(), rhs.strides().to_vec());
+            self.0.lock().unwrap().push(strides);
+            Backend::<f64>::mat_mul(&CpuBackend, alpha, lhs, rhs, beta, out)
+        }
+    }
+
+    let a = rand_array((4, 3));
+    let b = rand_array((4, 5));
+    let t = rand_array((2, 3, 4));
+    let u = rand_array((5, 3, 4));
+
+    let recorder = Arc::new(StrideRecorder::default());
+    let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>], expected_strides| {
+        recorder.0.lock().unwrap().clear();
+        let result = with_backend(recorder.clone(), || einsum(input_string, operands)).unwrap();
+        let correct_answer = einsum_path(input_string, operands, OptimizationMethod::Naive)
+            .unwrap()
+            .contraction_order
+            .lower()
+            .unwrap()
+            .execute(operands);
+        assert!(result.my_all_close(&correct_answer, TOL));
+        assert_eq!(recorder.0.lock().unwrap().as_slice(), &[expected_strides]);
+    };
+
+    // The transposed LHS is multiplied as it is
+    check("ji,jk->ik", &[&a, &b], (vec![1, 3], vec![5, 1]));
+    // Both groups of axes of the permuted operands can be merged
+    check("ijk,ljk->il", &[&t, &u], (vec![12, 1], vec![1, 12]));
+    // Reversed and stepped views
+    let reversed = a.slice(s![..;-1, ..]);
+    check("ji,jk->ik", &[&reversed, &b], (vec![1, -3], vec![5, 1]));
+    let stepped = b.slice(s![.., ..;2]);
+    check("ji,jk->ik", &[&a, &stepped], (vec![1, 3], vec![5, 2]));
+    // Axes that can't be merged are copied
+    let c = rand_array((4, 3, 2));
+    let d = rand_array((3, 4, 5));
+    let unmergeable = c.view().permuted_axes([1, 0, 2]);
+    check(
+        "jki,jkl->il",
+        &[&unmergeable, &d],
+        (vec![12, 1], vec![5, 1]),
+    );
+
+    // The stack axes of stacked products are merged and stepped through instead of the operands
+    // being copied
+    let w = rand_array((2, 2, 2, 5));
+    let stacked = rand_array((2, 2, 3, 4));
+    let stacked = stacked.slice(s![..;-1, ..;-1, .., ..;2]);
+    recorder.0.lock().unwrap().clear();
+    let result = with_backend(recorder.clone(), || {
+        einsum("abij,abjk->abik", &[&stacked, &w])
+    })
+    .unwrap();
+    let correct_answer = einsum("abij,abjk->abik", &[&stacked.to_owned(), &w]).unwrap();
+    assert!(result.my_all_close(&correct_answer, TOL));
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec![(vec![4, 2], vec![5, 1]); 4]
+    );
+
+    // The diagonal taken by the simplification of a contiguous operand is multiplied as it is
+    let e = rand_array((3, 3, 4));
+    check("iij,jk->ik", &[&e, &b], (vec![16, 1], vec![5, 1]));
+}
+
+#[test]
+fn it_fuses_adjacent_indices() {
+    let groups = |input_string: &str, shapes: &[Vec<usize>]| {
+        SizedContraction::from_string_and_shapes(input_string, shapes)
+            .unwrap()
+            .adjacent_index_groups()
+    };
+    assert_eq!(
+        groups("abij,ijcd->cdab", &[vec![2, 3, 4, 5], vec![4, 5, 6, 7]]),
+        vec![vec!['a', 'b'], vec!['i', 'j'], vec!['c', 'd']]
+    );
+    assert_eq!(
+        groups("ijk,jkl->il", &[vec![2, 3, 4], vec![3, 4, 5]]),
+        vec![vec!['j', 'k']]
+    );
+    assert!(groups("ij,ji->", &[vec![2, 3], vec![3, 2]]).is_empty());
+    assert!(groups("iij,ij->", &[vec![2, 2, 3], vec![2, 3]]).is_empty());
+
+    let t = rand_array((2, 3, 4, 5));
+    let u = rand_array((4, 5, 3, 2));
+    let v = rand_array((2, 3, 4));
+    let row = rand_array((1, 3, 4));
+    let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>]| {
+        let unfused = einsum_path(input_string, operands, OptimizationMethod::Naive)
+            .unwrap()
+            .contract_operands(operands);
+        let result = einsum(input_string, operands).unwrap();
+        assert_eq!(result.shape(), unfused.shape());
+        assert!(result.my_all_close(&unfused, TOL));
+    };
+    check("abij,ijcd->abcd", &[&t, &u]);
+    check("abij,ijcd->cdab", &[&t, &u]);
+    check("abij,ijcd->", &[&t, &u]);
+    check("abcd->abcd", &[&t]);
+    check("abcd->cdab", &[&t]);
+    check("abcd->ab", &[&t]);
+    check("abc,abc->abc", &[&v, &v]);
+    check("abc,abc->", &[&v, &v]);
+    check(
+        "...ij,ij->...",
+        &[&t, &u.index_axis(Axis(2), 0).index_axis(Axis(2), 0)],
+    );
+    // Broadcasting along a fused index, and axes that can't be merged
+    check("abc,abc->abc", &[&row, &v]);
+    let w = rand_array((3, 2, 4));
+    check("abc,abc->ab", &[&w.view().permuted_axes([1, 0, 2]), &v]);
+    let stepped = t.slice(s![.., .., ..;2, ..]);
+    check(
+        "abij,ijcd->abcd",
+        &[&stepped, &u.slice(s![..;2, .., .., ..])],
+    );
+
+    /// Records the permutations it performs
+    #[derive(Default)]
+    struct PermutationRecorder(std::sync::Mutex<Vec<Vec<usize>>>);
+
+    impl Backend<f64> for PermutationRecorder {
+        fn permute(&self, tensor: &ArrayViewD<f64>, permutation: &[usize]) -> ArrayD<f64> {
+            self.0.lock().unwrap().push(permutation.to_vec());
+            Backend::<f64>::permute(&CpuBackend, tensor, permutation)
+        }
+    }
+    let recorder = std::sync::Arc::new(PermutationRecorder::default());
+    with_backend(recorder.clone(), || einsum("abcd->cdab", &[&t])).unwrap();
+    assert_eq!(recorder.0.lock().unwrap().as_slice(), &[vec![1, 0]]);
+}
+
+#[test]
+fn it_squeezes_out_indices_of_length_one() {
+    assert_eq!(
+        SizedContraction::from_string_and_shapes(
+            "ib,bj,jk->ik",
+            &[vec![1, 1], vec![1, 3], vec![3, 1]]
+        )
+        .unwrap()
+        .squeezable_indices(),
+        vec!['i', 'b', 'k']
+    );
+
+    let check = |input_string: &str, operands: &[&dyn ArrayLike<f64>]| {
+        let unsqueezed = einsum_path(input_string, operands, OptimizationMethod::Naive)
+            .unwrap()
+            .contract_operands(operands);
+        let result = einsum(input_string, operands).unwrap();
+        assert_eq!(result.shape(), unsqueezed.shape());
+        assert!(result.my_all_close(&unsqueezed, TOL));
+    };
+    let b1 = rand_array((1, 2, 3));
+    let b2 = rand_array((1, 3, 4));
+    check("bij,bjk->bik", &[&b1, &b2]);
+    check("bij,bjk->kib", &[&b1, &b2]);
+    check("bij,bjk->ik", &[&b1, &b2]);
+    check("bij->jib", &[&b1]);
+    let one = rand_array((1, 1));
+    check("ij,jk->ik", &[&one, &one]);
+    check("ii->i", &[&one]);
+    check("ii->", &[&one]);
+    check("ij,jk,kl->", &[&one, &one, &one]);
+    let row = rand_array((1, 3));
+    let m = rand_array((2, 3));
+    check("ij,ij->ij", &[&row, &m]);
+    check("ij,kj->ikj", &[&row, &m]);
+    check("...ij,jk->...ik", &[&b1, &b2.index_axis(Axis(0), 0)]);
+}
+
+#[test]
+fn it_contracts_operands_with_zero_length_axes() {
+    let cases = [
+        ("ii->i", vec![vec![0, 0]], vec![0]),
+        ("ii->", vec![vec![0, 0]], vec![]),
+        ("iij->ji", vec![vec![0, 0, 3]], vec![3, 0]),
+        ("iij->j", vec![vec![0, 0, 3]], vec![3]),
+        ("i->ii", vec![vec![0]], vec![0, 0]),
+        ("ij,jk->ik", vec![vec![2, 0], vec![0, 3]], vec![2, 3]),
+        ("ij,jk->ik", vec![vec![0, 2], vec![2, 3]], vec![0, 3]),
+        (
+            "bij,bjk->ik",
+            vec![vec![0, 2, 3], vec![0, 3, 4]],
+            vec![2, 4],
+        ),
+        ("iij,ijk->k", vec![vec![0, 0, 3], vec![0, 3, 2]], vec![2]),
+        (
+            "ij,jk,kl->il",
+            vec![vec![2, 0], vec![0, 3], vec![3, 4]],
+            vec![2, 4],
+        ),
+    ];
+    for (input_string, shapes, output_shape) in cases {
+        let arrays: Vec<ArrayD<f64>> = shapes
+            .iter()
+            .map(|shape| ArrayD::ones(IxDyn(shape)))
+            .collect();
+        let operands: Vec<&dyn ArrayLike<f64>> =
+            arrays.iter().map(|x| x as &dyn ArrayLike<f64>).collect();
+        let zeros = ArrayD::<f64>::zeros(IxDyn(&output_shape));
+        assert_eq!(einsum(input_string, &operands).unwrap(), zeros);
+
+        let path = einsum_path(input_string, &operands, OptimizationMethod::Greedy).unwrap();
+        let mut num_steps_inspected = 0;
+        let result = path.execute_with(&operands, |_, _| num_steps_inspected += 1);
+        assert_eq!(result, zeros);
+        assert_eq!(num_steps_inspected, 1);
+        let mut out = ArrayD::from_elem(IxDyn(&output_shape), 2.);
+        path.contract_operands_into(&operands, &mut out.view_mut())
+            .unwrap();
+        assert_eq!(out, zeros);
+        let mut out = ArrayD::from_elem(IxDyn(&output_shape), 2.);
+        path.contract_operands_acc(&operands, 1., 3., &mut out.view_mut())
+            .unwrap();
+        assert_eq!(out, ArrayD::from_elem(IxDyn(&output_shape), 6.));
+        #[cfg(feature = "rayon")]
+        assert_eq!(path.par_contract_operands(&operands), zeros);
+    }
+}
+
+#[test]
+fn it_contracts_empty_stack_axes() {
+    let lhs = ArrayD::<f64>::ones(IxDyn(&[3, 0]));
+    let rhs = ArrayD::<f64>::ones(IxDyn(&[2, 1, 0]));
+    let zeros = ArrayD::<f64>::zeros(IxDyn(&[2, 3, 0]));
+    assert_eq!(
+        einsum_conj("ab,cab->cab", &[&lhs, &rhs], &[false, true]).unwrap(),
+        zeros
+    );
+    assert_eq!(
+        einsum_many(&["ab,cab->cab"], &[&lhs, &rhs]).unwrap(),
+        vec![zeros.clone()]
+    );
+
+    // Past the shortcut for zero-length indices, straight into the contractor
+    let sc = validate_and_size("ab,cab->cab", &[&lhs, &rhs]).unwrap();
+    let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    let options = PathOptions::new().with_pair_method(0, PairMethod::StackedTensordotGeneral);
+    let path: EinsumPath<f64> = EinsumPath::from_path_with_options(&order, &options).unwrap();
+    let steps = match &path.steps {
+        EinsumPathSteps::PairContractions(steps) => steps,
+        EinsumPathSteps::SingletonContraction(_) => panic!("expected a pair contraction"),
+    };
+    assert_eq!(steps[0].contract_pair(&lhs.view(), &rhs.view()), zeros);
+}
+
+#[test]
+fn it_returns_views_for_permutations() {
+    let a: Array3<f64> = rand_array((2, 3, 4));
+    let a_t = a.view().permuted_axes([2, 0, 1]);
+    for input_string in [
+        "ijk->ijk",
+        "ijk->kji",
+        "ijk->jki",
+        "ijk",
+        "kij",
+        "...k->k...",
+    ] {
+        let view = einsum_view(input_string, &[&a]).unwrap();
+        assert!(view.is_view(), "{}", input_string);
+        assert_eq!(view, einsum(input_string, &[&a]).unwrap());
+        let view = einsum_view(input_string, &[&a_t]).unwrap();
+        assert!(view.is_view(), "{}", input_string);
+        assert_eq!(view, einsum(input_string, &[&a_t]).unwrap());
+    }
+
+    let square: Array2<f64> = rand_array((3, 3));
+    let b: Array2<f64> = rand_array((4, 5));
+    let diagonal = einsum_view("ii->i", &[&square]).unwrap();
+    assert!(diagonal.is_view());
+    assert_eq!(diagonal, square.diag().into_dyn());
+    let corner = b.slice(s![..3, ..3]);
+    let cases: [(&str, &[&dyn ArrayLike<f64>]); 5] = [
+        ("ii->i", &[&corner]),
+        ("ij->i", &[&b]),
+        ("i->ii", &[&square.row(0)]),
+        ("ij,jk->ik", &[&square, &square]),
+        ("ij,ij->ij", &[&b, &b]),
+    ];
+    for (input_string, operands) in cases {
+        let result = einsum_view(input_string, operands).unwrap();
+        assert!(result.is_owned(), "{}", input_string);
+        assert!(result.my_all_close(&einsum(input_string, operands).unwrap(), TOL));
+    }
+    assert!(einsum_view("ij->k", &[&b]).is_err());
+}
+
+#[test]
+fn it_shares_the_data_of_shared_operands() {
+    let a = rand_array((2, 3, 4)).into_dyn().into_shared();
+    for input_string in ["ijk->ijk", "ijk->kji", "ijk"] {
+        let shared = einsum_shared(input_string, &[&a]).unwrap();
+        assert_eq!(shared.as_ptr(), a.as_ptr(), "{}", input_string);
+        assert_eq!(shared, einsum(input_string, &[&a]).unwrap());
+    }
+
+    // Inside with_standard_layout_output, only permutations in standard layout are shared
+    let shared = with_standard_layout_output(|| einsum_shared("ijk->ijk", &[&a])).unwrap();
+    assert_eq!(shared.as_ptr(), a.as_ptr());
+    let copied = with_standard_layout_output(|| einsum_shared("ijk->kji", &[&a])).unwrap();
+    assert_ne!(copied.as_ptr(), a.as_ptr());
+    assert!(copied.is_standard_layout());
+    assert_eq!(copied, einsum("ijk->kji", &[&a]).unwrap());
+
+    let b = rand_array((4, 5)).into_dyn().into_shared();
+    for (input_string, operands) in [("ijk->ik", vec![&a]), ("ijk,kl->il", vec![&a, &b])] {
+        let result = einsum_shared(input_string, &operands).unwrap();
+        let array_likes: Vec<&dyn ArrayLike<f64>> =
+            operands.iter().map(|&x| x as &dyn ArrayLike<f64>).collect();
+        let correct_answer = einsum(input_string, &array_likes);
+        assert!(result.my_all_close(&correct_answer.unwrap(), TOL));
+    }
+    assert!(einsum_shared("ij->i", &[&a]).is_err());
+}
+
+#[test]
+fn it_returns_diagonals_as_views() {
+    let a: Array3<f64> = rand_array((3, 3, 4));
+    for input_string in ["iij->ji", "iij->ij", "ijk->kji", "ijk"] {
+        let view = einsum_view_singleton(input_string, &a).unwrap();
+        assert_eq!(view, einsum(input_string, &[&a]).unwrap());
+        let first = view.iter().next().unwrap();
+        assert!(std::ptr::eq(first, a.iter().next().unwrap()));
+    }
+    let b: Array2<f64> = rand_array((4, 4));
+    let b_t = b.t();
+    let view = einsum_view_singleton("ii->i", &b_t).unwrap();
+    assert_eq!(view, b.diag().into_dyn());
+    let empty: Array2<f64> = Array::zeros((0, 0));
+    assert_eq!(
+        einsum_view_singleton("ii->i", &empty).unwrap().shape(),
+        &[0]
+    );
+
+    // Summations and output embeddings can't be views
+    assert!(einsum_view_singleton("iij->j", &a).is_err());
+    assert!(einsum_view_singleton("ii", &b).is_err());
+    assert!(einsum_view_singleton("ij->iij", &b).is_err());
+    // Neither can the diagonal of a tensor that isn't contiguous
+    assert!(einsum_view_singleton("ii->i", &b.slice(s![..3, ..3])).is_err());
+    // but its permutations can, and so can diagonals of reversed axes
+    for reversed in [b.slice(s![..;-1, ..;-1]), b.slice(s![..;-1, ..])] {
+        let view = einsum_view_singleton("ii->i", &reversed).unwrap();
+        assert_eq!(view, reversed.diag().into_dyn());
+    }
+    let reversed = b.slice(s![..3, ..;-1]);
+    let view = einsum_view_singleton("ij->ji", &reversed).unwrap();
+    assert_eq!(view, reversed.t().into_dyn());
+    assert!(einsum_view_singleton("ij,jk->ik", &b).is_err());
+}
+
+#[test]
+fn it_contracts_operands_with_negative_and_zero_strides() {
+    let a: Array3<f64> = rand_array((4, 4, 4));
+    let reversed = [
+        a.slice(s![..;-1, .., ..]),
+        a.slice(s![.., ..;-1, ..;-1]),
+        a.slice(s![..;-1, ..;-1, ..;-1]),
+        a.slice(s![..;-2, ..;2, ..;-2]),
+    ];
+    for input_string in [
+        "iij->ji",
+        "iii->i",
+        "iij->j",
+        "iji->",
+        "ijk->kj",
+        "ijk->kji",
+        "iji->ij",
+        "ijk->kjii",
+    ] {
+        for operand in reversed.iter() {
+            let correct_answer = einsum(input_string, &[&operand.to_owned()]).unwrap();
+            let result = einsum(input_string, &[operand]).unwrap();
+            assert!(
+                result.my_all_close(&correct_answer, TOL),
+                "{}",
+                input_string
+            );
+        }
+    }
+
+    let m: Array2<f64> = rand_array((4, 4));
+    let v: Array1<f64> = rand_array(4);
+    let m_reversed = m.slice(s![..;-1, ..;-1]);
+    let v_reversed = v.slice(s![..;-1]);
+    let broadcast = v.broadcast((4, 4)).unwrap();
+    let pairs: [(&str, &dyn ArrayLike<f64>, &dyn ArrayLike<f64>); 7] = [
+        ("ii,ij->j", &m_reversed, &m_reversed),
+        ("ij,ij->ij", &m_reversed, &broadcast),
+        ("ij,jk->ik", &m_reversed, &broadcast),
+        ("ij,kj->ik", &broadcast, &m_reversed),
+        ("ii,jj->ij", &broadcast, &m_reversed),
+        ("ij,j->i", &m_reversed, &v_reversed),
+        ("ijk,jk->i", &reversed[1], &m_reversed),
+    ];
+    for (input_string, lhs, rhs) in pairs {
+        let lhs_owned = lhs.into_dyn_view().to_owned();
+        let rhs_owned = rhs.into_dyn_view().to_owned();
+        let correct_answer = einsum(input_string, &[&lhs_owned, &rhs_owned]).unwrap();
+        let result = einsum(input_string, &[lhs, rhs]).unwrap();
+        assert!(
+            result.my_all_close(&correct_answer, TOL),
+            "{}",
+            input_string
+        );
+    }
+}
+
+#[test]
+fn it_shares_paths_between_threads() {
+    fn assert_send_sync<T: Send + Sync + 'static>(_: &T) {}
+
+    let m1 = rand_array((4, 5));
+    let m2 = rand_array((5, 6));
+    let m3 = rand_array((6, 4));
+    let arrays = [m1, m2, m3];
+    let cube = rand_array((2, 2, 4));
+    for s in ["ij,jk,ki->", "ij,jk,kl->il", "ij->ji"] {
+        let operands: Vec<&dyn ArrayLike<f64>> = arrays
+            .iter()
+            .take(s.split(',').count())
+            .map(|x| x as &dyn ArrayLike<f64>)
+            .collect();
+        let path = einsum_path(s, &operands, OptimizationMethod::Greedy).unwrap();
+        assert_send_sync(&path);
+        let expected = path.contract_operands(&operands);
+        std::thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| {
+                    let operands: Vec<&dyn ArrayLike<f64>> = arrays
+                        .iter()
+                        .take(s.split(',').count())
+                        .map(|x| x as &dyn ArrayLike<f64>)
+                        .collect();
+                    assert_eq!(path.contract_operands(&operands), expected);
+                });
+            }
+        });
+    }
+    let path = einsum_path("iij->j", &[&cube], OptimizationMethod::Naive).unwrap();
+    assert_send_sync(&path);
+    let expression: ContractExpression<f64> =
+        contract_expression("ij,jk->ik", &[vec![4, 5], vec![5, 6]]).unwrap();
+    assert_send_sync(&expression);
+}
+
+#[test]
+fn it_displays_paths() {
+    let m1 = rand_array((3, 3, 4));
+    let m2 = rand_array((4, 5));
+    let m3 = rand_array((5, 2));
+    let operands: [&dyn ArrayLike<f64>; 3] = [&m1, &m2, &m3];
+    let sc = SizedContraction::new("iij,jk,kl->il", &operands).unwrap();
+    let path: EinsumPath<f64> =
+        EinsumPath::from_path(&sc.with_explicit_path(&[(1, 2), (0, 1)]).unwrap());
+    let printed = path.to_string();
+    let lines: Vec<&str> = printed.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("step  operands"));
+    assert!(lines[1].contains("input 1, input 2"));
+    assert!(lines[1].contains("jk,kl->jl"));
+    assert!(lines[1].contains("[4, 2]"));
+    assert!(lines[1].ends_with("TensordotGeneral"));
+    assert!(lines[2].contains("input 0, step 0"));
+    assert!(lines[2].contains("iij,jl->il"));
+    assert!(lines[2].ends_with("TensordotGeneral, lhs: Diagonalization iij->ij"));
+
+    let steps = path.steps.to_string();
+    assert_eq!(steps.lines().count(), 2);
+    assert_eq!(
+        steps.lines().nth(1).unwrap(),
+        "step 1: TensordotGeneral, lhs: Diagonalization iij->ij"
+    );
+
+    let path = einsum_path("ij->iij", &[&m2], OptimizationMethod::Naive).unwrap();
+    assert!(path.to_string().lines().nth(1).unwrap().contains("input 0"));
+    assert_eq!(
+        path.steps.to_string(),
+        "step 0: Identity + DiagonalEmbedding"
+    );
+}
+
+#[test]
+fn it_matches_the_naive_reference_implementation() {
+    let a = rand_array((3, 4));
+    let b = rand_array((4, 5));
+    let c = rand_array((5, 3));
+    let square = rand_array((3, 3));
+    let cube = rand_array((3, 3, 4));
+    let batch = rand_array((2, 3, 4));
+    let row = rand_array((1, 4));
+    let vector = square.row(0);
+    let transposed = batch.view().permuted_axes([0, 2, 1]);
+    let cases: [(&str, Vec<&dyn ArrayLike<f64>>); 12] = [
+        ("ij,jk->ik", vec![&a, &b]),
+        ("ij,jk,ki->", vec![&a, &b, &c]),
+        ("ij,jk,kl->li", vec![&a, &b, &c]),
+        ("ii->i", vec![&square]),
+        ("ii", vec![&square]),
+        ("iij->ji", vec![&cube]),
+        ("i->ii", vec![&vector]),
+        ("ij,ij->ij", vec![&a, &row]),
+        ("...j,jk->...k", vec![&batch, &b]),
+        ("bij,bjk->bik", vec![&batch, &transposed]),
+        ("ij,kl->ijkl", vec![&a, &square]),
+        ("i,i->", vec![&vector, &vector]),
+    ];
+    for (input_string, operands) in cases.iter() {
+        let naive = einsum_naive(input_string, operands).unwrap();
+        let optimized = einsum(input_string, operands).unwrap();
+        assert!(naive.my_all_close(&optimized, TOL), "{}", input_string);
+    }
+    assert_eq!(
+        einsum_naive("ij,jk->ik", &[&a, &a]).unwrap_err(),
+        einsum("ij,jk->ik", &[&a, &a]).unwrap_err()
+    );
+}
+
+#[test]
+fn it_classifies_contractions() {
+    let pair = |lhs_simplification, rhs_simplification, method| ContractionClass::Pair {
+        lhs_simplification,
+        rhs_simplification,
+        method,
+        diagonal_embedding: false,
+    };
+    let cases = [
+        (
+            "ij,jk->ik",
+            vec![vec![2, 3], vec![3, 4]],
+            pair(None, None, PairMethod::TensordotGeneral),
+        ),
+        (
+            "bij,bjk->bik",
+            vec![vec![2, 3, 4], vec![2, 4, 5]],
+            pair(None, None, PairMethod::BatchedMatMul),
+        ),
+        (
+            "bijk,bjkl->bil",
+            vec![vec![2, 3, 4, 5], vec![2, 4, 5, 6]],
+            pair(None, None, PairMethod::StackedTensordotGeneral),
+        ),
+        (
+            "ij,i->j",
+            vec![vec![2, 3], vec![2]],
+            pair(None, None, PairMethod::MatrixVectorProduct),
+        ),
+        (
+            "i,i->",
+            vec![vec![2], vec![2]],
+            pair(None, None, PairMethod::DotProduct),
+        ),
+        (
+            "ij,ji->ij",
+            vec![vec![2, 3], vec![3, 2]],
+            pair(None, None, PairMethod::HadamardProductGeneral),
+        ),
+        (
+            "iij,jk->ik",
+            vec![vec![3, 3, 4], vec![4, 5]],
+            pair(
+                Some(SingletonMethod::Diagonalization),
+                None,
+                PairMethod::TensordotGeneral,
+            ),
+        ),
+        (
+            "ij,k->ji",
+            vec![vec![2, 3], vec![4]],
+            pair(
+                None,
+                Some(SingletonMethod::Summation),
+                PairMethod::MatrixScalarProductGeneral,
+            ),
+        ),
+        (
+            "ij->ji",
+            vec![vec![2, 3]],
+            ContractionClass::Singleton {
+                method: SingletonMethod::Permutation,
+                diagonal_embedding: false,
+            },
+        ),
+        (
+            "ij->iij",
+            vec![vec![2, 3]],
+            ContractionClass::Singleton {
+                method: SingletonMethod::Identity,
+                diagonal_embedding: true,
+            },
+        ),
+    ];
+    for (input_string, shapes, class) in cases.iter() {
+        let sc = SizedContraction::from_string_and_shapes(input_string, shapes).unwrap();
+        assert_eq!(
+            classify_contraction(&sc).unwrap(),
+            *class,
+            "{}",
+            input_string
+        );
+    }
+
+    let sc = SizedContraction::from_string_and_shapes(
+        "ij,jk,kl->il",
+        &[vec![2, 3], vec![3, 4], vec![4, 5]],
+    )
+    .unwrap();
+    assert!(classify_contraction(&sc).is_err());
+}
+
+#[test]
+fn it_uses_registered_contractors() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Performs a matrix multiplication `ij,jk->ik` and counts the calls
+    #[derive(Debug)]
+    struct CountingMatMul(Arc<AtomicUsize>);
+
+    impl PairContractor<f64> for CountingMatMul {
+        fn contract_pair<'a, 'b, 'c, 'd>(
+            &self,
+            lhs: &'b ArrayViewD<'a, f64>,
+            rhs: &'d ArrayViewD<'c, f64>,
+        ) -> ArrayD<f64>
+        where
+            'a: 'b,
+            'c: 'd,
+        {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let lhs = lhs.view().into_dimensionality::<Ix2>().unwrap();
+            let rhs = rhs.view().into_dimensionality::<Ix2>().unwrap();
+            lhs.dot(&rhs).into_dyn()
+        }
+    }
+
+    /// Transposes a matrix and counts the calls
+    #[derive(Debug)]
+    struct CountingTranspose(Arc<AtomicUsize>);
+
+    impl SingletonContractor<f64> for CountingTranspose {
+        fn contract_singleton<'a, 'b>(&self, tensor: &'b ArrayViewD<'a, f64>) -> ArrayD<f64>
+        where
+            'a: 'b,
+        {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            tensor.t().to_owned()
+        }
+    }
+
+    let pair_calls = Arc::new(AtomicUsize::new(0));
+    let singleton_calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = ContractorRegistry::new();
+    let counter = pair_calls.clone();
+    registry.register_pair_contractor(move |sc| {
+        let indices = &sc.contraction.operand_indices;
+        let is_mat_mul = indices[0].len() == 2
+            && indices[1].len() == 2
+            && indices[0][1] == indices[1][0]
+            && sc.contraction.output_indices == [indices[0][0], indices[1][1]];
+        if is_mat_mul {
+            Some(Box::new(CountingMatMul(counter.clone())))
+        } else {
+            None
+        }
+    });
+    let counter = singleton_calls.clone();
+    registry.register_singleton_contractor(move |sc| {
+        if sc.as_einsum_string() == "ij->ji" {
+            Some(Box::new(CountingTranspose(counter.clone())))
+        } else {
+            None
+        }
+    });
+
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    let c = rand_array((4, 5));
+    let d = rand_array((5, 2));
+    let cases: [(&str, Vec<&dyn ArrayLike<f64>>); 4] = [
+        ("ij,jk->ik", vec![&a, &b]),
+        ("ij,jk,kl->il", vec![&a, &b, &c]),
+        ("ij,jk,kl,li->", vec![&a, &b, &c, &d]),
+        ("ij->ji", vec![&a]),
+    ];
+    for (input_string, operands) in cases.iter() {
+        let custom = registry.einsum(input_string, operands).unwrap();
+        let built_in = einsum(input_string, operands).unwrap();
+        assert!(custom.my_all_close(&built_in, TOL), "{}", input_string);
+    }
+    // The steps of the longer contractions use the custom contractor only if their
+    // intermediate results happen to have the indices in matrix-multiplication order
+    assert!(pair_calls.load(Ordering::Relaxed) >= 1);
+    assert_eq!(singleton_calls.load(Ordering::Relaxed), 1);
+
+    pair_calls.store(0, Ordering::Relaxed);
+    registry.einsum("ij,jk->ik", &[&a, &b]).unwrap();
+    assert_eq!(pair_calls.load(Ordering::Relaxed), 1);
+    registry.einsum("ij,kj->ik", &[&a, &a]).unwrap();
+    assert_eq!(pair_calls.load(Ordering::Relaxed), 1);
+
+    let sc = SizedContraction::new("ij,jk->ik", &[&a, &b]).unwrap();
+    let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    assert_eq!(registry.path(&order).steps.to_string(), "step 0: Custom");
+    let sc = SizedContraction::new("ij,ij->ij", &[&a, &a]).unwrap();
+    let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    assert_eq!(
+        registry.path(&order).steps.to_string(),
+        "step 0: HadamardProductGeneral"
+    );
+}
+
+#[test]
+fn it_explains_the_choice_of_contractors() {
+    let sc = SizedContraction::from_string_and_shapes(
+        "ij,jk,kl->il",
+        &[vec![2, 3], vec![3, 4], vec![4, 5]],
+    )
+    .unwrap();
+    let explanation = sc.explain();
+    assert_eq!(explanation.len(), 2);
+    for step in explanation.iter() {
+        assert!(
+            step.reason.starts_with("TensordotGeneral, because"),
+            "{}",
+            step
+        );
+        assert!(matches!(
+            step.class,
+            ContractionClass::Pair {
+                method: PairMethod::TensordotGeneral,
+                ..
+            }
+        ));
+    }
+
+    let sc =
+        SizedContraction::from_string_and_shapes("ij,ij->ij", &[vec![2, 3], vec![2, 3]]).unwrap();
+    assert!(sc.explain()[0]
+        .reason
+        .starts_with("HadamardProductGeneral, because no index is contracted"));
+
+    let sc = SizedContraction::from_string_and_shapes("ij->iij", &[vec![2, 3]]).unwrap();
+    let explanation = sc.explain();
+    assert_eq!(explanation[0].einsum_string, "ij->iij");
+    assert!(explanation[0].reason.starts_with("Identity, because"));
+    assert!(explanation[0].reason.ends_with("(DiagonalEmbedding)"));
+
+    /// Multiplies two matrices
+    #[derive(Debug)]
+    struct MatMul;
+
+    impl PairContractor<f64> for MatMul {
+        fn contract_pair<'a, 'b, 'c, 'd>(
+            &self,
+            lhs: &'b ArrayViewD<'a, f64>,
+            rhs: &'d ArrayViewD<'c, f64>,
+        ) -> ArrayD<f64>
+        where
+            'a: 'b,
+            'c: 'd,
+        {
+            let lhs = lhs.view().into_dimensionality::<Ix2>().unwrap();
+            let rhs = rhs.view().into_dimensionality::<Ix2>().unwrap();
+            lhs.dot(&rhs).into_dyn()
+        }
+    }
+
+    let mut registry = ContractorRegistry::new();
+    registry.register_pair_contractor(|_| Some(Box::new(MatMul)));
+    let sc =
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![2, 3], vec![3, 4]]).unwrap();
+    let path = registry.path(&generate_optimized_order(&sc, OptimizationMethod::Naive));
+    assert_eq!(
+        path.explain()[0].reason,
+        "Custom, because a ContractorRegistry supplied it"
+    );
+}
+
+#[test]
+fn it_performs_steps_with_forced_pair_methods() {
+    let all_methods = [
+        PairMethod::HadamardProduct,
+        PairMethod::HadamardProductGeneral,
+        PairMethod::TensordotFixedPosition,
+        PairMethod::TensordotGeneral,
+        PairMethod::ScalarMatrixProduct,
+        PairMethod::ScalarMatrixProductGeneral,
+        PairMethod::MatrixScalarProduct,
+        PairMethod::MatrixScalarProductGeneral,
+        PairMethod::BroadcastProductGeneral,
+        PairMethod::StackedTensordotGeneral,
+        PairMethod::BatchedMatMul,
+        PairMethod::MatrixVectorProduct,
+        PairMethod::DotProduct,
+        PairMethod::Custom,
+    ];
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    let a_t = rand_array((3, 2));
+    let batch = rand_array((2, 3, 4));
+    let vector = rand_array(4);
+    let other_batch = rand_array((4, 2, 5));
+    let column = rand_array(2);
+    type Case<'a> = (&'a str, Vec<&'a dyn ArrayLike<f64>>, Vec<PairMethod>);
+    let cases: [Case; 8] = [
+        (
+            "ij,jk->ik",
+            vec![&a, &b],
+            vec![
+                PairMethod::TensordotFixedPosition,
+                PairMethod::TensordotGeneral,
+                PairMethod::StackedTensordotGeneral,
+                PairMethod::BatchedMatMul,
+            ],
+        ),
+        (
+            "ij,i->j",
+            vec![&a, &column],
+            vec![
+                PairMethod::TensordotGeneral,
+                PairMethod::StackedTensordotGeneral,
+                PairMethod::MatrixVectorProduct,
+            ],
+        ),
+        (
+            "i,i->",
+            vec![&column, &column],
+            vec![
+                PairMethod::TensordotFixedPosition,
+                PairMethod::TensordotGeneral,
+                PairMethod::StackedTensordotGeneral,
+                PairMethod::DotProduct,
+            ],
+        ),
+        (
+            "bij,jbk->kbi",
+            vec![&batch, &other_batch],
+            vec![
+                PairMethod::StackedTensordotGeneral,
+                PairMethod::BatchedMatMul,
+            ],
+        ),
+        (
+            "ij,ij->ij",
+            vec![&a, &a],
+            vec![
+                PairMethod::HadamardProduct,
+                PairMethod::HadamardProductGeneral,
+                PairMethod::BroadcastProductGeneral,
+                PairMethod::StackedTensordotGeneral,
+            ],
+        ),
+        (
+            "ij,ji->ij",
+            vec![&a, &a_t],
+            vec![
+                PairMethod::HadamardProductGeneral,
+                PairMethod::BroadcastProductGeneral,
+                PairMethod::StackedTensordotGeneral,
+            ],
+        ),
+        (
+            "ij,jk->ijk",
+            vec![&a, &b],
+            vec![
+                PairMethod::BroadcastProductGeneral,
+                PairMethod::StackedTensordotGeneral,
+            ],
+        ),
+        (
+            "bij,k->bji",
+            vec![&batch, &vector],
+            vec![
+                PairMethod::TensordotGeneral,
+                PairMethod::MatrixScalarProductGeneral,
+                PairMethod::BroadcastProductGeneral,
+                PairMethod::StackedTensordotGeneral,
+            ],
+        ),
+    ];
+    for (input_string, operands, supported) in cases.iter() {
+        let expected = einsum(input_string, operands).unwrap();
+        for &method in all_methods.iter() {
+            let options = PathOptions::new().with_pair_method(0, method);
+            match einsum_with_options(input_string, operands, &options) {
+                Ok(result) => {
+                    assert!(supported.contains(&method), "{} {:?}", input_string, method);
+                    assert!(
+                        result.my_all_close(&expected, TOL),
+                        "{} {:?}",
+                        input_string,
+                        method
+                    );
+                }
+                Err(error) => {
+                    assert!(
+                        !supported.contains(&method),
+                        "{} {:?}",
+                        input_string,
+                        method
+                    );
+                    assert_eq!(
+                        error,
+                        EinsumError::UnsupportedPairMethod { step: 0, method }
+                    );
+                }
+            }
+        }
+    }
+
+    let sc = validate_and_size("ij,jk->ik", &[&a, &b]).unwrap();
+    let order = generate_optimized_order(&sc, OptimizationMethod::Naive);
+    let options = PathOptions::new().with_pair_method(0, PairMethod::StackedTensordotGeneral);
+    let path: EinsumPath<f64> = EinsumPath::from_path_with_options(&order, &options).unwrap();
+    assert!(path.explain()[0]
+        .reason
+        .starts_with("StackedTensordotGeneral, because PathOptions forced it (instead of TensordotGeneral, because"));
+    let options = PathOptions::new().with_pair_method(1, PairMethod::StackedTensordotGeneral);
+    assert_eq!(
+        EinsumPath::<f64>::from_path_with_options(&order, &options).unwrap_err(),
+        EinsumError::UnsupportedPairMethod {
+            step: 1,
+            method: PairMethod::StackedTensordotGeneral
+        }
+    );
+}
+
+#[test]
+fn it_contracts_integer_labels() {
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    let cube = rand_array((3, 3, 4));
+    type Case<'a> = (
+        &'a str,
+        Vec<&'a dyn ArrayLike<f64>>,
+        Vec<Vec<usize>>,
+        Vec<usize>,
+    );
+    let cases: [Case; 4] = [
+        (
+            "ij,jk->ik",
+            vec![&a, &b],
+            vec![vec![0, 1], vec![1, 2]],
+            vec![0, 2],
+        ),
+        (
+            "ij,jk->ki",
+            vec![&a, &b],
+            vec![vec![7, 40], vec![40, 3]],
+            vec![3, 7],
+        ),
+        (
+            "iij->ji",
+            vec![&cube],
+            vec![vec![100, 100, 5]],
+            vec![5, 100],
+        ),
+        ("ij->", vec![&a], vec![vec![30, 31]], vec![]),
+    ];
+    for (input_string, operands, operand_labels, output_labels) in cases.iter() {
+        let expected = einsum(input_string, operands).unwrap();
+        let result = einsum_labels(operands, operand_labels, output_labels).unwrap();
+        assert!(result.my_all_close(&expected, TOL), "{}", input_string);
+    }
+
+    // A chain of 80 matrices, contracted with ncon, whose labels used to be letters
+    let matrices: Vec<Array2<f64>> = (0..80).map(|_| rand_array((2, 2)) * 0.5).collect();
+    let tensors: Vec<&dyn ArrayLike<f64>> =
+        matrices.iter().map(|x| x as &dyn ArrayLike<f64>).collect();
+    let connects: Vec<Vec<i32>> = (0..80)
+        .map(|n| {
+            vec![
+                if n == 0 { -1 } else { n },
+                if n == 79 { -2 } else { n + 1 },
+            ]
+        })
+        .collect();
+    let connects: Vec<&[i32]> = connects.iter().map(|x| &x[..]).collect();
+    let expected = matrices
+        .iter()
+        .skip(1)
+        .fold(matrices[0].clone(), |product, x| product.dot(x));
+    let result = ncon(&tensors, &connects, None).unwrap();
+    assert!(result.my_all_close(&expected.into_dyn(), TOL));
+
+    assert_eq!(
+        einsum_labels(&[&a], &[vec![0, 1]], &[2]).unwrap_err(),
+        EinsumError::OutputIndexNotInInputs { index: 'c' }
+    );
+    assert!(einsum_labels(&[&a], &[vec![0, 1_000_000]], &[0]).is_err());
+    // Errors name the integer label, not the index that stands for it
+    assert_eq!(
+        einsum_labels(&[&a, &b], &[vec![0, 1], vec![0, 2]], &[1, 2]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "0".to_string(),
+            first_operand: 0,
+            first_axis: 0,
+            expected: 2,
+            operand: 1,
+            axis: 0,
+            found: 3,
+        }
+    );
+    assert_eq!(
+        einsum_labels(&[&a, &b], &[vec![74, 1], vec![74, 2]], &[]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "74".to_string(),
+            first_operand: 0,
+            first_axis: 0,
+            expected: 2,
+            operand: 1,
+            axis: 0,
+            found: 3,
+        }
+    );
+}
+
+#[test]
+fn it_builds_contractions_from_indices() {
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    type Case<'a> = (&'a str, Vec<&'a [char]>, &'a [char]);
+    let cases: [Case; 4] = [
+        ("ij,jk->ik", vec![&['i', 'j'], &['j', 'k']], &['i', 'k']),
+        ("ij,jk->ki", vec![&['i', 'j'], &['j', 'k']], &['k', 'i']),
+        ("ij,jk->", vec![&['i', 'j'], &['j', 'k']], &[]),
+        (
+            "ij,jk->iijk",
+            vec![&['i', 'j'], &['j', 'k']],
+            &['i', 'i', 'j', 'k'],
+        ),
+    ];
+    for (input_string, operand_indices, output_indices) in cases.iter() {
+        let from_string = Contraction::new(input_string).unwrap();
+        let from_indices = Contraction::from_indices(operand_indices, output_indices).unwrap();
+        assert_eq!(from_indices.operand_indices, from_string.operand_indices);
+        assert_eq!(from_indices.output_indices, from_string.output_indices);
+        assert_eq!(
+            from_indices.summation_indices,
+            from_string.summation_indices
+        );
+
+        let sc = SizedContraction::from_contraction_and_operands(&from_indices, &[&a, &b]).unwrap();
+        let result = sc.contract_operands(&[&a, &b]);
+        let expected = einsum(input_string, &[&a, &b]).unwrap();
+        assert!(result.my_all_close(&expected, TOL), "{}", input_string);
+    }
+
+    // Indices that can't appear in a string
+    let contraction = Contraction::from_indices(&[&['α', 'β'], &['β', 'Ω']], &['α', 'Ω']).unwrap();
+    let sc = SizedContraction::from_contraction_and_operands(&contraction, &[&a, &b]).unwrap();
+    assert!(sc
+        .contract_operands(&[&a, &b])
+        .my_all_close(&a.dot(&b).into_dyn(), TOL));
+    assert!(Contraction::from_indices(&[&[0, usize::MAX]], &[0]).is_err());
+}
+
+#[test]
+fn it_builds_contractions_one_operand_at_a_time() {
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    let c = rand_array((4, 5));
+    let batch = rand_array((6, 2, 3));
+
+    let builder = Einsum::new()
+        .operand(&a, "ij")
+        .operand(&b, "jk")
+        .operand(&c, "kl")
+        .output("li");
+    assert_eq!(builder.input_string(), "ij,jk,kl->li");
+    let expected = einsum("ij,jk,kl->li", &[&a, &b, &c]).unwrap();
+    assert!(builder.eval().unwrap().my_all_close(&expected, TOL));
+
+    let result = Einsum::new()
+        .operand(&batch, "...ij")
+        .operand(&b, "jk")
+        .eval()
+        .unwrap();
+    let expected = einsum("...ij,jk", &[&batch, &b]).unwrap();
+    assert!(result.my_all_close(&expected, TOL));
+
+    // The first error is kept, even if later operands are fine
+    let builder = Einsum::new()
+        .operand(&a, "ijk")
+        .operand(&b, "jk")
+        .output("ik");
+    assert_eq!(
+        builder.error(),
+        Some(&EinsumError::RankMismatch {
+            operand: 0,
+            expected: 3,
+            found: 2
+        })
+    );
+    assert_eq!(builder.eval().unwrap_err(), *builder.error().unwrap());
+
+    let builder = Einsum::new().operand(&a, "ij").operand(&b, "j,k");
+    assert_eq!(
+        builder.error(),
+        Some(&EinsumError::Parse {
+            position: 1,
+            message: "invalid character in indices"
+        })
+    );
+    let builder = Einsum::new().operand(&a, "ij").output("ix");
+    assert!(builder.error().is_none());
+    assert_eq!(
+        builder.eval().unwrap_err(),
+        EinsumError::OutputIndexNotInInputs { index: 'x' }
+    );
+}
+
+#[test]
+fn it_parses_whitespace_separated_labels() {
+    let a = rand_array((2, 3, 4));
+    let b = rand_array((4, 5));
+    let c = rand_array((3, 3));
+
+    let cases = [
+        (
+            "labels: batch row chan_in, chan_in chan_out -> batch chan_out row",
+            "bri,io->bor",
+        ),
+        ("labels:batch row chan_in,chan_in chan_out", "bri,io->bor"),
+        ("labels: x1 x2 x3 , x3 y", "abc,cd->abd"),
+    ];
+    for &(labelled, indices) in cases.iter() {
+        let labelled_result = einsum(labelled, &[&a, &b]).unwrap();
+        let expected = einsum(indices, &[&a, &b]).unwrap();
+        assert!(labelled_result.my_all_close(&expected, TOL));
+    }
+    let transposed = einsum("labels:  ... col\t->   col ...  ", &[&a]).unwrap();
+    assert!(transposed.my_all_close(&einsum("...j->j...", &[&a]).unwrap(), TOL));
+
+    // A repeated label is a diagonal, as with single letters
+    let diagonal = einsum("labels: row row -> row", &[&c]).unwrap();
+    assert!(diagonal.my_all_close(&c.diag().into_dyn(), TOL));
+
+    let built = Einsum::labelled()
+        .operand(&a, "batch row chan_in")
+        .operand(&b, "chan_in chan_out")
+        .output("batch chan_out")
+        .eval()
+        .unwrap();
+    assert!(built.my_all_close(&einsum("bri,io->bo", &[&a, &b]).unwrap(), TOL));
+
+    let errors = [
+        (
+            "labels: batch row 1chan, chan out",
+            18,
+            "invalid character in label",
+        ),
+        (
+            "labels: batch row chan, chan-out",
+            28,
+            "invalid character in label",
+        ),
+        (
+            "labels: ... ... x, x",
+            12,
+            "only one ellipsis is allowed per operand",
+        ),
+        (
+            "labels: batch row chan, chan out -> batch cols",
+            42,
+            "requested output contains a label not found in inputs",
+        ),
+    ];
+    for &(input_string, position, message) in errors.iter() {
+        assert_eq!(
+            Contraction::new(input_string).unwrap_err(),
+            EinsumError::Parse { position, message }
+        );
+    }
+
+    // Without the prefix, whitespace is an error rather than a switch to labels
+    let message = "whitespace in indices (multi-character labels need the prefix \"labels:\")";
+    assert_eq!(
+        Contraction::new("ij, jk -> ik").unwrap_err(),
+        EinsumError::Parse {
+            position: 3,
+            message
+        }
+    );
+    assert_eq!(
+        Einsum::new()
+            .operand(&b, "ij")
+            .operand(&c, "batch row")
+            .error(),
+        Some(&EinsumError::Parse {
+            position: 5,
+            message
+        })
+    );
+    let row = c.row(0);
+    let single_labels = einsum("labels:x1,x1", &[&row, &row]).unwrap();
+    assert!(single_labels.my_all_close(&einsum("i,i", &[&row, &row]).unwrap(), TOL));
+
+    // Errors about the operands name the label, not the index that stands for it
+    let mismatch = einsum("labels: batch row chan_in, chan_out chan_in", &[&a, &b]).unwrap_err();
+    assert_eq!(
+        mismatch,
+        EinsumError::IndexLengthMismatch {
+            index: "chan_in".to_string(),
+            first_operand: 0,
+            first_axis: 2,
+            expected: 4,
+            operand: 1,
+            axis: 1,
+            found: 5,
+        }
+    );
+    assert_eq!(
+        mismatch.to_string(),
+        "index 'chan_in' has length 4 on axis 2 of operand 0 but length 5 on axis 1 of operand 1"
+    );
+    assert_eq!(
+        einsum("labels: ... chan, chan out", &[&a, &c]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "chan".to_string(),
+            first_operand: 0,
+            first_axis: 2,
+            expected: 4,
+            operand: 1,
+            axis: 0,
+            found: 3,
+        }
+    );
+}
+
+#[test]
+fn it_accepts_uppercase_and_unicode_indices() {
+    let a = rand_array((2, 3));
+    let b = rand_array((3, 4));
+    let c = rand_array((2, 3, 4));
+
+    let cases = [
+        ("μν,νρ->μρ", "ij,jk->ik"),
+        ("IJ,JK->IK", "ij,jk->ik"),
+        ("iJ,Jk", "ij,jk->ik"),
+        ("αβ,βγ->γα", "ij,jk->ki"),
+    ];
+    for &(letters, indices) in cases.iter() {
+        let result = einsum(letters, &[&a, &b]).unwrap();
+        assert!(result.my_all_close(&einsum(indices, &[&a, &b]).unwrap(), TOL));
+    }
+
+    // The implicit output is sorted by code point: 'B' < 'a' < 'β'
+    let sc = SizedContraction::new("βaB", &[&c]).unwrap();
+    assert_eq!(sc.contraction.output_indices, &['B', 'a', 'β']);
+
+    // Ellipsis axes skip the uppercase letters that are already used
+    let d = rand_array((4, 5));
+    let sc = SizedContraction::new("...Aj,jk->...Ak", &[&c, &d]).unwrap();
+    assert_eq!(sc.as_einsum_string(), "BAj,jk->BAk");
+    let all_uppercase: String = ('A'..='Z').collect();
+    let x = ArrayD::<f64>::ones(vec![1; 27]);
+    let sc = SizedContraction::new(&format!("...{}->...", all_uppercase), &[&x]).unwrap();
+    assert_eq!(sc.contraction.output_indices, &['\u{100}']);
+
+    assert_eq!(
+        Contraction::new("ij,j1->ik").unwrap_err(),
+        EinsumError::Parse {
+            position: 4,
+            message: "invalid character in indices"
+        }
+    );
+}
+
+#[test]
+fn it_contracts_scalar_operands() {
+    let s = arr0(3.0);
+    let t = arr0(-2.0);
+    let v = rand_array(4);
+    let m1 = rand_array((4, 5));
+    let m2 = rand_array((5, 3));
+
+    let scaled = einsum("i,->i", &[&v, &s]).unwrap();
+    assert!(scaled.my_all_close(&(&v * 3.0).into_dyn(), TOL));
+    let scaled = einsum(",i", &[&s, &v]).unwrap();
+    assert!(scaled.my_all_close(&(&v * 3.0).into_dyn(), TOL));
+    assert_eq!(einsum(",->", &[&s, &t]).unwrap(), arr0(-6.0).into_dyn());
+    assert_eq!(einsum("", &[&s]).unwrap(), arr0(3.0).into_dyn());
+    assert_eq!(
+        Contraction::new("i,->i").unwrap().operand_indices,
+        vec![vec!['i'], vec![]]
+    );
+    assert_eq!(
+        Contraction::new("i,,,j").unwrap().operand_indices,
+        vec![vec!['i'], vec![], vec![], vec!['j']]
+    );
+    assert!(Contraction::new("->").unwrap().operand_indices[0].is_empty());
+
+    let expected = (m1.dot(&m2) * -6.0).into_dyn();
+    for method in [OptimizationMethod::Naive, OptimizationMethod::Greedy] {
+        let operands: [&dyn ArrayLike<f64>; 4] = [&s, &m1, &t, &m2];
+        let path = einsum_path(",ij,,jk->ik", &operands, method).unwrap();
+        assert!(path
+            .contract_operands(&operands)
+            .my_all_close(&expected, TOL));
+    }
+    let broadcast = einsum(",...->...", &[&s, &m1]).unwrap();
+    assert!(broadcast.my_all_close(&(&m1 * 3.0).into_dyn(), TOL));
+    let scaled = einsum("labels: row col, -> col row", &[&m1, &s]).unwrap();
+    assert!(scaled.my_all_close(&(m1.t().to_owned() * 3.0).into_dyn(), TOL));
+
+    let built = Einsum::new()
+        .operand(&v, "i")
+        .operand(&s, "")
+        .eval()
+        .unwrap();
+    assert!(built.my_all_close(&(&v * 3.0).into_dyn(), TOL));
+
+    // A 0-d operand still needs empty indices
+    assert_eq!(
+        einsum("i,i->i", &[&v, &s]).unwrap_err(),
+        EinsumError::RankMismatch {
+            operand: 1,
+            expected: 1,
+            found: 0
+        }
+    );
+}
+
+#[test]
+fn it_returns_fully_reduced_outputs_as_scalars() {
+    let a = rand_array((3, 4));
+    let b = rand_array((4, 3));
+    let trace = einsum_scalar("ij,ji->", &[&a, &b]).unwrap();
+    assert!((trace - a.dot(&b).diag().sum()).abs() < TOL);
+    // An implicit output without indices works too
+    assert!((einsum_scalar("ii", &[&a.slice(s![.., ..3])]).unwrap() - a.diag().sum()).abs() < TOL);
+    assert!((einsum_scalar("i->", &[&a.row(0)]).unwrap() - a.row(0).sum()).abs() < TOL);
+    assert_eq!(einsum_scalar(",->", &[&arr0(2), &arr0(3)]).unwrap(), 6);
+    assert_eq!(
+        einsum_scalar("ij,jk", &[&a, &b]).unwrap_err(),
+        EinsumError::OutputRankMismatch {
+            expected: 2,
+            found: 0
+        }
+    );
+}
+
+#[test]
+fn it_computes_output_shapes_without_operands() {
+    let a = rand_array((2, 3, 4));
+    let b = rand_array((4, 5));
+    let c = rand_array((3, 3, 4));
+    for &input_string in ["ijk,kl->il", "ijk,kl", "ijk,kl->lij", "...k,kl->...l"].iter() {
+        let result = einsum(input_string, &[&a, &b]).unwrap();
+        let shape = output_shape(input_string, &[a.shape(), b.shape()]).unwrap();
+        assert_eq!(shape, result.shape());
+    }
+    for &input_string in ["iij", "iik->ik", "ijk->iijk"].iter() {
+        let result = einsum(input_string, &[&c]).unwrap();
+        assert_eq!(
+            output_shape(input_string, &[c.shape()]).unwrap(),
+            result.shape()
+        );
+    }
+
+    assert_eq!(
+        output_shape("ij,jk->ik", &[&[2, 3], &[4, 5]]).unwrap_err(),
+        SizedContraction::from_string_and_shapes("ij,jk->ik", &[vec![2, 3], vec![4, 5]])
+            .unwrap_err()
+    );
+    assert_eq!(
+        output_shape("ij,jk->ik", &[&[2, 3]]).unwrap_err(),
+        EinsumError::OperandCountMismatch {
+            expected: 2,
+            found: 1
+        }
+    );
+}
+
+#[test]
+fn it_sizes_contractions_from_shapes() {
+    let a = rand_array((2, 3, 4));
+    let b = rand_array((4, 5));
+    let c = rand_array((5, 2));
+    let operands: [&dyn ArrayLike<f64>; 3] = [&a, &b, &c];
+    for &input_string in ["ijk,kl,lm->im", "...k,kl,lm", "ijk,kl,li->j"].iter() {
+        let sc = validate_and_size_from_shapes(input_string, &[a.shape(), b.shape(), c.shape()])
+            .unwrap();
+        let from_operands = validate_and_size(input_string, &operands).unwrap();
+        assert_eq!(sc.as_einsum_string(), from_operands.as_einsum_string());
+        assert_eq!(sc.output_size, from_operands.output_size);
+
+        let order = generate_optimized_order(&sc, OptimizationMethod::Greedy);
+        let result = EinsumPath::from_path(&order).contract_operands(&operands);
+        assert!(result.my_all_close(&einsum(input_string, &operands).unwrap(), TOL));
+    }
+
+    assert_eq!(
+        validate_and_size_from_shapes("ij,jk", &[&[2, 3], &[4, 5]]).unwrap_err(),
+        EinsumError::IndexLengthMismatch {
+            index: "j".to_string(),
+            first_operand: 0,
+            first_axis: 1,
+            expected: 3,
+            operand: 1,
+            axis: 0,
+            found: 4
+        }
+    );
+}
@@ -0,0 +1,66 @@
+// The plan cache is global to the process, so these tests are kept out of einsum_test.rs,
+// whose tests would otherwise change the statistics while these run.
+use ndarray::prelude::*;
+use ndarray_einsum_beta::*;
+
+#[test]
+fn it_caches_contraction_orders() {
+    let a: Array3<f64> = Array::range(0., 24., 1.)
+        .into_shape_with_order((2, 3, 4))
+        .unwrap();
+    let b: Array3<f64> = Array::range(0., 40., 1.)
+        .into_shape_with_order((2, 4, 5))
+        .unwrap();
+    let c: Array2<f64> = Array::range(0., 12., 1.)
+        .into_shape_with_order((3, 4))
+        .unwrap();
+    let uncached = einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+    assert_eq!(plan_cache_stats(), PlanCacheStats::default());
+
+    enable_plan_cache(2);
+    for _ in 0..3 {
+        assert_eq!(einsum("bij,bjk->bik", &[&a, &b]).unwrap(), uncached);
+    }
+    let stats = plan_cache_stats();
+    assert_eq!(
+        (stats.hits, stats.misses, stats.len, stats.capacity),
+        (2, 1, 1, 2)
+    );
+
+    // Errors aren't cached
+    assert!(einsum("bij,bjk->bik", &[&b, &a]).is_err());
+    assert!(einsum("bij,bjk->bik", &[&b, &a]).is_err());
+    assert_eq!(plan_cache_stats().len, 1);
+
+    // Different shapes are different entries, and the least recently used one is evicted
+    einsum("ij->ji", &[&c]).unwrap();
+    einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+    let d: Array2<f64> = Array::zeros((5, 6));
+    einsum("ij->ji", &[&d]).unwrap();
+    let stats = plan_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (3, 5, 2));
+    einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+    einsum("ij->ji", &[&c]).unwrap();
+    let stats = plan_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (4, 6, 2));
+
+    // The other string-based functions use the cache too
+    let mut out = Array::zeros((2, 3, 5));
+    einsum_into("bij,bjk->bik", &[&a, &b], &mut out.view_mut().into_dyn()).unwrap();
+    assert_eq!(out.into_dyn(), uncached);
+    assert_eq!(plan_cache_stats().hits, 5);
+
+    enable_plan_cache(1);
+    assert_eq!(plan_cache_stats().len, 1);
+
+    clear_plan_cache();
+    let stats = plan_cache_stats();
+    assert_eq!(
+        (stats.hits, stats.misses, stats.len, stats.capacity),
+        (0, 0, 0, 1)
+    );
+
+    disable_plan_cache();
+    einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+    assert_eq!(plan_cache_stats(), PlanCacheStats::default());
+}
@@ -0,0 +1,133 @@
+// Counts the bytes allocated on the current thread with a global allocator, which would apply
+// to every test in einsum_test.rs, so these tests are kept in their own binary.
+use ndarray::prelude::*;
+use ndarray_einsum_beta::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static BYTES_ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        let _ = BYTES_ALLOCATED.try_with(|bytes| bytes.set(bytes.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by `op` and the number of bytes they took up.
+fn allocated_by(op: impl FnOnce()) -> (usize, usize) {
+    let allocations_before = ALLOCATIONS.with(|allocations| allocations.get());
+    let bytes_before = BYTES_ALLOCATED.with(|bytes| bytes.get());
+    op();
+    (
+        ALLOCATIONS.with(|allocations| allocations.get()) - allocations_before,
+        BYTES_ALLOCATED.with(|bytes| bytes.get()) - bytes_before,
+    )
+}
+
+#[test]
+fn it_reuses_the_intermediate_results_of_a_workspace() {
+    let n = 64;
+    let intermediate_bytes = n * n * std::mem::size_of::<f64>();
+    let m1: Array2<f64> = Array::ones((n, n));
+    let m2: Array2<f64> = Array::ones((n, n));
+    let m3: Array2<f64> = Array::ones((n, n));
+    let m4: Array2<f64> = Array::ones((n, n));
+    let operands: [&dyn ArrayLike<f64>; 4] = [&m1, &m2, &m3, &m4];
+    // The allocations that are left once the workspace has its buffers (see the documentation
+    // of `EinsumWorkspace`): the shape of the output if it isn't a scalar, the list of steps and
+    // the views of the operands; a few index permutations for each step; and the packing
+    // buffers of each matrix multiplication. The last step of the trace copies whichever of its
+    // operands isn't contiguous, which depends on the order in which the path was built, so
+    // only its intermediate results are checked.
+    let cases = [
+        ("ij,jk,kl,lm->im", Some(13)),
+        ("ij,jk,kl,lm->mi", Some(13)),
+        ("ij,jk,kl,li->", None),
+    ];
+    for (input_string, steady_state_allocations) in cases {
+        let path = einsum_path(input_string, &operands, OptimizationMethod::Naive).unwrap();
+        let expected = path.contract_operands(&operands);
+        let mut out = ArrayD::zeros(path.output_shape());
+        let mut workspace = EinsumWorkspace::new();
+        let (_, first_call) = allocated_by(|| {
+            path.contract_operands_with_workspace(&operands, &mut workspace, &mut out.view_mut())
+                .unwrap()
+        });
+        assert!(first_call >= 2 * intermediate_bytes, "{}", input_string);
+        assert_eq!(out, expected);
+        assert_eq!(workspace.len(), 2 * n * n);
+
+        out.fill(0.);
+        let (later_allocations, later_call) = allocated_by(|| {
+            path.contract_operands_with_workspace(&operands, &mut workspace, &mut out.view_mut())
+                .unwrap()
+        });
+        // The intermediate results aren't allocated again
+        assert!(
+            first_call - later_call >= 2 * intermediate_bytes,
+            "{}",
+            input_string
+        );
+        if let Some(steady_state_allocations) = steady_state_allocations {
+            assert_eq!(
+                later_allocations, steady_state_allocations,
+                "{}",
+                input_string
+            );
+        }
+        assert_eq!(out, expected);
+    }
+
+    // A workspace used with another path gets buffers of the right shapes for it
+    let mut workspace = EinsumWorkspace::for_path(
+        &einsum_path("ij,jk,kl,lm->im", &operands, OptimizationMethod::Naive).unwrap(),
+    );
+    let v: Array1<f64> = Array::ones(n);
+    let path = einsum_path("ij,j,jk->k", &[&m1, &v, &m2], OptimizationMethod::Naive).unwrap();
+    let mut out = Array1::zeros(n).into_dyn();
+    path.contract_operands_with_workspace(&[&m1, &v, &m2], &mut workspace, &mut out.view_mut())
+        .unwrap();
+    assert_eq!(out, path.contract_operands(&[&m1, &v, &m2]));
+    assert_eq!(workspace.len(), n);
+
+    let mut wrong_shape = Array1::zeros(n + 1).into_dyn();
+    assert!(path
+        .contract_operands_with_workspace(
+            &[&m1, &v, &m2],
+            &mut workspace,
+            &mut wrong_shape.view_mut()
+        )
+        .is_err());
+
+    // The operands are checked against the path, as for `contract_operands_into`
+    assert_eq!(
+        path.contract_operands_with_workspace(&[&m1, &v], &mut workspace, &mut out.view_mut()),
+        Err(EinsumError::OperandCountMismatch {
+            expected: 3,
+            found: 2
+        })
+    );
+    let short: Array1<f64> = Array::ones(n - 1);
+    assert!(matches!(
+        path.contract_operands_with_workspace(
+            &[&m1, &short, &m2],
+            &mut workspace,
+            &mut out.view_mut()
+        ),
+        Err(EinsumError::ShapeMismatch { operand: 1, .. })
+    ));
+}